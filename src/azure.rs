@@ -0,0 +1,531 @@
+use anyhow::{bail, Result as AnyResult};
+use base64::Engine;
+use regex::Regex;
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+
+use crate::github::{
+    CheckResult, CheckState, GitHubRepo, PullRequestDescription, PullRequestOutput, PullReviews,
+    PullState, PullStatus, RepoAccess,
+};
+use crate::glob::Glob;
+
+/// Environment variable with the personal access token for Azure DevOps.
+pub const AZURE_DEVOPS_TOKEN_ENV: &str = "AZURE_DEVOPS_TOKEN";
+
+/// Host every Azure DevOps repo is recorded under, including the `visualstudio.com` ones.
+pub const AZURE_DEVOPS_HOST: &str = "dev.azure.com";
+
+const AZURE_DEVOPS_API_URL: &str = "https://dev.azure.com";
+const API_VERSION: &str = "7.1";
+
+/// Reviewers vote 10 to approve and 5 to approve with suggestions, -5 to wait for the author
+/// and -10 to reject.
+const APPROVED_VOTE: i64 = 5;
+const REJECTED_VOTE: i64 = -5;
+
+/// Client for the REST API of Azure DevOps Services.
+#[derive(Debug)]
+pub struct AzureDevOpsClient {
+    http: Client,
+    /// Base of the API, the organization and project are added to it.
+    api_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct List<T> {
+    value: Vec<T>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Project {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Repository {
+    default_branch: Option<String>,
+    #[serde(default)]
+    is_disabled: bool,
+    web_url: String,
+    project: Project,
+}
+
+#[derive(Debug, Deserialize)]
+struct Commit {
+    #[serde(rename = "commitId")]
+    commit_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Reviewer {
+    unique_name: String,
+    vote: i64,
+    #[serde(default)]
+    is_required: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PullRequest {
+    pull_request_id: i64,
+    status: String,
+    creation_date: String,
+    closed_date: Option<String>,
+    source_ref_name: String,
+    target_ref_name: String,
+    merge_status: Option<String>,
+    last_merge_source_commit: Option<Commit>,
+    #[serde(default)]
+    reviewers: Vec<Reviewer>,
+    repository: Repository,
+}
+
+#[derive(Debug, Deserialize)]
+struct StatusContext {
+    name: String,
+    genre: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Status {
+    state: String,
+    context: StatusContext,
+    target_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PolicyType {
+    display_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PolicySettings {
+    display_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PolicyConfiguration {
+    #[serde(default)]
+    is_blocking: bool,
+    #[serde(rename = "type")]
+    policy_type: PolicyType,
+    settings: PolicySettings,
+}
+
+#[derive(Debug, Deserialize)]
+struct PolicyEvaluation {
+    status: String,
+    configuration: PolicyConfiguration,
+}
+
+impl AzureDevOpsClient {
+    pub fn new(token: &str, api_url: Option<&str>) -> AnyResult<Self> {
+        // Personal access tokens go in basic auth, with an empty user name
+        let credentials = base64::engine::general_purpose::STANDARD.encode(format!(":{}", token));
+        let http = Client::builder()
+            .user_agent(crate::github::user_agent(
+                std::env::var("CLU_USER_AGENT_SUFFIX").ok().as_deref(),
+            ))
+            .default_headers(
+                vec![(
+                    reqwest::header::AUTHORIZATION,
+                    reqwest::header::HeaderValue::from_str(&format!("Basic {}", credentials))?,
+                )]
+                .into_iter()
+                .collect(),
+            )
+            .build()?;
+
+        Ok(Self {
+            http,
+            api_url: api_url
+                .unwrap_or(AZURE_DEVOPS_API_URL)
+                .trim_end_matches('/')
+                .to_owned(),
+        })
+    }
+
+    /// Opens a PR for the branch, or updates the one that's already open. Azure DevOps has no
+    /// assignees or milestones, so those are left off.
+    pub async fn sync_pull_request(
+        &self,
+        repo: &GitHubRepo,
+        description: PullRequestDescription<'_>,
+        existing: Option<i64>,
+    ) -> AnyResult<PullRequestOutput> {
+        if !description.assignees.is_empty() || description.milestone.is_some() {
+            warn!(
+                "{} is on Azure DevOps, which has no assignees or milestones for PR's",
+                repo
+            );
+        }
+
+        let target_ref = match description.base {
+            Some(base) => format!("refs/heads/{}", base),
+            None => match self.repository(repo).await?.default_branch {
+                Some(branch) => branch,
+                None => bail!("{} has no default branch", repo),
+            },
+        };
+        let source_ref = format!("refs/heads/{}", description.branch);
+
+        let existing = match existing {
+            Some(id) => Some(id),
+            None => {
+                let request = self.http.get(self.repo_url(repo, "/pullrequests")).query(&[
+                    ("searchCriteria.sourceRefName", source_ref.as_str()),
+                    ("searchCriteria.status", "active"),
+                ]);
+                let open: List<PullRequest> = self.send(request).await?;
+                open.value.first().map(|it| it.pull_request_id)
+            }
+        };
+
+        let mut body = serde_json::json!({
+            "title": description.title,
+            "description": description.body,
+            "targetRefName": target_ref,
+        });
+        let pull: PullRequest = match existing {
+            Some(id) => {
+                info!("Updating PR #{} of {}", id, repo);
+                let request = self
+                    .http
+                    .patch(self.repo_url(repo, &format!("/pullrequests/{}", id)))
+                    .json(&body);
+                self.send(request).await?
+            }
+            None => {
+                info!("Opening a PR on {}", repo);
+                body["sourceRefName"] = serde_json::json!(source_ref);
+                let labels: Vec<serde_json::Value> = description
+                    .labels
+                    .iter()
+                    .map(|it| serde_json::json!({ "name": it }))
+                    .collect();
+                body["labels"] = serde_json::json!(labels);
+                let request = self
+                    .http
+                    .post(self.repo_url(repo, "/pullrequests"))
+                    .json(&body);
+                self.send(request).await?
+            }
+        };
+
+        Ok(PullRequestOutput {
+            id: pull.pull_request_id.to_string(),
+            number: pull.pull_request_id,
+            permalink: permalink(&pull),
+            created_at: pull.creation_date,
+            head_ref: short_ref(&pull.source_ref_name).to_owned(),
+        })
+    }
+
+    pub async fn fetch_pull_state(
+        &self,
+        repo: &GitHubRepo,
+        id: i64,
+        ignored_checks: &[Glob],
+    ) -> AnyResult<PullState> {
+        let pull: PullRequest = self
+            .send(
+                self.http
+                    .get(self.repo_url(repo, &format!("/pullrequests/{}", id))),
+            )
+            .await?;
+        let statuses: List<Status> = self
+            .send(
+                self.http
+                    .get(self.repo_url(repo, &format!("/pullrequests/{}/statuses", id))),
+            )
+            .await?;
+
+        // Build policies report through policy evaluations rather than statuses
+        let artifact_id = format!(
+            "vstfs:///CodeReview/CodeReviewId/{}/{}",
+            pull.repository.project.id, id
+        );
+        let request = self
+            .http
+            .get(format!(
+                "{}/{}/_apis/policy/evaluations",
+                self.api_url, repo.owner
+            ))
+            .query(&[
+                ("artifactId", artifact_id.as_str()),
+                ("api-version", "7.1-preview.1"),
+            ]);
+        let evaluations: List<PolicyEvaluation> = self.send(request).await?;
+
+        Ok(pull_state(
+            pull,
+            statuses.value,
+            evaluations.value,
+            ignored_checks,
+        ))
+    }
+
+    pub async fn add_comment(&self, repo: &GitHubRepo, id: i64, body: &str) -> AnyResult<()> {
+        let request = self
+            .http
+            .post(self.repo_url(repo, &format!("/pullrequests/{}/threads", id)))
+            .json(&serde_json::json!({
+                "comments": [{ "content": body, "commentType": 1 }],
+                "status": 1,
+            }));
+        let _: serde_json::Value = self.send(request).await?;
+
+        Ok(())
+    }
+
+    /// Disabled repos are treated like archived ones. Whether the token can push isn't known
+    /// up front, a push that's refused fails the push.
+    pub async fn fetch_repo_access(&self, repo: &GitHubRepo) -> AnyResult<RepoAccess> {
+        let repository = self.repository(repo).await?;
+
+        Ok(RepoAccess {
+            archived: repository.is_disabled,
+            can_push: true,
+        })
+    }
+
+    async fn repository(&self, repo: &GitHubRepo) -> AnyResult<Repository> {
+        self.send(self.http.get(self.repo_url(repo, ""))).await
+    }
+
+    /// The owner of Azure DevOps repos is `<organization>/<project>`.
+    fn repo_url(&self, repo: &GitHubRepo, path: &str) -> String {
+        format!(
+            "{}/{}/_apis/git/repositories/{}{}?api-version={}",
+            self.api_url, repo.owner, repo.repo, path, API_VERSION
+        )
+    }
+
+    async fn send<T: DeserializeOwned>(&self, request: reqwest::RequestBuilder) -> AnyResult<T> {
+        let response = request.send().await?;
+        let status = response.status();
+        let body: serde_json::Value = response.json().await?;
+        debug!("Azure DevOps Response: {:?}", body);
+
+        if !status.is_success() {
+            bail!("Azure DevOps responded {}: {}", status, body);
+        }
+
+        Ok(serde_json::from_value(body)?)
+    }
+}
+
+fn short_ref(name: &str) -> &str {
+    name.strip_prefix("refs/heads/").unwrap_or(name)
+}
+
+fn permalink(pull: &PullRequest) -> String {
+    format!(
+        "{}/pullrequest/{}",
+        pull.repository.web_url, pull.pull_request_id
+    )
+}
+
+fn pull_state(
+    pull: PullRequest,
+    statuses: Vec<Status>,
+    evaluations: Vec<PolicyEvaluation>,
+    ignored_checks: &[Glob],
+) -> PullState {
+    let mut checks = Vec::new();
+    for status in statuses {
+        let name = match status.context.genre {
+            Some(genre) => format!("{}/{}", genre, status.context.name),
+            None => status.context.name,
+        };
+        checks.push(CheckResult {
+            ignored: ignored_checks.iter().any(|it| it.is_match(&name)),
+            name,
+            state: match status.state.as_str() {
+                "succeeded" => CheckState::Passed,
+                "failed" | "error" => CheckState::Failed,
+                "notApplicable" => CheckState::Skipped,
+                _ => CheckState::Pending,
+            },
+            url: status.target_url,
+            required: false,
+        });
+    }
+    for evaluation in evaluations {
+        let configuration = evaluation.configuration;
+        if configuration.policy_type.display_name != "Build" {
+            continue;
+        }
+        let name = configuration
+            .settings
+            .display_name
+            .unwrap_or(configuration.policy_type.display_name);
+        checks.push(CheckResult {
+            ignored: ignored_checks.iter().any(|it| it.is_match(&name)),
+            name,
+            state: match evaluation.status.as_str() {
+                "approved" => CheckState::Passed,
+                "rejected" | "broken" => CheckState::Failed,
+                "notApplicable" => CheckState::Skipped,
+                _ => CheckState::Pending,
+            },
+            url: None,
+            required: configuration.is_blocking,
+        });
+    }
+
+    let mut reviews = PullReviews::default();
+    let mut missing_required = false;
+    for reviewer in &pull.reviewers {
+        let user = reviewer.unique_name.clone();
+        if reviewer.vote >= APPROVED_VOTE {
+            reviews.approved_by.push(user);
+        } else if reviewer.vote <= REJECTED_VOTE {
+            reviews.changes_requested_by.push(user);
+        } else {
+            missing_required |= reviewer.is_required;
+            reviews.requested.push(user);
+        }
+    }
+
+    let failed = checks
+        .iter()
+        .any(|it| it.state == CheckState::Failed && !it.ignored);
+    let status = match pull.status.as_str() {
+        "completed" => PullStatus::Merged,
+        "abandoned" => PullStatus::ClosedWithoutMerge,
+        _ if pull.merge_status.as_deref() == Some("conflicts") => PullStatus::Conflicting,
+        _ if failed => PullStatus::ChecksFailed,
+        _ if missing_required || !reviews.changes_requested_by.is_empty() => {
+            PullStatus::NeedsApproval
+        }
+        _ => PullStatus::Mergeable,
+    };
+
+    PullState {
+        id: pull.pull_request_id.to_string(),
+        status,
+        permalink: permalink(&pull),
+        created_at: pull.creation_date,
+        closed_at: pull.closed_date,
+        base_ref: short_ref(&pull.target_ref_name).to_owned(),
+        checks,
+        head_oid: pull
+            .last_merge_source_commit
+            .map(|it| it.commit_id)
+            .unwrap_or_default(),
+        head_ref_id: None,
+        behind: false,
+        required_checks: Vec::new(),
+        reviews,
+    }
+}
+
+/// Reads the clone URLs Azure DevOps hands out, on `dev.azure.com` and the older
+/// `<organization>.visualstudio.com`, over HTTPS and SSH.
+pub(crate) fn extract_azure_devops_info(url: &str) -> Option<GitHubRepo> {
+    let patterns = [
+        r"^https://([^@/]+@)?dev\.azure\.com/(?P<org>[^/]+)/(?P<project>[^/]+)/_git/(?P<repo>[^/]+?)(\.git)?/?$",
+        r"^(ssh://)?git@ssh\.dev\.azure\.com(:22)?[:/]v3/(?P<org>[^/]+)/(?P<project>[^/]+)/(?P<repo>[^/]+?)(\.git)?$",
+        r"^https://(?P<org>[^./]+)\.visualstudio\.com/(DefaultCollection/)?(?P<project>[^/]+)/_git/(?P<repo>[^/]+?)(\.git)?/?$",
+        r"^([^@]+@)?vs-ssh\.visualstudio\.com:v3/(?P<org>[^/]+)/(?P<project>[^/]+)/(?P<repo>[^/]+?)(\.git)?$",
+    ];
+
+    patterns.iter().find_map(|pattern| {
+        let captures = Regex::new(pattern).unwrap().captures(url)?;
+        Some(GitHubRepo {
+            host: AZURE_DEVOPS_HOST.to_owned(),
+            owner: format!("{}/{}", &captures["org"], &captures["project"]),
+            repo: captures["repo"].to_owned(),
+            clone_url: url.to_owned(),
+        })
+    })
+}
+
+#[test]
+fn validate_extract_azure_devops_info() {
+    for url in [
+        "https://dev.azure.com/acme/platform/_git/clu",
+        "https://acme@dev.azure.com/acme/platform/_git/clu",
+        "git@ssh.dev.azure.com:v3/acme/platform/clu",
+        "https://acme.visualstudio.com/platform/_git/clu",
+        "https://acme.visualstudio.com/DefaultCollection/platform/_git/clu",
+        "acme@vs-ssh.visualstudio.com:v3/acme/platform/clu",
+    ] {
+        let repo = extract_azure_devops_info(url).unwrap();
+        assert_eq!(AZURE_DEVOPS_HOST, repo.host, "{}", url);
+        assert_eq!("acme/platform", repo.owner, "{}", url);
+        assert_eq!("clu", repo.repo, "{}", url);
+    }
+    assert!(extract_azure_devops_info("git@github.com:ethankhall/clu.git").is_none());
+}
+
+#[test]
+fn validate_pull_state() {
+    let pull = |status: &str, vote: i64| -> PullRequest {
+        serde_json::from_value(serde_json::json!({
+            "pullRequestId": 7, "status": status,
+            "creationDate": "2021-03-21T17:30:00Z", "closedDate": null,
+            "sourceRefName": "refs/heads/migration", "targetRefName": "refs/heads/main",
+            "mergeStatus": "succeeded",
+            "lastMergeSourceCommit": { "commitId": "abc" },
+            "reviewers": [{ "uniqueName": "ethan@example.com", "vote": vote, "isRequired": true }],
+            "repository": {
+                "defaultBranch": "refs/heads/main",
+                "webUrl": "https://dev.azure.com/acme/platform/_git/clu",
+                "project": { "id": "1234" },
+            },
+        }))
+        .unwrap()
+    };
+    let build = |status: &str| -> Vec<PolicyEvaluation> {
+        serde_json::from_value(serde_json::json!([{
+            "status": status,
+            "configuration": {
+                "isBlocking": true,
+                "type": { "displayName": "Build" },
+                "settings": { "displayName": "ci" },
+            },
+        }]))
+        .unwrap()
+    };
+
+    let state = pull_state(pull("active", 10), Vec::new(), build("approved"), &[]);
+    assert_eq!(PullStatus::Mergeable, state.status);
+    assert_eq!("main", state.base_ref);
+    assert_eq!(
+        "https://dev.azure.com/acme/platform/_git/clu/pullrequest/7",
+        state.permalink
+    );
+    assert_eq!(
+        PullStatus::ChecksFailed,
+        pull_state(pull("active", 10), Vec::new(), build("rejected"), &[]).status
+    );
+    assert_eq!(
+        PullStatus::Mergeable,
+        pull_state(
+            pull("active", 10),
+            Vec::new(),
+            build("rejected"),
+            &[Glob::new("ci")]
+        )
+        .status
+    );
+    assert_eq!(
+        PullStatus::NeedsApproval,
+        pull_state(pull("active", 0), Vec::new(), build("approved"), &[]).status
+    );
+    assert_eq!(
+        PullStatus::Merged,
+        pull_state(pull("completed", 10), Vec::new(), Vec::new(), &[]).status
+    );
+}
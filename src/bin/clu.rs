@@ -1,27 +1,68 @@
-use clap::{ArgGroup, Args, Parser, Subcommand};
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
 use futures::stream::{self, StreamExt};
 use indicatif::ProgressStyle;
-use std::fs::{create_dir_all, read_to_string, File};
+use std::fs::{create_dir_all, File};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::time::SystemTime;
 use tracing::level_filters::LevelFilter;
 use tracing_indicatif::filter::hide_indicatif_span_fields;
 use tracing_indicatif::span_ext::IndicatifSpanExt;
 use tracing_indicatif::IndicatifLayer;
 use tracing_subscriber::fmt::format::{DefaultFields, PrettyFields};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::{Layer, Registry};
 
 use tracing_subscriber::fmt::format::Format;
 
 use anyhow::Result as AnyResult;
+use serde::Serialize;
 use tracing::{debug, error, info, info_span, warn};
 
 use clu::commands::*;
-use clu::github::GithubApiClient;
-use clu::migration::{ExecutionOptions, MigrationStatus, MigrationTask};
+use clu::dashboard::{Dashboard, TargetProgress};
+use clu::events::EventLog;
+use clu::filter::{pick_sample, Percentage, Shard, TargetFilter};
+use clu::github::{CloneProtocol, GitHubError, GitHubHosts};
+use clu::migration::{
+    MigrationError, MigrationOutcome, MigrationStatus, MigrationTask, RetryQueue, RunSettings,
+    TargetsFailed,
+};
 use clu::models::*;
+use clu::notify::{RunSummary, TeamsNotifier};
+use clu::output::OutputFormat;
+use clu::prompt::PushPrompt;
+use clu::queue::{DirectoryQueue, JobQueue, LocalQueue};
+use clu::steps::MigrationStepResult;
+
+/// How many targets are migrated at the same time.
+const RUN_CONCURRENCY: usize = 3;
+
+/// How many times a target is run when GitHub keeps rate limiting it or failing.
+const MAX_RETRIED_RUNS: u32 = 3;
+
+/// Exit code when the run finished, but some of its targets failed.
+const EXIT_TARGETS_FAILED: u8 = 1;
+
+/// Exit code when clu couldn't do what it was asked, like when the migration file doesn't parse.
+const EXIT_UNABLE_TO_RUN: u8 = 2;
+
+/// Exit code when GitHub couldn't be reached, kept failing or kept rate limiting after the
+/// retries. Running again later can work without changing anything.
+const EXIT_RUNTIME_FAILURE: u8 = 3;
+
+/// A PR is reported as flaky once it has gone between mergeable and failing checks this many
+/// times across `check-status` runs.
+const FLAKY_STATUS_FLIPS: usize = 2;
+
+/// `check-status` warns about PR's branched from a pinned `base-sha` once their base branch has
+/// this many commits past it.
+const BASE_DIVERGENCE_COMMITS: u64 = 50;
+
+/// Where `run-migration` works when neither `--work-directory` nor the profile say otherwise.
+const DEFAULT_WORK_DIRECTORY: &str = "work-dir";
 
 /// Clu is a migration tool, intended to make cross company migrations easier
 ///
@@ -42,6 +83,11 @@ pub struct Opts {
     #[clap(flatten)]
     pub logging_opts: LoggingOpts,
 
+    /// How `check-status`, `run-migration` and `run-followup` print their results. With `json`
+    /// only the results go to stdout, and the logs go to stderr.
+    #[clap(long, value_enum, global(true), default_value = "text")]
+    pub output: OutputFormat,
+
     #[clap(subcommand)]
     pub sub_command: SubCommand,
 }
@@ -52,10 +98,68 @@ pub enum SubCommand {
     Init,
     /// Run a migration, and write the results back to the file.
     RunMigration(RunMigrationArgs),
+    /// Pick up a `run-migration` that was interrupted, running the targets it didn't finish.
+    /// Takes the same flags as the run.
+    Resume(RunMigrationArgs),
     /// Check the status of a migration.
     CheckStatus(CheckStatusArgs),
+    /// Dry run every target and print what each PR would change, without pushing anything.
+    Plan(PlanArgs),
+    /// Check the migration file, its scripts and the access to its targets, reporting every
+    /// problem at once.
+    Validate(ValidateArgs),
     /// Runs a script against each open PR.
     RunFollowup(RunFollowupArgs),
+    /// Push bundles created with `--export-bundles` and open the PR's.
+    PublishBundles(PublishBundlesArgs),
+    /// Delete migration branches whose PR's have been merged or closed.
+    PruneBranches(PruneBranchesArgs),
+    /// Close the open PR's of an abandoned migration, delete their branches and forget them.
+    Rollback(RollbackArgs),
+    /// Delete the workspaces of targets that are done and old backups of the migration file.
+    Clean(CleanArgs),
+    /// Summarize step timings and diff sizes from the last run, highlighting outliers.
+    Stats(StatsArgs),
+    /// Summarize the targets from the migration file, without any network calls: which have
+    /// PR's, which failed and at which step, and which are skipped.
+    Status(StatusArgs),
+    /// List the past runs of the migration with how many targets succeeded, stopped or failed,
+    /// or how each target ended in one of them.
+    History(HistoryArgs),
+    /// Pack the migration, its state and history, the error summaries and the logs of each
+    /// target into a `.tar.gz`, for an audit or a postmortem.
+    Archive(ArchiveArgs),
+    /// Render the state of the migration into a report that can be shared, like an HTML page.
+    Report(ReportArgs),
+    /// Keep one issue per target in the tracking repo, labeled with the state of the PR.
+    SyncIssues(SyncIssuesArgs),
+    /// Send a digest of newly merged, newly failing and stale PR's.
+    Digest(DigestArgs),
+    /// Approve every open PR of the migration with a second account.
+    Approve(ApproveArgs),
+    /// Post a templated comment on every open PR of the migration, like a heads up before
+    /// they're merged.
+    Comment(CommentArgs),
+    /// Close the PR's that have been open for too long with failing checks.
+    CloseStale(CloseStaleArgs),
+    /// Run the checks of the PR's that failed them again, for when CI is flaky.
+    RerunChecks(RerunChecksArgs),
+    /// Merge the base branch into PR's that have fallen behind it.
+    UpdateBranches(UpdateBranchesArgs),
+    /// Add the repos found by `targets-from` to the targets of the migration.
+    Discover(DiscoverArgs),
+    /// Add repos to the targets of the migration, keeping the rest of the file as it is.
+    AddTarget(AddTargetArgs),
+    /// Add the repos listed in a file, or on stdin, to the targets of the migration.
+    Import(ImportArgs),
+    /// Queue the targets in a shared directory, for `run-migration --queue-dir` workers.
+    Enqueue(EnqueueArgs),
+    /// Write the results of the `run-migration --queue-dir` workers into the migration file.
+    CollectResults(CollectResultsArgs),
+    /// Write the results files of `run-migration --shard` into the migration file.
+    MergeResults(MergeResultsArgs),
+    /// Show which targets changed between two runs recorded with `--run-label`.
+    Compare(CompareArgs),
 }
 
 #[derive(Args, Debug)]
@@ -68,25 +172,243 @@ pub struct CheckStatusArgs {
     /// Token to be used when talking to GitHub
     #[clap(long, env = "GITHUB_TOKEN")]
     pub github_token: String,
+
+    /// Use the token and API of this profile from the `profiles` of the migration.
+    #[clap(long)]
+    pub profile: Option<String>,
+
+    /// Forget the PR's that were closed without being merged, so the next `run-migration`
+    /// opens new ones.
+    #[clap(long)]
+    pub rerun_closed: bool,
+
+    /// Delete the migration branch of PR's that have been merged, for repos that don't delete
+    /// branches automatically.
+    #[clap(long)]
+    pub delete_merged_branches: bool,
+
+    /// How the status is printed. `--output json` takes precedence over it.
+    #[clap(long, value_enum, default_value = "markdown")]
+    pub format: StatusFormat,
+
+    /// Keep polling the PR's, printing the ones whose status changed since the last poll.
+    #[clap(long)]
+    pub watch: bool,
+
+    /// How long to wait between polls with `--watch`, like `90s`, `5m` or `1h`.
+    #[clap(long, value_parser = clu::watch::parse_interval, default_value = "5m", requires = "watch")]
+    pub interval: std::time::Duration,
+
+    /// Stop watching once every PR is merged or closed.
+    #[clap(long, requires = "watch")]
+    pub until_merged: bool,
+
+    #[clap(flatten)]
+    pub filter: TargetFilter,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatusFormat {
+    /// Sections grouping the PR's by what they're waiting on.
+    Markdown,
+    /// A row per PR with its repo, URL, status and when the status last changed, for
+    /// spreadsheets.
+    Csv,
 }
 
 #[derive(Args, Debug)]
 pub struct RunMigrationArgs {
     /// A TOML file that defines the input needed to run a migration. This file will be updated
-    /// with the results of the run.
-    #[clap(long)]
-    pub migration_definition: String,
+    /// with the results of the run. Can be given more than once, or be a directory or a pattern
+    /// like `migrations/*.toml`, to run the migrations one after the other.
+    #[clap(long = "migration-definition", required = true)]
+    pub migration_definitions: Vec<String>,
 
     /// Folder where the work will take place
-    #[clap(long = "work-directory", default_value("work-dir"))]
+    #[clap(long = "work-directory", default_value(DEFAULT_WORK_DIRECTORY))]
     pub work_directory_root: String,
 
     /// Token to be used when talking to GitHub
     #[clap(long, env = "GITHUB_TOKEN")]
     pub github_token: String,
 
+    /// Use the settings of this profile from the `profiles` of the migration, like the token
+    /// and API of a sandbox org. The flags that are given win over it, except for the token.
+    #[clap(long)]
+    pub profile: Option<String>,
+
+    /// Don't check that the token has the permissions needed to push and open PR's before
+    /// starting the run.
+    #[clap(long)]
+    pub skip_permission_check: bool,
+
+    /// Clone and push over this protocol, overriding `clone-protocol` from the migration
+    /// definition. Useful when runners can only use HTTPS.
+    #[clap(long, value_enum, env = "CLU_CLONE_PROTOCOL")]
+    pub clone_protocol: Option<CloneProtocol>,
+
+    /// Only run the targets whose PR was closed without being merged. Targets the pre-flight
+    /// still says need the migration get their PR reopened, or a new one when it can't be.
+    #[clap(long, conflicts_with = "only_failed")]
+    pub closed_only: bool,
+
+    /// Only run the targets whose last run failed, leaving the workspaces of the other targets
+    /// in the work directory as they are.
+    #[clap(long)]
+    pub only_failed: bool,
+
+    /// Archive the workspace of every target that fails into this directory, so the failure
+    /// can be reproduced after the work directory is cleaned up.
+    #[clap(long)]
+    pub snapshot_failures: Option<String>,
+
+    /// Keep the git objects of the repo in the snapshots, instead of only the checkout.
+    #[clap(long, requires = "snapshot_failures")]
+    pub snapshot_git_objects: bool,
+
+    /// Record how each target ended under this label, so runs can be compared with
+    /// `clu compare`.
+    #[clap(long)]
+    pub run_label: Option<String>,
+
+    /// Work as one of many workers, running the targets queued in this shared directory by
+    /// `enqueue` instead of every target.
+    #[clap(long, conflicts_with = "shard")]
+    pub queue_dir: Option<String>,
+
+    /// Only run this slice of the targets, like `2/5` for the second of five. The results are
+    /// written to their own file next to the migration file instead of into it, to be combined
+    /// with `merge-results`.
+    #[clap(long)]
+    pub shard: Option<Shard>,
+
+    /// Only run this many of the targets, to pilot the migration before running every target.
+    /// They're the first ones by name, unless `--seed` is given.
+    #[clap(long)]
+    pub limit: Option<usize>,
+
+    /// Only run this share of the targets, like `5%`, to pilot the migration. They're the first
+    /// ones by name, unless `--seed` is given.
+    #[clap(long)]
+    pub sample: Option<Percentage>,
+
+    /// Pick the targets of `--limit` or `--sample` at random with this seed. The same seed picks
+    /// the same targets again.
+    #[clap(long)]
+    pub seed: Option<u64>,
+
+    /// Show a full screen dashboard of the targets and their logs instead of the progress bars.
+    /// The logs of `clu` itself are written to `clu.log` in the work directory.
+    #[clap(long)]
+    pub tui: bool,
+
+    /// Once the steps of a target are done, show what it changed and ask whether to push it,
+    /// skip it, abort the run, or open a shell in its repo first. The targets are run one at a
+    /// time.
+    #[clap(long, conflicts_with = "tui")]
+    pub interactive: bool,
+
+    /// Append an event to this NDJSON file as each step of a target finishes, with the target,
+    /// step, status, duration and error, so dashboards can follow the run as it happens.
+    #[clap(long)]
+    pub events: Option<String>,
+
+    /// Stop starting targets once one fails. The targets that were left can be run with
+    /// `clu resume`.
+    #[clap(long)]
+    pub fail_fast: bool,
+
+    /// Go ahead without asking to confirm first, like in CI.
+    #[clap(long, short = 'y')]
+    pub yes: bool,
+
     #[clap(flatten)]
     pub dry_run_opts: DryRunOpts,
+
+    #[clap(flatten)]
+    pub filter: TargetFilter,
+
+    /// Set by `clu resume`, which skips the targets the interrupted run finished.
+    #[clap(skip)]
+    pub resume: bool,
+}
+
+impl RunMigrationArgs {
+    /// Fills in what the `--profile` sets, before the work directory is used for the logs. With
+    /// many migrations the profile is the one of the first.
+    fn apply_profile(&mut self) -> AnyResult<()> {
+        let profile = match &self.profile {
+            Some(name) => {
+                let definitions =
+                    clu::state::expand_migration_definitions(&self.migration_definitions)?;
+                load_profile(&definitions[0], name)?
+            }
+            None => return Ok(()),
+        };
+        if let Some(token) = profile_token(&profile)? {
+            self.github_token = token;
+        }
+        if let Some(work_directory) = profile.work_directory {
+            if self.work_directory_root == DEFAULT_WORK_DIRECTORY {
+                self.work_directory_root = work_directory;
+            }
+        }
+
+        let opts = &mut self.dry_run_opts;
+        let publishing_flag = opts.skip_pull_request
+            || opts.skip_push
+            || opts.export_bundles.is_some()
+            || opts.export_patches.is_some();
+        if profile.dry_run && !publishing_flag {
+            opts.dry_run = true;
+        }
+
+        Ok(())
+    }
+}
+
+impl CheckStatusArgs {
+    fn apply_profile(&mut self) -> AnyResult<()> {
+        if let Some(name) = &self.profile {
+            let profile = load_profile(&self.migration_definition, name)?;
+            if let Some(token) = profile_token(&profile)? {
+                self.github_token = token;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn load_profile(migration_definition: &str, name: &str) -> AnyResult<Profile> {
+    let migration = clu::state::load_migration(migration_definition)?;
+    match migration.definition.profiles.get(name) {
+        Some(profile) => Ok(profile.clone()),
+        None => anyhow::bail!(
+            "{} has no profile `{}`, its profiles are: {}",
+            migration_definition,
+            name,
+            migration
+                .definition
+                .profiles
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// The token of the profile wins over `--github-token`, which usually comes from `GITHUB_TOKEN`
+/// and would be the production token.
+fn profile_token(profile: &Profile) -> AnyResult<Option<String>> {
+    match &profile.token_env {
+        Some(name) => match std::env::var(name) {
+            Ok(token) => Ok(Some(token)),
+            Err(_) => anyhow::bail!("{} has the token of the profile, but isn't set", name),
+        },
+        None => Ok(None),
+    }
 }
 
 #[derive(Args, Debug)]
@@ -105,6 +427,21 @@ pub struct DryRunOpts {
     /// The remote will not be updated, the PR will not be updated. Local scripts will run.
     #[clap(long, group = "publish-group")]
     pub dry_run: bool,
+
+    /// Instead of pushing, write each prepared branch as a git bundle into this directory.
+    /// The bundles can be published later with `publish-bundles`.
+    #[clap(long, group = "publish-group")]
+    pub export_bundles: Option<String>,
+
+    /// Instead of pushing, write each prepared branch as a `git format-patch` series into a
+    /// directory per target under this one, for projects that take patches by email.
+    #[clap(long, group = "publish-group")]
+    pub export_patches: Option<String>,
+
+    /// Mail the series written by `--export-patches` with `git send-email`, to the `email`
+    /// section of the migration.
+    #[clap(long, requires = "export_patches")]
+    pub send_patches: bool,
 }
 
 #[derive(Args, Debug)]
@@ -140,59 +477,460 @@ impl LoggingOpts {
 }
 
 #[tokio::main]
-async fn main() -> AnyResult<()> {
+async fn main() -> ExitCode {
+    match run_cli().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+/// Tells a CI job whether the run needs fixing, or can just be tried again.
+fn exit_code(e: &anyhow::Error) -> u8 {
+    // The run itself went through, some of its targets didn't
+    if let Some(failed) = e.downcast_ref::<TargetsFailed>() {
+        return match failed.gave_up == failed.failed {
+            true => EXIT_RUNTIME_FAILURE,
+            false => EXIT_TARGETS_FAILED,
+        };
+    }
+
+    let runtime = e.chain().any(|cause| {
+        cause.downcast_ref::<GitHubError>().map_or(false, |it| {
+            it.retry_wait().is_some() || matches!(it, GitHubError::MutationNotConfirmed { .. })
+        }) || cause.downcast_ref::<reqwest::Error>().is_some()
+    });
+    match runtime {
+        true => EXIT_RUNTIME_FAILURE,
+        false => EXIT_UNABLE_TO_RUN,
+    }
+}
+
+async fn run_cli() -> AnyResult<()> {
     dotenv::dotenv().ok();
 
-    let opt = Opts::parse();
-    configure_logging(&opt.logging_opts);
+    let mut opt = Opts::parse();
+    match &mut opt.sub_command {
+        SubCommand::RunMigration(args) | SubCommand::Resume(args) => args.apply_profile()?,
+        SubCommand::CheckStatus(args) => args.apply_profile()?,
+        _ => {}
+    }
+    // Output meant for other programs is kept away from the logs
+    let logs_to_stderr = opt.output.is_json()
+        || matches!(&opt.sub_command, SubCommand::CheckStatus(args) if args.format == StatusFormat::Csv);
+    let log_destination = match &opt.sub_command {
+        // The dashboard takes over the terminal, so the logs can't be written to it
+        SubCommand::RunMigration(args) | SubCommand::Resume(args) if args.tui => {
+            create_dir_all(&args.work_directory_root)?;
+            LogDestination::File(File::create(
+                Path::new(&args.work_directory_root).join("clu.log"),
+            )?)
+        }
+        // The progress bars would be drawn over the prompt
+        SubCommand::RunMigration(args) | SubCommand::Resume(args) if args.interactive => {
+            LogDestination::Plain
+        }
+        _ => LogDestination::ProgressBars,
+    };
+    configure_logging(&opt.logging_opts, logs_to_stderr, log_destination);
 
     match opt.sub_command {
         SubCommand::Init => run_init().await,
-        SubCommand::RunMigration(args) => run_migration(args).await,
-        SubCommand::CheckStatus(args) => check_status(args).await,
-        SubCommand::RunFollowup(args) => run_followup(args).await,
+        SubCommand::RunMigration(args) => run_migration(args, opt.output).await,
+        SubCommand::Resume(mut args) => {
+            args.resume = true;
+            run_migration(args, opt.output).await
+        }
+        SubCommand::CheckStatus(args) => check_status(args, opt.output).await,
+        SubCommand::Plan(args) => run_plan(args).await,
+        SubCommand::Validate(args) => run_validate(args).await,
+        SubCommand::RunFollowup(args) => run_followup(args, opt.output).await,
+        SubCommand::PublishBundles(args) => run_publish_bundles(args).await,
+        SubCommand::PruneBranches(args) => run_prune_branches(args).await,
+        SubCommand::Rollback(args) => run_rollback(args).await,
+        SubCommand::Clean(args) => run_clean(args).await,
+        SubCommand::Stats(args) => run_stats(args).await,
+        SubCommand::Status(args) => run_status(args).await,
+        SubCommand::Comment(args) => run_comment(args).await,
+        SubCommand::CloseStale(args) => run_close_stale(args).await,
+        SubCommand::RerunChecks(args) => run_rerun_checks(args).await,
+        SubCommand::History(args) => run_history(args).await,
+        SubCommand::Archive(args) => run_archive(args).await,
+        SubCommand::Report(args) => run_report(args).await,
+        SubCommand::SyncIssues(args) => run_sync_issues(args).await,
+        SubCommand::Digest(args) => run_digest(args).await,
+        SubCommand::Approve(args) => run_approve(args).await,
+        SubCommand::UpdateBranches(args) => run_update_branches(args).await,
+        SubCommand::Discover(args) => run_discover(args).await,
+        SubCommand::AddTarget(args) => run_add_target(args).await,
+        SubCommand::Import(args) => run_import(args).await,
+        SubCommand::Enqueue(args) => run_enqueue(args).await,
+        SubCommand::CollectResults(args) => run_collect_results(args).await,
+        SubCommand::MergeResults(args) => run_merge_results(args).await,
+        SubCommand::Compare(args) => run_compare(args).await,
+    }
+}
+
+/// A PR seen by `check-status`, for `--output json`.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+struct PullStatusOutput {
+    target: String,
+    repo: String,
+    url: String,
+    status: clu::github::PullStatus,
+    base_ref: String,
+    created_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    closed_at: Option<String>,
+    failed_checks: Vec<FailedCheckOutput>,
+    missing_required_checks: Vec<String>,
+    /// The base branch is failing the checks too.
+    failing_on_base: bool,
+    status_flips: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    awaiting_review_days: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    base_commits_ahead: Option<u64>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+struct FailedCheckOutput {
+    name: String,
+    required: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+}
+
+async fn check_status(args: CheckStatusArgs, output: OutputFormat) -> AnyResult<()> {
+    if !args.watch {
+        return poll_status(&args, output, true).await.map(|_| ());
+    }
+
+    // The first poll prints the whole status, the ones after it only what changed
+    let mut statuses = poll_status(&args, output, true).await?;
+    loop {
+        if args.until_merged && clu::watch::all_closed(&statuses) {
+            info!("Every PR is merged or closed, done watching");
+            return Ok(());
+        }
+        tokio::time::sleep(args.interval).await;
+
+        let polled = poll_status(&args, output, false).await?;
+        let changes = clu::watch::status_changes(&statuses, &polled);
+        if output.is_json() {
+            clu::output::print_json(&changes)?;
+        } else if !changes.is_empty() {
+            println!("{}", clu::watch::format_changes(&changes));
+        }
+        statuses = polled;
     }
 }
 
-async fn check_status(args: CheckStatusArgs) -> AnyResult<()> {
-    use clu::github::PullStatus;
+/// Polls the PR's once and records their state in the migration file. Returns the URL and
+/// status of the PR of each target.
+async fn poll_status(
+    args: &CheckStatusArgs,
+    output: OutputFormat,
+    print_report: bool,
+) -> AnyResult<clu::watch::PolledStatuses> {
+    use clu::github::{ChecksSummary, PullStatus};
 
     let mut checks_failed: Vec<String> = Vec::new();
+    let mut checks_failed_on_base: Vec<String> = Vec::new();
+    let mut conflicting: Vec<String> = Vec::new();
     let mut not_approved: Vec<String> = Vec::new();
     let mut mergeable: Vec<String> = Vec::new();
     let mut merged: Vec<String> = Vec::new();
-
-    let results: MigrationFile = toml::from_str(&read_to_string(args.migration_definition)?)?;
-    let github_api = GithubApiClient::new(&args.github_token)?;
-    for (_name, target) in results.targets {
-        let pull = match target.pull_request {
+    let mut closed: Vec<String> = Vec::new();
+    let mut missing: Vec<String> = Vec::new();
+    let mut flaky: Vec<String> = Vec::new();
+    let mut diverged: Vec<String> = Vec::new();
+    let mut awaiting_review: Vec<String> = Vec::new();
+    let mut outputs: Vec<PullStatusOutput> = Vec::new();
+    let mut csv_rows: Vec<String> = Vec::new();
+    let mut statuses = std::collections::BTreeMap::new();
+
+    let mut results: MigrationFile =
+        clu::state::load_migration_matching(&args.migration_definition, |name, target| {
+            args.filter.matches(name, target)
+        })?;
+    let github_hosts = GitHubHosts::new(
+        &args.github_token,
+        &results.profile_host_configs(args.profile.as_deref()),
+    )?;
+    let mut names = Vec::new();
+    let mut pulls = Vec::new();
+    for (name, target) in &results.targets {
+        if !args.filter.matches(name, target) {
+            continue;
+        }
+        let pull = match &target.pull_request {
             Some(pull) => pull,
             _ => continue,
         };
 
-        let github_repo = clu::github::extract_github_info(&target.repo)?;
+        names.push(name.clone());
+        pulls.push((pull.github_repo(&target.repo)?, pull.pr_number));
+    }
 
-        let state = github_api
-            .fetch_pull_state(&github_repo, pull.pr_number)
-            .await?;
+    let states = github_hosts
+        .fetch_pull_states(&pulls, &results.definition.ignored_check_patterns())
+        .await?;
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs();
+    for ((name, (github_repo, _)), state) in names.iter().zip(pulls.iter()).zip(states.into_iter())
+    {
+        // PR's on the other forges only get the status, the rest of the checks use GitHub's API
+        let github_api = github_hosts.client_for(github_repo).ok();
+        let target = results.targets.get_mut(name).unwrap();
+        target.record_status(state.status.clone(), now);
+        statuses.insert(
+            name.clone(),
+            (state.permalink.clone(), state.status.clone()),
+        );
+        if let Some(pull) = target.pull_request.as_mut() {
+            pull.state = Some(state.status.clone());
+        }
+        let last_updated = match &state.closed_at {
+            Some(closed_at) => closed_at.clone(),
+            None => clu::github::format_github_timestamp(target.status_since().unwrap_or(now)),
+        };
+        csv_rows.push(clu::output::csv_row(&[
+            &github_repo.to_string(),
+            &state.permalink,
+            &state.status.to_string(),
+            &last_updated,
+        ]));
+        let flips = target.status_flips();
+        let mut pull_output = PullStatusOutput {
+            target: name.clone(),
+            repo: github_repo.to_string(),
+            url: state.permalink.clone(),
+            status: state.status.clone(),
+            base_ref: state.base_ref.clone(),
+            created_at: state.created_at.clone(),
+            closed_at: state.closed_at.clone(),
+            failed_checks: state
+                .failed_checks()
+                .map(|check| FailedCheckOutput {
+                    name: check.name.clone(),
+                    required: check.required,
+                    url: check.url.clone(),
+                })
+                .collect(),
+            missing_required_checks: state
+                .missing_required_checks()
+                .iter()
+                .map(|it| it.to_string())
+                .collect(),
+            failing_on_base: false,
+            status_flips: flips,
+            awaiting_review_days: None,
+            base_commits_ahead: None,
+        };
+        // Nothing else can be looked up about a PR that's gone
+        if state.status == PullStatus::Missing {
+            missing.push(format!(
+                "- {} (not found in {})",
+                state.permalink, github_repo
+            ));
+            outputs.push(pull_output);
+            continue;
+        }
+        if flips >= FLAKY_STATUS_FLIPS {
+            flaky.push(format!(
+                "- {} (flipped between mergeable and failing {} times)",
+                state.permalink, flips
+            ));
+        }
+
+        target.record_reviews(state.reviews.clone(), now);
+        let pending_since = target
+            .reviews
+            .as_ref()
+            .and_then(|it| it.pending_since)
+            .filter(|_| !state.status.is_closed());
+        if let Some(since) = pending_since {
+            let days = now.saturating_sub(since) / (24 * 60 * 60);
+            pull_output.awaiting_review_days = Some(days);
+            awaiting_review.push(format!(
+                "- {} (waiting on {} for {} days)",
+                state.permalink,
+                state.reviews.requested.join(", "),
+                days
+            ));
+        }
+
+        let base_sha = target
+            .base_sha
+            .as_ref()
+            .filter(|_| !state.status.is_closed());
+        if let (Some(github_api), Some(base_sha)) = (github_api, base_sha) {
+            match github_api
+                .commits_ahead(github_repo, base_sha, &state.base_ref)
+                .await
+            {
+                Ok(ahead) if ahead >= BASE_DIVERGENCE_COMMITS => {
+                    pull_output.base_commits_ahead = Some(ahead);
+                    diverged.push(format!(
+                        "- {} (`{}` is {} commits past `{}`)",
+                        state.permalink, state.base_ref, ahead, base_sha
+                    ))
+                }
+                Ok(ahead) => pull_output.base_commits_ahead = Some(ahead),
+                Err(e) => warn!(
+                    "{}: unable to compare {} to {}: {:?}",
+                    name, base_sha, state.base_ref, e
+                ),
+            }
+        }
 
         match state.status {
-            PullStatus::ChecksFailed => checks_failed.push(format!("- {}", state.permalink)),
+            PullStatus::ChecksFailed => {
+                let failing_on_base = match github_api {
+                    Some(github_api) => {
+                        github_api
+                            .is_branch_failing(github_repo, &state.base_ref)
+                            .await
+                    }
+                    None => Ok(false),
+                };
+                let failing_on_base = match failing_on_base {
+                    Ok(failing) => failing,
+                    Err(e) => {
+                        warn!(
+                            "Unable to check {} of {}: {:?}",
+                            state.base_ref, github_repo, e
+                        );
+                        false
+                    }
+                };
+                pull_output.failing_on_base = failing_on_base;
+
+                let mut entry = if failing_on_base {
+                    format!(
+                        "- {} ({}, `{}` is also failing)",
+                        state.permalink,
+                        state.checks_summary(),
+                        state.base_ref
+                    )
+                } else {
+                    format!("- {} ({})", state.permalink, state.checks_summary())
+                };
+                for check in state.failed_checks() {
+                    let kind = if check.required {
+                        "required"
+                    } else {
+                        "optional"
+                    };
+                    match &check.url {
+                        Some(url) => {
+                            entry.push_str(&format!("\n  - `{}` ({}): {}", check.name, kind, url))
+                        }
+                        None => entry.push_str(&format!("\n  - `{}` ({})", check.name, kind)),
+                    }
+                }
+                for name in state.missing_required_checks() {
+                    entry.push_str(&format!("\n  - `{}` (required): not reported", name));
+                }
+
+                if failing_on_base {
+                    checks_failed_on_base.push(entry);
+                } else {
+                    checks_failed.push(entry);
+                }
+            }
+            PullStatus::Conflicting => conflicting.push(format!("- {}", state.permalink)),
             PullStatus::NeedsApproval => not_approved.push(format!("- {}", state.permalink)),
-            PullStatus::Mergeable => mergeable.push(format!("- {}", state.permalink)),
-            PullStatus::Merged => merged.push(format!("- {}", state.permalink)),
+            PullStatus::Mergeable => match state.checks_summary() {
+                ChecksSummary::Passing => mergeable.push(format!("- {}", state.permalink)),
+                summary => mergeable.push(format!("- {} ({})", state.permalink, summary)),
+            },
+            PullStatus::Merged => {
+                merged.push(format!("- {}", state.permalink));
+
+                let branch = state.head_ref_id.as_ref();
+                let deleting = github_api.zip(branch.filter(|_| args.delete_merged_branches));
+                if let Some((github_api, ref_id)) = deleting {
+                    match github_api.delete_ref(ref_id).await {
+                        Ok(_) => info!("{}: deleted the branch of {}", name, state.permalink),
+                        Err(e) => warn!(
+                            "{}: unable to delete the branch of {}: {:?}",
+                            name, state.permalink, e
+                        ),
+                    }
+                }
+            }
+            PullStatus::ClosedWithoutMerge => {
+                let closed_on = state
+                    .closed_at
+                    .as_deref()
+                    .and_then(|it| it.get(..10))
+                    .map(|it| format!(" (closed {})", it))
+                    .unwrap_or_default();
+                closed.push(format!("- {}{}", state.permalink, closed_on));
+
+                if args.rerun_closed {
+                    info!(
+                        "{}: {} was closed, it will be re-run",
+                        name, state.permalink
+                    );
+                    target.pull_request = None;
+                }
+            }
+            PullStatus::Missing => {}
         }
+        outputs.push(pull_output);
     }
 
     checks_failed.sort();
+    checks_failed_on_base.sort();
+    conflicting.sort();
     not_approved.sort();
     mergeable.sort();
     merged.sort();
+    closed.sort();
+    missing.sort();
+    flaky.sort();
+    diverged.sort();
+    awaiting_review.sort();
+
+    clu::state::save_migration(&args.migration_definition, &results)?;
+
+    if !print_report {
+        return Ok(statuses);
+    }
+    if output.is_json() {
+        clu::output::print_json(&outputs)?;
+        return Ok(statuses);
+    }
+    if args.format == StatusFormat::Csv {
+        println!("repo,pr-url,status,last-updated");
+        for row in csv_rows {
+            println!("{}", row);
+        }
+        return Ok(statuses);
+    }
 
     println!(
         "# Migration Results
 ## Checks Failed
 
+### Introduced by Migration
+
+{}
+
+### Pre-existing on Base
+
+{}
+
+## Conflicting
+
 {}
 
 ## Not Approved
@@ -205,14 +943,41 @@ async fn check_status(args: CheckStatusArgs) -> AnyResult<()> {
 
 ## Merged
 
+{}
+
+## Closed Without Merge
+
+{}
+
+## Missing
+
+{}
+
+## Flaky
+
+{}
+
+## Base Diverged
+
+{}
+
+## Awaiting Review
+
 {}",
         checks_failed.join("\n"),
+        checks_failed_on_base.join("\n"),
+        conflicting.join("\n"),
         not_approved.join("\n"),
         mergeable.join("\n"),
-        merged.join("\n")
+        merged.join("\n"),
+        closed.join("\n"),
+        missing.join("\n"),
+        flaky.join("\n"),
+        diverged.join("\n"),
+        awaiting_review.join("\n")
     );
 
-    Ok(())
+    Ok(statuses)
 }
 
 async fn run_init() -> AnyResult<()> {
@@ -227,21 +992,46 @@ async fn run_init() -> AnyResult<()> {
         checkout: RepoCheckout {
             branch_name: "ethankhall/foo-example".to_owned(),
             pre_flight: "/usr/bin/true".to_owned(),
+            skip_lfs_smudge: false,
+            sparse_paths: Vec::new(),
+            branch_namespace: None,
         },
         pr: PrCreationDetails {
             title: "Example Title".to_owned(),
             description: "This is a TOML file\n\nSo you can add newlines between the PR's"
                 .to_owned(),
+            labels: Vec::new(),
+            assignees: Vec::new(),
+            milestone: None,
+            template: None,
+            comment: None,
+            issue_on_failure: false,
+            dispatch_workflow: None,
+            max_prs_per_hour: None,
         },
         steps: vec![MigrationStepDefinition {
             name: "Example".to_owned(),
             migration_script: "examples/example-migration.sh".to_owned(),
         }],
+        tracking: None,
+        tracking_issues: None,
+        notifications: None,
+        targets_from: None,
+        ignored_checks: Vec::new(),
+        clone_protocol: None,
+        reviewers: Vec::new(),
+        metadata_command: None,
+        state_dir: None,
+        hosts: BTreeMap::new(),
+        email: None,
+        profiles: BTreeMap::new(),
+        followups: BTreeMap::new(),
     };
 
     let migration_input = MigrationFile {
         targets,
         definition,
+        unloaded: Default::default(),
     };
 
     let definition = toml::to_string_pretty(&migration_input)?;
@@ -253,46 +1043,433 @@ async fn run_init() -> AnyResult<()> {
     Ok(())
 }
 
-pub async fn run_migration(args: RunMigrationArgs) -> AnyResult<()> {
+/// How a target ended in `run-migration`, for `--output json`.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+struct RunOutput {
+    /// Set when many migrations are run at once.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    migration: Option<String>,
+    target: String,
+    outcome: RunOutcome,
+    step: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pull_request: Option<String>,
+    duration_ms: u64,
+    /// Failed because GitHub was still rate limiting or failing after the retries.
+    #[serde(skip)]
+    gave_up: bool,
+}
+
+/// How the targets of one of the migrations of `run-migration` went.
+struct DefinitionRun {
+    migration_definition: String,
+    outputs: Vec<RunOutput>,
+    errors: Vec<String>,
+    no_changes: Vec<String>,
+    stopped: bool,
+}
+
+pub async fn run_migration(args: RunMigrationArgs, output: OutputFormat) -> AnyResult<()> {
     use std::collections::BTreeMap;
+
+    let definitions = clu::state::expand_migration_definitions(&args.migration_definitions)?;
+    let many = definitions.len() > 1;
+    if many && args.tui {
+        anyhow::bail!("--tui runs one migration at a time");
+    }
+    if many && args.queue_dir.is_some() {
+        anyhow::bail!("--queue-dir runs one migration at a time");
+    }
+
+    // One client for all of them, so they share the rate limits
+    let mut host_configs = BTreeMap::new();
+    let mut max_prs_per_hour: Option<u32> = None;
+    for migration_definition in &definitions {
+        let migration_input = clu::state::load_migration(migration_definition)?;
+        for (host, config) in migration_input.profile_host_configs(args.profile.as_deref()) {
+            host_configs.entry(host).or_insert(config);
+        }
+        if let Some(limit) = migration_input.definition.pr.max_prs_per_hour {
+            max_prs_per_hour = Some(max_prs_per_hour.map_or(limit, |it| it.min(limit)));
+        }
+    }
+    let github_hosts = GitHubHosts::new(&args.github_token, &host_configs)?
+        .with_max_prs_per_hour(max_prs_per_hour);
+
+    let mut runs = Vec::new();
+    for migration_definition in &definitions {
+        if many {
+            info!("Running the migration of {}", migration_definition);
+        }
+        let run = run_definition(&args, migration_definition, &github_hosts, output).await?;
+        let stopped = run.stopped;
+        runs.push(run);
+        if stopped {
+            warn!(
+                "The run was stopped, the migrations after {} aren't run",
+                migration_definition
+            );
+            break;
+        }
+    }
+
+    let mut outputs = Vec::new();
+    if many {
+        if !output.is_json() {
+            println!("{}", format_definition_runs(&runs, definitions.len()));
+        }
+        let mut errors = Vec::new();
+        let mut no_changes = Vec::new();
+        for run in &mut runs {
+            for entry in &run.errors {
+                errors.push(format!("{}: {}", run.migration_definition, entry));
+            }
+            for entry in &run.no_changes {
+                no_changes.push(format!("{}: {}", run.migration_definition, entry));
+            }
+            for mut target in std::mem::take(&mut run.outputs) {
+                target.migration = Some(run.migration_definition.clone());
+                outputs.push(target);
+            }
+        }
+        // Each migration wrote its own, the last one would win
+        if !errors.is_empty() {
+            std::fs::write("migration.errors.txt", errors.join("\n"))?;
+        }
+        if !no_changes.is_empty() {
+            std::fs::write("migration.no-changes.txt", no_changes.join("\n"))?;
+        }
+    } else {
+        outputs = runs.pop().map(|it| it.outputs).unwrap_or_default();
+    }
+
+    if output.is_json() {
+        clu::output::print_json(&outputs)?;
+    }
+
+    let failed = outputs
+        .iter()
+        .filter(|it| it.outcome == RunOutcome::Failed)
+        .count();
+    if failed > 0 {
+        anyhow::bail!(TargetsFailed {
+            failed,
+            total: outputs.len(),
+            gave_up: outputs
+                .iter()
+                .filter(|it| it.outcome == RunOutcome::Failed && it.gave_up)
+                .count(),
+        });
+    }
+
+    Ok(())
+}
+
+/// The combined summary of running many migrations.
+fn format_definition_runs(runs: &[DefinitionRun], total: usize) -> String {
+    let mut lines = vec![
+        "| Migration | Targets | Succeeded | Stopped | Failed |".to_owned(),
+        "|-----------|---------|-----------|---------|--------|".to_owned(),
+    ];
+    for run in runs {
+        let count = |outcome: RunOutcome| {
+            run.outputs
+                .iter()
+                .filter(|it| it.outcome == outcome)
+                .count()
+        };
+        lines.push(format!(
+            "| {} | {} | {} | {} | {} |",
+            run.migration_definition,
+            run.outputs.len(),
+            count(RunOutcome::Succeeded),
+            count(RunOutcome::Stopped),
+            count(RunOutcome::Failed)
+        ));
+    }
+
+    let mut summary = format!("# Migration Runs\n\n{}", lines.join("\n"));
+    if runs.len() < total {
+        summary.push_str(&format!(
+            "\n\n{} of the {} migrations weren't run, the run was stopped",
+            total - runs.len(),
+            total
+        ));
+    }
+    summary
+}
+
+async fn run_definition(
+    args: &RunMigrationArgs,
+    migration_definition: &str,
+    github_hosts: &GitHubHosts,
+    output: OutputFormat,
+) -> AnyResult<DefinitionRun> {
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::sync::atomic::{AtomicBool, Ordering};
     use std::sync::{Arc, Mutex};
 
-    let mut migration_input: MigrationFile =
-        toml::from_str(&read_to_string(&args.migration_definition)?)?;
+    if args.tui && output.is_json() {
+        anyhow::bail!("--tui can't be used with --output json");
+    }
+    if args.tui && !console::user_attended() {
+        anyhow::bail!("--tui needs to be run in a terminal");
+    }
+    if args.interactive && !console::user_attended_stderr() {
+        anyhow::bail!("--interactive needs to be run in a terminal");
+    }
+
+    // Shards keep their results apart, so the workers don't overwrite each other's
+    let results_path = match &args.shard {
+        Some(shard) => shard.results_path(migration_definition),
+        None => migration_definition.to_owned(),
+    };
+    let load_path = match args.resume && Path::new(&results_path).exists() {
+        true => results_path.as_str(),
+        false => migration_definition,
+    };
+    let mut migration_input: MigrationFile = clu::state::load_migration(load_path)?;
+    if let Some(shard) = &args.shard {
+        migration_input
+            .targets
+            .retain(|name, _| shard.contains(name));
+        migration_input.definition.state_dir = None;
+        info!(
+            "Running shard {} of {}, writing the results to {}",
+            shard.index, shard.count, results_path
+        );
+    }
 
     let epoch_start = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)?
         .as_secs();
     std::fs::copy(
-        &args.migration_definition,
-        format!("{}.{}.bck", &args.migration_definition, epoch_start),
+        migration_definition,
+        format!("{}.{}.bck", migration_definition, epoch_start),
     )?;
 
     debug!("targets: {:?}", &migration_input.targets);
     debug!("definition: {:?}", &migration_input);
 
-    info!("Processing {} repos", &migration_input.targets.len());
+    info!(
+        "Processing {} repos, run id {}",
+        &migration_input.targets.len(),
+        clu::github::run_id()
+    );
 
     create_dir_all(&args.work_directory_root)?;
-    let work_directory_root = args.work_directory_root;
+    let work_directory_root = args.work_directory_root.clone();
+
+    let bundle_dir = match &args.dry_run_opts.export_bundles {
+        Some(dir) => {
+            create_dir_all(dir)?;
+            Some(PathBuf::from(dir).canonicalize()?)
+        }
+        None => None,
+    };
+    let patch_dir = match &args.dry_run_opts.export_patches {
+        Some(dir) => {
+            create_dir_all(dir)?;
+            Some(PathBuf::from(dir).canonicalize()?)
+        }
+        None => None,
+    };
+    if args.dry_run_opts.send_patches && migration_input.definition.email.is_none() {
+        anyhow::bail!("--send-patches needs an `email` section in the migration");
+    }
 
-    let github_client = GithubApiClient::new(&args.github_token)?;
     let result_map = Arc::new(Mutex::new(BTreeMap::default()));
+    let push_prompt = match args.interactive {
+        true => Some(PushPrompt::new()),
+        false => None,
+    };
+    let events = match &args.events {
+        Some(path) => Some(EventLog::open(Path::new(path))?),
+        None => None,
+    };
+    let stopping = AtomicBool::new(false);
+    let is_stopping = || {
+        stopping.load(Ordering::SeqCst) || push_prompt.as_ref().map_or(false, |it| it.is_aborted())
+    };
 
+    let mut definition = migration_input.definition.clone();
+    if args.clone_protocol.is_some() {
+        definition.clone_protocol = args.clone_protocol;
+    }
+    definition.checkout.branch_name = definition.checkout.head_branch(migration_definition);
+
+    let settings = RunSettings {
+        skip_pull_request: args.dry_run_opts.skip_pull_request,
+        skip_push: args.dry_run_opts.skip_push,
+        dry_run: args.dry_run_opts.dry_run,
+        bundle_dir,
+        patch_dir,
+        send_patches: args.dry_run_opts.send_patches,
+        work_dir: PathBuf::from(&work_directory_root),
+        push_prompt: push_prompt.as_ref(),
+        events: events.as_ref(),
+    };
+
+    // Targets that are filtered out are kept in the migration file as they are
     let mut tasks = Vec::new();
     for (pretty_name, target) in &migration_input.targets {
-        tasks.push((
-            result_map.clone(),
-            prepare_migration(
-                &migration_input.definition,
-                &github_client,
-                &args.dry_run_opts,
-                &work_directory_root,
-                pretty_name,
-                target,
-            )
-            .await?,
-        ));
+        if !args.filter.matches(pretty_name, target) {
+            continue;
+        }
+        tasks.push(
+            MigrationTask::prepare(&definition, github_hosts, &settings, pretty_name, target)
+                .await?,
+        );
+    }
+    if tasks.len() < migration_input.targets.len() {
+        info!(
+            "{} of the {} targets match --only, --exclude and --tag",
+            tasks.len(),
+            migration_input.targets.len()
+        );
+    }
+
+    for task in &tasks {
+        if let Some(target) = migration_input.targets.get_mut(&task.pretty_name) {
+            target.metadata = task.metadata.clone();
+        }
+    }
+
+    if args.closed_only {
+        let closed =
+            closed_pull_requests(github_hosts, &migration_input.definition, &tasks).await?;
+        for task in tasks.iter_mut() {
+            task.skip = task.skip || !closed.contains(&task.pretty_name);
+        }
+        info!("{} targets have PR's closed without merge", closed.len());
+    }
+
+    if args.only_failed {
+        for task in tasks.iter_mut() {
+            let failed = migration_input.targets[&task.pretty_name]
+                .last_run
+                .as_ref()
+                .map_or(false, |it| it.outcome == RunOutcome::Failed);
+            task.skip = task.skip || !failed;
+        }
+        info!(
+            "{} targets failed in their last run",
+            tasks.iter().filter(|it| !it.skip).count()
+        );
+    }
+
+    let duplicates = clu::migration::find_duplicate_tasks(&tasks);
+    for (duplicate, primary) in &duplicates {
+        warn!(
+            "{} pushes the same branch to the same repo as {}, skipping it",
+            duplicate, primary
+        );
+    }
+    for task in tasks.iter_mut() {
+        task.skip = task.skip || duplicates.contains_key(&task.pretty_name);
+    }
+
+    if args.limit.is_some() || args.sample.is_some() {
+        let candidates: Vec<&str> = tasks
+            .iter()
+            .filter(|it| !it.skip)
+            .map(|it| it.pretty_name.as_str())
+            .collect();
+        let mut count = candidates.len();
+        if let Some(sample) = &args.sample {
+            count = count.min(sample.of(candidates.len()));
+        }
+        if let Some(limit) = args.limit {
+            count = count.min(limit);
+        }
+        let picked: BTreeSet<String> = pick_sample(&candidates, count, args.seed)
+            .into_iter()
+            .map(|it| it.to_owned())
+            .collect();
+        info!(
+            "Piloting the migration on {} of the {} targets",
+            picked.len(),
+            candidates.len()
+        );
+        for task in tasks.iter_mut() {
+            task.skip = task.skip || !picked.contains(&task.pretty_name);
+        }
+    }
+
+    if args.resume {
+        let checkpoint = match clu::state::load_checkpoint(&results_path)? {
+            Some(checkpoint) => checkpoint,
+            None => anyhow::bail!("{} has no interrupted run to resume", results_path),
+        };
+        for task in tasks.iter_mut() {
+            task.skip = task.skip || checkpoint.targets.contains_key(&task.pretty_name);
+        }
+        info!(
+            "Resuming the run, {} targets were already finished",
+            checkpoint.targets.len()
+        );
+        for (name, finished) in checkpoint.targets {
+            if let Some(target) = migration_input.targets.get_mut(&name) {
+                target.merge_results(finished);
+            }
+        }
+    } else if clu::state::load_checkpoint(&results_path)?.is_some() {
+        warn!("The last run was interrupted, it's being started over instead of resumed with `clu resume`");
+    }
+    let checkpoint = RunCheckpoint {
+        migration_definition: &results_path,
+        started_at: epoch_start,
+        state: Mutex::new((migration_input.clone(), args.resume)),
+    };
+
+    let dry_run_opts = &args.dry_run_opts;
+    let publishing = !(dry_run_opts.dry_run
+        || dry_run_opts.skip_push
+        || dry_run_opts.export_bundles.is_some()
+        || dry_run_opts.export_patches.is_some());
+    if publishing && !args.skip_permission_check {
+        for client in github_hosts.clients() {
+            client.check_token_scopes().await?;
+        }
+        let github_task = tasks
+            .iter()
+            .find(|it| !it.skip && it.exec_opts.is_on_github());
+        if let Some(task) = github_task {
+            let missing = task
+                .exec_opts
+                .github_client
+                .probe_permissions(&task.repo, !dry_run_opts.skip_pull_request)
+                .await?;
+            if !missing.is_empty() {
+                let missing: Vec<String> = missing.iter().map(|it| it.to_string()).collect();
+                anyhow::bail!(
+                    "The token is missing permissions needed for the run, checked against {}: {}",
+                    task.repo,
+                    missing.join(", ")
+                );
+            }
+        }
+    }
+
+    // With --interactive every push is confirmed on its own
+    if publishing && !args.interactive {
+        let pull_requests = match dry_run_opts.skip_pull_request {
+            true => "without opening PR's".to_owned(),
+            false => format!("and open PR's titled \"{}\"", definition.pr.title),
+        };
+        clu::prompt::confirm(
+            &format!(
+                "About to force push `{}` to {} repos {}, from {}",
+                definition.checkout.branch_name,
+                tasks.iter().filter(|it| !it.skip).count(),
+                pull_requests,
+                migration_definition
+            ),
+            args.yes,
+        )?;
     }
 
     let header_span = info_span!("run", "indicatif.pb_show" = true);
@@ -301,38 +1478,245 @@ pub async fn run_migration(args: RunMigrationArgs) -> AnyResult<()> {
 
     let _span = header_span.enter();
 
-    stream::iter(tasks)
-        .for_each_concurrent(3, |(result_map, task)| {
-            let header_span = &header_span;
-            async move {
-                header_span.pb_inc(1);
+    let dashboard = match args.tui {
+        true => {
+            let dashboard = Arc::new(Dashboard::new(
+                &definition.checkout.branch_name,
+                &PathBuf::from(&work_directory_root).canonicalize()?,
+                tasks.iter().map(|it| it.pretty_name.clone()),
+            ));
+            Some((dashboard.clone(), dashboard.spawn()))
+        }
+        false => None,
+    };
+    let dashboard_ref = dashboard.as_ref().map(|(it, _)| it.as_ref());
 
-                let action_span =
-                    info_span!(parent: header_span, "action", "indicatif.pb_show" = true);
-                action_span.pb_set_message(&format!("action {}", task.pretty_name,));
-                action_span.pb_set_style(&progress_bar_without_pos());
-                let _span = action_span.enter();
+    let queue: Box<dyn JobQueue> = match &args.queue_dir {
+        Some(dir) => Box::new(DirectoryQueue::new(&PathBuf::from(dir))?),
+        None => Box::new(LocalQueue::new(
+            tasks.iter().map(|it| it.pretty_name.clone()),
+        )),
+    };
 
-                let migration_status = task.run().await;
-                let mut result_map = result_map.lock().unwrap();
-                result_map.insert(task.pretty_name, migration_status);
+    // The prompt waits for an answer, so the targets are asked about one at a time
+    let concurrency = match args.interactive {
+        true => 1,
+        false => RUN_CONCURRENCY,
+    };
+    let fail_fast = args.fail_fast;
+    stream::iter(0..concurrency)
+        .for_each_concurrent(concurrency, |_| {
+            let header_span = &header_span;
+            let result_map = &result_map;
+            let tasks = &tasks;
+            let queue = &queue;
+            let checkpoint = &checkpoint;
+            let is_stopping = &is_stopping;
+            let stopping = &stopping;
+            async move {
+                loop {
+                    if is_stopping() {
+                        break;
+                    }
+                    // Targets this worker doesn't know, like ones left out by `--only`, are
+                    // left for the other workers
+                    let runnable = |name: &str| tasks.iter().any(|it| it.pretty_name == name);
+                    let name = match queue.claim(&runnable).await {
+                        Ok(Some(name)) => name,
+                        Ok(None) => break,
+                        Err(e) => {
+                            error!("Unable to take a target from the queue: {:?}", e);
+                            break;
+                        }
+                    };
+                    let task = match tasks.iter().find(|it| it.pretty_name == name) {
+                        Some(task) => task,
+                        None => {
+                            warn!("{} was queued, but isn't a target of the migration", name);
+                            release_claim(queue.as_ref(), &name).await;
+                            continue;
+                        }
+                    };
+                    header_span.pb_inc(1);
+
+                    let migration_status =
+                        run_task_with_progress(header_span, task, dashboard_ref).await;
+                    // Targets that will be retried aren't done yet
+                    if migration_status.status.retry_wait().is_none() {
+                        checkpoint.record(task, &migration_status);
+                        complete_claim(queue.as_ref(), checkpoint, &task.pretty_name).await;
+                        if fail_fast && migration_status.status.is_failure() {
+                            warn!("{} failed, no more targets are started", task.pretty_name);
+                            stopping.store(true, Ordering::SeqCst);
+                        }
+                    }
+                    let mut result_map = result_map.lock().unwrap();
+                    result_map.insert(task.pretty_name.clone(), migration_status);
+                }
             }
         })
         .await;
 
+    let mut retry_queue = RetryQueue::new();
+    {
+        let result_map = result_map.lock().unwrap();
+        for task in &tasks {
+            let wait = result_map
+                .get(&task.pretty_name)
+                .and_then(|it| it.status.retry_wait());
+            if let Some(wait) = wait {
+                retry_queue.park(task, wait, 1);
+            }
+        }
+    }
+
+    if !retry_queue.is_empty() {
+        warn!(
+            "{} repos were rate limited or hit GitHub errors, they will be retried",
+            retry_queue.len()
+        );
+    }
+
+    while let Some(entry) = retry_queue.next_ready() {
+        if is_stopping() {
+            warn!(
+                "The run was stopped, {} is not retried",
+                entry.item.pretty_name
+            );
+            release_claim(queue.as_ref(), &entry.item.pretty_name).await;
+            continue;
+        }
+        tokio::time::sleep_until(entry.resume_at.into()).await;
+        info!(
+            "{}: Retrying (attempt {})",
+            entry.item.pretty_name,
+            entry.attempt + 1
+        );
+
+        let migration_status =
+            run_task_with_progress(&header_span, entry.item, dashboard_ref).await;
+        match migration_status.status.retry_wait() {
+            Some(wait) if entry.attempt < MAX_RETRIED_RUNS => {
+                retry_queue.park(entry.item, wait, entry.attempt + 1)
+            }
+            _ => {
+                if let Some(dashboard) = dashboard_ref {
+                    dashboard.update(
+                        &entry.item.pretty_name,
+                        TargetProgress::Done(migration_status.status.run_result()),
+                    );
+                }
+                checkpoint.record(entry.item, &migration_status);
+                complete_claim(queue.as_ref(), &checkpoint, &entry.item.pretty_name).await;
+            }
+        }
+
+        let mut result_map = result_map.lock().unwrap();
+        result_map.insert(entry.item.pretty_name.clone(), migration_status);
+    }
+
+    let snapshot_dir = match &args.snapshot_failures {
+        Some(dir) => {
+            create_dir_all(dir)?;
+            Some(PathBuf::from(dir).canonicalize()?)
+        }
+        None => None,
+    };
+
     let mut error_log = Vec::default();
-    let result_map = result_map.lock().unwrap();
-    for (pretty_name, status) in result_map.iter() {
-        let status = &status;
+    let mut zero_diff = Vec::default();
+    let mut pull_requests = Vec::default();
+    let mut outputs = Vec::default();
+    let mut run_targets = BTreeMap::new();
+    let result_map = std::mem::take(&mut *result_map.lock().unwrap());
+    for (pretty_name, outcome) in result_map.iter() {
+        let snapshot = match &snapshot_dir {
+            Some(dir) if outcome.status.is_failure() => {
+                snapshot_failure(
+                    &work_directory_root,
+                    dir,
+                    pretty_name,
+                    args.snapshot_git_objects,
+                )
+                .await
+            }
+            _ => String::new(),
+        };
 
-        match status {
+        if outcome.metrics.is_zero_diff() {
+            zero_diff.push(pretty_name.clone());
+        }
+
+        let run_result = outcome.status.run_result();
+        let skipped = tasks
+            .iter()
+            .any(|it| &it.pretty_name == pretty_name && it.skip);
+        if !skipped {
+            migration_input
+                .targets
+                .get_mut(pretty_name)
+                .unwrap()
+                .last_run = Some(run_result.clone());
+            run_targets.insert(pretty_name.clone(), run_result.clone());
+        }
+        outputs.push(RunOutput {
+            migration: None,
+            target: pretty_name.clone(),
+            outcome: run_result.outcome,
+            step: run_result.step,
+            error: outcome.status.error().map(|e| e.to_string()),
+            pull_request: match &outcome.status {
+                MigrationStatus::PullRequest(result) => {
+                    result.result.as_ref().ok().map(|pr| pr.url.clone())
+                }
+                _ => None,
+            },
+            duration_ms: outcome.metrics.steps.iter().map(|it| it.duration_ms).sum(),
+            gave_up: outcome.status.retry_wait().is_some(),
+        });
+
+        if let Some(label) = &args.run_label {
+            migration_input
+                .targets
+                .get_mut(pretty_name)
+                .unwrap()
+                .runs
+                .insert(label.clone(), outcome.status.run_result());
+        }
+
+        if !outcome.metrics.steps.is_empty() {
+            migration_input
+                .targets
+                .get_mut(pretty_name)
+                .unwrap()
+                .metrics = Some(outcome.metrics.clone());
+        }
+
+        match &outcome.status {
             MigrationStatus::PullRequest(result) => match &result.result {
                 Err(e) => {
                     warn!("{}: Unable to run migration because of {}", pretty_name, e);
                     error_log.push(format!(
-                        "{}: Unable to run migration because of {}",
-                        pretty_name, e
+                        "{}: Unable to run migration because of {}{}",
+                        pretty_name, e, snapshot
                     ));
+
+                    let target = migration_input.targets.get_mut(pretty_name).unwrap();
+                    let task = tasks.iter().find(|it| &it.pretty_name == pretty_name);
+                    let wants_issue = definition.pr.issue_on_failure
+                        && task.map_or(false, |it| it.exec_opts.is_on_github())
+                        && target.manual_issue.is_none()
+                        && matches!(e, MigrationError::UnableToCreatePullRequest { .. });
+                    if let Some(task) = task.filter(|_| wants_issue) {
+                        let github_client = task.exec_opts.github_client;
+                        match open_manual_issue(github_client, &definition, &task.repo, e).await {
+                            Ok(issue) => target.manual_issue = Some(issue),
+                            Err(e) => warn!(
+                                "{}: Unable to open an issue for the migration: {:?}",
+                                pretty_name, e
+                            ),
+                        }
+                    }
                 }
                 Ok(pr) => {
                     migration_input
@@ -340,14 +1724,55 @@ pub async fn run_migration(args: RunMigrationArgs) -> AnyResult<()> {
                         .get_mut(pretty_name)
                         .unwrap()
                         .pull_request = Some(pr.clone());
+                    pull_requests.push(pr.url.clone());
+                }
+            },
+            MigrationStatus::Pushed(result) => match &result.result {
+                Err(e) => {
+                    warn!("{}: Unable to run migration because {}", pretty_name, e);
+                    error_log.push(format!(
+                        "{}: Unable to run migration because {}{}",
+                        pretty_name, e, snapshot
+                    ));
+                }
+                Ok(pushed) => {
+                    info!(
+                        "{}: Pushed {} to {} at {}",
+                        pretty_name, pushed.branch, pushed.remote, pushed.commit
+                    );
+                    migration_input.targets.get_mut(pretty_name).unwrap().pushed =
+                        Some(pushed.clone());
+                }
+            },
+            MigrationStatus::Patches(result) => match &result.result {
+                Err(e) => {
+                    warn!("{}: Unable to run migration because {}", pretty_name, e);
+                    error_log.push(format!(
+                        "{}: Unable to run migration because {}{}",
+                        pretty_name, e, snapshot
+                    ));
+                }
+                Ok(series) => {
+                    info!(
+                        "{}: Wrote {} mails to {}{}",
+                        pretty_name,
+                        series.message_ids.len(),
+                        series.dir,
+                        if series.sent { " and sent them" } else { "" }
+                    );
+                    migration_input
+                        .targets
+                        .get_mut(pretty_name)
+                        .unwrap()
+                        .patches = Some(series.clone());
                 }
             },
             MigrationStatus::EmptyResponse(result) => match &result.result {
                 Err(e) => {
                     warn!("{}: Unable to run migration because: {}", pretty_name, e);
                     error_log.push(format!(
-                        "{}: Unable to run migration because: {}",
-                        pretty_name, e
+                        "{}: Unable to run migration because: {}{}",
+                        pretty_name, e, snapshot
                     ));
                 }
                 Ok(_) => {
@@ -360,9 +1785,38 @@ pub async fn run_migration(args: RunMigrationArgs) -> AnyResult<()> {
         }
     }
 
-    let updated_migration_input = &toml::to_string_pretty(&migration_input)?;
-    let mut results = File::create(args.migration_definition)?;
-    results.write_all(updated_migration_input.as_bytes())?;
+    clu::migration::alias_duplicate_targets(&mut migration_input.targets, &duplicates);
+
+    // Again with what the end of the run adds, like the manual issues and the duplicates
+    for pretty_name in result_map.keys() {
+        if let Err(e) = queue
+            .complete(pretty_name, &migration_input.targets[pretty_name])
+            .await
+        {
+            error!(
+                "{}: Unable to record the result in the queue: {:?}",
+                pretty_name, e
+            );
+        }
+    }
+
+    clu::state::save_migration(&results_path, &migration_input)?;
+    clu::state::record_run(
+        &results_path,
+        clu::state::RunRecord {
+            id: clu::github::run_id().to_owned(),
+            started_at: epoch_start,
+            finished_at: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)?
+                .as_secs(),
+            label: args.run_label.clone(),
+            targets: run_targets,
+        },
+    )?;
+    // A stopped run keeps its checkpoint, so `clu resume` runs the targets that were left
+    if !is_stopping() {
+        clu::state::remove_checkpoint(&results_path)?;
+    }
 
     if !error_log.is_empty() {
         let mut error_results = File::create("migration.errors.txt")?;
@@ -370,48 +1824,273 @@ pub async fn run_migration(args: RunMigrationArgs) -> AnyResult<()> {
         error!("Created migration.errors.txt with the summary of errors");
     }
 
-    Ok(())
+    if !zero_diff.is_empty() {
+        let mut zero_diff_results = File::create("migration.no-changes.txt")?;
+        zero_diff_results.write_all(zero_diff.join("\n").as_bytes())?;
+        warn!(
+            "{} repos passed pre-flight but the steps made no changes, see migration.no-changes.txt",
+            zero_diff.len()
+        );
+    }
+
+    if let Some(teams) = migration_input
+        .definition
+        .notifications
+        .as_ref()
+        .and_then(|it| it.teams.as_ref())
+    {
+        let summary = RunSummary {
+            migration: migration_input.definition.checkout.branch_name.clone(),
+            total: migration_input.targets.len(),
+            pull_requests,
+            errors: error_log.clone(),
+            no_changes: zero_diff.clone(),
+        };
+        if let Err(e) = TeamsNotifier::new(teams).send_run_summary(&summary).await {
+            error!("Unable to send run summary to Teams: {:?}", e);
+        }
+    }
+
+    if let Some(tracking) = &migration_input.definition.tracking {
+        if args.dry_run_opts.dry_run {
+            info!("Dry run, not publishing results to {}", tracking.repo);
+        } else if let Err(e) = clu::tracking::publish_results(
+            github_hosts,
+            tracking,
+            &format!(
+                "Update results for {}",
+                migration_input.definition.checkout.branch_name
+            ),
+            &[
+                Path::new(&results_path),
+                Path::new("migration.errors.txt"),
+                Path::new("migration.no-changes.txt"),
+            ],
+        )
+        .await
+        {
+            error!("Unable to publish results to {}: {:?}", tracking.repo, e);
+        }
+    }
+
+    // The logs went to a file, so the errors are repeated once the dashboard is closed
+    if let Some((dashboard, drawing)) = dashboard {
+        dashboard.finish_run();
+        drawing.await?;
+        println!(
+            "# Migration Run\n\n{} of {} targets failed, the logs are in {}\n\n{}",
+            error_log.len(),
+            outputs.len(),
+            Path::new(&work_directory_root).join("clu.log").display(),
+            error_log.join("\n")
+        );
+    }
+
+    Ok(DefinitionRun {
+        migration_definition: migration_definition.to_owned(),
+        outputs,
+        errors: error_log,
+        no_changes: zero_diff,
+        stopped: is_stopping(),
+    })
+}
+
+/// Saves the result of each target as soon as it's done, so an interrupted run only loses the
+/// targets that were running. The end of the run still records everything else, like the manual
+/// issues and the error summary.
+struct RunCheckpoint<'a> {
+    migration_definition: &'a str,
+    started_at: u64,
+    /// The targets as they were recorded, and whether the checkpoint file was started.
+    state: std::sync::Mutex<(MigrationFile, bool)>,
+}
+
+impl RunCheckpoint<'_> {
+    /// The target as it was last recorded.
+    fn target(&self, name: &str) -> Option<TargetDescription> {
+        self.state.lock().unwrap().0.targets.get(name).cloned()
+    }
+
+    fn record(&self, task: &MigrationTask<'_>, outcome: &MigrationOutcome) {
+        if task.skip {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let (migration, started) = &mut *state;
+        if let Some(target) = migration.targets.get_mut(&task.pretty_name) {
+            match &outcome.status {
+                MigrationStatus::PullRequest(MigrationStepResult { result: Ok(pr), .. }) => {
+                    target.pull_request = Some(pr.clone())
+                }
+                MigrationStatus::Pushed(MigrationStepResult {
+                    result: Ok(pushed), ..
+                }) => target.pushed = Some(pushed.clone()),
+                MigrationStatus::Patches(MigrationStepResult {
+                    result: Ok(series), ..
+                }) => target.patches = Some(series.clone()),
+                _ => {}
+            }
+            if !outcome.metrics.steps.is_empty() {
+                target.metrics = Some(outcome.metrics.clone());
+            }
+            target.last_run = Some(outcome.status.run_result());
+        }
+
+        // The checkpoint of an interrupted run is only replaced once the new run finishes a target
+        let saved = match *started {
+            true => Ok(()),
+            false => clu::state::start_checkpoint(self.migration_definition, self.started_at),
+        }
+        .and_then(|_| {
+            *started = true;
+            clu::state::checkpoint_target(self.migration_definition, migration, &task.pretty_name)
+        });
+        if let Err(e) = saved {
+            warn!(
+                "{}: Unable to checkpoint the result: {:?}",
+                task.pretty_name, e
+            );
+        }
+    }
+}
+
+/// Hands a finished target to the queue right away, so its claim doesn't go stale while the
+/// worker runs the rest, and its result isn't lost when the worker dies.
+async fn complete_claim(queue: &dyn JobQueue, checkpoint: &RunCheckpoint<'_>, name: &str) {
+    let target = match checkpoint.target(name) {
+        Some(target) => target,
+        None => return,
+    };
+    if let Err(e) = queue.complete(name, &target).await {
+        error!(
+            "{}: Unable to record the result in the queue: {:?}",
+            name, e
+        );
+    }
 }
 
-#[allow(clippy::needless_lifetimes)]
-async fn prepare_migration<'a>(
+/// Gives a target that won't be run back to the queue, for another worker or a later run.
+async fn release_claim(queue: &dyn JobQueue, name: &str) {
+    if let Err(e) = queue.release(name).await {
+        error!(
+            "{}: Unable to give the target back to the queue: {:?}",
+            name, e
+        );
+    }
+}
+
+/// Asks the owners of a repo to do the migration by hand, since the PR couldn't be created.
+async fn open_manual_issue(
+    github_client: &clu::github::GithubApiClient,
     definition: &MigrationDefinition,
-    github_client: &'a GithubApiClient,
-    dry_run_opts: &DryRunOpts,
+    repo: &clu::github::GitHubRepo,
+    error: &MigrationError,
+) -> AnyResult<CreatedIssue> {
+    let mut reason = error.to_string();
+    if let Some(source) = std::error::Error::source(error) {
+        reason = format!("{} {}", reason, source);
+    }
+
+    let issue = github_client
+        .create_issue(
+            repo,
+            &format!("Manual migration needed: {}", definition.pr.title),
+            &format!(
+                "A pull request for the `{}` migration couldn't be created, so it needs to be \
+                done by hand.\n\n> {}\n\n## {}\n\n{}",
+                definition.checkout.branch_name,
+                reason,
+                definition.pr.title,
+                definition.pr.description
+            ),
+        )
+        .await?;
+    info!("Opened {} for the manual migration of {}", issue.url, repo);
+
+    Ok(CreatedIssue {
+        issue_number: issue.number,
+        url: issue.url,
+    })
+}
+
+/// Archives the workspace of a failed target, returning the note for the error report.
+async fn snapshot_failure(
     work_directory_root: &str,
+    snapshot_dir: &Path,
     pretty_name: &str,
-    target: &TargetDescription,
-) -> anyhow::Result<MigrationTask<'a>> {
-    debug!("Processing {:?}", &pretty_name);
-    let work_dir = PathBuf::from(&work_directory_root);
-
-    let env = match &target.env {
-        Some(value) => value.clone(),
-        None => std::collections::BTreeMap::default(),
-    };
+    include_git_objects: bool,
+) -> String {
+    let archive = snapshot_dir.join(format!("{}.tar.gz", pretty_name));
+    let workspace_dir = Path::new(work_directory_root).join(pretty_name);
+    if !workspace_dir.exists() {
+        return String::new();
+    }
 
-    let exec_options = ExecutionOptions {
-        skip_pull_request: dry_run_opts.skip_pull_request,
-        skip_push: dry_run_opts.skip_push,
-        dry_run: dry_run_opts.dry_run,
-        work_dir,
-        env,
-        github_client,
-    };
+    match clu::workspace::snapshot_workspace(&workspace_dir, &archive, include_git_objects).await {
+        Ok(_) => format!(" (workspace snapshot: {})", archive.display()),
+        Err(e) => {
+            warn!("{}: Unable to snapshot the workspace: {}", pretty_name, e);
+            String::new()
+        }
+    }
+}
 
-    let github_repo = match clu::github::extract_github_info(&target.repo) {
-        Ok(repo) => repo,
-        Err(e) => anyhow::bail!(clu::migration::MigrationError::InvalidGitRepo { source: e }),
-    };
+/// The names of the tasks whose PR was closed without being merged.
+async fn closed_pull_requests(
+    github_hosts: &GitHubHosts,
+    definition: &MigrationDefinition,
+    tasks: &[MigrationTask<'_>],
+) -> AnyResult<Vec<String>> {
+    let with_pulls: Vec<&MigrationTask> = tasks
+        .iter()
+        .filter(|it| it.pull_request.is_some())
+        .collect();
+    let pulls: Vec<(clu::github::GitHubRepo, i64)> = with_pulls
+        .iter()
+        .map(|it| (it.repo.clone(), it.pull_request.as_ref().unwrap().pr_number))
+        .collect();
+
+    let states = github_hosts
+        .fetch_pull_states(&pulls, &definition.ignored_check_patterns())
+        .await?;
+
+    Ok(with_pulls
+        .iter()
+        .zip(states.iter())
+        .filter(|(_, state)| state.status == clu::github::PullStatus::ClosedWithoutMerge)
+        .map(|(task, _)| task.pretty_name.clone())
+        .collect())
+}
 
-    Ok(MigrationTask::new(
-        pretty_name,
-        github_repo,
-        definition.clone(),
-        exec_options,
-        target.pull_request.clone(),
-        target.skip,
-    ))
+async fn run_task_with_progress(
+    header_span: &tracing::Span,
+    task: &MigrationTask<'_>,
+    dashboard: Option<&Dashboard>,
+) -> MigrationOutcome {
+    let action_span = info_span!(parent: header_span, "action", "indicatif.pb_show" = true);
+    action_span.pb_set_message(&format!("action {}", task.pretty_name,));
+    action_span.pb_set_style(&progress_bar_without_pos());
+    let _span = action_span.enter();
+
+    if let Some(dashboard) = dashboard {
+        dashboard.update(
+            &task.pretty_name,
+            TargetProgress::Running {
+                since: std::time::Instant::now(),
+            },
+        );
+    }
+    let outcome = task.run().await;
+    if let Some(dashboard) = dashboard {
+        let progress = match outcome.status.retry_wait() {
+            Some(_) => TargetProgress::Retrying,
+            None => TargetProgress::Done(outcome.status.run_result()),
+        };
+        dashboard.update(&task.pretty_name, progress);
+    }
+
+    outcome
 }
 
 fn default_progress_bar() -> ProgressStyle {
@@ -430,11 +2109,37 @@ pub fn progress_bar_without_pos() -> ProgressStyle {
     .progress_chars("##-")
 }
 
-fn configure_logging(logging_opts: &LoggingOpts) {
+/// Where the logs are written.
+enum LogDestination {
+    /// The terminal, under the progress bars.
+    ProgressBars,
+    /// The terminal, without any progress bars.
+    Plain,
+    File(File),
+}
+
+fn configure_logging(
+    logging_opts: &LoggingOpts,
+    logs_to_stderr: bool,
+    log_destination: LogDestination,
+) {
     let indicatif_layer = IndicatifLayer::new()
         .with_span_field_formatter(hide_indicatif_span_fields(DefaultFields::new()))
         .with_progress_style(default_progress_bar());
 
+    let to_file = matches!(log_destination, LogDestination::File(_));
+    let progress_bars = matches!(log_destination, LogDestination::ProgressBars);
+    let writer = match (log_destination, logs_to_stderr) {
+        (LogDestination::File(file), _) => BoxMakeWriter::new(std::sync::Mutex::new(file)),
+        (LogDestination::Plain, true) => BoxMakeWriter::new(std::io::stderr),
+        (LogDestination::Plain, false) => BoxMakeWriter::new(std::io::stdout),
+        (LogDestination::ProgressBars, true) => {
+            BoxMakeWriter::new(indicatif_layer.get_stderr_writer())
+        }
+        (LogDestination::ProgressBars, false) => {
+            BoxMakeWriter::new(indicatif_layer.get_stdout_writer())
+        }
+    };
     let layer = tracing_subscriber::fmt::layer()
         .event_format(
             Format::default()
@@ -442,11 +2147,50 @@ fn configure_logging(logging_opts: &LoggingOpts) {
                 .without_time()
                 .compact(),
         )
-        .with_writer(indicatif_layer.get_stdout_writer())
+        .with_writer(writer)
+        .with_ansi(!to_file)
         .fmt_fields(PrettyFields::new())
         .with_filter(logging_opts.to_level());
 
+    // Without the layer the progress bars aren't drawn
+    let indicatif_layer = match progress_bars {
+        true => Some(indicatif_layer),
+        false => None,
+    };
     let subscriber = Registry::default().with(layer).with(indicatif_layer);
 
     tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
 }
+
+#[test]
+fn validate_exit_code() {
+    let failed = |failed, gave_up| {
+        anyhow::Error::new(TargetsFailed {
+            failed,
+            total: 3,
+            gave_up,
+        })
+    };
+    assert_eq!(EXIT_TARGETS_FAILED, exit_code(&failed(2, 0)));
+    assert_eq!(EXIT_TARGETS_FAILED, exit_code(&failed(2, 1)));
+    assert_eq!(EXIT_RUNTIME_FAILURE, exit_code(&failed(2, 2)));
+
+    assert_eq!(
+        EXIT_UNABLE_TO_RUN,
+        exit_code(&anyhow::anyhow!("Unable to parse migration.toml"))
+    );
+    let not_found = GitHubError::NoSuchUser {
+        login: "ghost".to_owned(),
+    };
+    assert_eq!(EXIT_UNABLE_TO_RUN, exit_code(&not_found.into()));
+
+    let rate_limited = anyhow::Error::new(GitHubError::RateLimited {
+        wait: std::time::Duration::from_secs(60),
+    })
+    .context("Unable to list the repos of ethankhall");
+    assert_eq!(EXIT_RUNTIME_FAILURE, exit_code(&rate_limited));
+    let not_confirmed = GitHubError::MutationNotConfirmed {
+        error: "timed out".to_owned(),
+    };
+    assert_eq!(EXIT_RUNTIME_FAILURE, exit_code(&not_confirmed.into()));
+}
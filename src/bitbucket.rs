@@ -0,0 +1,721 @@
+use anyhow::{bail, Result as AnyResult};
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+
+use crate::github::{
+    format_github_timestamp, CheckResult, CheckState, GitHubRepo, PullRequestDescription,
+    PullRequestOutput, PullReviews, PullState, PullStatus, RepoAccess,
+};
+use crate::glob::Glob;
+
+/// Environment variable with the token for Bitbucket hosts that don't name their own.
+pub const BITBUCKET_TOKEN_ENV: &str = "BITBUCKET_TOKEN";
+
+/// Host of Bitbucket Cloud, recognized as Bitbucket without an entry in `hosts`.
+pub const BITBUCKET_HOST: &str = "bitbucket.org";
+
+/// API of Bitbucket Cloud, which isn't served from the host the repos are cloned from.
+pub const BITBUCKET_API_URL: &str = "https://api.bitbucket.org/2.0";
+
+/// Bitbucket Cloud and Bitbucket Server (or Data Center) have unrelated REST APIs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitbucketFlavor {
+    Cloud,
+    Server,
+}
+
+/// Client for the REST API of Bitbucket Cloud or a Bitbucket Server.
+#[derive(Debug)]
+pub struct BitbucketClient {
+    http: Client,
+    /// Base of the API, like `https://api.bitbucket.org/2.0` or
+    /// `https://bitbucket.example.com/rest/api/1.0`.
+    api_url: String,
+    flavor: BitbucketFlavor,
+}
+
+#[derive(Debug, Deserialize)]
+struct Page<T> {
+    values: Vec<T>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CloudRepository {
+    mainbranch: Option<CloudBranch>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ServerRepository {
+    #[serde(default)]
+    archived: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct CloudBranch {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CloudCommit {
+    hash: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CloudEndpoint {
+    branch: CloudBranch,
+    commit: Option<CloudCommit>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CloudLink {
+    href: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CloudLinks {
+    html: CloudLink,
+}
+
+#[derive(Debug, Deserialize)]
+struct CloudUser {
+    display_name: String,
+    nickname: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CloudParticipant {
+    role: String,
+    #[serde(default)]
+    approved: bool,
+    state: Option<String>,
+    user: CloudUser,
+}
+
+#[derive(Debug, Deserialize)]
+struct CloudPullRequest {
+    id: i64,
+    state: String,
+    created_on: String,
+    updated_on: String,
+    links: CloudLinks,
+    source: CloudEndpoint,
+    destination: CloudEndpoint,
+    #[serde(default)]
+    participants: Vec<CloudParticipant>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CloudDiffStat {
+    status: String,
+}
+
+/// Build statuses look the same on Cloud and Server.
+#[derive(Debug, Deserialize)]
+struct BuildStatus {
+    key: String,
+    name: Option<String>,
+    state: String,
+    url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ServerRef {
+    display_id: String,
+    latest_commit: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ServerLink {
+    href: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ServerLinks {
+    #[serde(rename = "self", default)]
+    self_links: Vec<ServerLink>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ServerUser {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ServerReviewer {
+    user: ServerUser,
+    status: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ServerPullRequest {
+    id: i64,
+    version: i64,
+    state: String,
+    created_date: u64,
+    closed_date: Option<u64>,
+    links: ServerLinks,
+    from_ref: ServerRef,
+    to_ref: ServerRef,
+    #[serde(default)]
+    reviewers: Vec<ServerReviewer>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ServerMerge {
+    can_merge: bool,
+    conflicted: bool,
+}
+
+impl BitbucketClient {
+    pub fn new(token: &str, api_url: &str, flavor: BitbucketFlavor) -> AnyResult<Self> {
+        let http = Client::builder()
+            .user_agent(crate::github::user_agent(
+                std::env::var("CLU_USER_AGENT_SUFFIX").ok().as_deref(),
+            ))
+            .default_headers(
+                vec![(
+                    reqwest::header::AUTHORIZATION,
+                    reqwest::header::HeaderValue::from_str(&format!("Bearer {}", token))?,
+                )]
+                .into_iter()
+                .collect(),
+            )
+            .build()?;
+
+        Ok(Self {
+            http,
+            api_url: api_url.trim_end_matches('/').to_owned(),
+            flavor,
+        })
+    }
+
+    /// Opens a PR for the branch, or updates the one that's already open. Bitbucket has no
+    /// labels, assignees or milestones, so those are left off.
+    pub async fn sync_pull_request(
+        &self,
+        repo: &GitHubRepo,
+        description: PullRequestDescription<'_>,
+        existing: Option<i64>,
+    ) -> AnyResult<PullRequestOutput> {
+        if !description.labels.is_empty()
+            || !description.assignees.is_empty()
+            || description.milestone.is_some()
+        {
+            warn!(
+                "{} is on Bitbucket, which has no labels, assignees or milestones for PR's",
+                repo
+            );
+        }
+
+        match self.flavor {
+            BitbucketFlavor::Cloud => self.sync_cloud(repo, description, existing).await,
+            BitbucketFlavor::Server => self.sync_server(repo, description, existing).await,
+        }
+    }
+
+    pub async fn fetch_pull_state(
+        &self,
+        repo: &GitHubRepo,
+        id: i64,
+        ignored_checks: &[Glob],
+    ) -> AnyResult<PullState> {
+        match self.flavor {
+            BitbucketFlavor::Cloud => {
+                let pull: CloudPullRequest = self
+                    .send(
+                        self.http
+                            .get(self.repo_url(repo, &format!("/pullrequests/{}", id))),
+                    )
+                    .await?;
+                let statuses: Page<BuildStatus> = self
+                    .send(
+                        self.http
+                            .get(self.repo_url(repo, &format!("/pullrequests/{}/statuses", id))),
+                    )
+                    .await?;
+                let diffstat: Page<CloudDiffStat> = self
+                    .send(
+                        self.http
+                            .get(self.repo_url(repo, &format!("/pullrequests/{}/diffstat", id))),
+                    )
+                    .await?;
+                let conflicted = diffstat
+                    .values
+                    .iter()
+                    .any(|it| it.status == "merge conflict");
+
+                Ok(cloud_pull_state(
+                    pull,
+                    statuses.values,
+                    conflicted,
+                    ignored_checks,
+                ))
+            }
+            BitbucketFlavor::Server => {
+                let pull = self.server_pull_request(repo, id).await?;
+                let merge: ServerMerge = self
+                    .send(
+                        self.http
+                            .get(self.repo_url(repo, &format!("/pull-requests/{}/merge", id))),
+                    )
+                    .await?;
+                let statuses = match &pull.from_ref.latest_commit {
+                    Some(commit) => {
+                        let url = format!(
+                            "{}/commits/{}",
+                            self.api_url
+                                .replace("/rest/api/1.0", "/rest/build-status/1.0"),
+                            commit
+                        );
+                        let statuses: Page<BuildStatus> = self.send(self.http.get(url)).await?;
+                        statuses.values
+                    }
+                    None => Vec::new(),
+                };
+
+                Ok(server_pull_state(pull, statuses, merge, ignored_checks))
+            }
+        }
+    }
+
+    pub async fn add_comment(&self, repo: &GitHubRepo, id: i64, body: &str) -> AnyResult<()> {
+        let request = match self.flavor {
+            BitbucketFlavor::Cloud => self
+                .http
+                .post(self.repo_url(repo, &format!("/pullrequests/{}/comments", id)))
+                .json(&serde_json::json!({ "content": { "raw": body } })),
+            BitbucketFlavor::Server => self
+                .http
+                .post(self.repo_url(repo, &format!("/pull-requests/{}/comments", id)))
+                .json(&serde_json::json!({ "text": body })),
+        };
+        let _: serde_json::Value = self.send(request).await?;
+
+        Ok(())
+    }
+
+    /// Bitbucket Cloud can't archive repos, and neither can tell whether the token can push,
+    /// so a push that's refused only fails the push.
+    pub async fn fetch_repo_access(&self, repo: &GitHubRepo) -> AnyResult<RepoAccess> {
+        let archived = match self.flavor {
+            BitbucketFlavor::Cloud => {
+                let _: CloudRepository = self.send(self.http.get(self.repo_url(repo, ""))).await?;
+                false
+            }
+            BitbucketFlavor::Server => {
+                let repository: ServerRepository =
+                    self.send(self.http.get(self.repo_url(repo, ""))).await?;
+                repository.archived
+            }
+        };
+
+        Ok(RepoAccess {
+            archived,
+            can_push: true,
+        })
+    }
+
+    async fn sync_cloud(
+        &self,
+        repo: &GitHubRepo,
+        description: PullRequestDescription<'_>,
+        existing: Option<i64>,
+    ) -> AnyResult<PullRequestOutput> {
+        let destination = match description.base {
+            Some(base) => base.to_owned(),
+            None => {
+                let repository: CloudRepository =
+                    self.send(self.http.get(self.repo_url(repo, ""))).await?;
+                match repository.mainbranch {
+                    Some(branch) => branch.name,
+                    None => bail!("{} has no main branch", repo),
+                }
+            }
+        };
+
+        let mut body = serde_json::json!({
+            "title": description.title,
+            "description": description.body,
+            "destination": { "branch": { "name": destination } },
+        });
+
+        let existing = match existing {
+            Some(id) => Some(id),
+            None => {
+                let query = format!(
+                    "source.branch.name=\"{}\" AND state=\"OPEN\"",
+                    description.branch
+                );
+                let request = self
+                    .http
+                    .get(self.repo_url(repo, "/pullrequests"))
+                    .query(&[("q", query)]);
+                let open: Page<CloudPullRequest> = self.send(request).await?;
+                open.values.first().map(|it| it.id)
+            }
+        };
+
+        let pull: CloudPullRequest = match existing {
+            Some(id) => {
+                info!("Updating PR #{} of {}", id, repo);
+                let request = self
+                    .http
+                    .put(self.repo_url(repo, &format!("/pullrequests/{}", id)))
+                    .json(&body);
+                self.send(request).await?
+            }
+            None => {
+                info!("Opening a PR on {}", repo);
+                body["source"] = serde_json::json!({ "branch": { "name": description.branch } });
+                let request = self
+                    .http
+                    .post(self.repo_url(repo, "/pullrequests"))
+                    .json(&body);
+                self.send(request).await?
+            }
+        };
+
+        Ok(PullRequestOutput {
+            id: pull.id.to_string(),
+            number: pull.id,
+            permalink: pull.links.html.href,
+            created_at: pull.created_on,
+            head_ref: pull.source.branch.name,
+        })
+    }
+
+    async fn sync_server(
+        &self,
+        repo: &GitHubRepo,
+        description: PullRequestDescription<'_>,
+        existing: Option<i64>,
+    ) -> AnyResult<PullRequestOutput> {
+        let to_branch = match description.base {
+            Some(base) => base.to_owned(),
+            None => {
+                let branch: ServerRef = self
+                    .send(self.http.get(self.repo_url(repo, "/default-branch")))
+                    .await?;
+                branch.display_id
+            }
+        };
+
+        let mut body = serde_json::json!({
+            "title": description.title,
+            "description": description.body,
+            "toRef": { "id": format!("refs/heads/{}", to_branch) },
+        });
+
+        let existing = match existing {
+            Some(id) => Some(self.server_pull_request(repo, id).await?),
+            None => {
+                let request = self
+                    .http
+                    .get(self.repo_url(repo, "/pull-requests"))
+                    .query(&[
+                        ("at", format!("refs/heads/{}", description.branch)),
+                        ("direction", "OUTGOING".to_owned()),
+                        ("state", "OPEN".to_owned()),
+                    ]);
+                let open: Page<ServerPullRequest> = self.send(request).await?;
+                open.values.into_iter().next()
+            }
+        };
+
+        let pull: ServerPullRequest = match existing {
+            Some(pull) => {
+                info!("Updating PR #{} of {}", pull.id, repo);
+                // Updates are rejected unless they name the version they're changing
+                body["version"] = serde_json::json!(pull.version);
+                let request = self
+                    .http
+                    .put(self.repo_url(repo, &format!("/pull-requests/{}", pull.id)))
+                    .json(&body);
+                self.send(request).await?
+            }
+            None => {
+                info!("Opening a PR on {}", repo);
+                body["fromRef"] =
+                    serde_json::json!({ "id": format!("refs/heads/{}", description.branch) });
+                let request = self
+                    .http
+                    .post(self.repo_url(repo, "/pull-requests"))
+                    .json(&body);
+                self.send(request).await?
+            }
+        };
+
+        Ok(PullRequestOutput {
+            id: pull.id.to_string(),
+            number: pull.id,
+            permalink: server_permalink(&pull),
+            created_at: format_github_timestamp(pull.created_date / 1000),
+            head_ref: pull.from_ref.display_id,
+        })
+    }
+
+    async fn server_pull_request(
+        &self,
+        repo: &GitHubRepo,
+        id: i64,
+    ) -> AnyResult<ServerPullRequest> {
+        self.send(
+            self.http
+                .get(self.repo_url(repo, &format!("/pull-requests/{}", id))),
+        )
+        .await
+    }
+
+    /// Cloud addresses repos as `repositories/<workspace>/<slug>`, Server as
+    /// `projects/<key>/repos/<slug>`. Server's HTTP clone URLs put the project under `scm/`.
+    fn repo_url(&self, repo: &GitHubRepo, path: &str) -> String {
+        match self.flavor {
+            BitbucketFlavor::Cloud => format!(
+                "{}/repositories/{}/{}{}",
+                self.api_url, repo.owner, repo.repo, path
+            ),
+            BitbucketFlavor::Server => {
+                let full_name = repo.to_string();
+                let full_name = full_name.strip_prefix("scm/").unwrap_or(&full_name);
+                let (project, slug) = full_name.rsplit_once('/').unwrap_or(("", full_name));
+                format!(
+                    "{}/projects/{}/repos/{}{}",
+                    self.api_url, project, slug, path
+                )
+            }
+        }
+    }
+
+    async fn send<T: DeserializeOwned>(&self, request: reqwest::RequestBuilder) -> AnyResult<T> {
+        let response = request.send().await?;
+        let status = response.status();
+        let body: serde_json::Value = response.json().await?;
+        debug!("Bitbucket Response: {:?}", body);
+
+        if !status.is_success() {
+            bail!("Bitbucket responded {}: {}", status, body);
+        }
+
+        Ok(serde_json::from_value(body)?)
+    }
+}
+
+fn server_permalink(pull: &ServerPullRequest) -> String {
+    pull.links
+        .self_links
+        .first()
+        .map(|it| it.href.clone())
+        .unwrap_or_default()
+}
+
+fn build_checks(statuses: Vec<BuildStatus>, ignored_checks: &[Glob]) -> Vec<CheckResult> {
+    statuses
+        .into_iter()
+        .map(|status| {
+            let name = status.name.unwrap_or(status.key);
+            CheckResult {
+                ignored: ignored_checks.iter().any(|it| it.is_match(&name)),
+                name,
+                state: match status.state.as_str() {
+                    "SUCCESSFUL" => CheckState::Passed,
+                    "FAILED" | "STOPPED" => CheckState::Failed,
+                    _ => CheckState::Pending,
+                },
+                url: status.url,
+                required: false,
+            }
+        })
+        .collect()
+}
+
+fn has_failed(checks: &[CheckResult]) -> bool {
+    checks
+        .iter()
+        .any(|it| it.state == CheckState::Failed && !it.ignored)
+}
+
+fn cloud_pull_state(
+    pull: CloudPullRequest,
+    statuses: Vec<BuildStatus>,
+    conflicted: bool,
+    ignored_checks: &[Glob],
+) -> PullState {
+    let checks = build_checks(statuses, ignored_checks);
+
+    let mut reviews = PullReviews::default();
+    for participant in pull.participants.iter().filter(|it| it.role == "REVIEWER") {
+        let user = participant
+            .user
+            .nickname
+            .clone()
+            .unwrap_or_else(|| participant.user.display_name.clone());
+        if participant.approved {
+            reviews.approved_by.push(user);
+        } else if participant.state.as_deref() == Some("changes_requested") {
+            reviews.changes_requested_by.push(user);
+        } else {
+            reviews.requested.push(user);
+        }
+    }
+
+    let status = match pull.state.as_str() {
+        "MERGED" => PullStatus::Merged,
+        "DECLINED" | "SUPERSEDED" => PullStatus::ClosedWithoutMerge,
+        _ if conflicted => PullStatus::Conflicting,
+        _ if has_failed(&checks) => PullStatus::ChecksFailed,
+        _ if !reviews.changes_requested_by.is_empty() => PullStatus::NeedsApproval,
+        _ => PullStatus::Mergeable,
+    };
+    let closed_at = match status {
+        PullStatus::Merged | PullStatus::ClosedWithoutMerge => Some(pull.updated_on),
+        _ => None,
+    };
+
+    PullState {
+        id: pull.id.to_string(),
+        status,
+        permalink: pull.links.html.href,
+        created_at: pull.created_on,
+        closed_at,
+        base_ref: pull.destination.branch.name,
+        checks,
+        head_oid: pull.source.commit.map(|it| it.hash).unwrap_or_default(),
+        head_ref_id: None,
+        behind: false,
+        required_checks: Vec::new(),
+        reviews,
+    }
+}
+
+fn server_pull_state(
+    pull: ServerPullRequest,
+    statuses: Vec<BuildStatus>,
+    merge: ServerMerge,
+    ignored_checks: &[Glob],
+) -> PullState {
+    let checks = build_checks(statuses, ignored_checks);
+
+    let mut reviews = PullReviews::default();
+    for reviewer in &pull.reviewers {
+        let user = reviewer.user.name.clone();
+        match reviewer.status.as_str() {
+            "APPROVED" => reviews.approved_by.push(user),
+            "NEEDS_WORK" => reviews.changes_requested_by.push(user),
+            _ => reviews.requested.push(user),
+        }
+    }
+
+    let status = match pull.state.as_str() {
+        "MERGED" => PullStatus::Merged,
+        "DECLINED" => PullStatus::ClosedWithoutMerge,
+        _ if merge.conflicted => PullStatus::Conflicting,
+        _ if has_failed(&checks) => PullStatus::ChecksFailed,
+        // Vetoes other than conflicts and builds are the approvals the repo requires
+        _ if !merge.can_merge => PullStatus::NeedsApproval,
+        _ => PullStatus::Mergeable,
+    };
+
+    PullState {
+        id: pull.id.to_string(),
+        status,
+        permalink: server_permalink(&pull),
+        created_at: format_github_timestamp(pull.created_date / 1000),
+        closed_at: pull
+            .closed_date
+            .map(|it| format_github_timestamp(it / 1000)),
+        base_ref: pull.to_ref.display_id,
+        checks,
+        head_oid: pull.from_ref.latest_commit.unwrap_or_default(),
+        head_ref_id: None,
+        behind: false,
+        required_checks: Vec::new(),
+        reviews,
+    }
+}
+
+#[test]
+fn validate_cloud_pull_state() {
+    let pull = |state: &str| -> CloudPullRequest {
+        serde_json::from_value(serde_json::json!({
+            "id": 7, "state": state,
+            "created_on": "2021-01-01T00:00:00Z", "updated_on": "2021-01-02T00:00:00Z",
+            "links": { "html": { "href": "https://bitbucket.org/platform/clu/pull-requests/7" } },
+            "source": { "branch": { "name": "migration" }, "commit": { "hash": "abc" } },
+            "destination": { "branch": { "name": "main" }, "commit": null },
+            "participants": [
+                { "role": "REVIEWER", "approved": false, "state": null,
+                  "user": { "display_name": "Ethan", "nickname": "ethankhall" } },
+            ],
+        }))
+        .unwrap()
+    };
+    let failed = || -> Vec<BuildStatus> {
+        serde_json::from_value(serde_json::json!([
+            { "key": "build", "name": null, "state": "FAILED", "url": null },
+        ]))
+        .unwrap()
+    };
+
+    let state = cloud_pull_state(pull("OPEN"), Vec::new(), false, &[]);
+    assert_eq!(PullStatus::Mergeable, state.status);
+    assert_eq!(vec!["ethankhall".to_owned()], state.reviews.requested);
+    assert_eq!(
+        PullStatus::ChecksFailed,
+        cloud_pull_state(pull("OPEN"), failed(), false, &[]).status
+    );
+    assert_eq!(
+        PullStatus::Mergeable,
+        cloud_pull_state(pull("OPEN"), failed(), false, &[Glob::new("build")]).status
+    );
+    assert_eq!(
+        PullStatus::Conflicting,
+        cloud_pull_state(pull("OPEN"), failed(), true, &[]).status
+    );
+    let state = cloud_pull_state(pull("DECLINED"), Vec::new(), false, &[]);
+    assert_eq!(PullStatus::ClosedWithoutMerge, state.status);
+    assert_eq!(Some("2021-01-02T00:00:00Z".to_owned()), state.closed_at);
+}
+
+#[test]
+fn validate_server_pull_state() {
+    let pull = |state: &str| -> ServerPullRequest {
+        serde_json::from_value(serde_json::json!({
+            "id": 7, "version": 2, "state": state,
+            "createdDate": 1616347800000_u64, "closedDate": null,
+            "links": { "self": [{ "href": "https://bitbucket.example.com/projects/PLAT/repos/clu/pull-requests/7" }] },
+            "fromRef": { "displayId": "migration", "latestCommit": "abc" },
+            "toRef": { "displayId": "main", "latestCommit": "def" },
+            "reviewers": [{ "user": { "name": "ethankhall" }, "status": "NEEDS_WORK" }],
+        }))
+        .unwrap()
+    };
+    let merge = |can_merge: bool, conflicted: bool| ServerMerge {
+        can_merge,
+        conflicted,
+    };
+
+    let state = server_pull_state(pull("OPEN"), Vec::new(), merge(true, false), &[]);
+    assert_eq!(PullStatus::Mergeable, state.status);
+    assert_eq!("2021-03-21T17:30:00Z", state.created_at);
+    assert_eq!(
+        vec!["ethankhall".to_owned()],
+        state.reviews.changes_requested_by
+    );
+    assert_eq!(
+        PullStatus::NeedsApproval,
+        server_pull_state(pull("OPEN"), Vec::new(), merge(false, false), &[]).status
+    );
+    assert_eq!(
+        PullStatus::Conflicting,
+        server_pull_state(pull("OPEN"), Vec::new(), merge(false, true), &[]).status
+    );
+    assert_eq!(
+        PullStatus::Merged,
+        server_pull_state(pull("MERGED"), Vec::new(), merge(false, false), &[]).status
+    );
+}
@@ -0,0 +1,713 @@
+use anyhow::{bail, Result as AnyResult};
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use regex::Regex;
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+
+use crate::github::{
+    format_github_timestamp, GitHubRepo, PullRequestDescription, PullRequestOutput, PullReviews,
+    PullState, PullStatus, RepoAccess,
+};
+use crate::glob::Glob;
+
+/// Credential helper of the AWS CLI, which signs the HTTPS clones and pushes of CodeCommit.
+pub const CODECOMMIT_CREDENTIAL_HELPER: &str = "!aws codecommit credential-helper $@";
+
+const API_VERSION_TARGET: &str = "CodeCommit_20150413";
+const JSON_CONTENT_TYPE: &str = "application/x-amz-json-1.1";
+
+/// Credentials of an IAM user or role, read from the same environment variables as the AWS CLI.
+#[derive(Clone)]
+pub struct AwsCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+impl AwsCredentials {
+    pub fn from_env() -> AnyResult<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|it| !it.is_empty());
+        match (var("AWS_ACCESS_KEY_ID"), var("AWS_SECRET_ACCESS_KEY")) {
+            (Some(access_key_id), Some(secret_access_key)) => Ok(Self {
+                access_key_id,
+                secret_access_key,
+                session_token: var("AWS_SESSION_TOKEN"),
+            }),
+            _ => bail!("AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY are needed for CodeCommit"),
+        }
+    }
+}
+
+impl fmt::Debug for AwsCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AwsCredentials")
+            .field("access_key_id", &self.access_key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Client for the CodeCommit API of one region. Requests are signed with Signature Version 4.
+#[derive(Debug)]
+pub struct CodeCommitClient {
+    http: Client,
+    region: String,
+    /// Like `https://codecommit.us-east-1.amazonaws.com`.
+    endpoint: String,
+    credentials: AwsCredentials,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RepositoryMetadata {
+    default_branch: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GetRepositoryOutput {
+    repository_metadata: RepositoryMetadata,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MergeMetadata {
+    #[serde(default)]
+    is_merged: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PullRequestTarget {
+    source_reference: String,
+    destination_reference: String,
+    source_commit: Option<String>,
+    destination_commit: Option<String>,
+    merge_metadata: Option<MergeMetadata>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PullRequest {
+    pull_request_id: String,
+    /// Seconds since the epoch, with a fraction.
+    creation_date: f64,
+    last_activity_date: f64,
+    pull_request_status: String,
+    revision_id: Option<String>,
+    pull_request_targets: Vec<PullRequestTarget>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PullRequestOutputWrapper {
+    pull_request: PullRequest,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ListPullRequestsOutput {
+    pull_request_ids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Evaluation {
+    #[serde(default)]
+    approved: bool,
+    #[serde(default)]
+    overridden: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Approval {
+    user_arn: String,
+    approval_state: String,
+}
+
+impl CodeCommitClient {
+    pub fn new(region: &str, api_url: Option<&str>) -> AnyResult<Self> {
+        let http = Client::builder()
+            .user_agent(crate::github::user_agent(
+                std::env::var("CLU_USER_AGENT_SUFFIX").ok().as_deref(),
+            ))
+            .build()?;
+        let endpoint = match api_url {
+            Some(api_url) => api_url.trim_end_matches('/').to_owned(),
+            None => format!("https://codecommit.{}.amazonaws.com", region),
+        };
+
+        Ok(Self {
+            http,
+            region: region.to_owned(),
+            endpoint,
+            credentials: AwsCredentials::from_env()?,
+        })
+    }
+
+    /// Opens a PR for the branch, or updates the title and description of the one that's
+    /// already open. CodeCommit has no labels, assignees or milestones, and the destination of a
+    /// PR can't be changed once it's open.
+    pub async fn sync_pull_request(
+        &self,
+        repo: &GitHubRepo,
+        description: PullRequestDescription<'_>,
+        existing: Option<i64>,
+    ) -> AnyResult<PullRequestOutput> {
+        if !description.labels.is_empty()
+            || !description.assignees.is_empty()
+            || description.milestone.is_some()
+        {
+            warn!(
+                "{} is on CodeCommit, which has no labels, assignees or milestones for PR's",
+                repo
+            );
+        }
+
+        let source = format!("refs/heads/{}", description.branch);
+        let existing = match existing {
+            Some(id) => Some(id.to_string()),
+            None => self.open_pull_request(repo, &source).await?,
+        };
+
+        let pull = match existing {
+            Some(id) => {
+                info!("Updating PR #{} of {}", id, repo);
+                let _: serde_json::Value = self
+                    .call(
+                        "UpdatePullRequestTitle",
+                        serde_json::json!({ "pullRequestId": id, "title": description.title }),
+                    )
+                    .await?;
+                let updated: PullRequestOutputWrapper = self
+                    .call(
+                        "UpdatePullRequestDescription",
+                        serde_json::json!({ "pullRequestId": id, "description": description.body }),
+                    )
+                    .await?;
+                updated.pull_request
+            }
+            None => {
+                let destination = match description.base {
+                    Some(base) => base.to_owned(),
+                    None => self.default_branch(repo).await?,
+                };
+                info!("Opening a PR on {}", repo);
+                let created: PullRequestOutputWrapper = self
+                    .call(
+                        "CreatePullRequest",
+                        serde_json::json!({
+                            "title": description.title,
+                            "description": description.body,
+                            "targets": [{
+                                "repositoryName": repo.repo,
+                                "sourceReference": source,
+                                "destinationReference": format!("refs/heads/{}", destination),
+                            }],
+                        }),
+                    )
+                    .await?;
+                created.pull_request
+            }
+        };
+
+        if let (Some(base), Some(target)) = (description.base, pull.pull_request_targets.first()) {
+            if short_ref(&target.destination_reference) != base {
+                warn!(
+                    "PR #{} of {} can't be moved to {}, CodeCommit can't change the destination",
+                    pull.pull_request_id, repo, base
+                );
+            }
+        }
+
+        Ok(PullRequestOutput {
+            id: pull.pull_request_id.clone(),
+            number: pull.pull_request_id.parse()?,
+            permalink: self.permalink(repo, &pull.pull_request_id),
+            created_at: format_github_timestamp(pull.creation_date as u64),
+            head_ref: description.branch.to_owned(),
+        })
+    }
+
+    /// CodeCommit has no statuses for PR's, so only merge conflicts and approval rules decide
+    /// the state.
+    pub async fn fetch_pull_state(
+        &self,
+        repo: &GitHubRepo,
+        id: i64,
+        _ignored_checks: &[Glob],
+    ) -> AnyResult<PullState> {
+        let pull = self.pull_request(&id.to_string()).await?;
+
+        let mut mergeable = true;
+        let mut evaluation = None;
+        let mut approvals = Vec::new();
+        if pull.pull_request_status == "OPEN" {
+            if let Some(target) = pull.pull_request_targets.first() {
+                #[derive(Deserialize)]
+                struct MergeConflicts {
+                    mergeable: bool,
+                }
+                let conflicts: MergeConflicts = self
+                    .call(
+                        "GetMergeConflicts",
+                        serde_json::json!({
+                            "repositoryName": repo.repo,
+                            "destinationCommitSpecifier": target.destination_commit,
+                            "sourceCommitSpecifier": target.source_commit,
+                            "mergeOption": "THREE_WAY_MERGE",
+                        }),
+                    )
+                    .await?;
+                mergeable = conflicts.mergeable;
+            }
+
+            if let Some(revision_id) = &pull.revision_id {
+                #[derive(Deserialize)]
+                struct Evaluated {
+                    evaluation: Evaluation,
+                }
+                #[derive(Deserialize)]
+                struct ApprovalStates {
+                    approvals: Vec<Approval>,
+                }
+                let request = serde_json::json!({
+                    "pullRequestId": pull.pull_request_id,
+                    "revisionId": revision_id,
+                });
+                let evaluated: Evaluated = self
+                    .call("EvaluatePullRequestApprovalRules", request.clone())
+                    .await?;
+                evaluation = Some(evaluated.evaluation);
+                let states: ApprovalStates =
+                    self.call("GetPullRequestApprovalStates", request).await?;
+                approvals = states.approvals;
+            }
+        }
+
+        let permalink = self.permalink(repo, &pull.pull_request_id);
+        Ok(pull_state(
+            pull, permalink, mergeable, evaluation, approvals,
+        ))
+    }
+
+    pub async fn add_comment(&self, repo: &GitHubRepo, id: i64, body: &str) -> AnyResult<()> {
+        let pull = self.pull_request(&id.to_string()).await?;
+        let target = match pull.pull_request_targets.first() {
+            Some(target) => target,
+            None => bail!("PR #{} of {} has no commits to comment on", id, repo),
+        };
+
+        let _: serde_json::Value = self
+            .call(
+                "PostCommentForPullRequest",
+                serde_json::json!({
+                    "pullRequestId": pull.pull_request_id,
+                    "repositoryName": repo.repo,
+                    "beforeCommitId": target.destination_commit,
+                    "afterCommitId": target.source_commit,
+                    "content": body,
+                }),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// CodeCommit repos can't be archived. Whether the credentials can push isn't known up
+    /// front, a push that's refused fails the push.
+    pub async fn fetch_repo_access(&self, repo: &GitHubRepo) -> AnyResult<RepoAccess> {
+        self.repository(repo).await?;
+
+        Ok(RepoAccess {
+            archived: false,
+            can_push: true,
+        })
+    }
+
+    async fn repository(&self, repo: &GitHubRepo) -> AnyResult<GetRepositoryOutput> {
+        self.call(
+            "GetRepository",
+            serde_json::json!({ "repositoryName": repo.repo }),
+        )
+        .await
+    }
+
+    async fn default_branch(&self, repo: &GitHubRepo) -> AnyResult<String> {
+        match self
+            .repository(repo)
+            .await?
+            .repository_metadata
+            .default_branch
+        {
+            Some(branch) => Ok(branch),
+            None => bail!("{} has no default branch", repo),
+        }
+    }
+
+    async fn pull_request(&self, id: &str) -> AnyResult<PullRequest> {
+        let pull: PullRequestOutputWrapper = self
+            .call("GetPullRequest", serde_json::json!({ "pullRequestId": id }))
+            .await?;
+
+        Ok(pull.pull_request)
+    }
+
+    /// Open PR's can only be listed by repo, so each is read to find the one of the branch.
+    async fn open_pull_request(
+        &self,
+        repo: &GitHubRepo,
+        source: &str,
+    ) -> AnyResult<Option<String>> {
+        let open: ListPullRequestsOutput = self
+            .call(
+                "ListPullRequests",
+                serde_json::json!({ "repositoryName": repo.repo, "pullRequestStatus": "OPEN" }),
+            )
+            .await?;
+
+        for id in open.pull_request_ids {
+            let pull = self.pull_request(&id).await?;
+            if pull
+                .pull_request_targets
+                .iter()
+                .any(|it| it.source_reference == source)
+            {
+                return Ok(Some(id));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn permalink(&self, repo: &GitHubRepo, id: &str) -> String {
+        format!(
+            "https://{region}.console.aws.amazon.com/codesuite/codecommit/repositories/{}/pull-requests/{}/details?region={region}",
+            repo.repo,
+            id,
+            region = self.region
+        )
+    }
+
+    async fn call<T: DeserializeOwned>(
+        &self,
+        action: &str,
+        body: serde_json::Value,
+    ) -> AnyResult<T> {
+        let payload = serde_json::to_vec(&body)?;
+        let amz_date = amz_date(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs());
+        let host = self
+            .endpoint
+            .split("://")
+            .last()
+            .unwrap_or_default()
+            .to_owned();
+
+        let mut headers = vec![
+            ("content-type", JSON_CONTENT_TYPE.to_owned()),
+            ("host", host),
+            ("x-amz-date", amz_date.clone()),
+            ("x-amz-target", format!("{}.{}", API_VERSION_TARGET, action)),
+        ];
+        if let Some(token) = &self.credentials.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let authorization = sign_request(
+            &self.credentials,
+            &self.region,
+            "codecommit",
+            "POST",
+            &headers,
+            &payload,
+        )?;
+
+        let mut request = self
+            .http
+            .post(&self.endpoint)
+            .header(reqwest::header::AUTHORIZATION, authorization);
+        for (name, value) in headers.iter().filter(|(name, _)| *name != "host") {
+            request = request.header(*name, value);
+        }
+        let response = request.body(payload).send().await?;
+        let status = response.status();
+        let body: serde_json::Value = response.json().await?;
+        debug!("CodeCommit Response: {:?}", body);
+
+        if !status.is_success() {
+            bail!("CodeCommit responded {}: {}", status, body);
+        }
+
+        Ok(serde_json::from_value(body)?)
+    }
+}
+
+/// The Signature Version 4 `Authorization` header for a request to `/` with `headers`, which
+/// need an `x-amz-date`.
+fn sign_request(
+    credentials: &AwsCredentials,
+    region: &str,
+    service: &str,
+    method: &str,
+    headers: &[(&str, String)],
+    payload: &[u8],
+) -> AnyResult<String> {
+    let mut headers: Vec<(String, &str)> = headers
+        .iter()
+        .map(|(name, value)| (name.to_lowercase(), value.trim()))
+        .collect();
+    headers.sort();
+    let amz_date = match headers.iter().find(|(name, _)| name == "x-amz-date") {
+        Some((_, amz_date)) => amz_date.to_string(),
+        None => bail!("Requests to AWS need an x-amz-date header to be signed"),
+    };
+
+    let canonical_headers: Vec<String> = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value))
+        .collect();
+    let canonical_headers = canonical_headers.concat();
+    let signed_headers: Vec<&str> = headers.iter().map(|(name, _)| name.as_str()).collect();
+    let signed_headers = signed_headers.join(";");
+    let canonical_request = format!(
+        "{}\n/\n\n{}\n{}\n{}",
+        method,
+        canonical_headers,
+        signed_headers,
+        hex(&openssl::sha::sha256(payload))
+    );
+
+    let date = &amz_date[..8];
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex(&openssl::sha::sha256(canonical_request.as_bytes()))
+    );
+
+    let mut key = hmac(
+        format!("AWS4{}", credentials.secret_access_key).as_bytes(),
+        date.as_bytes(),
+    )?;
+    for part in [region, service, "aws4_request"] {
+        key = hmac(&key, part.as_bytes())?;
+    }
+    let signature = hex(&hmac(&key, string_to_sign.as_bytes())?);
+
+    Ok(format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        credentials.access_key_id, scope, signed_headers, signature
+    ))
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> AnyResult<Vec<u8>> {
+    let key = PKey::hmac(key)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    signer.update(data)?;
+
+    Ok(signer.sign_to_vec()?)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let digits: Vec<String> = bytes.iter().map(|it| format!("{:02x}", it)).collect();
+    digits.concat()
+}
+
+/// Like `20150830T123600Z`.
+fn amz_date(seconds: u64) -> String {
+    format_github_timestamp(seconds).replace(['-', ':'], "")
+}
+
+fn short_ref(name: &str) -> &str {
+    name.strip_prefix("refs/heads/").unwrap_or(name)
+}
+
+fn pull_state(
+    pull: PullRequest,
+    permalink: String,
+    mergeable: bool,
+    evaluation: Option<Evaluation>,
+    approvals: Vec<Approval>,
+) -> PullState {
+    let target = pull.pull_request_targets.first();
+    let merged = target
+        .and_then(|it| it.merge_metadata.as_ref())
+        .map_or(false, |it| it.is_merged);
+    let approved = evaluation.map_or(true, |it| it.approved || it.overridden);
+
+    let status = match pull.pull_request_status.as_str() {
+        "CLOSED" if merged => PullStatus::Merged,
+        "CLOSED" => PullStatus::ClosedWithoutMerge,
+        _ if !mergeable => PullStatus::Conflicting,
+        _ if !approved => PullStatus::NeedsApproval,
+        _ => PullStatus::Mergeable,
+    };
+
+    let closed_at = match pull.pull_request_status.as_str() {
+        "CLOSED" => Some(format_github_timestamp(pull.last_activity_date as u64)),
+        _ => None,
+    };
+
+    PullState {
+        id: pull.pull_request_id.clone(),
+        status,
+        permalink,
+        created_at: format_github_timestamp(pull.creation_date as u64),
+        closed_at,
+        base_ref: target
+            .map(|it| short_ref(&it.destination_reference).to_owned())
+            .unwrap_or_default(),
+        checks: Vec::new(),
+        head_oid: target
+            .and_then(|it| it.source_commit.clone())
+            .unwrap_or_default(),
+        head_ref_id: None,
+        behind: false,
+        required_checks: Vec::new(),
+        reviews: PullReviews {
+            approved_by: approvals
+                .into_iter()
+                .filter(|it| it.approval_state == "APPROVE")
+                .map(|it| match it.user_arn.rsplit_once('/') {
+                    Some((_, user)) => user.to_owned(),
+                    None => it.user_arn,
+                })
+                .collect(),
+            ..PullReviews::default()
+        },
+    }
+}
+
+/// CodeCommit's Git endpoints are `git-codecommit.<region>.amazonaws.com`.
+pub fn codecommit_region(host: &str) -> Option<&str> {
+    host.strip_prefix("git-codecommit.")?
+        .strip_suffix(".amazonaws.com")
+        .filter(|it| !it.contains('.'))
+}
+
+/// Reads the HTTPS and SSH clone URLs of CodeCommit, and the `codecommit::<region>://` ones of
+/// `git-remote-codecommit`. The region is the owner of the repo.
+pub(crate) fn extract_codecommit_info(url: &str) -> Option<GitHubRepo> {
+    let patterns = [
+        r"^(https|ssh)://([^@/]+@)?git-codecommit\.(?P<region>[a-z0-9-]+)\.amazonaws\.com(:\d+)?/v1/repos/(?P<repo>[^/]+?)/?$",
+        r"^codecommit::(?P<region>[a-z0-9-]+)://([^@/]+@)?(?P<repo>[^/@]+)$",
+    ];
+
+    patterns.iter().find_map(|pattern| {
+        let captures = Regex::new(pattern).unwrap().captures(url)?;
+        Some(GitHubRepo {
+            host: format!("git-codecommit.{}.amazonaws.com", &captures["region"]),
+            owner: captures["region"].to_owned(),
+            repo: captures["repo"].to_owned(),
+            clone_url: url.to_owned(),
+        })
+    })
+}
+
+#[test]
+fn validate_extract_codecommit_info() {
+    for url in [
+        "https://git-codecommit.us-east-2.amazonaws.com/v1/repos/clu",
+        "ssh://APKAEIBAERJR2EXAMPLE@git-codecommit.us-east-2.amazonaws.com/v1/repos/clu",
+        "codecommit::us-east-2://clu",
+        "codecommit::us-east-2://deploy@clu",
+    ] {
+        let repo = extract_codecommit_info(url).unwrap();
+        assert_eq!(
+            "git-codecommit.us-east-2.amazonaws.com", repo.host,
+            "{}",
+            url
+        );
+        assert_eq!("us-east-2", repo.owner, "{}", url);
+        assert_eq!("clu", repo.repo, "{}", url);
+    }
+    assert!(extract_codecommit_info("git@github.com:ethankhall/clu.git").is_none());
+    assert_eq!(
+        Some("us-east-2"),
+        codecommit_region("git-codecommit.us-east-2.amazonaws.com")
+    );
+    assert_eq!(None, codecommit_region("github.com"));
+}
+
+#[test]
+fn validate_sign_request() {
+    // The `get-vanilla` case of the AWS Signature Version 4 test suite
+    let credentials = AwsCredentials {
+        access_key_id: "AKIDEXAMPLE".to_owned(),
+        secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_owned(),
+        session_token: None,
+    };
+    let headers = [
+        ("Host", "example.amazonaws.com".to_owned()),
+        ("X-Amz-Date", "20150830T123600Z".to_owned()),
+    ];
+
+    assert_eq!(
+        "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, SignedHeaders=host;x-amz-date, Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31",
+        sign_request(&credentials, "us-east-1", "service", "GET", &headers, b"").unwrap()
+    );
+    assert_eq!("20150830T123600Z", amz_date(1440938160));
+}
+
+#[test]
+fn validate_pull_state() {
+    let pull = |status: &str, merged: bool| -> PullRequest {
+        serde_json::from_value(serde_json::json!({
+            "pullRequestId": "7", "creationDate": 1616347800.5, "lastActivityDate": 1616347900.0,
+            "pullRequestStatus": status, "revisionId": "abc",
+            "pullRequestTargets": [{
+                "sourceReference": "refs/heads/migration", "destinationReference": "refs/heads/main",
+                "sourceCommit": "def", "destinationCommit": "123",
+                "mergeMetadata": { "isMerged": merged },
+            }],
+        }))
+        .unwrap()
+    };
+    let evaluation = |approved: bool| Evaluation {
+        approved,
+        overridden: false,
+    };
+
+    let state = pull_state(pull("OPEN", false), String::new(), true, None, Vec::new());
+    assert_eq!(PullStatus::Mergeable, state.status);
+    assert_eq!("main", state.base_ref);
+    assert_eq!("2021-03-21T17:30:00Z", state.created_at);
+    assert_eq!(
+        PullStatus::Conflicting,
+        pull_state(pull("OPEN", false), String::new(), false, None, Vec::new()).status
+    );
+    let approvals = vec![Approval {
+        user_arn: "arn:aws:iam::123456789012:user/ethan".to_owned(),
+        approval_state: "APPROVE".to_owned(),
+    }];
+    let state = pull_state(
+        pull("OPEN", false),
+        String::new(),
+        true,
+        Some(evaluation(false)),
+        approvals,
+    );
+    assert_eq!(PullStatus::NeedsApproval, state.status);
+    assert_eq!(vec!["ethan".to_owned()], state.reviews.approved_by);
+    assert_eq!(
+        PullStatus::Merged,
+        pull_state(pull("CLOSED", true), String::new(), true, None, Vec::new()).status
+    );
+    assert_eq!(
+        PullStatus::ClosedWithoutMerge,
+        pull_state(
+            pull("CLOSED", false),
+            String::new(),
+            true,
+            Some(evaluation(true)),
+            Vec::new()
+        )
+        .status
+    );
+}
@@ -0,0 +1,128 @@
+use clap::Args;
+
+use std::collections::BTreeMap;
+
+use anyhow::Result as AnyResult;
+use tracing::{info, warn};
+
+use crate::filter::TargetFilter;
+use crate::github::{GitHubHosts, PullStatus};
+use crate::models::*;
+
+#[derive(Args, Debug)]
+pub struct ApproveArgs {
+    /// A TOML file that was used with `run-migration`.
+    #[clap(long)]
+    pub migration_definition: String,
+
+    /// Token used to look up the PR's.
+    #[clap(long, env = "GITHUB_TOKEN")]
+    pub github_token: String,
+
+    /// Token of the account that approves the PR's. GitHub doesn't allow approving your own
+    /// PR, so this has to be a different account than the one that opened them.
+    #[clap(long, env = "CLU_APPROVER_TOKEN")]
+    pub approver_token: String,
+
+    /// Comment to include with the approval.
+    #[clap(long)]
+    pub body: Option<String>,
+
+    #[clap(flatten)]
+    pub filter: TargetFilter,
+}
+
+pub async fn run_approve(args: ApproveArgs) -> AnyResult<()> {
+    let migration_input: MigrationFile = crate::state::load_migration(&args.migration_definition)?;
+    let hosts = &migration_input.host_configs();
+    let github_hosts = GitHubHosts::new(&args.github_token, hosts)?;
+    // The approver token is used on every host, instead of the tokens of the migration
+    let approver_hosts: BTreeMap<String, HostConfig> = hosts
+        .iter()
+        .map(|(host, config)| {
+            let config = HostConfig {
+                token_env: None,
+                ..config.clone()
+            };
+            (host.clone(), config)
+        })
+        .collect();
+    let approver_hosts = GitHubHosts::new(&args.approver_token, &approver_hosts)?;
+
+    let mut names = Vec::new();
+    let mut pulls = Vec::new();
+    for (name, target) in &migration_input.targets {
+        if !args.filter.matches(name, target) {
+            continue;
+        }
+        if let Some(pull) = &target.pull_request {
+            names.push(name);
+            pulls.push((pull.github_repo(&target.repo)?, pull.pr_number));
+        }
+    }
+
+    let states = github_hosts
+        .fetch_pull_states(&pulls, &migration_input.definition.ignored_check_patterns())
+        .await?;
+
+    let mut approved: Vec<String> = Vec::new();
+    let mut skipped: Vec<String> = Vec::new();
+    let mut failed: Vec<String> = Vec::new();
+
+    for ((name, (repo, _)), state) in names.iter().zip(pulls.iter()).zip(states.iter()) {
+        if state.status == PullStatus::Merged {
+            skipped.push(format!("- {} (merged)", state.permalink));
+            continue;
+        }
+        if state.status == PullStatus::ClosedWithoutMerge {
+            skipped.push(format!("- {} (closed)", state.permalink));
+            continue;
+        }
+        if state.status == PullStatus::Missing {
+            skipped.push(format!("- {} (missing)", state.permalink));
+            continue;
+        }
+
+        let approver = match approver_hosts.client_for(repo) {
+            Ok(approver) => approver,
+            Err(e) => {
+                warn!("{}: unable to approve {}: {}", name, state.permalink, e);
+                failed.push(format!("- {}: {}", state.permalink, e));
+                continue;
+            }
+        };
+        match approver
+            .approve_pull_request(&state.id, args.body.as_deref())
+            .await
+        {
+            Ok(review) => {
+                info!("{}: approved {}", name, state.permalink);
+                approved.push(format!("- {}", review));
+            }
+            Err(e) => {
+                warn!("{}: unable to approve {}: {:?}", name, state.permalink, e);
+                failed.push(format!("- {}: {}", state.permalink, e));
+            }
+        }
+    }
+
+    println!(
+        "# Approvals
+## Approved
+
+{}
+
+## Skipped
+
+{}
+
+## Failed
+
+{}",
+        approved.join("\n"),
+        skipped.join("\n"),
+        failed.join("\n")
+    );
+
+    Ok(())
+}
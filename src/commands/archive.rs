@@ -0,0 +1,162 @@
+use clap::Args;
+
+use std::fs::{create_dir_all, read, remove_dir_all, write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::Result as AnyResult;
+use tracing::info;
+
+use crate::filter::TargetFilter;
+use crate::models::*;
+
+/// What redacted values are replaced with.
+const REDACTED: &str = "[redacted]";
+
+/// Environment variables whose names contain one of these are redacted by `--redact`.
+const SECRET_NAME_PARTS: [&str; 4] = ["TOKEN", "SECRET", "PASSWORD", "KEY"];
+
+#[derive(Args, Debug)]
+pub struct ArchiveArgs {
+    /// A TOML file that was used with `run-migration`. It isn't changed.
+    #[clap(long)]
+    pub migration_definition: String,
+
+    /// Folder the migration was run in, the logs of the targets are read from it.
+    #[clap(long = "work-directory", default_value("work-dir"))]
+    pub work_directory_root: String,
+
+    /// Where to write the archive. Defaults to `clu-archive-<timestamp>.tar.gz`.
+    #[clap(long)]
+    pub output_file: Option<String>,
+
+    /// Replace the values of environment variables that look like secrets, like
+    /// `GITHUB_TOKEN`, wherever they show up in the archived files.
+    #[clap(long)]
+    pub redact: bool,
+
+    /// Replace the value of this environment variable too, can be given more than once.
+    #[clap(long = "redact-env")]
+    pub redact_env: Vec<String>,
+
+    #[clap(flatten)]
+    pub filter: TargetFilter,
+}
+
+/// Packs up what's known about a migration for an audit or a postmortem: the migration with its
+/// state, its run history, the error summaries, and the logs of each target.
+pub async fn run_archive(args: ArchiveArgs) -> AnyResult<()> {
+    let migration_input: MigrationFile = crate::state::load_migration(&args.migration_definition)?;
+
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs();
+    let name = format!("clu-archive-{}", file_timestamp(now));
+    let output_file = match &args.output_file {
+        Some(output_file) => PathBuf::from(output_file),
+        None => PathBuf::from(format!("{}.tar.gz", name)),
+    };
+    let staging = std::env::temp_dir()
+        .join(format!("clu-archive-{}", std::process::id()))
+        .join(&name);
+    create_dir_all(&staging)?;
+
+    let mut secrets = Vec::new();
+    for (key, value) in std::env::vars() {
+        let looks_secret = args.redact && SECRET_NAME_PARTS.iter().any(|it| key.contains(it));
+        if looks_secret || args.redact_env.contains(&key) {
+            secrets.push(value);
+        }
+    }
+    let copy = |from: &Path, to: &str| -> AnyResult<bool> {
+        if !from.is_file() {
+            return Ok(false);
+        }
+        let text = String::from_utf8_lossy(&read(from)?).into_owned();
+        let to = staging.join(to);
+        if let Some(parent) = to.parent() {
+            create_dir_all(parent)?;
+        }
+        write(to, redact(&text, &secrets))?;
+        Ok(true)
+    };
+
+    // The state kept in a `state-dir` is put back into the migration, so it's one file
+    let file_name = Path::new(&args.migration_definition)
+        .file_name()
+        .map(|it| it.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "migration.toml".to_owned());
+    write(
+        staging.join(&file_name),
+        redact(&toml::to_string_pretty(&migration_input)?, &secrets),
+    )?;
+    for sidecar in ["history", "checkpoint"] {
+        copy(
+            Path::new(&format!("{}.{}", args.migration_definition, sidecar)),
+            &format!("{}.{}", file_name, sidecar),
+        )?;
+    }
+    for summary in ["migration.errors.txt", "migration.no-changes.txt"] {
+        copy(Path::new(summary), summary)?;
+    }
+
+    let work_dir = Path::new(&args.work_directory_root);
+    copy(&work_dir.join("clu.log"), "logs/clu.log")?;
+    let mut logged = 0;
+    for (target, description) in &migration_input.targets {
+        if !args.filter.matches(target, description) {
+            continue;
+        }
+        let mut found = false;
+        for log in ["stdout.log", "stderr.log"] {
+            found |= copy(
+                &work_dir.join(target).join(log),
+                &format!("logs/{}/{}", target, log),
+            )?;
+        }
+        if found {
+            logged += 1;
+        }
+    }
+
+    let archived = crate::workspace::snapshot_workspace(&staging, &output_file, true).await;
+    remove_dir_all(staging.parent().unwrap_or(&staging))?;
+    archived?;
+
+    info!(
+        "Archived {} with the logs of {} targets into {}",
+        args.migration_definition,
+        logged,
+        output_file.display()
+    );
+    println!("{}", output_file.display());
+
+    Ok(())
+}
+
+/// Like `2024-03-01T12:00:00Z`, without the characters that don't belong in a file name.
+fn file_timestamp(seconds: u64) -> String {
+    crate::github::format_github_timestamp(seconds).replace(['-', ':'], "")
+}
+
+/// Very short values would be replaced in unrelated places, so they're left alone.
+fn redact(text: &str, secrets: &[String]) -> String {
+    let mut text = text.to_owned();
+    for secret in secrets {
+        if secret.len() >= 4 {
+            text = text.replace(secret.as_str(), REDACTED);
+        }
+    }
+
+    text
+}
+
+#[test]
+fn validate_redact() {
+    let secrets = vec!["ghp_abcdef".to_owned(), "no".to_owned()];
+    assert_eq!(
+        "token=[redacted] no change",
+        redact("token=ghp_abcdef no change", &secrets)
+    );
+    assert_eq!("20240301T120000Z", file_timestamp(1709294400));
+}
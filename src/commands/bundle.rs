@@ -0,0 +1,210 @@
+use clap::Args;
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result as AnyResult;
+use futures::stream::{self, StreamExt};
+use tracing::{info, warn};
+
+use crate::filter::TargetFilter;
+use crate::github::{CloneProtocol, GitHubHosts};
+use crate::migration::MigrationError;
+use crate::models::*;
+use crate::steps::{bundle_path, ImportBundleStep, UpdateGithubStep};
+use crate::steps::{MigrationStep, MigrationStepResult};
+use crate::workspace::Workspace;
+
+#[derive(Args, Debug)]
+pub struct PublishBundlesArgs {
+    /// A TOML file that defines the input needed to run a migration. This file will be updated
+    /// with the results of the run.
+    #[clap(long)]
+    pub migration_definition: String,
+
+    /// Token to be used when talking to GitHub
+    #[clap(long, env = "GITHUB_TOKEN")]
+    pub github_token: String,
+
+    /// Folder where the work will take place
+    #[clap(long = "work-directory", default_value("publish-dir"))]
+    pub work_directory_root: String,
+
+    /// Directory containing the bundles created by `run-migration --export-bundles`
+    #[clap(long)]
+    pub bundle_dir: String,
+
+    /// Push over this protocol, overriding `clone-protocol` from the migration definition.
+    #[clap(long, value_enum, env = "CLU_CLONE_PROTOCOL")]
+    pub clone_protocol: Option<CloneProtocol>,
+
+    /// Go ahead without asking to confirm first, like in CI.
+    #[clap(long, short = 'y')]
+    pub yes: bool,
+
+    #[clap(flatten)]
+    pub filter: TargetFilter,
+}
+
+pub async fn run_publish_bundles(args: PublishBundlesArgs) -> AnyResult<()> {
+    let mut migration_input: MigrationFile =
+        crate::state::load_migration(&args.migration_definition)?;
+
+    let mut definition = migration_input.definition.clone();
+    if args.clone_protocol.is_some() {
+        definition.clone_protocol = args.clone_protocol;
+    }
+    definition.checkout.branch_name = definition.checkout.head_branch(&args.migration_definition);
+
+    let github_hosts = GitHubHosts::new(&args.github_token, &migration_input.host_configs())?
+        .with_max_prs_per_hour(definition.pr.max_prs_per_hour);
+    let bundle_dir = PathBuf::from(&args.bundle_dir);
+    std::fs::create_dir_all(&args.work_directory_root)?;
+    let target_dir = PathBuf::from(&args.work_directory_root).canonicalize()?;
+
+    let mut work_queue = Vec::new();
+    for (name, target) in &migration_input.targets {
+        if !args.filter.matches(name, target) {
+            continue;
+        }
+        if target.skip {
+            continue;
+        }
+        let push_only = target.forge == Some(Forge::None)
+            || target.is_local()
+            || target
+                .github_repo()
+                .map_or(false, |it| github_hosts.is_push_only(&it));
+        if push_only {
+            warn!(
+                "{} is push-only, which bundles can't be published for",
+                name
+            );
+            continue;
+        }
+
+        if !bundle_path(&bundle_dir, name).exists() {
+            info!("{} has no bundle, skipping", name);
+            continue;
+        }
+
+        work_queue.push(WorkTask {
+            repo_name: name.clone(),
+            github_hosts: &github_hosts,
+            definition: &definition,
+            target,
+            bundle_dir: &bundle_dir,
+            target_dir: &target_dir,
+        });
+    }
+
+    crate::prompt::confirm(
+        &format!(
+            "About to force push `{}` to {} repos and open PR's titled \"{}\", from {}",
+            definition.checkout.branch_name,
+            work_queue.len(),
+            definition.pr.title,
+            args.migration_definition
+        ),
+        args.yes,
+    )?;
+
+    let results: Vec<(String, MigrationStepResult<CreatedPullRequest>)> = stream::iter(work_queue)
+        .map(|task| async move { (task.repo_name.clone(), task.publish().await) })
+        .buffer_unordered(3)
+        .collect()
+        .await;
+
+    for (name, status) in results {
+        match status.result {
+            Ok(pr) => {
+                info!("{} published {}", name, pr.url);
+                migration_input.targets.get_mut(&name).unwrap().pull_request = Some(pr);
+            }
+            Err(e) => warn!("{} was not published: {}", name, e),
+        }
+    }
+
+    crate::state::save_migration(&args.migration_definition, &migration_input)?;
+
+    Ok(())
+}
+
+struct WorkTask<'a> {
+    repo_name: String,
+    github_hosts: &'a GitHubHosts,
+    definition: &'a MigrationDefinition,
+    target: &'a TargetDescription,
+    bundle_dir: &'a Path,
+    target_dir: &'a Path,
+}
+
+impl<'a> WorkTask<'a> {
+    async fn publish(&self) -> MigrationStepResult<CreatedPullRequest> {
+        let definition = self.definition.with_metadata(&self.target.metadata);
+        let github_repo = match self.target.github_repo() {
+            Ok(github_repo) => match self.definition.clone_protocol {
+                Some(protocol) if !github_repo.host.is_empty() => {
+                    github_repo.with_protocol(protocol)
+                }
+                _ => github_repo,
+            },
+            Err(e) => {
+                return MigrationStepResult::failure(
+                    "invalid-url",
+                    MigrationError::InvalidGitRepo { source: e },
+                )
+            }
+        };
+
+        let forge = match self.github_hosts.forge_for(&github_repo) {
+            Ok(forge) => forge,
+            Err(e) => {
+                return MigrationStepResult::failure(
+                    "invalid-url",
+                    MigrationError::InvalidGitRepo { source: e },
+                )
+            }
+        };
+
+        let mut workspace = match Workspace::new_clean_workspace(&self.repo_name, self.target_dir) {
+            Ok(workspace) => workspace,
+            Err(e) => return MigrationStepResult::failure("workspace", MigrationError::IoError(e)),
+        };
+
+        let status = ImportBundleStep::new(
+            &definition.checkout.branch_name,
+            self.bundle_dir,
+            &github_repo,
+        )
+        .execute_step(&mut workspace)
+        .await;
+        if let Err(e) = status.result {
+            return MigrationStepResult::failure(status.name, e);
+        }
+
+        let body = match crate::template::render_pr_body(
+            &definition,
+            &github_repo,
+            &self.repo_name,
+            self.target.metrics.as_ref().and_then(|it| it.diff.as_ref()),
+            &self.target.metadata,
+        ) {
+            Ok(body) => body,
+            Err(e) => {
+                return MigrationStepResult::failure("pr-template", MigrationError::IoError(e))
+            }
+        };
+
+        UpdateGithubStep::new(
+            forge,
+            &github_repo,
+            self.target.pull_request.clone(),
+            &definition,
+        )
+        .with_body(&body)
+        .with_metadata(&self.target.metadata)
+        .with_base(self.target.base_ref.as_deref())
+        .execute_step(&mut workspace)
+        .await
+    }
+}
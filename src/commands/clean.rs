@@ -0,0 +1,208 @@
+use clap::Args;
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result as AnyResult;
+use tracing::warn;
+
+use crate::filter::TargetFilter;
+use crate::models::*;
+
+#[derive(Args, Debug)]
+pub struct CleanArgs {
+    /// A TOML file that was used with `run-migration`.
+    #[clap(long)]
+    pub migration_definition: String,
+
+    /// Folder the migration was run in.
+    #[clap(long = "work-directory", default_value("work-dir"))]
+    pub work_directory_root: String,
+
+    /// How many of the newest backups of the migration file are kept.
+    #[clap(long, default_value = "5")]
+    pub keep_backups: usize,
+
+    /// Actually delete the workspaces and backups. Without this only what would be deleted is
+    /// printed.
+    #[clap(long)]
+    pub delete: bool,
+
+    #[clap(flatten)]
+    pub filter: TargetFilter,
+}
+
+/// Removes the workspaces of the targets that are done with, and the old backups
+/// `run-migration` makes of the migration file.
+pub async fn run_clean(args: CleanArgs) -> AnyResult<()> {
+    let migration_input: MigrationFile = crate::state::load_migration(&args.migration_definition)?;
+
+    let mut removable = Vec::new();
+    for (name, target) in &migration_input.targets {
+        if !args.filter.matches(name, target) || !is_done(target) {
+            continue;
+        }
+
+        let workspace = Path::new(&args.work_directory_root).join(name);
+        if workspace.is_dir() {
+            removable.push(workspace);
+        }
+    }
+    removable.extend(old_backups(&args.migration_definition, args.keep_backups)?);
+
+    let mut reclaimed = 0;
+    let mut entries = Vec::new();
+    for path in &removable {
+        let size = disk_usage(path);
+        if args.delete {
+            let removed = match path.is_dir() {
+                true => std::fs::remove_dir_all(path),
+                false => std::fs::remove_file(path),
+            };
+            if let Err(e) = removed {
+                warn!("Unable to delete {}: {}", path.display(), e);
+                continue;
+            }
+        }
+
+        reclaimed += size;
+        entries.push(format!("- {} ({})", path.display(), format_bytes(size)));
+    }
+
+    let verb = match args.delete {
+        true => "Reclaimed",
+        false => "Would reclaim",
+    };
+    println!(
+        "# Cleaned\n\n{}\n\n{} {}",
+        entries.join("\n"),
+        verb,
+        format_bytes(reclaimed)
+    );
+
+    Ok(())
+}
+
+/// The workspace of a target is only kept while it can still be needed: to look into a failure,
+/// or for the PR that's still open. Branches that were only pushed, or written as patches, are
+/// done once the run worked. Local targets keep their branch in the workspace, so theirs
+/// are never removed.
+fn is_done(target: &TargetDescription) -> bool {
+    if target.is_local() {
+        return false;
+    }
+
+    let pull_closed = target
+        .pull_request
+        .as_ref()
+        .and_then(|it| it.state.as_ref())
+        .map_or(false, |it| it.is_closed());
+    let last_outcome = target.last_run.as_ref().map(|it| &it.outcome);
+    let nothing_to_do = target.pull_request.is_none() && last_outcome == Some(&RunOutcome::Stopped);
+    let published = (target.pushed.is_some() || target.patches.is_some())
+        && last_outcome == Some(&RunOutcome::Succeeded);
+
+    pull_closed || nothing_to_do || published
+}
+
+/// The `<migration>.<epoch>.bck` files, except for the `keep` newest.
+fn old_backups(migration_definition: &str, keep: usize) -> AnyResult<Vec<PathBuf>> {
+    let path = Path::new(migration_definition);
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let prefix = format!(
+        "{}.",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    );
+
+    let mut backups = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let file_name = entry?.file_name().to_string_lossy().into_owned();
+        let epoch = file_name
+            .strip_prefix(&prefix)
+            .and_then(|it| it.strip_suffix(".bck"))
+            .and_then(|it| it.parse::<u64>().ok());
+        if let Some(epoch) = epoch {
+            backups.push((epoch, dir.join(file_name)));
+        }
+    }
+    backups.sort();
+
+    let old = backups.len().saturating_sub(keep);
+    Ok(backups
+        .into_iter()
+        .take(old)
+        .map(|(_, path)| path)
+        .collect())
+}
+
+/// Bytes used by a file, or everything under a directory. Links aren't followed.
+fn disk_usage(path: &Path) -> u64 {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return 0,
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+
+    match std::fs::read_dir(path) {
+        Ok(entries) => entries
+            .filter_map(|it| it.ok())
+            .map(|it| disk_usage(&it.path()))
+            .sum(),
+        Err(_) => 0,
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[test]
+fn validate_clean() {
+    let mut target = TargetDescription::new("git@github.com:ethankhall/clu.git");
+    assert!(!is_done(&target));
+    target.last_run = Some(RunResult {
+        outcome: RunOutcome::Stopped,
+        step: "pre-flight".to_owned(),
+        error: None,
+        error_kind: None,
+    });
+    assert!(is_done(&target));
+    assert!(!is_done(&TargetDescription::new("./clu")));
+
+    let root = std::env::temp_dir().join(format!("clu-clean-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    for epoch in [1, 2, 10] {
+        std::fs::write(root.join(format!("migration.toml.{}.bck", epoch)), "").unwrap();
+    }
+    std::fs::write(root.join("migration.toml"), "1234").unwrap();
+
+    let migration = root.join("migration.toml");
+    let backups = old_backups(migration.to_str().unwrap(), 1).unwrap();
+    assert_eq!(
+        vec![
+            root.join("migration.toml.1.bck"),
+            root.join("migration.toml.2.bck")
+        ],
+        backups
+    );
+    assert_eq!(4, disk_usage(&root));
+    std::fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!("512 B", format_bytes(512));
+    assert_eq!("1.5 MiB", format_bytes(1024 * 1536));
+}
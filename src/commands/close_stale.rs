@@ -0,0 +1,197 @@
+use clap::Args;
+
+use std::time::SystemTime;
+
+use anyhow::Result as AnyResult;
+use tracing::{info, warn};
+
+use crate::filter::TargetFilter;
+use crate::github::{parse_github_timestamp, GitHubHosts, PullRequestOutput, PullStatus};
+use crate::models::*;
+
+#[derive(Args, Debug)]
+pub struct CloseStaleArgs {
+    /// A TOML file that was used with `run-migration`. With `--forget` the closed PR's are
+    /// removed from it.
+    #[clap(long)]
+    pub migration_definition: String,
+
+    /// Token to be used when talking to GitHub
+    #[clap(long, env = "GITHUB_TOKEN")]
+    pub github_token: String,
+
+    /// PR's with failing checks that have been open for longer than this are closed.
+    #[clap(long, default_value("30"))]
+    pub days: u64,
+
+    /// Markdown comment to post before closing each PR, with the same placeholders as
+    /// `clu comment`.
+    #[clap(long)]
+    pub comment: Option<String>,
+
+    /// Forget the PR's that were closed, so the next `run-migration` opens fresh ones.
+    #[clap(long)]
+    pub forget: bool,
+
+    /// Only list the PR's that would be closed.
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// Go ahead without asking to confirm first, like in CI.
+    #[clap(long, short = 'y')]
+    pub yes: bool,
+
+    #[clap(flatten)]
+    pub filter: TargetFilter,
+}
+
+/// Closes the PR's that have been failing for so long nobody is going to fix them. PR's on
+/// other forges than GitHub are listed as failed.
+pub async fn run_close_stale(args: CloseStaleArgs) -> AnyResult<()> {
+    let mut migration_input: MigrationFile =
+        crate::state::load_migration(&args.migration_definition)?;
+    let github_hosts = GitHubHosts::new(&args.github_token, &migration_input.host_configs())?;
+
+    let mut names = Vec::new();
+    let mut pulls = Vec::new();
+    for (name, target) in &migration_input.targets {
+        if !args.filter.matches(name, target) {
+            continue;
+        }
+        if let Some(pull) = &target.pull_request {
+            names.push(name.clone());
+            pulls.push((pull.github_repo(&target.repo)?, pull.pr_number));
+        }
+    }
+
+    let states = github_hosts
+        .fetch_pull_states(&pulls, &migration_input.definition.ignored_check_patterns())
+        .await?;
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs();
+
+    let stale: Vec<_> = names
+        .iter()
+        .zip(pulls.iter())
+        .zip(states.iter())
+        .filter(|(_, state)| is_stale(&state.status, &state.created_at, now, args.days))
+        .collect();
+
+    if args.dry_run || stale.is_empty() {
+        let listed: Vec<String> = stale
+            .iter()
+            .map(|(_, state)| format!("- {}", state.permalink))
+            .collect();
+        println!(
+            "# Stale PR's\n\nFailing checks and open for more than {} days:\n\n{}",
+            args.days,
+            listed.join("\n")
+        );
+        return Ok(());
+    }
+
+    crate::prompt::confirm(
+        &format!(
+            "About to close {} PR's of {} that have been failing for more than {} days",
+            stale.len(),
+            args.migration_definition,
+            args.days
+        ),
+        args.yes,
+    )?;
+
+    let branch = migration_input
+        .definition
+        .checkout
+        .head_branch(&args.migration_definition);
+    let mut closed: Vec<String> = Vec::new();
+    let mut failed: Vec<String> = Vec::new();
+    for ((name, (repo, pr_number)), state) in stale {
+        let client = match github_hosts.client_for(repo) {
+            Ok(client) => client,
+            Err(e) => {
+                warn!("{}: unable to close {}: {}", name, state.permalink, e);
+                failed.push(format!("- {}: {}", state.permalink, e));
+                continue;
+            }
+        };
+
+        if let Some(comment) = &args.comment {
+            let target = &migration_input.targets[name];
+            let head_ref = match target.pull_request.as_ref() {
+                Some(pull) if !pull.head_ref.is_empty() => pull.head_ref.clone(),
+                _ => branch.clone(),
+            };
+            let pull = PullRequestOutput {
+                id: state.id.clone(),
+                number: *pr_number,
+                permalink: state.permalink.clone(),
+                created_at: state.created_at.clone(),
+                head_ref,
+            };
+            let body = super::comment::render_comment(comment, name, repo, &pull, &target.metadata);
+            if let Err(e) = client.add_comment(&state.id, &body).await {
+                warn!(
+                    "{}: unable to comment on {}: {:?}",
+                    name, state.permalink, e
+                );
+            }
+        }
+
+        match client.close_pull_request(&state.id).await {
+            Ok(_) => {
+                info!("{}: closed {}", name, state.permalink);
+                closed.push(format!("- {}", state.permalink));
+                if args.forget {
+                    if let Some(target) = migration_input.targets.get_mut(name) {
+                        target.pull_request = None;
+                    }
+                }
+            }
+            Err(e) => {
+                warn!("{}: unable to close {}: {:?}", name, state.permalink, e);
+                failed.push(format!("- {}: {}", state.permalink, e));
+            }
+        }
+    }
+
+    if args.forget {
+        crate::state::save_migration(&args.migration_definition, &migration_input)?;
+    }
+
+    println!(
+        "# Closed Stale PR's
+## Closed
+
+{}
+
+## Failed
+
+{}",
+        closed.join("\n"),
+        failed.join("\n")
+    );
+
+    Ok(())
+}
+
+fn is_stale(status: &PullStatus, created_at: &str, now: u64, days: u64) -> bool {
+    let created_at = match parse_github_timestamp(created_at) {
+        Some(created_at) => created_at,
+        None => return false,
+    };
+
+    *status == PullStatus::ChecksFailed && now.saturating_sub(created_at) / 86400 > days
+}
+
+#[test]
+fn validate_is_stale() {
+    let now = parse_github_timestamp("2024-03-01T00:00:00Z").unwrap();
+    let created_at = "2024-01-01T00:00:00Z";
+
+    assert!(is_stale(&PullStatus::ChecksFailed, created_at, now, 30));
+    assert!(!is_stale(&PullStatus::ChecksFailed, created_at, now, 60));
+    assert!(!is_stale(&PullStatus::Mergeable, created_at, now, 30));
+    assert!(!is_stale(&PullStatus::ChecksFailed, "not a time", now, 30));
+}
@@ -0,0 +1,212 @@
+use clap::Args;
+
+use std::collections::BTreeMap;
+
+use anyhow::Result as AnyResult;
+use tracing::{info, warn};
+
+use crate::filter::TargetFilter;
+use crate::github::{GitHubHosts, GitHubRepo, PullRequestOutput, PullStatus};
+use crate::models::*;
+
+#[derive(Args, Debug)]
+pub struct CommentArgs {
+    /// A TOML file that was used with `run-migration`.
+    #[clap(long)]
+    pub migration_definition: String,
+
+    /// Token to be used when talking to GitHub
+    #[clap(long, env = "GITHUB_TOKEN")]
+    pub github_token: String,
+
+    /// Markdown comment to post. `{{target}}`, `{{repo}}`, `{{owner}}`, `{{name}}`,
+    /// `{{branch}}`, `{{pr_url}}` and `{{metadata.<key>}}` are replaced with the values for
+    /// each target.
+    #[clap(
+        long,
+        conflicts_with = "body_file",
+        required_unless_present = "body_file"
+    )]
+    pub body: Option<String>,
+
+    /// Read the comment from this file instead of `--body`.
+    #[clap(long)]
+    pub body_file: Option<String>,
+
+    /// Print the comment each PR would get, without posting any.
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// Go ahead without asking to confirm first, like in CI.
+    #[clap(long, short = 'y')]
+    pub yes: bool,
+
+    #[clap(flatten)]
+    pub filter: TargetFilter,
+}
+
+/// Posts the same comment on every open PR of the migration, like a heads up that they'll be
+/// merged on Friday.
+pub async fn run_comment(args: CommentArgs) -> AnyResult<()> {
+    let template = match (&args.body, &args.body_file) {
+        (Some(body), _) => body.clone(),
+        (None, Some(path)) => crate::template::load(std::path::Path::new(path))?,
+        (None, None) => anyhow::bail!("Either --body or --body-file is needed"),
+    };
+
+    let migration_input: MigrationFile = crate::state::load_migration(&args.migration_definition)?;
+    let github_hosts = GitHubHosts::new(&args.github_token, &migration_input.host_configs())?;
+
+    let mut names = Vec::new();
+    let mut pulls = Vec::new();
+    for (name, target) in &migration_input.targets {
+        if !args.filter.matches(name, target) {
+            continue;
+        }
+        if let Some(pull) = &target.pull_request {
+            names.push(name);
+            pulls.push((pull.github_repo(&target.repo)?, pull.pr_number));
+        }
+    }
+
+    let states = github_hosts
+        .fetch_pull_states(&pulls, &migration_input.definition.ignored_check_patterns())
+        .await?;
+
+    // Older migration files don't have the branch of the PR
+    let branch = migration_input
+        .definition
+        .checkout
+        .head_branch(&args.migration_definition);
+    let mut open = Vec::new();
+    let mut skipped: Vec<String> = Vec::new();
+    for ((name, (repo, pr_number)), state) in names.iter().zip(pulls.iter()).zip(states.iter()) {
+        if state.status.is_closed() || state.status == PullStatus::Missing {
+            skipped.push(format!("- {} ({})", state.permalink, state.status));
+            continue;
+        }
+
+        let target = &migration_input.targets[name.as_str()];
+        let head_ref = match target.pull_request.as_ref() {
+            Some(pull) if !pull.head_ref.is_empty() => pull.head_ref.clone(),
+            _ => branch.clone(),
+        };
+        let pull = PullRequestOutput {
+            id: state.id.clone(),
+            number: *pr_number,
+            permalink: state.permalink.clone(),
+            created_at: state.created_at.clone(),
+            head_ref,
+        };
+        let body = render_comment(&template, name, repo, &pull, &target.metadata);
+        open.push((name, repo, pull, body));
+    }
+
+    if args.dry_run {
+        let previews: Vec<String> = open
+            .iter()
+            .map(|(_, _, pull, body)| format!("## {}\n\n{}", pull.permalink, body))
+            .collect();
+        println!("# Comment Preview\n\n{}", previews.join("\n\n"));
+        return Ok(());
+    }
+
+    crate::prompt::confirm(
+        &format!(
+            "About to comment on {} open PR's of {}",
+            open.len(),
+            args.migration_definition
+        ),
+        args.yes,
+    )?;
+
+    let mut commented: Vec<String> = Vec::new();
+    let mut failed: Vec<String> = Vec::new();
+    for (name, repo, pull, body) in &open {
+        let forge = match github_hosts.forge_for(repo) {
+            Ok(forge) => forge,
+            Err(e) => {
+                warn!("{}: unable to comment on {}: {}", name, pull.permalink, e);
+                failed.push(format!("- {}: {}", pull.permalink, e));
+                continue;
+            }
+        };
+        match forge.add_pull_request_comment(repo, pull, body).await {
+            Ok(_) => {
+                info!("{}: commented on {}", name, pull.permalink);
+                commented.push(format!("- {}", pull.permalink));
+            }
+            Err(e) => {
+                warn!("{}: unable to comment on {}: {:?}", name, pull.permalink, e);
+                failed.push(format!("- {}: {}", pull.permalink, e));
+            }
+        }
+    }
+
+    println!(
+        "# Comments
+## Commented
+
+{}
+
+## Skipped
+
+{}
+
+## Failed
+
+{}",
+        commented.join("\n"),
+        skipped.join("\n"),
+        failed.join("\n")
+    );
+
+    Ok(())
+}
+
+pub(crate) fn render_comment(
+    template: &str,
+    target_name: &str,
+    repo: &GitHubRepo,
+    pull: &PullRequestOutput,
+    metadata: &BTreeMap<String, String>,
+) -> String {
+    let metadata = crate::metadata::metadata_variables(metadata);
+    let mut variables = BTreeMap::new();
+    for (name, value) in &metadata {
+        variables.insert(name.as_str(), value.clone());
+    }
+    variables.insert("target", target_name.to_owned());
+    variables.insert("repo", repo.to_string());
+    variables.insert("owner", repo.owner.clone());
+    variables.insert("name", repo.repo.clone());
+    variables.insert("branch", pull.head_ref.clone());
+    variables.insert("pr_url", pull.permalink.clone());
+
+    crate::template::render(template, &variables)
+}
+
+#[test]
+fn validate_render_comment() {
+    let repo = crate::github::extract_github_info("git@github.com:ethankhall/clu.git").unwrap();
+    let pull = PullRequestOutput {
+        id: "PR_1".to_owned(),
+        number: 7,
+        permalink: "https://github.com/ethankhall/clu/pull/7".to_owned(),
+        created_at: "2023-11-14T22:13:20Z".to_owned(),
+        head_ref: "clu/update".to_owned(),
+    };
+    let mut metadata = BTreeMap::new();
+    metadata.insert("team".to_owned(), "platform".to_owned());
+
+    assert_eq!(
+        "@platform, https://github.com/ethankhall/clu/pull/7 of ethankhall/clu (clu) is merged Friday",
+        render_comment(
+            "@{{metadata.team}}, {{pr_url}} of {{repo}} ({{target}}) is merged Friday",
+            "clu",
+            &repo,
+            &pull,
+            &metadata
+        )
+    );
+}
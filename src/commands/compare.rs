@@ -0,0 +1,155 @@
+use clap::Args;
+
+use std::collections::BTreeMap;
+
+use anyhow::Result as AnyResult;
+
+use crate::filter::TargetFilter;
+use crate::models::*;
+
+#[derive(Args, Debug)]
+pub struct CompareArgs {
+    /// A TOML file that was used with `run-migration --run-label`.
+    #[clap(long)]
+    pub migration_definition: String,
+
+    /// Label of the earlier run.
+    pub before: String,
+
+    /// Label of the later run.
+    pub after: String,
+
+    #[clap(flatten)]
+    pub filter: TargetFilter,
+}
+
+pub async fn run_compare(args: CompareArgs) -> AnyResult<()> {
+    let mut migration_input: MigrationFile =
+        crate::state::load_migration(&args.migration_definition)?;
+    migration_input
+        .targets
+        .retain(|name, target| args.filter.matches(name, target));
+
+    let comparison = compare_runs(&migration_input.targets, &args.before, &args.after);
+
+    println!(
+        "# Comparing `{}` to `{}`
+## Newly Succeeded
+
+{}
+
+## Newly Failed
+
+{}
+
+## Changed
+
+{}",
+        args.before,
+        args.after,
+        comparison.newly_succeeded.join("\n"),
+        comparison.newly_failed.join("\n"),
+        comparison.changed.join("\n")
+    );
+
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+struct Comparison {
+    newly_succeeded: Vec<String>,
+    newly_failed: Vec<String>,
+    changed: Vec<String>,
+}
+
+fn compare_runs(
+    targets: &BTreeMap<String, TargetDescription>,
+    before: &str,
+    after: &str,
+) -> Comparison {
+    let mut comparison = Comparison::default();
+
+    for (name, target) in targets {
+        let (before, after) = match (target.runs.get(before), target.runs.get(after)) {
+            (before, Some(after)) if before != Some(after) => (before, after),
+            _ => continue,
+        };
+
+        let entry = match before {
+            Some(before) => format!("- {}: {} -> {}", name, before, after),
+            None => format!("- {}: not run -> {}", name, after),
+        };
+        let was = before.map(|it| &it.outcome);
+
+        if after.outcome == RunOutcome::Succeeded && was != Some(&RunOutcome::Succeeded) {
+            comparison.newly_succeeded.push(entry);
+        } else if after.outcome == RunOutcome::Failed && was != Some(&RunOutcome::Failed) {
+            comparison.newly_failed.push(entry);
+        } else {
+            comparison.changed.push(entry);
+        }
+    }
+
+    comparison
+}
+
+#[test]
+fn validate_compare_runs() {
+    let result = |outcome: RunOutcome, step: &str| RunResult {
+        outcome,
+        step: step.to_owned(),
+        error: None,
+        error_kind: None,
+    };
+    let target = |runs: Vec<(&str, RunResult)>| {
+        let mut target = TargetDescription::new("git@github.com:ethankhall/clu.git");
+        for (label, run) in runs {
+            target.runs.insert(label.to_owned(), run);
+        }
+        target
+    };
+
+    let mut targets = BTreeMap::new();
+    targets.insert(
+        "fixed".to_owned(),
+        target(vec![
+            ("a", result(RunOutcome::Failed, "clone")),
+            ("b", result(RunOutcome::Succeeded, "pull-request")),
+        ]),
+    );
+    targets.insert(
+        "broken".to_owned(),
+        target(vec![(
+            "b",
+            result(RunOutcome::Failed, "migration-step:exec"),
+        )]),
+    );
+    targets.insert(
+        "moved".to_owned(),
+        target(vec![
+            ("a", result(RunOutcome::Failed, "clone")),
+            ("b", result(RunOutcome::Failed, "push")),
+        ]),
+    );
+    targets.insert(
+        "same".to_owned(),
+        target(vec![
+            ("a", result(RunOutcome::Stopped, "pre-flight")),
+            ("b", result(RunOutcome::Stopped, "pre-flight")),
+        ]),
+    );
+
+    let comparison = compare_runs(&targets, "a", "b");
+    assert_eq!(
+        vec!["- fixed: failed at `clone` -> succeeded at `pull-request`"],
+        comparison.newly_succeeded
+    );
+    assert_eq!(
+        vec!["- broken: not run -> failed at `migration-step:exec`"],
+        comparison.newly_failed
+    );
+    assert_eq!(
+        vec!["- moved: failed at `clone` -> failed at `push`"],
+        comparison.changed
+    );
+}
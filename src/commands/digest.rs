@@ -0,0 +1,121 @@
+use clap::Args;
+
+use std::time::SystemTime;
+
+use anyhow::Result as AnyResult;
+use tracing::info;
+
+use crate::filter::TargetFilter;
+use crate::github::{parse_github_timestamp, GitHubHosts, PullStatus};
+use crate::models::*;
+use crate::notify::{Digest, EmailNotifier, TeamsNotifier};
+
+#[derive(Args, Debug)]
+pub struct DigestArgs {
+    /// A TOML file that defines the input needed to run a migration. This file will be updated
+    /// with the statuses that were seen, so the next digest only reports changes.
+    #[clap(long)]
+    pub migration_definition: String,
+
+    /// Token to be used when talking to GitHub
+    #[clap(long, env = "GITHUB_TOKEN")]
+    pub github_token: String,
+
+    /// PR's that have been open for longer than this are reported as stale
+    #[clap(long, default_value("14"))]
+    pub stale_days: u64,
+
+    #[clap(flatten)]
+    pub filter: TargetFilter,
+}
+
+/// Builds a digest of what changed since the last time it was run and sends it to the
+/// configured notifiers. Intended to be run once a day from cron or CI.
+pub async fn run_digest(args: DigestArgs) -> AnyResult<()> {
+    let mut migration_input: MigrationFile =
+        crate::state::load_migration(&args.migration_definition)?;
+    let github_hosts = GitHubHosts::new(&args.github_token, &migration_input.host_configs())?;
+
+    let mut names = Vec::new();
+    let mut pulls = Vec::new();
+    for (name, target) in &migration_input.targets {
+        if !args.filter.matches(name, target) {
+            continue;
+        }
+        if let Some(pull) = &target.pull_request {
+            names.push(name.clone());
+            pulls.push((pull.github_repo(&target.repo)?, pull.pr_number));
+        }
+    }
+
+    let states = github_hosts
+        .fetch_pull_states(&pulls, &migration_input.definition.ignored_check_patterns())
+        .await?;
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs();
+
+    let mut digest = Digest {
+        migration: migration_input.definition.checkout.branch_name.clone(),
+        total: states.len(),
+        ..Default::default()
+    };
+
+    for (name, state) in names.iter().zip(states.into_iter()) {
+        let target = migration_input.targets.get_mut(name).unwrap();
+        let previous = target.last_status.take();
+
+        match &state.status {
+            PullStatus::Merged => {
+                digest.merged += 1;
+                if previous != Some(PullStatus::Merged) {
+                    digest.newly_merged.push(state.permalink.clone());
+                }
+            }
+            PullStatus::ChecksFailed => {
+                if previous != Some(PullStatus::ChecksFailed) {
+                    digest.newly_failing.push(state.permalink.clone());
+                }
+            }
+            _ => {}
+        }
+
+        if !state.status.is_closed() {
+            if let Some(created_at) = parse_github_timestamp(&state.created_at) {
+                let age_days = now.saturating_sub(created_at) / 86400;
+                if age_days >= args.stale_days {
+                    digest
+                        .stale
+                        .push(format!("{} (open {} days)", state.permalink, age_days));
+                }
+            }
+        }
+
+        target.last_status = Some(state.status);
+    }
+
+    digest.newly_merged.sort();
+    digest.newly_failing.sort();
+    digest.stale.sort();
+
+    println!("{}", digest.render_text());
+
+    let notifications = migration_input
+        .definition
+        .notifications
+        .clone()
+        .unwrap_or_default();
+    if let Some(email) = &notifications.email {
+        EmailNotifier::new(email).send_digest(&digest).await?;
+    }
+    if let Some(teams) = &notifications.teams {
+        TeamsNotifier::new(teams).send_digest(&digest).await?;
+    }
+    if notifications.email.is_none() && notifications.teams.is_none() {
+        info!("No notifications configured, only printing the digest");
+    }
+
+    crate::state::save_migration(&args.migration_definition, &migration_input)?;
+
+    Ok(())
+}
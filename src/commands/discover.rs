@@ -0,0 +1,158 @@
+use clap::Args;
+
+use anyhow::Result as AnyResult;
+use tracing::info;
+
+use crate::github::{DiscoveredRepo, GithubApiClient};
+use crate::glob::Glob;
+use crate::models::*;
+
+#[derive(Args, Debug)]
+pub struct DiscoverArgs {
+    /// A TOML file that defines the input needed to run a migration. The repos that are found
+    /// are added to its targets.
+    #[clap(long)]
+    pub migration_definition: String,
+
+    /// Token to be used when talking to GitHub
+    #[clap(long, env = "GITHUB_TOKEN")]
+    pub github_token: String,
+
+    /// Organization to discover repos in, instead of `targets-from.org`.
+    #[clap(long)]
+    pub org: Option<String>,
+
+    /// Code search query to discover repos with, instead of `targets-from.search`.
+    #[clap(long)]
+    pub search: Option<String>,
+
+    /// Team, as `org/team-slug`, whose repos are discovered instead of `targets-from.team`.
+    #[clap(long)]
+    pub team: Option<String>,
+}
+
+pub async fn run_discover(args: DiscoverArgs) -> AnyResult<()> {
+    let mut migration_input: MigrationFile =
+        crate::state::load_migration(&args.migration_definition)?;
+    let github_api = GithubApiClient::new(&args.github_token)?;
+
+    let mut source = migration_input
+        .definition
+        .targets_from
+        .clone()
+        .unwrap_or_default();
+    if args.org.is_some() {
+        source.org = args.org;
+    }
+    if args.search.is_some() {
+        source.search = args.search;
+    }
+    if args.team.is_some() {
+        source.team = args.team;
+    }
+
+    if source.org.is_none() && source.search.is_none() && source.team.is_none() {
+        anyhow::bail!(
+            "Nothing to discover from, set `targets-from` or pass `--org`, `--team` or `--search`"
+        );
+    }
+
+    let mut found = Vec::new();
+    if let Some(org) = &source.org {
+        info!("Listing repositories of {}", org);
+        found.extend(github_api.list_org_repositories(org).await?);
+    }
+    if let Some(team) = &source.team {
+        let (org, slug) = match team.split_once('/') {
+            Some(parts) => parts,
+            None => anyhow::bail!("`{}` is not a team, it should be `org/team-slug`", team),
+        };
+        info!("Listing repositories administered by {}", team);
+        found.extend(github_api.list_team_repositories(org, slug).await?);
+    }
+    if let Some(query) = &source.search {
+        info!("Searching code for `{}`", query);
+        found.extend(github_api.search_code_repositories(query).await?);
+    }
+
+    let exclude: Vec<Glob> = source.exclude.iter().map(Glob::new).collect();
+    let mut added = Vec::new();
+    for repo in found {
+        if !is_wanted(&repo, &source, &exclude) {
+            continue;
+        }
+
+        let known = migration_input.targets.contains_key(&repo.name)
+            || migration_input
+                .targets
+                .values()
+                .any(|it| it.repo == repo.ssh_url);
+        if known {
+            continue;
+        }
+
+        added.push(format!("- {}", repo.name_with_owner));
+        migration_input
+            .targets
+            .insert(repo.name, TargetDescription::new(&repo.ssh_url));
+    }
+
+    crate::state::save_migration(&args.migration_definition, &migration_input)?;
+
+    println!("# Added {} Targets\n\n{}", added.len(), added.join("\n"));
+
+    Ok(())
+}
+
+fn is_wanted(repo: &DiscoveredRepo, source: &TargetSource, exclude: &[Glob]) -> bool {
+    if repo.archived && !source.include_archived {
+        return false;
+    }
+
+    if exclude.iter().any(|it| it.is_match(&repo.name_with_owner)) {
+        return false;
+    }
+
+    if !source.languages.is_empty() {
+        match &repo.language {
+            Some(language) if source.languages.iter().any(|it| it == language) => {}
+            _ => return false,
+        }
+    }
+
+    if !source.topics.is_empty() && !repo.topics.iter().any(|it| source.topics.contains(it)) {
+        return false;
+    }
+
+    !repo
+        .topics
+        .iter()
+        .any(|it| source.exclude_topics.contains(it))
+}
+
+#[test]
+fn validate_is_wanted() {
+    let repo = DiscoveredRepo {
+        name: "payments".to_owned(),
+        name_with_owner: "ethankhall/payments".to_owned(),
+        ssh_url: "git@github.com:ethankhall/payments.git".to_owned(),
+        archived: false,
+        language: Some("Rust".to_owned()),
+        topics: vec!["service".to_owned()],
+    };
+
+    let mut source = TargetSource {
+        languages: vec!["Rust".to_owned()],
+        topics: vec!["service".to_owned()],
+        ..Default::default()
+    };
+    assert!(is_wanted(&repo, &source, &[]));
+    assert!(!is_wanted(&repo, &source, &[Glob::new("ethankhall/pay*")]));
+
+    source.languages = vec!["Go".to_owned()];
+    assert!(!is_wanted(&repo, &source, &[]));
+
+    source.languages = Vec::new();
+    source.exclude_topics = vec!["service".to_owned()];
+    assert!(!is_wanted(&repo, &source, &[]));
+}
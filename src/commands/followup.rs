@@ -1,17 +1,21 @@
 use clap::Args;
 
 use std::collections::BTreeMap;
-use std::fs::read_to_string;
 use std::path::PathBuf;
+use std::time::Instant;
 
 use anyhow::Result as AnyResult;
 use futures::stream::{self, StreamExt};
+use serde::Serialize;
 use tracing::{info, warn};
 
-use crate::github::GithubApiClient;
+use crate::filter::TargetFilter;
+use crate::github::GitHubHosts;
 use crate::github::PullStatus;
+use crate::glob::Glob;
 use crate::migration::MigrationError;
 use crate::models::*;
+use crate::output::OutputFormat;
 use crate::steps::FollowUpStep;
 use crate::steps::{MigrationStep, MigrationStepResult};
 use crate::workspace::Workspace;
@@ -31,17 +35,60 @@ pub struct RunFollowupArgs {
     #[clap(long = "work-directory", default_value("follow-up-dir"))]
     pub work_directory_root: String,
 
-    pub followup_script: String,
+    /// Script to run for each PR. Can be left out for `--name`.
+    #[clap(required_unless_present = "name", conflicts_with = "name")]
+    pub followup_script: Option<String>,
+
+    /// Run the follow-up with this name from the `followups` of the migration, with its `env`.
+    #[clap(long)]
+    pub name: Option<String>,
+
+    /// How many PR's to run the script on at the same time.
+    #[clap(long, default_value("3"))]
+    pub concurrency: usize,
+
+    /// Only run the script on the PR's with this status, like `mergeable` or `checks-failed`,
+    /// can be given more than once.
+    #[clap(long = "status", value_enum)]
+    pub statuses: Vec<PullStatus>,
+
+    #[clap(flatten)]
+    pub filter: TargetFilter,
 }
 
-pub async fn run_followup(args: RunFollowupArgs) -> AnyResult<()> {
-    let results: MigrationFile = toml::from_str(&read_to_string(args.migration_definition)?)?;
+/// How the follow-up went on one PR, for `--output json`.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+struct FollowupOutput {
+    target: String,
+    pull_request: String,
+    outcome: RunOutcome,
+    step: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    duration_ms: u64,
+}
+
+pub async fn run_followup(args: RunFollowupArgs, output: OutputFormat) -> AnyResult<()> {
+    let results: MigrationFile =
+        crate::state::load_migration_matching(&args.migration_definition, |name, target| {
+            args.filter.matches(name, target)
+        })?;
 
-    let github_api = GithubApiClient::new(&args.github_token)?;
+    let github_hosts = GitHubHosts::new(&args.github_token, &results.host_configs())?;
 
+    let followup = pick_followup(
+        &results.definition,
+        args.name.as_deref(),
+        args.followup_script.as_deref(),
+    )?;
+    let ignored_checks = results.definition.ignored_check_patterns();
     let mut work_queue = Vec::new();
 
     for (name, target) in results.targets {
+        if !args.filter.matches(&name, &target) {
+            continue;
+        }
         let target_dir = PathBuf::from(&args.work_directory_root);
 
         let pull = match target.pull_request {
@@ -51,42 +98,72 @@ pub async fn run_followup(args: RunFollowupArgs) -> AnyResult<()> {
 
         work_queue.push(WorkTask {
             repo_name: name,
-            github_api: &github_api,
+            github_hosts: &github_hosts,
             pull,
             clone_url: target.repo,
             target_dir: target_dir.clone(),
-            followup_script: args.followup_script.clone(),
+            followup: &followup,
+            ignored_checks: &ignored_checks,
+            statuses: &args.statuses,
         });
     }
 
-    stream::iter(work_queue)
-        .for_each_concurrent(3, |task| async move {
+    let mut outputs: Vec<FollowupOutput> = stream::iter(work_queue)
+        .map(|task| async move {
+            let started = Instant::now();
             let migration_status = task.run_follow_up().await;
-            match migration_status.result {
-                Ok(_) => info!("{} ran follow up successfully", task.repo_name),
-                Err(e) => warn!(
-                    "{} did not run follow-up successfully: {:?}",
-                    task.repo_name, e
-                ),
+            let outcome = match &migration_status.result {
+                Ok(_) => {
+                    info!("{} ran follow up successfully", task.repo_name);
+                    match migration_status.terminal {
+                        true => RunOutcome::Stopped,
+                        false => RunOutcome::Succeeded,
+                    }
+                }
+                Err(e) => {
+                    warn!(
+                        "{} did not run follow-up successfully: {:?}",
+                        task.repo_name, e
+                    );
+                    RunOutcome::Failed
+                }
+            };
+
+            FollowupOutput {
+                target: task.repo_name.clone(),
+                pull_request: task.pull.url.clone(),
+                outcome,
+                step: migration_status.name,
+                error: migration_status.result.err().map(|e| e.to_string()),
+                duration_ms: started.elapsed().as_millis() as u64,
             }
         })
+        .buffer_unordered(args.concurrency.max(1))
+        .collect()
         .await;
 
+    if output.is_json() {
+        outputs.sort_by(|a, b| a.target.cmp(&b.target));
+        crate::output::print_json(&outputs)?;
+    }
+
     Ok(())
 }
 
 struct WorkTask<'a> {
     repo_name: String,
-    github_api: &'a GithubApiClient,
+    github_hosts: &'a GitHubHosts,
     pull: CreatedPullRequest,
     clone_url: String,
     target_dir: PathBuf,
-    followup_script: String,
+    followup: &'a FollowupDefinition,
+    ignored_checks: &'a [Glob],
+    statuses: &'a [PullStatus],
 }
 
 impl<'a> WorkTask<'a> {
     async fn run_follow_up(&self) -> MigrationStepResult<()> {
-        let github_repo = match crate::github::extract_github_info(&self.clone_url) {
+        let github_repo = match self.pull.github_repo(&self.clone_url) {
             Ok(github_repo) => github_repo,
             Err(e) => {
                 return MigrationStepResult::failure(
@@ -96,9 +173,18 @@ impl<'a> WorkTask<'a> {
             }
         };
 
-        let pr_state = match self
-            .github_api
-            .fetch_pull_state(&github_repo, self.pull.pr_number)
+        let forge = match self.github_hosts.forge_for(&github_repo) {
+            Ok(forge) => forge,
+            Err(e) => {
+                return MigrationStepResult::failure(
+                    "invalid-url",
+                    MigrationError::InvalidGitRepo { source: e },
+                )
+            }
+        };
+
+        let pr_state = match forge
+            .fetch_pull_state(&github_repo, self.pull.pr_number, self.ignored_checks)
             .await
         {
             Ok(pr_state) => pr_state,
@@ -114,11 +200,20 @@ impl<'a> WorkTask<'a> {
             }
         };
 
-        if pr_state.status == PullStatus::Merged {
-            MigrationStepResult::abort("merged");
+        match pr_state.status {
+            PullStatus::Merged => return MigrationStepResult::abort("merged"),
+            PullStatus::ClosedWithoutMerge => return MigrationStepResult::abort("closed"),
+            _ => {}
+        }
+        if !self.statuses.is_empty() && !self.statuses.contains(&pr_state.status) {
+            info!(
+                "{} is {}, the follow-up isn't run on it",
+                self.repo_name, pr_state.status
+            );
+            return MigrationStepResult::abort("status");
         }
 
-        let mut env_vars = BTreeMap::new();
+        let mut env_vars = self.followup.env.clone();
         env_vars.insert("CLU_PULL_REQUEST_URL".to_owned(), pr_state.permalink);
         env_vars.insert("CLU_CLONE_URL".to_owned(), self.clone_url.to_owned());
 
@@ -130,8 +225,79 @@ impl<'a> WorkTask<'a> {
                 }
             };
         workspace.set_env_vars(&mut env_vars);
-        FollowUpStep::new(&self.followup_script)
+        FollowUpStep::new(&self.followup.script)
             .execute_step(&mut workspace)
             .await
     }
 }
+
+/// The follow-up named in the migration, or the script given on the command line.
+fn pick_followup(
+    definition: &MigrationDefinition,
+    name: Option<&str>,
+    script: Option<&str>,
+) -> AnyResult<FollowupDefinition> {
+    match (name, script) {
+        (Some(name), _) => match definition.followups.get(name) {
+            Some(followup) => Ok(followup.clone()),
+            None if definition.followups.is_empty() => {
+                anyhow::bail!(
+                    "There is no follow-up named `{}`, the migration has no `followups`",
+                    name
+                )
+            }
+            None => {
+                let known: Vec<&str> = definition.followups.keys().map(|it| it.as_str()).collect();
+                anyhow::bail!(
+                    "There is no follow-up named `{}`, the migration has: {}",
+                    name,
+                    known.join(", ")
+                )
+            }
+        },
+        (None, Some(script)) => Ok(FollowupDefinition {
+            script: script.to_owned(),
+            env: BTreeMap::new(),
+        }),
+        (None, None) => anyhow::bail!("Either a follow-up script or --name is needed"),
+    }
+}
+
+#[test]
+fn validate_pick_followup() {
+    let migration: MigrationFile = toml::from_str(
+        r#"
+        [targets.clu]
+        repo = "git@github.com:ethankhall/clu.git"
+
+        [checkout]
+        branch-name = "clu/update"
+        pre-flight = "/usr/bin/true"
+
+        [pr]
+        title = "Update"
+        description = "Update"
+
+        [[steps]]
+        name = "update"
+        migration-script = "update.sh"
+
+        [followups.nudge-owners]
+        script = "followups/nudge.sh"
+        env = { CHANNEL = "migrations" }
+        "#,
+    )
+    .unwrap();
+    let definition = &migration.definition;
+
+    let followup = pick_followup(definition, Some("nudge-owners"), None).unwrap();
+    assert_eq!("followups/nudge.sh", followup.script);
+    assert_eq!(Some(&"migrations".to_owned()), followup.env.get("CHANNEL"));
+    assert_eq!(
+        "fix.sh",
+        pick_followup(definition, None, Some("fix.sh"))
+            .unwrap()
+            .script
+    );
+    assert!(pick_followup(definition, Some("missing"), None).is_err());
+}
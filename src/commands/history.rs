@@ -0,0 +1,120 @@
+use clap::Args;
+
+use anyhow::{bail, Result as AnyResult};
+
+use crate::github::format_github_timestamp;
+use crate::models::RunOutcome;
+use crate::state::{RunHistory, RunRecord};
+
+#[derive(Args, Debug)]
+pub struct HistoryArgs {
+    /// A TOML file that has been used with `run-migration`. It isn't changed.
+    #[clap(long)]
+    pub migration_definition: String,
+
+    /// Show how each target ended in the run with this id, instead of listing the runs.
+    #[clap(long)]
+    pub run: Option<String>,
+}
+
+/// Lists the past runs of the migration, from the history `run-migration` keeps next to the
+/// migration file.
+pub async fn run_history(args: HistoryArgs) -> AnyResult<()> {
+    let history = crate::state::load_history(&args.migration_definition)?;
+
+    match &args.run {
+        None => println!("{}", format_runs(&history)),
+        Some(id) => match history.runs.iter().find(|run| &run.id == id) {
+            Some(run) => println!("{}", format_run(run)),
+            None => bail!(
+                "{} has no run `{}`, `clu history` lists them",
+                args.migration_definition,
+                id
+            ),
+        },
+    }
+
+    Ok(())
+}
+
+fn count(run: &RunRecord, outcome: RunOutcome) -> usize {
+    run.targets
+        .values()
+        .filter(|it| it.outcome == outcome)
+        .count()
+}
+
+fn format_runs(history: &RunHistory) -> String {
+    let mut lines = vec![
+        "| Run | Label | Started | Duration | Targets | Succeeded | Stopped | Failed |".to_owned(),
+        "| --- | --- | --- | --- | --- | --- | --- | --- |".to_owned(),
+    ];
+    for run in &history.runs {
+        lines.push(format!(
+            "| {} | {} | {} | {}s | {} | {} | {} | {} |",
+            run.id,
+            run.label.as_deref().unwrap_or_default(),
+            format_github_timestamp(run.started_at),
+            run.finished_at.saturating_sub(run.started_at),
+            run.targets.len(),
+            count(run, RunOutcome::Succeeded),
+            count(run, RunOutcome::Stopped),
+            count(run, RunOutcome::Failed),
+        ));
+    }
+
+    format!("# Runs\n\n{}", lines.join("\n"))
+}
+
+fn format_run(run: &RunRecord) -> String {
+    let targets: Vec<String> = run
+        .targets
+        .iter()
+        .map(|(name, result)| format!("- {}: {}", name, result))
+        .collect();
+
+    format!(
+        "# Run {}
+
+Started {}, took {}s. {} targets: {} succeeded, {} stopped, {} failed.
+
+{}",
+        run.id,
+        format_github_timestamp(run.started_at),
+        run.finished_at.saturating_sub(run.started_at),
+        run.targets.len(),
+        count(run, RunOutcome::Succeeded),
+        count(run, RunOutcome::Stopped),
+        count(run, RunOutcome::Failed),
+        targets.join("\n")
+    )
+}
+
+#[test]
+fn validate_format_history() {
+    let history: RunHistory = toml::from_str(
+        r#"
+        [[runs]]
+        id = "1700000000-42"
+        started-at = 1700000000
+        finished-at = 1700000090
+        label = "first"
+
+        [runs.targets.clu]
+        outcome = "succeeded"
+        step = "pull-request"
+
+        [runs.targets.broken]
+        outcome = "failed"
+        step = "update"
+        "#,
+    )
+    .unwrap();
+
+    let runs = format_runs(&history);
+    assert!(runs.contains("| 1700000000-42 | first | 2023-11-14T22:13:20Z | 90s | 2 | 1 | 0 | 1 |"));
+
+    let run = format_run(&history.runs[0]);
+    assert!(run.contains("2 targets: 1 succeeded, 0 stopped, 1 failed."));
+    assert!(run.contains("- broken: "));
+}
@@ -0,0 +1,148 @@
+use clap::Args;
+
+use anyhow::Result as AnyResult;
+use tracing::{info, warn};
+
+use crate::filter::TargetFilter;
+use crate::github::{GitHubHosts, GitHubRepo, GithubApiClient, PullState, PullStatus};
+use crate::models::*;
+
+/// Every status label starts with this, so stale ones can be found and removed.
+const STATUS_LABEL_PREFIX: &str = "migration:";
+
+#[derive(Args, Debug)]
+pub struct SyncIssuesArgs {
+    /// A TOML file that defines the input needed to run a migration. This file will be updated
+    /// with the issue numbers.
+    #[clap(long)]
+    pub migration_definition: String,
+
+    /// Token to be used when talking to GitHub
+    #[clap(long, env = "GITHUB_TOKEN")]
+    pub github_token: String,
+
+    #[clap(flatten)]
+    pub filter: TargetFilter,
+}
+
+pub async fn run_sync_issues(args: SyncIssuesArgs) -> AnyResult<()> {
+    let mut migration_input: MigrationFile =
+        crate::state::load_migration(&args.migration_definition)?;
+
+    let tracking_repo = match &migration_input.definition.tracking_issues {
+        Some(tracking) => crate::github::extract_github_info(&tracking.repo)?,
+        None => anyhow::bail!("The migration definition has no `tracking-issues` section"),
+    };
+
+    let github_hosts = GitHubHosts::new(&args.github_token, &migration_input.host_configs())?;
+    let github_api = github_hosts.client_for(&tracking_repo)?;
+
+    let mut names = Vec::new();
+    let mut pulls = Vec::new();
+    for (name, target) in &migration_input.targets {
+        if !args.filter.matches(name, target) {
+            continue;
+        }
+        if let Some(pull) = &target.pull_request {
+            names.push(name.clone());
+            pulls.push((pull.github_repo(&target.repo)?, pull.pr_number));
+        }
+    }
+
+    let states = github_hosts
+        .fetch_pull_states(&pulls, &migration_input.definition.ignored_check_patterns())
+        .await?;
+    let branch_name = migration_input.definition.checkout.branch_name.clone();
+
+    for ((name, (repo, _)), state) in names.iter().zip(pulls.iter()).zip(states.iter()) {
+        let target = migration_input.targets.get_mut(name).unwrap();
+        let issue = IssueSync {
+            github_api,
+            tracking_repo: &tracking_repo,
+            branch_name: &branch_name,
+            name,
+            repo,
+            state,
+        };
+
+        match issue.sync(target.tracking_issue).await {
+            Ok(number) => target.tracking_issue = Some(number),
+            Err(e) => warn!("{}: unable to sync tracking issue: {:?}", name, e),
+        }
+    }
+
+    crate::state::save_migration(&args.migration_definition, &migration_input)?;
+
+    Ok(())
+}
+
+fn status_label(status: &PullStatus) -> String {
+    let suffix = match status {
+        PullStatus::Merged => "merged",
+        PullStatus::ClosedWithoutMerge => "closed",
+        PullStatus::ChecksFailed => "checks-failed",
+        PullStatus::Conflicting => "conflicting",
+        PullStatus::NeedsApproval | PullStatus::Mergeable => "open",
+        PullStatus::Missing => "missing",
+    };
+
+    format!("{}{}", STATUS_LABEL_PREFIX, suffix)
+}
+
+struct IssueSync<'a> {
+    github_api: &'a GithubApiClient,
+    tracking_repo: &'a GitHubRepo,
+    branch_name: &'a str,
+    name: &'a str,
+    repo: &'a GitHubRepo,
+    state: &'a PullState,
+}
+
+impl<'a> IssueSync<'a> {
+    /// Makes sure the issue exists and only has the label for the current status, returning
+    /// the issue number.
+    async fn sync(&self, existing_issue: Option<i64>) -> AnyResult<i64> {
+        let number = match existing_issue {
+            Some(number) => number,
+            None => {
+                let issue = self
+                    .github_api
+                    .create_issue(
+                        self.tracking_repo,
+                        &format!("{}: {}", self.branch_name, self.name),
+                        &format!(
+                            "Tracking the `{}` migration of {}.\n\nPull Request: {}",
+                            self.branch_name, self.repo, self.state.permalink
+                        ),
+                    )
+                    .await?;
+                issue.number
+            }
+        };
+
+        let issue = self
+            .github_api
+            .fetch_issue(self.tracking_repo, number)
+            .await?;
+        let desired = status_label(&self.state.status);
+
+        let stale: Vec<String> = issue
+            .labels
+            .iter()
+            .filter(|it| it.name.starts_with(STATUS_LABEL_PREFIX) && it.name != desired)
+            .map(|it| it.id.clone())
+            .collect();
+        if !stale.is_empty() {
+            self.github_api.remove_labels(&issue.id, stale).await?;
+        }
+
+        if !issue.labels.iter().any(|it| it.name == desired) {
+            info!("{}: marking {} as {}", self.name, issue.url, desired);
+            self.github_api
+                .add_labels(self.tracking_repo, &issue.id, &[desired])
+                .await?;
+        }
+
+        Ok(number)
+    }
+}
@@ -1,3 +1,49 @@
+mod approve;
+mod archive;
+mod bundle;
+mod clean;
+mod close_stale;
+mod comment;
+mod compare;
+mod digest;
+mod discover;
 mod followup;
+mod history;
+mod issues;
+mod plan;
+mod prune;
+mod queue;
+mod report;
+mod rerun_checks;
+mod rollback;
+mod shard;
+mod stats;
+mod status;
+mod targets;
+mod update_branches;
+mod validate;
 
+pub use approve::{run_approve, ApproveArgs};
+pub use archive::{run_archive, ArchiveArgs};
+pub use bundle::{run_publish_bundles, PublishBundlesArgs};
+pub use clean::{run_clean, CleanArgs};
+pub use close_stale::{run_close_stale, CloseStaleArgs};
+pub use comment::{run_comment, CommentArgs};
+pub use compare::{run_compare, CompareArgs};
+pub use digest::{run_digest, DigestArgs};
+pub use discover::{run_discover, DiscoverArgs};
 pub use followup::{run_followup, RunFollowupArgs};
+pub use history::{run_history, HistoryArgs};
+pub use issues::{run_sync_issues, SyncIssuesArgs};
+pub use plan::{run_plan, PlanArgs};
+pub use prune::{run_prune_branches, PruneBranchesArgs};
+pub use queue::{run_collect_results, run_enqueue, CollectResultsArgs, EnqueueArgs};
+pub use report::{run_report, ReportArgs, ReportFormat};
+pub use rerun_checks::{run_rerun_checks, RerunChecksArgs};
+pub use rollback::{run_rollback, RollbackArgs};
+pub use shard::{run_merge_results, MergeResultsArgs};
+pub use stats::{run_stats, StatsArgs};
+pub use status::{run_status, StatusArgs};
+pub use targets::{run_add_target, run_import, AddTargetArgs, ImportArgs};
+pub use update_branches::{run_update_branches, UpdateBranchesArgs};
+pub use validate::{run_validate, ValidateArgs};
@@ -0,0 +1,147 @@
+use clap::Args;
+use futures::StreamExt;
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use anyhow::Result as AnyResult;
+use tracing::warn;
+
+use crate::filter::TargetFilter;
+use crate::models::*;
+use crate::run::{RunEvent, RunOptions};
+use crate::steps::RepoCheck;
+
+/// Only this many of the files a target changes are listed, the diffstat still counts them all.
+const MAX_LISTED_FILES: usize = 50;
+
+#[derive(Args, Debug)]
+pub struct PlanArgs {
+    /// A TOML file that defines the input needed to run a migration. It isn't changed.
+    #[clap(long)]
+    pub migration_definition: String,
+
+    /// Folder where the targets are cloned and migrated. The workspaces are kept, so the
+    /// changes can be looked at in them.
+    #[clap(long = "work-directory", default_value("work-dir"))]
+    pub work_directory_root: String,
+
+    /// Token to be used when talking to GitHub
+    #[clap(long, env = "GITHUB_TOKEN")]
+    pub github_token: String,
+
+    #[clap(flatten)]
+    pub filter: TargetFilter,
+}
+
+/// Runs the pre-flight and steps of every target as a dry run, and prints what each PR would
+/// change. Nothing is pushed and no PR is opened.
+pub async fn run_plan(args: PlanArgs) -> AnyResult<()> {
+    let mut migration_input: MigrationFile =
+        crate::state::load_migration(&args.migration_definition)?;
+    migration_input
+        .targets
+        .retain(|name, target| args.filter.matches(name, target));
+    let mut definition = migration_input.definition.clone();
+    definition.checkout.branch_name = definition.checkout.head_branch(&args.migration_definition);
+
+    let mut options = RunOptions::new(&args.github_token, &args.work_directory_root);
+    options.dry_run = true;
+
+    let mut migration =
+        crate::run::run(definition.clone(), migration_input.targets, options).await?;
+    let mut errors = BTreeMap::new();
+    while let Some(event) = migration.events.next().await {
+        if let RunEvent::Finished {
+            target,
+            error: Some(error),
+            ..
+        } = event
+        {
+            errors.insert(target, error);
+        }
+    }
+    let report = migration.report.await??;
+    let work_dir = PathBuf::from(&args.work_directory_root).canonicalize()?;
+
+    let mut changes = Vec::new();
+    let mut unchanged = Vec::new();
+    let mut failed = Vec::new();
+    for (name, target) in &report.targets {
+        let result = match report.results.get(name) {
+            Some(result) => result,
+            None => {
+                unchanged.push(format!("- {} (skipped)", name));
+                continue;
+            }
+        };
+        if result.outcome == RunOutcome::Failed {
+            let error = errors.get(name).map(String::as_str).unwrap_or("unknown");
+            failed.push(format!(
+                "- {}: failed at `{}`, {}",
+                name, result.step, error
+            ));
+            continue;
+        }
+
+        let diff = target.metrics.as_ref().and_then(|it| it.diff.as_ref());
+        let diff = match diff {
+            Some(diff) if diff.files_changed > 0 => diff,
+            _ => {
+                unchanged.push(format!("- {} (stopped at `{}`)", name, result.step));
+                continue;
+            }
+        };
+
+        let title = definition.with_metadata(&target.metadata).pr.title;
+        let base = match &target.base_ref {
+            Some(base) => format!("`{}`", base),
+            None => "the default branch".to_owned(),
+        };
+        let mut entry = format!(
+            "### {}\n\nPR: {}\n\nAgainst {}, {} files changed, {} insertions(+), {} deletions(-)\n",
+            name, title, base, diff.files_changed, diff.insertions, diff.deletions
+        );
+
+        let base_revision =
+            RepoCheck::base_revision(target.base_ref.as_deref(), target.base_sha.as_deref());
+        match RepoCheck::changed_files(&work_dir.join(name).join("repo"), &base_revision) {
+            Ok(files) => {
+                entry.push('\n');
+                for file in files.iter().take(MAX_LISTED_FILES) {
+                    entry.push_str(&format!("- `{}`\n", file));
+                }
+                if files.len() > MAX_LISTED_FILES {
+                    entry.push_str(&format!("- and {} more\n", files.len() - MAX_LISTED_FILES));
+                }
+            }
+            Err(e) => warn!("{}: Unable to list the changed files: {}", name, e),
+        }
+        changes.push(entry);
+    }
+
+    println!(
+        "# Migration Plan
+
+{} of {} targets would get a PR for `{}`.
+
+## Changes
+
+{}
+## Without Changes
+
+{}
+
+## Failed
+
+{}",
+        changes.len(),
+        report.targets.len(),
+        definition.checkout.branch_name,
+        changes.join("\n"),
+        unchanged.join("\n"),
+        failed.join("\n")
+    );
+
+    Ok(())
+}
@@ -0,0 +1,111 @@
+use clap::Args;
+
+use anyhow::Result as AnyResult;
+use tracing::{info, warn};
+
+use crate::filter::TargetFilter;
+use crate::github::GitHubHosts;
+use crate::glob::Glob;
+use crate::models::*;
+
+#[derive(Args, Debug)]
+pub struct PruneBranchesArgs {
+    /// A TOML file that defines the input needed to run a migration.
+    #[clap(long)]
+    pub migration_definition: String,
+
+    /// Token to be used when talking to GitHub
+    #[clap(long, env = "GITHUB_TOKEN")]
+    pub github_token: String,
+
+    /// Branches to look for, `*` and `?` can be used as wildcards. Defaults to the
+    /// branch of the migration.
+    #[clap(long)]
+    pub pattern: Option<String>,
+
+    /// Actually delete the branches. Without this only the branches that would be
+    /// deleted are printed.
+    #[clap(long)]
+    pub delete: bool,
+
+    /// Go ahead without asking to confirm first, like in CI.
+    #[clap(long, short = 'y')]
+    pub yes: bool,
+
+    #[clap(flatten)]
+    pub filter: TargetFilter,
+}
+
+pub async fn run_prune_branches(args: PruneBranchesArgs) -> AnyResult<()> {
+    let migration_input: MigrationFile = crate::state::load_migration(&args.migration_definition)?;
+    let github_hosts = GitHubHosts::new(&args.github_token, &migration_input.host_configs())?;
+
+    let pattern = Glob::new(args.pattern.clone().unwrap_or_else(|| {
+        migration_input
+            .definition
+            .checkout
+            .head_branch(&args.migration_definition)
+    }));
+
+    let mut prunable = Vec::new();
+    for (name, target) in &migration_input.targets {
+        if !args.filter.matches(name, target) {
+            continue;
+        }
+        let github_repo = crate::github::extract_github_info(&target.repo)?;
+
+        let github_api = github_hosts.client_for(&github_repo)?;
+        for branch in github_api.list_branches(&github_repo, &pattern).await? {
+            if branch.is_prunable() {
+                prunable.push((name, github_repo.clone(), branch));
+            } else {
+                info!(
+                    "{}: keeping {} because it has an open PR or never had one",
+                    name, branch.name
+                );
+            }
+        }
+    }
+
+    println!("# Branches matching `{}` to delete\n", pattern);
+    for (name, repo, branch) in &prunable {
+        let pulls: Vec<String> = branch
+            .pull_requests
+            .iter()
+            .map(|it| it.permalink.clone())
+            .collect();
+        println!(
+            "- {} ({}): {} [{}]",
+            name,
+            repo,
+            branch.name,
+            pulls.join(", ")
+        );
+    }
+
+    if !args.delete {
+        info!("Dry run, re-run with --delete to delete the branches");
+        return Ok(());
+    }
+
+    let repos: std::collections::BTreeSet<&String> = prunable.iter().map(|it| it.0).collect();
+    crate::prompt::confirm(
+        &format!(
+            "About to delete {} branches matching `{}` from {} repos of {}",
+            prunable.len(),
+            pattern,
+            repos.len(),
+            args.migration_definition
+        ),
+        args.yes,
+    )?;
+
+    for (name, repo, branch) in &prunable {
+        match github_hosts.client_for(repo)?.delete_branch(branch).await {
+            Ok(_) => info!("{}: deleted {} from {}", name, branch.name, repo),
+            Err(e) => warn!("{}: unable to delete {}: {:?}", name, branch.name, e),
+        }
+    }
+
+    Ok(())
+}
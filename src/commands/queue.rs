@@ -0,0 +1,90 @@
+use clap::Args;
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Result as AnyResult;
+use tracing::{info, warn};
+
+use crate::filter::TargetFilter;
+use crate::models::*;
+use crate::queue::DirectoryQueue;
+
+#[derive(Args, Debug)]
+pub struct EnqueueArgs {
+    /// A TOML file that defines the input needed to run a migration.
+    #[clap(long)]
+    pub migration_definition: String,
+
+    /// Directory shared by the workers, that `run-migration --queue-dir` pulls targets from.
+    #[clap(long)]
+    pub queue_dir: String,
+
+    /// Put targets that were claimed more than this many minutes ago back in the queue, for
+    /// workers that died before finishing them.
+    #[clap(long, value_name = "MINUTES")]
+    pub requeue_stale: Option<u64>,
+
+    #[clap(flatten)]
+    pub filter: TargetFilter,
+}
+
+pub async fn run_enqueue(args: EnqueueArgs) -> AnyResult<()> {
+    let migration_input: MigrationFile = crate::state::load_migration(&args.migration_definition)?;
+    let queue = DirectoryQueue::new(&PathBuf::from(&args.queue_dir))?;
+
+    if let Some(minutes) = args.requeue_stale {
+        for name in queue.requeue_stale(Duration::from_secs(minutes * 60))? {
+            warn!(
+                "{} was claimed over {} minutes ago, queued it again",
+                name, minutes
+            );
+        }
+    }
+
+    let names: Vec<&String> = migration_input
+        .targets
+        .iter()
+        .filter(|(name, target)| !target.skip && args.filter.matches(name, target))
+        .map(|(name, _)| name)
+        .collect();
+    let added = queue.enqueue(names)?;
+    info!("Queued {} targets in {}", added, args.queue_dir);
+
+    Ok(())
+}
+
+#[derive(Args, Debug)]
+pub struct CollectResultsArgs {
+    /// A TOML file that defines the input needed to run a migration. This file will be updated
+    /// with the results the workers finished.
+    #[clap(long)]
+    pub migration_definition: String,
+
+    /// Directory shared by the workers.
+    #[clap(long)]
+    pub queue_dir: String,
+}
+
+pub async fn run_collect_results(args: CollectResultsArgs) -> AnyResult<()> {
+    let mut migration_input: MigrationFile =
+        crate::state::load_migration(&args.migration_definition)?;
+    let queue = DirectoryQueue::new(&PathBuf::from(&args.queue_dir))?;
+
+    let mut collected = 0;
+    for (name, result) in queue.results()? {
+        match migration_input.targets.get_mut(&name) {
+            Some(target) => {
+                target.merge_results(result);
+                collected += 1;
+            }
+            None => warn!("{} isn't a target of the migration, ignoring it", name),
+        }
+    }
+
+    crate::state::save_migration(&args.migration_definition, &migration_input)?;
+
+    println!("# Collected {} Results", collected);
+
+    Ok(())
+}
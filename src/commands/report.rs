@@ -0,0 +1,255 @@
+use clap::{Args, ValueEnum};
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::Result as AnyResult;
+use tracing::info;
+
+use crate::filter::TargetFilter;
+use crate::models::*;
+
+/// How many lines from the end of a workspace's `stderr.log` are shown.
+const LOG_EXCERPT_LINES: usize = 20;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// A standalone page, with the styles inline so it can be shared as one file.
+    Html,
+}
+
+#[derive(Args, Debug)]
+pub struct ReportArgs {
+    /// A TOML file that was used with `run-migration`.
+    #[clap(long)]
+    pub migration_definition: String,
+
+    #[clap(long, value_enum, default_value = "html")]
+    pub format: ReportFormat,
+
+    /// Folder the migration was run in, the logs of the workspaces are excerpted from it.
+    #[clap(long = "work-directory", default_value("work-dir"))]
+    pub work_directory_root: String,
+
+    /// Where the report is written.
+    #[clap(long, default_value("migration-report.html"))]
+    pub report_file: String,
+
+    #[clap(flatten)]
+    pub filter: TargetFilter,
+}
+
+pub async fn run_report(args: ReportArgs) -> AnyResult<()> {
+    let mut migration_input: MigrationFile =
+        crate::state::load_migration(&args.migration_definition)?;
+    migration_input
+        .targets
+        .retain(|name, target| args.filter.matches(name, target));
+    let report = match args.format {
+        ReportFormat::Html => render_html(
+            &migration_input.definition,
+            &migration_input.targets,
+            Path::new(&args.work_directory_root),
+        ),
+    };
+
+    std::fs::write(&args.report_file, report)?;
+    info!("Wrote the report to {}", args.report_file);
+
+    Ok(())
+}
+
+/// Where the target is at, from the last thing recorded for it.
+pub(crate) fn target_status(target: &TargetDescription) -> String {
+    match target {
+        TargetDescription {
+            pull_request: Some(pull),
+            ..
+        } => match &pull.state {
+            Some(state) => state.to_string(),
+            None => "open".to_owned(),
+        },
+        TargetDescription {
+            pushed: Some(_), ..
+        } => "pushed".to_owned(),
+        TargetDescription {
+            patches: Some(_), ..
+        } => "patches".to_owned(),
+        TargetDescription {
+            manual_issue: Some(_),
+            ..
+        } => "manual-issue".to_owned(),
+        TargetDescription { skip: true, .. } => "skipped".to_owned(),
+        TargetDescription {
+            metrics: Some(_), ..
+        } => "no-pull-request".to_owned(),
+        _ => "not-run".to_owned(),
+    }
+}
+
+fn render_html(
+    definition: &MigrationDefinition,
+    targets: &BTreeMap<String, TargetDescription>,
+    work_dir: &Path,
+) -> String {
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    let mut rows = String::new();
+    for (name, target) in targets {
+        let status = target_status(target);
+        *counts.entry(status.clone()).or_default() += 1;
+
+        let link = match (&target.pull_request, &target.manual_issue) {
+            (Some(pull), _) => format!("<a href=\"{}\">#{}</a>", escape(&pull.url), pull.pr_number),
+            (None, Some(issue)) => format!(
+                "<a href=\"{}\">issue #{}</a>",
+                escape(&issue.url),
+                issue.issue_number
+            ),
+            (None, None) => String::new(),
+        };
+        let steps: Vec<String> = target
+            .metrics
+            .iter()
+            .flat_map(|it| it.steps.iter())
+            .map(|step| {
+                let exit = match step.exit_code {
+                    Some(code) if code != 0 => format!(", exit {}", code),
+                    _ => String::new(),
+                };
+                format!(
+                    "{} {}{}",
+                    escape(&step.name),
+                    crate::commands::stats::format_duration(step.duration_ms),
+                    exit
+                )
+            })
+            .collect();
+        let diff = target
+            .metrics
+            .as_ref()
+            .and_then(|it| it.diff.as_ref())
+            .map(|it| format!("+{} -{}", it.insertions, it.deletions))
+            .unwrap_or_default();
+        let log = match log_excerpt(&work_dir.join(name).join("stderr.log")) {
+            Some(log) => format!(
+                "<details><summary>stderr</summary><pre>{}</pre></details>",
+                escape(&log)
+            ),
+            None => String::new(),
+        };
+
+        rows.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td class=\"status {}\">{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            escape(name),
+            escape(&target.repo),
+            escape(&status),
+            escape(&status),
+            link,
+            steps.join("<br>"),
+            diff,
+            log
+        ));
+    }
+
+    let summary: Vec<String> = counts
+        .iter()
+        .map(|(status, count)| format!("<li>{}: {}</li>", escape(status), count))
+        .collect();
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em; }}
+table {{ border-collapse: collapse; width: 100%; }}
+th, td {{ border: 1px solid #ddd; padding: 0.4em; text-align: left; vertical-align: top; }}
+th {{ background: #f4f4f4; }}
+pre {{ max-width: 60em; overflow-x: auto; }}
+.merged {{ color: #6f42c1; }}
+.mergeable {{ color: #1a7f37; }}
+.checks-failed, .conflicting, .closed-without-merge {{ color: #cf222e; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<p>Branch <code>{branch}</code>, {total} targets.</p>
+<ul>
+{summary}
+</ul>
+<table>
+<tr><th>Target</th><th>Repo</th><th>Status</th><th>PR</th><th>Steps</th><th>Diff</th><th>Log</th></tr>
+{rows}</table>
+</body>
+</html>
+"#,
+        title = escape(&definition.pr.title),
+        branch = escape(&definition.checkout.branch_name),
+        total = targets.len(),
+        summary = summary.join("\n"),
+        rows = rows
+    )
+}
+
+/// The end of a log, where the error that stopped the target usually is.
+fn log_excerpt(path: &Path) -> Option<String> {
+    let log = std::fs::read_to_string(path).ok()?;
+    let lines: Vec<&str> = log.lines().collect();
+    if lines.is_empty() {
+        return None;
+    }
+
+    let start = lines.len().saturating_sub(LOG_EXCERPT_LINES);
+    Some(lines[start..].join("\n"))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[test]
+fn validate_render_html() {
+    let file: MigrationFile = toml::from_str(
+        r#"
+        [targets.clu]
+        repo = "git@github.com:ethankhall/clu.git"
+
+        [targets.clu.pull-request]
+        pr_number = 7
+        url = "https://github.com/ethankhall/clu/pull/7"
+        state = "merged"
+
+        [targets.other]
+        repo = "git@github.com:ethankhall/other.git"
+
+        [checkout]
+        branch-name = "clu/update"
+        pre-flight = "/usr/bin/true"
+
+        [pr]
+        title = "Update <deps>"
+        description = "Update"
+
+        [[steps]]
+        name = "update"
+        migration-script = "update.sh"
+        "#,
+    )
+    .unwrap();
+
+    let html = render_html(
+        &file.definition,
+        &file.targets,
+        Path::new("/does/not/exist"),
+    );
+    assert!(html.contains("<title>Update &lt;deps&gt;</title>"));
+    assert!(html.contains("<a href=\"https://github.com/ethankhall/clu/pull/7\">#7</a>"));
+    assert!(html.contains("<li>merged: 1</li>"));
+    assert!(html.contains("<li>not-run: 1</li>"));
+    assert_eq!("merged", target_status(&file.targets["clu"]));
+}
@@ -0,0 +1,168 @@
+use clap::Args;
+
+use anyhow::Result as AnyResult;
+use tracing::{info, warn};
+
+use crate::filter::TargetFilter;
+use crate::github::{GitHubHosts, PullStatus};
+use crate::models::*;
+
+/// Headline of the empty commit pushed when the checks can't be re-requested.
+const EMPTY_COMMIT_HEADLINE: &str = "Run the checks again";
+
+#[derive(Args, Debug)]
+pub struct RerunChecksArgs {
+    /// A TOML file that was used with `run-migration`.
+    #[clap(long)]
+    pub migration_definition: String,
+
+    /// Token to be used when talking to GitHub
+    #[clap(long, env = "GITHUB_TOKEN")]
+    pub github_token: String,
+
+    /// Don't push an empty commit to the PR's whose checks can't be re-requested, like checks
+    /// reported by CI outside of GitHub.
+    #[clap(long)]
+    pub no_empty_commit: bool,
+
+    /// Only list the PR's whose checks would be run again.
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// Go ahead without asking to confirm first, like in CI.
+    #[clap(long, short = 'y')]
+    pub yes: bool,
+
+    #[clap(flatten)]
+    pub filter: TargetFilter,
+}
+
+/// Runs the failed checks of every PR with failing checks again, instead of re-running them by
+/// hand on each PR. Only PR's on GitHub have checks that can be re-run.
+pub async fn run_rerun_checks(args: RerunChecksArgs) -> AnyResult<()> {
+    let migration_input: MigrationFile = crate::state::load_migration(&args.migration_definition)?;
+    let github_hosts = GitHubHosts::new(&args.github_token, &migration_input.host_configs())?;
+
+    let mut names = Vec::new();
+    let mut pulls = Vec::new();
+    for (name, target) in &migration_input.targets {
+        if !args.filter.matches(name, target) {
+            continue;
+        }
+        if let Some(pull) = &target.pull_request {
+            names.push(name);
+            pulls.push((pull.github_repo(&target.repo)?, pull.pr_number));
+        }
+    }
+
+    let states = github_hosts
+        .fetch_pull_states(&pulls, &migration_input.definition.ignored_check_patterns())
+        .await?;
+    let failing: Vec<_> = names
+        .iter()
+        .zip(pulls.iter())
+        .zip(states.iter())
+        .filter(|(_, state)| state.status == PullStatus::ChecksFailed)
+        .collect();
+
+    if args.dry_run || failing.is_empty() {
+        let listed: Vec<String> = failing
+            .iter()
+            .map(|(_, state)| format!("- {}", state.permalink))
+            .collect();
+        println!("# Failing Checks\n\n{}", listed.join("\n"));
+        return Ok(());
+    }
+
+    crate::prompt::confirm(
+        &format!(
+            "About to run the checks of {} PR's of {} again",
+            failing.len(),
+            args.migration_definition
+        ),
+        args.yes,
+    )?;
+
+    let branch = migration_input
+        .definition
+        .checkout
+        .head_branch(&args.migration_definition);
+    let mut rerequested: Vec<String> = Vec::new();
+    let mut empty_commits: Vec<String> = Vec::new();
+    let mut failed: Vec<String> = Vec::new();
+    for ((name, (repo, _)), state) in failing {
+        let client = match github_hosts.client_for(repo) {
+            Ok(client) => client,
+            Err(e) => {
+                warn!(
+                    "{}: unable to re-run the checks of {}: {}",
+                    name, state.permalink, e
+                );
+                failed.push(format!("- {}: {}", state.permalink, e));
+                continue;
+            }
+        };
+
+        match client
+            .rerequest_failed_check_suites(repo, &state.head_oid)
+            .await
+        {
+            Ok(count) if count > 0 => {
+                info!("{}: re-requested {} check suites", name, count);
+                rerequested.push(format!("- {}", state.permalink));
+                continue;
+            }
+            Ok(_) => {}
+            Err(e) => warn!(
+                "{}: unable to re-request the checks of {}: {:?}",
+                name, state.permalink, e
+            ),
+        }
+
+        if args.no_empty_commit {
+            failed.push(format!(
+                "- {}: no check suites could be re-requested",
+                state.permalink
+            ));
+            continue;
+        }
+
+        let head_ref = match &migration_input.targets[name.as_str()].pull_request {
+            Some(pull) if !pull.head_ref.is_empty() => pull.head_ref.clone(),
+            _ => branch.clone(),
+        };
+        match client
+            .commit_files(repo, &head_ref, EMPTY_COMMIT_HEADLINE, Vec::new())
+            .await
+        {
+            Ok(commit) => {
+                info!("{}: pushed an empty commit {}", name, commit);
+                empty_commits.push(format!("- {}", state.permalink));
+            }
+            Err(e) => {
+                warn!("{}: unable to push to {}: {:?}", name, state.permalink, e);
+                failed.push(format!("- {}: {}", state.permalink, e));
+            }
+        }
+    }
+
+    println!(
+        "# Re-run Checks
+## Re-requested
+
+{}
+
+## Empty Commits
+
+{}
+
+## Failed
+
+{}",
+        rerequested.join("\n"),
+        empty_commits.join("\n"),
+        failed.join("\n")
+    );
+
+    Ok(())
+}
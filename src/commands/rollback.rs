@@ -0,0 +1,319 @@
+use clap::Args;
+
+use anyhow::{bail, Result as AnyResult};
+use std::collections::BTreeMap;
+use tracing::{info, warn};
+
+use crate::filter::TargetFilter;
+use crate::github::{GitHubHosts, GitHubRepo, PullState, PullStatus};
+use crate::glob::Glob;
+use crate::models::*;
+
+#[derive(Args, Debug)]
+pub struct RollbackArgs {
+    /// A TOML file that was used with `run-migration`. The PR's that were rolled back are removed
+    /// from it.
+    #[clap(long)]
+    pub migration_definition: String,
+
+    /// Token to be used when talking to GitHub
+    #[clap(long, env = "GITHUB_TOKEN")]
+    pub github_token: String,
+
+    /// Only list the PR's and branches that would be rolled back.
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// Go ahead without asking to confirm first, like in CI.
+    #[clap(long, short = 'y')]
+    pub yes: bool,
+
+    #[clap(flatten)]
+    pub filter: TargetFilter,
+}
+
+/// What rolling back a target does, worked out before anything is changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Rollback {
+    /// Close the PR when it's still open, then delete its branch.
+    Pull { close: bool, head_ref: String },
+    /// Delete the branch of a push-only target, which has no PR.
+    Pushed(PushedBranch),
+    /// Merged PR's are left alone and stay in the migration file.
+    Merged,
+    /// The PR can't be found, so there's nothing to roll back.
+    Missing,
+}
+
+/// Plans the rollback of a target from the state of its PR, or `None` when the target has
+/// neither a PR nor a pushed branch. `branch` is used when the PR didn't record its head.
+fn plan_rollback(
+    target: &TargetDescription,
+    status: Option<&PullStatus>,
+    branch: &str,
+) -> Option<Rollback> {
+    if let Some(pull) = &target.pull_request {
+        return Some(match status {
+            Some(PullStatus::Merged) => Rollback::Merged,
+            Some(PullStatus::Missing) | None => Rollback::Missing,
+            Some(status) => Rollback::Pull {
+                close: !status.is_closed(),
+                head_ref: if pull.head_ref.is_empty() {
+                    branch.to_owned()
+                } else {
+                    pull.head_ref.clone()
+                },
+            },
+        });
+    }
+    target.pushed.clone().map(Rollback::Pushed)
+}
+
+/// Removes what a finished rollback undid from the target, so the next `run-migration` starts
+/// from scratch.
+fn forget(target: &mut TargetDescription, rollback: &Rollback) {
+    match rollback {
+        Rollback::Pull { .. } => target.pull_request = None,
+        Rollback::Pushed(_) => target.pushed = None,
+        Rollback::Merged | Rollback::Missing => {}
+    }
+}
+
+/// Undoes an abandoned migration: closes its open PR's, deletes their branches and the branches
+/// of push-only targets, and forgets them, so nobody has to clean up hundreds of repos by hand.
+/// Merged PR's are left alone, and PR's on other forges than GitHub are listed as failed.
+pub async fn run_rollback(args: RollbackArgs) -> AnyResult<()> {
+    let mut migration_input: MigrationFile =
+        crate::state::load_migration(&args.migration_definition)?;
+    let github_hosts = GitHubHosts::new(&args.github_token, &migration_input.host_configs())?;
+
+    let mut names = Vec::new();
+    let mut pulls = Vec::new();
+    for (name, target) in &migration_input.targets {
+        if !args.filter.matches(name, target) {
+            continue;
+        }
+        if let Some(pull) = &target.pull_request {
+            names.push(name.clone());
+            pulls.push((pull.github_repo(&target.repo)?, pull.pr_number));
+        }
+    }
+
+    let states = github_hosts
+        .fetch_pull_states(&pulls, &migration_input.definition.ignored_check_patterns())
+        .await?;
+    let pull_states: BTreeMap<&String, (&GitHubRepo, &PullState)> = names
+        .iter()
+        .zip(pulls.iter().map(|(repo, _)| repo).zip(states.iter()))
+        .collect();
+
+    let branch = migration_input
+        .definition
+        .checkout
+        .head_branch(&args.migration_definition);
+    let mut merged: Vec<String> = Vec::new();
+    let mut failed: Vec<String> = Vec::new();
+    let mut rollbacks = Vec::new();
+    for (name, target) in &migration_input.targets {
+        if !args.filter.matches(name, target) {
+            continue;
+        }
+        let pull = pull_states.get(name).copied();
+        let rollback = match plan_rollback(target, pull.map(|(_, state)| &state.status), &branch) {
+            Some(rollback) => rollback,
+            None => continue,
+        };
+        let described = describe(name, pull, &rollback);
+        match rollback {
+            Rollback::Merged => merged.push(format!("- {}", described)),
+            Rollback::Missing => failed.push(format!("- {}: can't be found", described)),
+            _ => rollbacks.push((name.clone(), pull, rollback, described)),
+        }
+    }
+
+    if args.dry_run || rollbacks.is_empty() {
+        let listed: Vec<String> = rollbacks
+            .iter()
+            .map(|(_, _, _, described)| format!("- {}", described))
+            .collect();
+        println!(
+            "# Rollback\n\n{}\n\nMerged PR's are left alone:\n\n{}\n\nUnable to roll back:\n\n{}",
+            listed.join("\n"),
+            merged.join("\n"),
+            failed.join("\n")
+        );
+        return Ok(());
+    }
+
+    crate::prompt::confirm(
+        &format!(
+            "About to roll back {} PR's and pushed branches of {}, and forget them",
+            rollbacks.len(),
+            args.migration_definition
+        ),
+        args.yes,
+    )?;
+
+    let mut rolled_back: Vec<String> = Vec::new();
+    for (name, pull, rollback, described) in rollbacks {
+        let result = match (&rollback, pull) {
+            (Rollback::Pull { close, head_ref }, Some((repo, state))) => {
+                rollback_pull(&github_hosts, repo, state, *close, head_ref).await
+            }
+            (Rollback::Pushed(pushed), _) => delete_pushed_branch(pushed).await,
+            _ => continue,
+        };
+        match result {
+            Ok(_) => {
+                info!("{}: rolled back {}", name, described);
+                rolled_back.push(format!("- {}", described));
+                if let Some(target) = migration_input.targets.get_mut(&name) {
+                    forget(target, &rollback);
+                }
+            }
+            Err(e) => {
+                warn!("{}: unable to roll back {}: {:?}", name, described, e);
+                failed.push(format!("- {}: {}", described, e));
+            }
+        }
+    }
+
+    crate::state::save_migration(&args.migration_definition, &migration_input)?;
+
+    println!(
+        "# Rollback
+## Rolled Back
+
+{}
+
+## Merged
+
+{}
+
+## Failed
+
+{}",
+        rolled_back.join("\n"),
+        merged.join("\n"),
+        failed.join("\n")
+    );
+
+    Ok(())
+}
+
+/// How a rolled back target is listed: the PR's link, or the pushed branch and where it went.
+fn describe(name: &str, pull: Option<(&GitHubRepo, &PullState)>, rollback: &Rollback) -> String {
+    match (rollback, pull) {
+        (Rollback::Pushed(pushed), _) => format!("`{}` pushed to {}", pushed.branch, pushed.remote),
+        (_, Some((_, state))) => state.permalink.clone(),
+        (_, None) => name.to_owned(),
+    }
+}
+
+/// Closes the PR when `close` is set, then deletes its branch when it's still there.
+async fn rollback_pull(
+    github_hosts: &GitHubHosts,
+    repo: &GitHubRepo,
+    state: &PullState,
+    close: bool,
+    head_ref: &str,
+) -> AnyResult<()> {
+    let client = github_hosts.client_for(repo)?;
+    if close {
+        client.close_pull_request(&state.id).await?;
+    }
+
+    let branches = client.list_branches(repo, &Glob::new(head_ref)).await?;
+    for branch in branches.iter().filter(|it| it.name == head_ref) {
+        client.delete_branch(branch).await?;
+    }
+
+    Ok(())
+}
+
+/// Deletes the branch of a push-only target with `git push --delete`, since the remote can be
+/// any git remote. A branch that's already gone is fine.
+async fn delete_pushed_branch(pushed: &PushedBranch) -> AnyResult<()> {
+    // `git push` only runs inside a repo, any empty one will do
+    let scratch = std::env::temp_dir().join(format!("clu-rollback-{}", std::process::id()));
+    git2::Repository::init_bare(&scratch)?;
+
+    let output = async_process::Command::new("git")
+        .arg("-C")
+        .arg(&scratch)
+        .args(["push", &pushed.remote, "--delete", &pushed.branch])
+        .output()
+        .await;
+    let _ = std::fs::remove_dir_all(&scratch);
+    let output = output?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() && !stderr.contains("remote ref does not exist") {
+        bail!("unable to delete `{}`: {}", pushed.branch, stderr.trim());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn validate_plan_rollback() {
+    let mut target = TargetDescription::new("ethankhall/clu");
+    assert_eq!(None, plan_rollback(&target, None, "clu-branch"));
+
+    target.pull_request = Some(CreatedPullRequest {
+        pr_number: 1,
+        url: "https://github.com/ethankhall/clu/pull/1".to_owned(),
+        owner: "ethankhall".to_owned(),
+        repo: "clu".to_owned(),
+        head_ref: String::new(),
+        created_at: None,
+        state: None,
+        body_gist: None,
+    });
+    assert_eq!(
+        Some(Rollback::Pull {
+            close: true,
+            head_ref: "clu-branch".to_owned()
+        }),
+        plan_rollback(&target, Some(&PullStatus::Mergeable), "clu-branch")
+    );
+    assert_eq!(
+        Some(Rollback::Pull {
+            close: false,
+            head_ref: "clu-branch".to_owned()
+        }),
+        plan_rollback(&target, Some(&PullStatus::ClosedWithoutMerge), "clu-branch")
+    );
+    assert_eq!(
+        Some(Rollback::Merged),
+        plan_rollback(&target, Some(&PullStatus::Merged), "clu-branch")
+    );
+    assert_eq!(
+        Some(Rollback::Missing),
+        plan_rollback(&target, Some(&PullStatus::Missing), "clu-branch")
+    );
+
+    target.pull_request.as_mut().unwrap().head_ref = "older-branch".to_owned();
+    let rollback = plan_rollback(&target, Some(&PullStatus::Mergeable), "clu-branch").unwrap();
+    assert_eq!(
+        Rollback::Pull {
+            close: true,
+            head_ref: "older-branch".to_owned()
+        },
+        rollback
+    );
+    forget(&mut target, &Rollback::Merged);
+    assert!(target.pull_request.is_some());
+    forget(&mut target, &rollback);
+    assert!(target.pull_request.is_none());
+
+    let pushed = PushedBranch {
+        remote: "https://git.example.com/clu.git".to_owned(),
+        branch: "clu-branch".to_owned(),
+        commit: "abc123".to_owned(),
+    };
+    target.pushed = Some(pushed.clone());
+    let rollback = plan_rollback(&target, None, "clu-branch").unwrap();
+    assert_eq!(Rollback::Pushed(pushed), rollback);
+    forget(&mut target, &rollback);
+    assert!(target.pushed.is_none());
+}
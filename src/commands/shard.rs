@@ -0,0 +1,50 @@
+use clap::Args;
+
+use anyhow::Result as AnyResult;
+use tracing::warn;
+
+use crate::models::*;
+
+#[derive(Args, Debug)]
+pub struct MergeResultsArgs {
+    /// A TOML file that defines the input needed to run a migration. This file will be updated
+    /// with the results of the shards.
+    #[clap(long)]
+    pub migration_definition: String,
+
+    /// The results files written by `run-migration --shard`.
+    #[clap(required = true)]
+    pub shard_results: Vec<String>,
+}
+
+pub async fn run_merge_results(args: MergeResultsArgs) -> AnyResult<()> {
+    let mut migration_input: MigrationFile =
+        crate::state::load_migration(&args.migration_definition)?;
+
+    let mut merged = 0;
+    for shard_results in &args.shard_results {
+        let shard: MigrationFile = crate::state::load_migration(shard_results)?;
+        for (name, result) in shard.targets {
+            match migration_input.targets.get_mut(&name) {
+                Some(target) => {
+                    target.merge_results(result);
+                    merged += 1;
+                }
+                None => warn!(
+                    "{} from {} isn't a target of the migration, ignoring it",
+                    name, shard_results
+                ),
+            }
+        }
+    }
+
+    crate::state::save_migration(&args.migration_definition, &migration_input)?;
+
+    println!(
+        "# Merged {} Results from {} Shards",
+        merged,
+        args.shard_results.len()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,242 @@
+use clap::Args;
+
+use std::collections::BTreeMap;
+
+use anyhow::Result as AnyResult;
+
+use crate::filter::TargetFilter;
+use crate::models::*;
+
+/// A step is reported as an outlier when it took this many times longer than the median.
+const DURATION_OUTLIER_FACTOR: u64 = 10;
+
+/// A diff is reported as an outlier when it is this many times larger than the median.
+const DIFF_OUTLIER_FACTOR: u64 = 100;
+
+/// How many of the targets that took the longest are listed.
+const SLOWEST_TARGETS: usize = 10;
+
+#[derive(Args, Debug)]
+pub struct StatsArgs {
+    /// A TOML file that was used with `run-migration`.
+    #[clap(long)]
+    pub migration_definition: String,
+
+    #[clap(flatten)]
+    pub filter: TargetFilter,
+}
+
+pub async fn run_stats(args: StatsArgs) -> AnyResult<()> {
+    let migration_input: MigrationFile = crate::state::load_migration(&args.migration_definition)?;
+
+    let mut step_order: Vec<String> = Vec::new();
+    let mut durations: BTreeMap<String, Vec<(u64, &str)>> = BTreeMap::new();
+    let mut exit_codes: BTreeMap<String, BTreeMap<i32, u64>> = BTreeMap::new();
+    let mut diffs: Vec<(u64, &str)> = Vec::new();
+    let mut zero_diff: Vec<String> = Vec::new();
+    let mut totals: Vec<(u64, &str)> = Vec::new();
+
+    for (name, target) in &migration_input.targets {
+        if !args.filter.matches(name, target) {
+            continue;
+        }
+        let metrics = match &target.metrics {
+            Some(metrics) => metrics,
+            None => continue,
+        };
+
+        totals.push((metrics.steps.iter().map(|it| it.duration_ms).sum(), name));
+        for step in &metrics.steps {
+            if !durations.contains_key(&step.name) {
+                step_order.push(step.name.clone());
+            }
+            durations
+                .entry(step.name.clone())
+                .or_default()
+                .push((step.duration_ms, name));
+            if let Some(code) = step.exit_code {
+                *exit_codes
+                    .entry(step.name.clone())
+                    .or_default()
+                    .entry(code)
+                    .or_default() += 1;
+            }
+        }
+
+        if metrics.is_zero_diff() {
+            zero_diff.push(format!("- {}", name));
+        } else if let Some(diff) = &metrics.diff {
+            diffs.push((diff.lines_changed(), name));
+        }
+    }
+
+    let mut outliers: Vec<String> = Vec::new();
+
+    println!("# Migration Stats\n## Steps\n");
+    println!("| Step | Runs | p50 | p95 | Max | Exit Codes |");
+    println!("|------|------|-----|-----|-----|------------|");
+    for step_name in &step_order {
+        let mut step_durations = durations.remove(step_name).unwrap_or_default();
+        step_durations.sort();
+        let sorted: Vec<u64> = step_durations.iter().map(|(it, _)| *it).collect();
+        let median = percentile(&sorted, 50);
+
+        let codes: Vec<String> = exit_codes
+            .get(step_name)
+            .map(|codes| {
+                codes
+                    .iter()
+                    .map(|(code, count)| format!("{}: {}", code, count))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        println!(
+            "| {} | {} | {} | {} | {} | {} |",
+            step_name,
+            sorted.len(),
+            format_duration(median),
+            format_duration(percentile(&sorted, 95)),
+            format_duration(sorted.last().copied().unwrap_or_default()),
+            codes.join(", ")
+        );
+
+        if median > 0 {
+            for (duration, target) in &step_durations {
+                if *duration >= median * DURATION_OUTLIER_FACTOR {
+                    outliers.push(format!(
+                        "- {}: `{}` took {} (median {})",
+                        target,
+                        step_name,
+                        format_duration(*duration),
+                        format_duration(median)
+                    ));
+                }
+            }
+        }
+    }
+
+    diffs.sort();
+    let sorted: Vec<u64> = diffs.iter().map(|(it, _)| *it).collect();
+    let median = percentile(&sorted, 50);
+    println!(
+        "\n## Diff Size\n\nLines changed p50: {}, p95: {}, max: {}",
+        median,
+        percentile(&sorted, 95),
+        sorted.last().copied().unwrap_or_default()
+    );
+
+    if median > 0 {
+        for (lines, target) in &diffs {
+            if *lines >= median * DIFF_OUTLIER_FACTOR {
+                outliers.push(format!(
+                    "- {}: changed {} lines (median {})",
+                    target, lines, median
+                ));
+            }
+        }
+    }
+
+    // Slowest first, ties by name
+    totals.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(b.1)));
+    println!("\n## Slowest Targets\n");
+    println!("| Target | Total |");
+    println!("|--------|-------|");
+    for (total, target) in totals.iter().take(SLOWEST_TARGETS) {
+        println!("| {} | {} |", target, format_duration(*total));
+    }
+
+    println!("\n## Failures\n");
+    println!("| Step | Error | Targets |");
+    println!("|------|-------|---------|");
+    for ((step, kind), count) in failure_counts(&migration_input.targets, &args.filter) {
+        println!("| {} | {} | {} |", step, kind, count);
+    }
+
+    println!("\n## Outliers\n\n{}", outliers.join("\n"));
+    println!(
+        "\n## Pre-flight Passed Without Changes\n\n{}",
+        zero_diff.join("\n")
+    );
+
+    Ok(())
+}
+
+/// How many targets failed their last run, by the step and the kind of error. Files from before
+/// the kind was recorded have it as `unknown`.
+fn failure_counts(
+    targets: &BTreeMap<String, TargetDescription>,
+    filter: &TargetFilter,
+) -> BTreeMap<(String, String), u64> {
+    let mut counts = BTreeMap::new();
+    for (name, target) in targets {
+        if !filter.matches(name, target) {
+            continue;
+        }
+        let run = match &target.last_run {
+            Some(run) if run.outcome == RunOutcome::Failed => run,
+            _ => continue,
+        };
+        let kind = run.error_kind.as_deref().unwrap_or("unknown");
+        *counts
+            .entry((run.step.clone(), kind.to_owned()))
+            .or_default() += 1;
+    }
+
+    counts
+}
+
+/// Nearest-rank percentile of an already sorted list.
+pub(crate) fn percentile(sorted: &[u64], pct: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+
+    let rank = (pct * sorted.len()).div_ceil(100).max(1);
+    sorted[rank.min(sorted.len()) - 1]
+}
+
+pub(crate) fn format_duration(millis: u64) -> String {
+    format!("{:.1}s", millis as f64 / 1000.0)
+}
+
+#[test]
+fn validate_percentile() {
+    assert_eq!(0, percentile(&[], 50));
+    assert_eq!(3, percentile(&[1, 2, 3, 4, 5], 50));
+    assert_eq!(5, percentile(&[1, 2, 3, 4, 5], 95));
+    assert_eq!(1, percentile(&[1, 2, 3, 4, 5], 1));
+}
+
+#[test]
+fn validate_failure_counts() {
+    let failed = |step: &str, kind: Option<&str>| {
+        let mut target = TargetDescription::new("git@github.com:ethankhall/clu.git");
+        target.last_run = Some(RunResult {
+            outcome: RunOutcome::Failed,
+            step: step.to_owned(),
+            error: None,
+            error_kind: kind.map(|it| it.to_owned()),
+        });
+        target
+    };
+    let mut targets = BTreeMap::new();
+    targets.insert("a".to_owned(), failed("update", Some("step-exited")));
+    targets.insert("b".to_owned(), failed("update", Some("step-exited")));
+    targets.insert("c".to_owned(), failed("checkout", None));
+    targets.insert(
+        "d".to_owned(),
+        TargetDescription::new("git@github.com:ethankhall/clu.git"),
+    );
+
+    let counts = failure_counts(&targets, &TargetFilter::default());
+    assert_eq!(2, counts.len());
+    assert_eq!(
+        Some(&2),
+        counts.get(&("update".to_owned(), "step-exited".to_owned()))
+    );
+    assert_eq!(
+        Some(&1),
+        counts.get(&("checkout".to_owned(), "unknown".to_owned()))
+    );
+}
@@ -0,0 +1,176 @@
+use clap::Args;
+
+use std::collections::BTreeMap;
+
+use anyhow::Result as AnyResult;
+
+use crate::filter::TargetFilter;
+use crate::models::*;
+
+#[derive(Args, Debug)]
+pub struct StatusArgs {
+    /// A TOML file that defines the input needed to run a migration. It isn't changed.
+    #[clap(long)]
+    pub migration_definition: String,
+
+    #[clap(flatten)]
+    pub filter: TargetFilter,
+}
+
+/// Summarizes what the migration file recorded about the targets, without asking any forge.
+/// `check-status` is what fetches the current state of the PR's.
+pub async fn run_status(args: StatusArgs) -> AnyResult<()> {
+    let mut migration_input: MigrationFile =
+        crate::state::load_migration(&args.migration_definition)?;
+    migration_input
+        .targets
+        .retain(|name, target| args.filter.matches(name, target));
+
+    println!("{}", summarize(&migration_input.targets));
+
+    Ok(())
+}
+
+fn summarize(targets: &BTreeMap<String, TargetDescription>) -> String {
+    let mut pull_requests = Vec::new();
+    let mut pushed = Vec::new();
+    let mut failed = Vec::new();
+    let mut stopped = Vec::new();
+    let mut skipped = Vec::new();
+    let mut not_run = Vec::new();
+
+    for (name, target) in targets {
+        if target.skip {
+            skipped.push(format!("- {}", name));
+            continue;
+        }
+
+        let published =
+            target.pull_request.is_some() || target.pushed.is_some() || target.patches.is_some();
+        if let Some(pull) = &target.pull_request {
+            let state = match &pull.state {
+                Some(state) => state.to_string(),
+                None => "not checked".to_owned(),
+            };
+            pull_requests.push(format!("- {}: {} ({})", name, pull.url, state));
+        } else if let Some(branch) = &target.pushed {
+            pushed.push(format!(
+                "- {}: pushed `{}` to {}",
+                name, branch.branch, branch.remote
+            ));
+        } else if let Some(series) = &target.patches {
+            pushed.push(format!("- {}: patches in {}", name, series.dir));
+        }
+
+        match &target.last_run {
+            Some(run) if run.outcome == RunOutcome::Failed => {
+                let error = match &run.error {
+                    Some(error) => format!(", {}", error),
+                    None => String::new(),
+                };
+                failed.push(format!("- {}: failed at `{}`{}", name, run.step, error));
+            }
+            Some(run) if run.outcome == RunOutcome::Stopped && !published => {
+                stopped.push(format!("- {}: stopped at `{}`", name, run.step))
+            }
+            None if !published => not_run.push(format!("- {}", name)),
+            _ => {}
+        }
+    }
+
+    format!(
+        "# Migration Status
+
+{} targets: {} with PR's, {} pushed, {} failed, {} stopped, {} skipped, {} not run.
+
+## Pull Requests
+
+{}
+
+## Pushed
+
+{}
+
+## Failed
+
+{}
+
+## Stopped
+
+{}
+
+## Skipped
+
+{}
+
+## Not Run
+
+{}",
+        targets.len(),
+        pull_requests.len(),
+        pushed.len(),
+        failed.len(),
+        stopped.len(),
+        skipped.len(),
+        not_run.len(),
+        pull_requests.join("\n"),
+        pushed.join("\n"),
+        failed.join("\n"),
+        stopped.join("\n"),
+        skipped.join("\n"),
+        not_run.join("\n")
+    )
+}
+
+#[test]
+fn validate_summarize() {
+    let file: MigrationFile = toml::from_str(
+        r#"
+        [targets.clu]
+        repo = "git@github.com:ethankhall/clu.git"
+
+        [targets.clu.pull-request]
+        pr_number = 7
+        url = "https://github.com/ethankhall/clu/pull/7"
+        state = "mergeable"
+
+        [targets.broken]
+        repo = "git@github.com:ethankhall/broken.git"
+
+        [targets.broken.last-run]
+        outcome = "failed"
+        step = "update"
+        error = "Migration step `update` exited non-zero."
+
+        [targets.later]
+        repo = "git@github.com:ethankhall/later.git"
+        skip = true
+
+        [targets.new]
+        repo = "git@github.com:ethankhall/new.git"
+
+        [checkout]
+        branch-name = "clu/update"
+        pre-flight = "/usr/bin/true"
+
+        [pr]
+        title = "Update"
+        description = "Update"
+
+        [[steps]]
+        name = "update"
+        migration-script = "update.sh"
+        "#,
+    )
+    .unwrap();
+
+    let summary = summarize(&file.targets);
+    assert!(summary
+        .contains("4 targets: 1 with PR's, 0 pushed, 1 failed, 0 stopped, 1 skipped, 1 not run."));
+    assert!(summary.contains("- clu: https://github.com/ethankhall/clu/pull/7 (mergeable)"));
+    assert!(
+        summary.contains("- broken: failed at `update`, Migration step `update` exited non-zero.")
+    );
+    assert!(summary.contains("## Skipped\n\n- later"));
+    assert!(summary.contains("## Not Run\n\n- new"));
+}
@@ -0,0 +1,338 @@
+use clap::Args;
+
+use std::fs::read_to_string;
+use std::io::Read;
+
+use anyhow::Result as AnyResult;
+use serde::Deserialize;
+use toml_edit::{value, Array, DocumentMut, Item, Table};
+
+use crate::github::GitHubRepo;
+use crate::models::*;
+
+#[derive(Args, Debug)]
+pub struct AddTargetArgs {
+    /// A TOML file that defines the input needed to run a migration. The repos are added to its
+    /// targets, and the rest of the file is left as it is.
+    #[clap(long)]
+    pub migration_definition: String,
+
+    /// Name for the target, instead of the name of the repo. Only used with a single repo.
+    #[clap(long)]
+    pub name: Option<String>,
+
+    /// Tag to give the new targets, can be given more than once.
+    #[clap(long = "tag")]
+    pub tags: Vec<String>,
+
+    /// The URLs of the repos to add, like `git@github.com:org/repo.git`.
+    #[clap(required = true)]
+    pub repos: Vec<String>,
+}
+
+pub async fn run_add_target(args: AddTargetArgs) -> AnyResult<()> {
+    if args.name.is_some() && args.repos.len() > 1 {
+        anyhow::bail!("--name can only be used when adding a single repo");
+    }
+
+    let new_targets = args
+        .repos
+        .iter()
+        .map(|repo| NewTarget {
+            name: args.name.clone(),
+            repo: repo.clone(),
+            tags: args.tags.clone(),
+        })
+        .collect();
+    let added = add_targets(&args.migration_definition, new_targets)?;
+    added.print();
+
+    Ok(())
+}
+
+#[derive(Args, Debug)]
+pub struct ImportArgs {
+    /// A TOML file that defines the input needed to run a migration. The repos are added to its
+    /// targets, and the rest of the file is left as it is.
+    #[clap(long)]
+    pub migration_definition: String,
+
+    /// File with a repo URL per line, or a JSON list. `-` reads it from stdin.
+    #[clap(long, default_value = "-")]
+    pub from: String,
+
+    /// Tag to give the imported targets, on top of the tags from the JSON.
+    #[clap(long = "tag")]
+    pub tags: Vec<String>,
+}
+
+pub async fn run_import(args: ImportArgs) -> AnyResult<()> {
+    let text = match args.from.as_str() {
+        "-" => {
+            let mut text = String::new();
+            std::io::stdin().read_to_string(&mut text)?;
+            text
+        }
+        path => read_to_string(path)?,
+    };
+
+    let mut new_targets = parse_import(&text)?;
+    for target in new_targets.iter_mut() {
+        target.tags.extend(args.tags.iter().cloned());
+    }
+    let added = add_targets(&args.migration_definition, new_targets)?;
+    added.print();
+
+    Ok(())
+}
+
+/// An entry of a JSON import, either the URL or the target with its name and tags.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum ImportedTarget {
+    Repo(String),
+    Target {
+        repo: String,
+        name: Option<String>,
+        #[serde(default)]
+        tags: Vec<String>,
+    },
+}
+
+/// A JSON list, or a URL per line where blank lines and `#` comments are ignored.
+fn parse_import(text: &str) -> AnyResult<Vec<NewTarget>> {
+    if text.trim_start().starts_with('[') {
+        let imported: Vec<ImportedTarget> = serde_json::from_str(text)?;
+        return Ok(imported
+            .into_iter()
+            .map(|it| match it {
+                ImportedTarget::Repo(repo) => NewTarget {
+                    name: None,
+                    repo,
+                    tags: Vec::new(),
+                },
+                ImportedTarget::Target { repo, name, tags } => NewTarget { name, repo, tags },
+            })
+            .collect());
+    }
+
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|it| !it.is_empty() && !it.starts_with('#'))
+        .map(|repo| NewTarget {
+            name: None,
+            repo: repo.to_owned(),
+            tags: Vec::new(),
+        })
+        .collect())
+}
+
+pub(crate) struct NewTarget {
+    pub name: Option<String>,
+    pub repo: String,
+    pub tags: Vec<String>,
+}
+
+pub(crate) struct AddedTargets {
+    pub added: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+impl AddedTargets {
+    pub fn print(&self) {
+        println!(
+            "# Added {} Targets\n\n{}\n\n## Skipped\n\n{}",
+            self.added.len(),
+            self.added.join("\n"),
+            self.skipped.join("\n")
+        );
+    }
+}
+
+/// Adds the targets by editing the migration file in place, so its comments and layout are
+/// kept. Repos that are already a target, even under another URL, are skipped.
+pub(crate) fn add_targets(
+    migration_definition: &str,
+    new_targets: Vec<NewTarget>,
+) -> AnyResult<AddedTargets> {
+    let migration: MigrationFile = crate::state::load_migration(migration_definition)?;
+    let mut document: DocumentMut = read_to_string(migration_definition)?.parse()?;
+    let mut known: Vec<(String, Option<GitHubRepo>)> = migration
+        .targets
+        .iter()
+        .map(|(name, target)| (name.clone(), target.github_repo().ok()))
+        .collect();
+
+    let targets = document
+        .entry("targets")
+        .or_insert_with(|| {
+            let mut table = Table::new();
+            table.set_implicit(true);
+            Item::Table(table)
+        })
+        .as_table_mut()
+        .ok_or_else(|| anyhow::anyhow!("`targets` in {} isn't a table", migration_definition))?;
+
+    let mut added = AddedTargets {
+        added: Vec::new(),
+        skipped: Vec::new(),
+    };
+    for new_target in new_targets {
+        let repo = match TargetDescription::new(&new_target.repo).github_repo() {
+            Ok(repo) => repo,
+            Err(e) => {
+                added.skipped.push(format!("- {}: {}", new_target.repo, e));
+                continue;
+            }
+        };
+
+        let existing = known
+            .iter()
+            .find(|(_, it)| it.as_ref().map_or(false, |it| is_same_repo(it, &repo)));
+        if let Some((name, _)) = existing {
+            added.skipped.push(format!(
+                "- {}: already the target {}",
+                new_target.repo, name
+            ));
+            continue;
+        }
+
+        let name = match new_target.name {
+            Some(name) => name,
+            None => target_name(&repo, |name| known.iter().any(|(it, _)| it == name)),
+        };
+        if known.iter().any(|(it, _)| it == &name) {
+            added
+                .skipped
+                .push(format!("- {}: the name {} is taken", new_target.repo, name));
+            continue;
+        }
+
+        let mut table = Table::new();
+        table["repo"] = value(&new_target.repo);
+        if !new_target.tags.is_empty() {
+            table["tags"] = value(new_target.tags.iter().collect::<Array>());
+        }
+        targets.insert(&name, Item::Table(table));
+
+        added.added.push(format!("- {}: {}", name, new_target.repo));
+        known.push((name, Some(repo)));
+    }
+
+    if !added.added.is_empty() {
+        std::fs::write(migration_definition, document.to_string())?;
+    }
+
+    Ok(added)
+}
+
+/// The same repo can be cloned over SSH or HTTPS, with or without `.git`.
+fn is_same_repo(a: &GitHubRepo, b: &GitHubRepo) -> bool {
+    let trimmed = |it: &GitHubRepo| it.repo.trim_end_matches(".git").to_lowercase();
+
+    a.clone_url == b.clone_url
+        || (a.host.eq_ignore_ascii_case(&b.host)
+            && a.owner.eq_ignore_ascii_case(&b.owner)
+            && trimmed(a) == trimmed(b))
+}
+
+/// The name of the repo, or `<owner>-<repo>` when another target already has that name.
+fn target_name<F: Fn(&str) -> bool>(repo: &GitHubRepo, is_taken: F) -> String {
+    let name = match repo.repo.as_str() {
+        "" => repo
+            .clone_url
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .to_owned(),
+        name => name.trim_end_matches(".git").to_owned(),
+    };
+    if !is_taken(&name) || repo.owner.is_empty() {
+        return name;
+    }
+
+    format!("{}-{}", repo.owner.replace('/', "-"), name)
+}
+
+#[test]
+fn validate_add_targets() {
+    let path = std::env::temp_dir().join(format!("clu-add-target-{}.toml", std::process::id()));
+    let path = path.to_str().unwrap();
+    std::fs::write(
+        path,
+        r#"# Upgrades the build
+[targets.clu]
+repo = "git@github.com:ethankhall/clu.git"
+
+[checkout]
+branch-name = "upgrade"
+pre-flight = "/usr/bin/true"
+
+[pr]
+title = "Upgrade"
+description = "Upgrade"
+
+[[steps]]
+name = "upgrade"
+migration-script = "/usr/bin/true"
+"#,
+    )
+    .unwrap();
+
+    let new_target = |repo: &str| NewTarget {
+        name: None,
+        repo: repo.to_owned(),
+        tags: vec!["java".to_owned()],
+    };
+    let added = add_targets(
+        path,
+        vec![
+            new_target("https://github.com/ethankhall/clu"),
+            new_target("git@github.com:other/clu.git"),
+            new_target("git@github.com:ethankhall/dummy-repo.git"),
+            new_target("git@github.com:ethankhall/dummy-repo.git"),
+        ],
+    )
+    .unwrap();
+    assert_eq!(
+        vec![
+            "- other-clu: git@github.com:other/clu.git",
+            "- dummy-repo: git@github.com:ethankhall/dummy-repo.git"
+        ],
+        added.added
+    );
+    assert_eq!(2, added.skipped.len());
+
+    let text = read_to_string(path).unwrap();
+    assert!(text.starts_with("# Upgrades the build\n"));
+    let migration = crate::state::load_migration(path).unwrap();
+    assert_eq!(
+        vec!["java".to_owned()],
+        migration.targets["dummy-repo"].tags
+    );
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn validate_parse_import() {
+    let targets = parse_import(
+        "# from the org query\ngit@github.com:org/a.git\n\n  git@github.com:org/b.git\n",
+    )
+    .unwrap();
+    let repos: Vec<&str> = targets.iter().map(|it| it.repo.as_str()).collect();
+    assert_eq!(
+        vec!["git@github.com:org/a.git", "git@github.com:org/b.git"],
+        repos
+    );
+
+    let targets = parse_import(
+        r#"["git@github.com:org/a.git", {"repo": "git@github.com:org/b.git", "name": "bee", "tags": ["go"]}]"#,
+    )
+    .unwrap();
+    assert_eq!(2, targets.len());
+    assert_eq!(None, targets[0].name);
+    assert_eq!(Some("bee".to_owned()), targets[1].name);
+    assert_eq!(vec!["go".to_owned()], targets[1].tags);
+}
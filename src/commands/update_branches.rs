@@ -0,0 +1,99 @@
+use clap::Args;
+
+use anyhow::Result as AnyResult;
+use tracing::{info, warn};
+
+use crate::filter::TargetFilter;
+use crate::github::{GitHubHosts, PullStatus};
+use crate::models::*;
+
+#[derive(Args, Debug)]
+pub struct UpdateBranchesArgs {
+    /// A TOML file that was used with `run-migration`.
+    #[clap(long)]
+    pub migration_definition: String,
+
+    /// Token to be used when talking to GitHub
+    #[clap(long, env = "GITHUB_TOKEN")]
+    pub github_token: String,
+
+    #[clap(flatten)]
+    pub filter: TargetFilter,
+}
+
+/// Brings PR's that fell behind their base branch up to date, without re-running the migration.
+pub async fn run_update_branches(args: UpdateBranchesArgs) -> AnyResult<()> {
+    let migration_input: MigrationFile = crate::state::load_migration(&args.migration_definition)?;
+    let github_hosts = GitHubHosts::new(&args.github_token, &migration_input.host_configs())?;
+
+    let mut names = Vec::new();
+    let mut pulls = Vec::new();
+    for (name, target) in &migration_input.targets {
+        if !args.filter.matches(name, target) {
+            continue;
+        }
+        if let Some(pull) = &target.pull_request {
+            names.push(name);
+            pulls.push((pull.github_repo(&target.repo)?, pull.pr_number));
+        }
+    }
+
+    let states = github_hosts
+        .fetch_pull_states(&pulls, &migration_input.definition.ignored_check_patterns())
+        .await?;
+
+    let mut updated: Vec<String> = Vec::new();
+    let mut failed: Vec<String> = Vec::new();
+    let mut conflicting: Vec<String> = Vec::new();
+
+    for ((name, (repo, pr_number)), state) in names.iter().zip(pulls.iter()).zip(states.iter()) {
+        // GitHub can't merge the base into a conflicting branch, these need a rebase
+        if state.status == PullStatus::Conflicting {
+            conflicting.push(format!("- {}", state.permalink));
+            continue;
+        }
+
+        if state.status == PullStatus::Missing {
+            failed.push(format!("- {}: can't be found", state.permalink));
+            continue;
+        }
+        if state.status.is_closed() || !state.behind {
+            continue;
+        }
+
+        match github_hosts
+            .client_for(repo)?
+            .update_pull_request_branch(repo, *pr_number, &state.head_oid)
+            .await
+        {
+            Ok(_) => {
+                info!("{}: updating {}", name, state.permalink);
+                updated.push(format!("- {}", state.permalink));
+            }
+            Err(e) => {
+                warn!("{}: unable to update {}: {:?}", name, state.permalink, e);
+                failed.push(format!("- {}: {}", state.permalink, e));
+            }
+        }
+    }
+
+    println!(
+        "# Branch Updates
+## Updated
+
+{}
+
+## Failed
+
+{}
+
+## Conflicting
+
+{}",
+        updated.join("\n"),
+        failed.join("\n"),
+        conflicting.join("\n")
+    );
+
+    Ok(())
+}
@@ -0,0 +1,218 @@
+use clap::Args;
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use anyhow::Result as AnyResult;
+use tracing::info;
+
+use crate::filter::TargetFilter;
+use crate::github::GitHubHosts;
+use crate::models::*;
+
+#[derive(Args, Debug)]
+pub struct ValidateArgs {
+    /// A TOML file that defines the input needed to run a migration. It isn't changed.
+    #[clap(long)]
+    pub migration_definition: String,
+
+    /// Token to be used when talking to GitHub
+    #[clap(long, env = "GITHUB_TOKEN")]
+    pub github_token: String,
+
+    /// Only check the migration file and the scripts, without asking the forges whether the
+    /// targets can be seen.
+    #[clap(long)]
+    pub skip_access_check: bool,
+
+    #[clap(flatten)]
+    pub filter: TargetFilter,
+}
+
+/// Checks everything a run depends on up front, and reports every problem at once instead of
+/// the run failing on the first one.
+pub async fn run_validate(args: ValidateArgs) -> AnyResult<()> {
+    let mut migration_input: MigrationFile =
+        match crate::state::load_migration(&args.migration_definition) {
+            Ok(migration) => migration,
+            Err(e) => anyhow::bail!("{} can't be read: {}", args.migration_definition, e),
+        };
+    migration_input
+        .targets
+        .retain(|name, target| args.filter.matches(name, target));
+
+    let mut definition = migration_input.definition.clone();
+    definition.checkout.branch_name = definition.checkout.head_branch(&args.migration_definition);
+    let mut problems = definition_problems(&definition, &migration_input.targets);
+
+    if !args.skip_access_check {
+        problems.extend(access_problems(&args.github_token, &migration_input).await);
+    }
+
+    if problems.is_empty() {
+        info!(
+            "{} and its {} targets are valid",
+            args.migration_definition,
+            migration_input.targets.len()
+        );
+        return Ok(());
+    }
+
+    println!("# Problems\n\n{}", problems.join("\n"));
+    anyhow::bail!(
+        "{} has {} problems",
+        args.migration_definition,
+        problems.len()
+    )
+}
+
+/// Problems with the migration itself, found without talking to any forge.
+fn definition_problems(
+    definition: &MigrationDefinition,
+    targets: &std::collections::BTreeMap<String, TargetDescription>,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    let branch = &definition.checkout.branch_name;
+    if !git2::Reference::is_valid_name(&format!("refs/heads/{}", branch)) {
+        problems.push(format!("- `{}` isn't a valid branch name", branch));
+    }
+
+    problems.extend(script_problem(
+        "pre-flight",
+        &definition.checkout.pre_flight,
+    ));
+    if definition.steps.is_empty() {
+        problems.push("- There are no steps".to_owned());
+    }
+    for step in &definition.steps {
+        problems.extend(script_problem(
+            &format!("step `{}`", step.name),
+            &step.migration_script,
+        ));
+    }
+    for reviewer in &definition.reviewers {
+        problems.extend(script_problem(
+            &format!("reviewer `{}`", reviewer.name),
+            &reviewer.command,
+        ));
+    }
+    if let Some(template) = &definition.pr.template {
+        if !Path::new(template).is_file() {
+            problems.push(format!("- The PR template `{}` doesn't exist", template));
+        }
+    }
+
+    for (name, target) in targets {
+        match target.github_repo() {
+            Err(e) => problems.push(format!("- Target {}: `{}` {}", name, target.repo, e)),
+            Ok(repo) if target.is_local() && !Path::new(&repo.clone_url).is_dir() => problems.push(
+                format!("- Target {}: `{}` isn't a directory", name, target.repo),
+            ),
+            Ok(_) => {}
+        }
+    }
+
+    problems
+}
+
+/// Scripts are run like the steps run them, so a relative path is relative to where `clu` is
+/// run from. Only the first word is checked, the rest are its arguments.
+fn script_problem(label: &str, command: &str) -> Option<String> {
+    let script = command.split_whitespace().next().unwrap_or_default();
+    if script.is_empty() {
+        return Some(format!("- The {} has no command", label));
+    }
+
+    match std::fs::metadata(script) {
+        Err(_) => Some(format!("- The {} script `{}` doesn't exist", label, script)),
+        Ok(metadata) if !metadata.is_file() => {
+            Some(format!("- The {} script `{}` isn't a file", label, script))
+        }
+        Ok(metadata) if metadata.permissions().mode() & 0o111 == 0 => Some(format!(
+            "- The {} script `{}` isn't executable",
+            label, script
+        )),
+        Ok(_) => None,
+    }
+}
+
+/// Asks the forge of every target whether the token can see it. Push-only and local targets
+/// have no forge to ask.
+async fn access_problems(github_token: &str, migration_input: &MigrationFile) -> Vec<String> {
+    let github_hosts = match GitHubHosts::new(github_token, &migration_input.host_configs()) {
+        Ok(hosts) => hosts,
+        Err(e) => return vec![format!("- The hosts can't be set up: {}", e)],
+    };
+
+    let mut problems = Vec::new();
+    for (name, target) in &migration_input.targets {
+        let repo = match target.github_repo() {
+            Ok(repo) => repo,
+            // Already reported with the definition
+            Err(_) => continue,
+        };
+        let forge = match github_hosts.forge_for_target(target, &repo) {
+            Ok(Some(forge)) => forge,
+            Ok(None) => continue,
+            Err(e) => {
+                problems.push(format!("- Target {}: {}", name, e));
+                continue;
+            }
+        };
+
+        match forge.fetch_repo_details(&repo).await {
+            Ok(access) if access.archived => {
+                problems.push(format!("- Target {}: {} is archived", name, repo))
+            }
+            Ok(access) if !access.can_push => problems.push(format!(
+                "- Target {}: the token can't push to {}",
+                name, repo
+            )),
+            Ok(_) => {}
+            Err(e) => problems.push(format!(
+                "- Target {}: {} can't be seen with the token: {}",
+                name, repo, e
+            )),
+        }
+    }
+
+    problems
+}
+
+#[test]
+fn validate_definition_problems() {
+    let mut definition: MigrationFile = toml::from_str(
+        r#"
+        [targets.clu]
+        repo = "git@github.com:ethankhall/clu.git"
+
+        [targets.broken]
+        repo = "not a repo"
+
+        [checkout]
+        branch-name = "clu..update"
+        pre-flight = "/bin/sh"
+
+        [pr]
+        title = "Update"
+        description = "Update"
+
+        [[steps]]
+        name = "update"
+        migration-script = "/does/not/exist.sh --all"
+        "#,
+    )
+    .unwrap();
+
+    let problems = definition_problems(&definition.definition, &definition.targets);
+    assert_eq!(3, problems.len(), "{:?}", problems);
+    assert!(problems[0].contains("valid branch name"));
+    assert!(problems[1].contains("`/does/not/exist.sh` doesn't exist"));
+    assert!(problems[2].contains("Target broken"));
+
+    definition.definition.checkout.branch_name = "clu/update".to_owned();
+    definition.definition.steps[0].migration_script = "/bin/sh -c true".to_owned();
+    definition.targets.remove("broken");
+    assert!(definition_problems(&definition.definition, &definition.targets).is_empty());
+}
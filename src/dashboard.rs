@@ -0,0 +1,336 @@
+use console::{style, Key, Term};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::models::{RunOutcome, RunResult};
+
+/// How often the dashboard is redrawn, so the running times and logs stay current.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Switches to the alternate screen, so the terminal is left as it was once the dashboard is
+/// closed.
+const ENTER_ALTERNATE_SCREEN: &str = "\x1b[?1049h";
+const LEAVE_ALTERNATE_SCREEN: &str = "\x1b[?1049l";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TargetProgress {
+    Pending,
+    Running {
+        since: Instant,
+    },
+    /// Rate limited or hit a GitHub error, it runs again once the wait is over.
+    Retrying,
+    Done(RunResult),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LogView {
+    Stderr,
+    Stdout,
+}
+
+impl LogView {
+    fn file_name(&self) -> &'static str {
+        match self {
+            LogView::Stderr => "stderr.log",
+            LogView::Stdout => "stdout.log",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct DashboardState {
+    targets: BTreeMap<String, TargetProgress>,
+    selected: usize,
+    log: LogView,
+    run_finished: bool,
+}
+
+/// Full screen view of a `run-migration`, shown with `--tui` instead of the progress bars. It
+/// lists every target with where it's at, and tails the logs of the selected one.
+#[derive(Debug)]
+pub struct Dashboard {
+    title: String,
+    /// The work directory of the run, the logs of the targets are in their workspaces in it.
+    work_dir: PathBuf,
+    state: Mutex<DashboardState>,
+}
+
+impl Dashboard {
+    pub fn new<I: IntoIterator<Item = String>>(title: &str, work_dir: &Path, targets: I) -> Self {
+        Self {
+            title: title.to_owned(),
+            work_dir: work_dir.to_path_buf(),
+            state: Mutex::new(DashboardState {
+                targets: targets
+                    .into_iter()
+                    .map(|it| (it, TargetProgress::Pending))
+                    .collect(),
+                selected: 0,
+                log: LogView::Stderr,
+                run_finished: false,
+            }),
+        }
+    }
+
+    pub fn update(&self, target: &str, progress: TargetProgress) {
+        let mut state = self.state.lock().unwrap();
+        state.targets.insert(target.to_owned(), progress);
+    }
+
+    /// Once the run is done the dashboard stays up, with the final state of every target,
+    /// until it's closed.
+    pub fn finish_run(&self) {
+        self.state.lock().unwrap().run_finished = true;
+    }
+
+    /// Draws the dashboard until the run is finished and `q` is pressed. Ctrl-C leaves the
+    /// dashboard and exits, the run can be picked up with `clu resume`.
+    pub fn spawn(self: Arc<Self>) -> JoinHandle<()> {
+        let (key_sender, mut keys) = mpsc::unbounded_channel();
+        let reader = self.clone();
+        // Reading a key blocks, and a blocking task would keep the runtime from shutting down.
+        // The thread ends with the key that closes the dashboard.
+        std::thread::spawn(move || {
+            let term = Term::stdout();
+            while let Ok(key) = term.read_key_raw() {
+                let closing = key == Key::CtrlC
+                    || (key == Key::Char('q') && reader.state.lock().unwrap().run_finished);
+                if key_sender.send(key).is_err() || closing {
+                    break;
+                }
+            }
+        });
+
+        tokio::spawn(async move {
+            let term = Term::buffered_stdout();
+            term.write_str(ENTER_ALTERNATE_SCREEN).ok();
+            term.hide_cursor().ok();
+
+            let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+            loop {
+                tokio::select! {
+                    key = keys.recv() => match key {
+                        Some(Key::CtrlC) => {
+                            leave(&term);
+                            eprintln!("Interrupted, the run can be picked up with `clu resume`");
+                            std::process::exit(130);
+                        }
+                        Some(key) => {
+                            if self.handle_key(key) {
+                                break;
+                            }
+                        }
+                        None => {}
+                    },
+                    _ = interval.tick() => {}
+                }
+
+                let (height, width) = term.size();
+                term.move_cursor_to(0, 0).ok();
+                for line in self.render(width as usize, height as usize) {
+                    term.clear_line().ok();
+                    term.write_line(&line).ok();
+                }
+                term.clear_to_end_of_screen().ok();
+                term.flush().ok();
+            }
+
+            leave(&term);
+        })
+    }
+
+    /// Returns whether the dashboard should be closed.
+    fn handle_key(&self, key: Key) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match key {
+            Key::ArrowUp | Key::Char('k') => state.selected = state.selected.saturating_sub(1),
+            Key::ArrowDown | Key::Char('j') => {
+                state.selected = (state.selected + 1).min(state.targets.len().saturating_sub(1))
+            }
+            Key::Tab | Key::Enter => {
+                state.log = match state.log {
+                    LogView::Stderr => LogView::Stdout,
+                    LogView::Stdout => LogView::Stderr,
+                }
+            }
+            Key::Char('q') => return state.run_finished,
+            _ => {}
+        }
+
+        false
+    }
+
+    fn render(&self, width: usize, height: usize) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for progress in state.targets.values() {
+            *counts.entry(progress_name(progress)).or_default() += 1;
+        }
+        let counts: Vec<String> = [
+            "succeeded",
+            "stopped",
+            "failed",
+            "running",
+            "retrying",
+            "pending",
+        ]
+        .iter()
+        .map(|name| format!("{} {}", counts.get(name).unwrap_or(&0), name))
+        .collect();
+
+        let mut lines = vec![
+            format!("{}", style(&self.title).bold()),
+            counts.join(", "),
+            String::new(),
+        ];
+
+        // The list takes up to half of the screen, scrolled to keep the selected target on it
+        let list_height = (height / 2).max(1);
+        let first = state.selected.saturating_sub(list_height.saturating_sub(1));
+        let name_width = state.targets.keys().map(|it| it.len()).max().unwrap_or(0);
+        let mut selected_name = None;
+        for (index, (name, progress)) in state
+            .targets
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(list_height)
+        {
+            let marker = match index == state.selected {
+                true => {
+                    selected_name = Some(name.clone());
+                    ">"
+                }
+                false => " ",
+            };
+            let line = format!(
+                "{} {:name_width$}  {}",
+                marker,
+                name,
+                describe(progress),
+                name_width = name_width
+            );
+            let line = console::truncate_str(&line, width, "…").into_owned();
+            lines.push(match progress {
+                TargetProgress::Done(result) if result.outcome == RunOutcome::Failed => {
+                    style(line).red().to_string()
+                }
+                TargetProgress::Done(result) if result.outcome == RunOutcome::Succeeded => {
+                    style(line).green().to_string()
+                }
+                TargetProgress::Running { .. } => style(line).yellow().to_string(),
+                _ => line,
+            });
+        }
+
+        lines.push(String::new());
+        let footer = match state.run_finished {
+            true => "The run is done. ↑/↓ select a target, tab switches the log, q quits",
+            false => "↑/↓ select a target, tab switches between its stderr and stdout",
+        };
+
+        if let Some(name) = selected_name {
+            lines.push(format!(
+                "{}",
+                style(format!("── {} of {} ──", state.log.file_name(), name)).dim()
+            ));
+            let log_height = height.saturating_sub(lines.len() + 2);
+            let path = self.work_dir.join(&name).join(state.log.file_name());
+            for line in tail(&path, log_height) {
+                lines.push(console::truncate_str(&line, width, "…").into_owned());
+            }
+        }
+        while lines.len() + 1 < height {
+            lines.push(String::new());
+        }
+        lines.push(format!("{}", style(footer).dim()));
+
+        lines
+    }
+}
+
+fn leave(term: &Term) {
+    term.show_cursor().ok();
+    term.write_str(LEAVE_ALTERNATE_SCREEN).ok();
+    term.flush().ok();
+}
+
+fn progress_name(progress: &TargetProgress) -> &'static str {
+    match progress {
+        TargetProgress::Pending => "pending",
+        TargetProgress::Running { .. } => "running",
+        TargetProgress::Retrying => "retrying",
+        TargetProgress::Done(result) => match result.outcome {
+            RunOutcome::Succeeded => "succeeded",
+            RunOutcome::Stopped => "stopped",
+            RunOutcome::Failed => "failed",
+        },
+    }
+}
+
+fn describe(progress: &TargetProgress) -> String {
+    match progress {
+        TargetProgress::Running { since } => {
+            format!("running for {}s", since.elapsed().as_secs())
+        }
+        TargetProgress::Done(result) => result.to_string(),
+        other => progress_name(other).to_owned(),
+    }
+}
+
+/// The last lines of a log, nothing when the target hasn't written it yet.
+fn tail(path: &Path, lines: usize) -> Vec<String> {
+    let log = match std::fs::read_to_string(path) {
+        Ok(log) => log,
+        Err(_) => return Vec::new(),
+    };
+    let all: Vec<&str> = log.lines().collect();
+    let start = all.len().saturating_sub(lines);
+
+    all[start..].iter().map(|it| it.to_string()).collect()
+}
+
+#[test]
+fn validate_dashboard_render() {
+    let dashboard = Dashboard::new(
+        "clu/update",
+        Path::new("/does/not/exist"),
+        vec!["clu".to_owned(), "other".to_owned(), "third".to_owned()],
+    );
+    dashboard.update(
+        "clu",
+        TargetProgress::Done(RunResult {
+            outcome: RunOutcome::Failed,
+            step: "update".to_owned(),
+            error: None,
+            error_kind: None,
+        }),
+    );
+    dashboard.update(
+        "other",
+        TargetProgress::Running {
+            since: Instant::now(),
+        },
+    );
+
+    let lines = dashboard.render(80, 20);
+    assert_eq!(20, lines.len());
+    assert!(lines[1].contains("1 failed, 1 running, 0 retrying, 1 pending"));
+    assert!(lines.iter().any(|it| it.contains("> clu")));
+    assert!(lines.iter().any(|it| it.contains("stderr.log of clu")));
+
+    assert!(!dashboard.handle_key(Key::ArrowDown));
+    assert!(!dashboard.handle_key(Key::Tab));
+    assert!(!dashboard.handle_key(Key::Char('q')));
+    let lines = dashboard.render(80, 20);
+    assert!(lines.iter().any(|it| it.contains("stdout.log of other")));
+
+    dashboard.finish_run();
+    assert!(dashboard.handle_key(Key::Char('q')));
+}
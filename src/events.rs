@@ -0,0 +1,114 @@
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tracing::warn;
+
+/// How a step ended, in the events of `run-migration --events`.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum StepStatus {
+    Succeeded,
+    /// The step worked, but the target doesn't go any further, like when the pre-flight says
+    /// the migration isn't needed.
+    Stopped,
+    Failed,
+}
+
+/// One line of the event stream, written as soon as a step of a target is done.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct StepEvent {
+    /// The id of the run, the same as in the `X-Clu-Run-Id` header.
+    pub run_id: String,
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub target: String,
+    pub step: String,
+    pub status: StepStatus,
+    pub duration_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Appends an event per step to an NDJSON file, so other tools can tail the progress of a run.
+/// Each event is flushed as it's written.
+#[derive(Debug)]
+pub struct EventLog {
+    file: Mutex<File>,
+}
+
+impl EventLog {
+    /// Opens the file for appending, so the events of earlier runs are kept.
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn record(
+        &self,
+        target: &str,
+        step: &str,
+        status: StepStatus,
+        duration_ms: u64,
+        exit_code: Option<i32>,
+        error: Option<String>,
+    ) {
+        let event = StepEvent {
+            run_id: crate::github::run_id().to_owned(),
+            timestamp: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|it| it.as_secs())
+                .unwrap_or_default(),
+            target: target.to_owned(),
+            step: step.to_owned(),
+            status,
+            duration_ms,
+            exit_code,
+            error,
+        };
+
+        // Losing an event isn't worth failing the target over
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = write_event(&mut *file, &event) {
+            warn!("Unable to write the event of {} `{}`: {}", target, step, e);
+        }
+    }
+}
+
+fn write_event(file: &mut impl Write, event: &StepEvent) -> std::io::Result<()> {
+    let mut line = serde_json::to_string(event)?;
+    line.push('\n');
+    file.write_all(line.as_bytes())?;
+    file.flush()
+}
+
+#[test]
+fn validate_write_event() {
+    let event = StepEvent {
+        run_id: "10-1".to_owned(),
+        timestamp: 10,
+        target: "clu".to_owned(),
+        step: "update".to_owned(),
+        status: StepStatus::Failed,
+        duration_ms: 1500,
+        exit_code: Some(2),
+        error: Some("Migration step `update` exited non-zero.".to_owned()),
+    };
+
+    let mut out = Vec::new();
+    write_event(&mut out, &event).unwrap();
+    write_event(&mut out, &event).unwrap();
+    let text = String::from_utf8(out).unwrap();
+    assert_eq!(2, text.lines().count());
+    assert_eq!(
+        r#"{"run-id":"10-1","timestamp":10,"target":"clu","step":"update","status":"failed","duration-ms":1500,"exit-code":2,"error":"Migration step `update` exited non-zero."}"#,
+        text.lines().next().unwrap()
+    );
+}
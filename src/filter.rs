@@ -0,0 +1,192 @@
+use clap::Args;
+
+use std::str::FromStr;
+
+use crate::glob::Glob;
+use crate::models::TargetDescription;
+
+/// Picks the targets a command works on, so part of a migration can be worked on without
+/// editing the file. Names can use `*` and `?` as wildcards.
+#[derive(Args, Debug, Clone, Default)]
+pub struct TargetFilter {
+    /// Only work on the targets whose name matches this pattern, can be given more than once.
+    #[clap(long = "only")]
+    pub only: Vec<String>,
+
+    /// Leave out the targets whose name matches this pattern, can be given more than once.
+    #[clap(long = "exclude")]
+    pub exclude: Vec<String>,
+
+    /// Only work on the targets that have this tag. When given more than once the targets need
+    /// to have all of them.
+    #[clap(long = "tag")]
+    pub tags: Vec<String>,
+}
+
+impl TargetFilter {
+    pub fn matches(&self, name: &str, target: &TargetDescription) -> bool {
+        let matches_any =
+            |patterns: &[String]| patterns.iter().any(|it| Glob::new(it).is_match(name));
+
+        (self.only.is_empty() || matches_any(&self.only))
+            && !matches_any(&self.exclude)
+            && self.tags.iter().all(|tag| target.tags.contains(tag))
+    }
+}
+
+/// One of `count` slices of the targets, like `2/5`, so workers can split a migration between
+/// them. A target's slice only depends on its name, so every worker agrees on it without talking
+/// to the others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    /// Starts at 1.
+    pub index: u64,
+    pub count: u64,
+}
+
+impl Shard {
+    pub fn contains(&self, name: &str) -> bool {
+        let digest = openssl::sha::sha1(name.as_bytes());
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&digest[..8]);
+
+        u64::from_be_bytes(bytes) % self.count == self.index - 1
+    }
+
+    /// Where the shard writes its results, next to the migration file, for `merge-results`.
+    pub fn results_path(&self, migration_definition: &str) -> String {
+        format!(
+            "{}.shard-{}-of-{}.toml",
+            migration_definition.trim_end_matches(".toml"),
+            self.index,
+            self.count
+        )
+    }
+}
+
+impl FromStr for Shard {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (index, count) = text
+            .split_once('/')
+            .ok_or_else(|| format!("`{}` isn't like `2/5`", text))?;
+        let index: u64 = index
+            .parse()
+            .map_err(|_| format!("`{}` isn't a number", index))?;
+        let count: u64 = count
+            .parse()
+            .map_err(|_| format!("`{}` isn't a number", count))?;
+        if index == 0 || index > count {
+            return Err(format!("the shard needs to be between 1 and {}", count));
+        }
+
+        Ok(Self { index, count })
+    }
+}
+
+/// A share of the targets, like `5%`, for piloting a migration with `run-migration --sample`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Percentage(f64);
+
+impl Percentage {
+    /// How many of `count` targets are in the share, rounded up so a small share of a few
+    /// targets still picks one.
+    pub fn of(&self, count: usize) -> usize {
+        (count as f64 * self.0 / 100.0).ceil() as usize
+    }
+}
+
+impl FromStr for Percentage {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let number = text
+            .trim()
+            .strip_suffix('%')
+            .ok_or_else(|| format!("`{}` isn't a percentage like `5%`", text))?;
+        let percentage: f64 = number
+            .parse()
+            .map_err(|_| format!("`{}` isn't a number", number))?;
+        if !(percentage > 0.0 && percentage <= 100.0) {
+            return Err("the percentage needs to be more than 0 and at most 100".to_owned());
+        }
+
+        Ok(Self(percentage))
+    }
+}
+
+/// Picks `count` of the names. Without a seed they're the first ones by name, with one they're
+/// shuffled by it, so the same seed picks the same targets again, like on `clu resume`.
+pub fn pick_sample<'a>(names: &[&'a str], count: usize, seed: Option<u64>) -> Vec<&'a str> {
+    let mut names = names.to_vec();
+    match seed {
+        None => names.sort_unstable(),
+        Some(seed) => names
+            .sort_by_cached_key(|name| openssl::sha::sha1(format!("{}:{}", seed, name).as_bytes())),
+    }
+    names.truncate(count);
+
+    names
+}
+
+#[test]
+fn validate_target_filter() {
+    let mut target = TargetDescription::new("git@github.com:ethankhall/clu.git");
+    assert!(TargetFilter::default().matches("payments-api", &target));
+
+    let filter = TargetFilter {
+        only: vec!["payments-*".to_owned()],
+        exclude: vec!["payments-legacy".to_owned()],
+        ..TargetFilter::default()
+    };
+    assert!(filter.matches("payments-api", &target));
+    assert!(!filter.matches("payments-legacy", &target));
+    assert!(!filter.matches("billing", &target));
+
+    let filter = TargetFilter {
+        tags: vec!["java".to_owned(), "tier1".to_owned()],
+        ..TargetFilter::default()
+    };
+    target.tags = vec!["java".to_owned()];
+    assert!(!filter.matches("billing", &target));
+    target.tags.push("tier1".to_owned());
+    assert!(filter.matches("billing", &target));
+}
+
+#[test]
+fn validate_shard() {
+    let shards: Vec<Shard> = (1..=3)
+        .map(|it| format!("{}/3", it).parse().unwrap())
+        .collect();
+    for name in ["clu", "payments-api", "billing", "legacy-repo"] {
+        assert_eq!(1, shards.iter().filter(|it| it.contains(name)).count());
+    }
+
+    assert_eq!(
+        "migration.shard-2-of-3.toml",
+        shards[1].results_path("migration.toml")
+    );
+    assert!("0/3".parse::<Shard>().is_err());
+    assert!("4/3".parse::<Shard>().is_err());
+    assert!("2".parse::<Shard>().is_err());
+}
+
+#[test]
+fn validate_sample() {
+    let sample: Percentage = "5%".parse().unwrap();
+    assert_eq!(5, sample.of(100));
+    assert_eq!(1, sample.of(3));
+    assert_eq!(0, sample.of(0));
+    assert_eq!(3, "100%".parse::<Percentage>().unwrap().of(3));
+    assert!("5".parse::<Percentage>().is_err());
+    assert!("0%".parse::<Percentage>().is_err());
+    assert!("150%".parse::<Percentage>().is_err());
+
+    let names = ["d", "b", "a", "c"];
+    assert_eq!(vec!["a", "b"], pick_sample(&names, 2, None));
+    let seeded = pick_sample(&names, 2, Some(7));
+    assert_eq!(2, seeded.len());
+    assert_eq!(seeded, pick_sample(&names, 2, Some(7)));
+    assert_eq!(4, pick_sample(&names, 10, Some(7)).len());
+}
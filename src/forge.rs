@@ -0,0 +1,291 @@
+use anyhow::Result as AnyResult;
+use async_trait::async_trait;
+use std::fmt::Debug;
+
+use crate::azure::AzureDevOpsClient;
+use crate::bitbucket::BitbucketClient;
+use crate::codecommit::CodeCommitClient;
+use crate::gerrit::GerritClient;
+use crate::github::{
+    GitHubRepo, GithubApiClient, PullRequestDescription, PullRequestOutput, PullState, RepoAccess,
+};
+use crate::gitlab::GitLabClient;
+use crate::glob::Glob;
+
+/// What clu needs from the service a repo is hosted on to open and follow its PR's.
+/// Implemented for GitHub, GitLab, Bitbucket, Azure DevOps, CodeCommit and Gerrit; other services
+/// can be added by implementing it and routing their hosts to it.
+#[async_trait]
+pub trait ForgeClient: Debug + Send + Sync {
+    /// Opens a PR for the branch, or updates `existing` or the PR that's already open for it.
+    async fn sync_pull_request(
+        &self,
+        repo: &GitHubRepo,
+        description: PullRequestDescription<'_>,
+        existing: Option<i64>,
+    ) -> AnyResult<PullRequestOutput>;
+
+    async fn fetch_pull_state(
+        &self,
+        repo: &GitHubRepo,
+        number: i64,
+        ignored_checks: &[Glob],
+    ) -> AnyResult<PullState>;
+
+    /// States of many PR's, in the order of `pulls`. Fetches them one at a time unless the
+    /// forge can do better.
+    async fn fetch_pull_states(
+        &self,
+        pulls: &[(GitHubRepo, i64)],
+        ignored_checks: &[Glob],
+    ) -> AnyResult<Vec<PullState>> {
+        let mut states = Vec::with_capacity(pulls.len());
+        for (repo, number) in pulls {
+            states.push(self.fetch_pull_state(repo, *number, ignored_checks).await?);
+        }
+
+        Ok(states)
+    }
+
+    /// Whether the repo is archived and can be pushed to.
+    async fn fetch_repo_details(&self, repo: &GitHubRepo) -> AnyResult<RepoAccess>;
+
+    async fn add_pull_request_comment(
+        &self,
+        repo: &GitHubRepo,
+        pull: &PullRequestOutput,
+        body: &str,
+    ) -> AnyResult<()>;
+
+    /// The GitHub client behind the forge, for the features only GitHub has.
+    fn as_github(&self) -> Option<&GithubApiClient> {
+        None
+    }
+
+    /// The Gerrit client behind the forge. Changes for Gerrit are pushed for review instead of
+    /// as a branch.
+    fn as_gerrit(&self) -> Option<&GerritClient> {
+        None
+    }
+}
+
+#[async_trait]
+impl ForgeClient for GithubApiClient {
+    async fn sync_pull_request(
+        &self,
+        repo: &GitHubRepo,
+        description: PullRequestDescription<'_>,
+        existing: Option<i64>,
+    ) -> AnyResult<PullRequestOutput> {
+        GithubApiClient::sync_pull_request(self, repo, description, existing).await
+    }
+
+    async fn fetch_pull_state(
+        &self,
+        repo: &GitHubRepo,
+        number: i64,
+        ignored_checks: &[Glob],
+    ) -> AnyResult<PullState> {
+        GithubApiClient::fetch_pull_state(self, repo, number, ignored_checks).await
+    }
+
+    async fn fetch_pull_states(
+        &self,
+        pulls: &[(GitHubRepo, i64)],
+        ignored_checks: &[Glob],
+    ) -> AnyResult<Vec<PullState>> {
+        GithubApiClient::fetch_pull_states(self, pulls, ignored_checks).await
+    }
+
+    async fn fetch_repo_details(&self, repo: &GitHubRepo) -> AnyResult<RepoAccess> {
+        self.fetch_repo_access(repo).await
+    }
+
+    async fn add_pull_request_comment(
+        &self,
+        _repo: &GitHubRepo,
+        pull: &PullRequestOutput,
+        body: &str,
+    ) -> AnyResult<()> {
+        self.add_comment(&pull.id, body).await.map(|_| ())
+    }
+
+    fn as_github(&self) -> Option<&GithubApiClient> {
+        Some(self)
+    }
+}
+
+#[async_trait]
+impl ForgeClient for GitLabClient {
+    async fn sync_pull_request(
+        &self,
+        repo: &GitHubRepo,
+        description: PullRequestDescription<'_>,
+        existing: Option<i64>,
+    ) -> AnyResult<PullRequestOutput> {
+        self.sync_merge_request(repo, description, existing).await
+    }
+
+    async fn fetch_pull_state(
+        &self,
+        repo: &GitHubRepo,
+        number: i64,
+        ignored_checks: &[Glob],
+    ) -> AnyResult<PullState> {
+        self.fetch_merge_request_state(repo, number, ignored_checks)
+            .await
+    }
+
+    async fn fetch_repo_details(&self, repo: &GitHubRepo) -> AnyResult<RepoAccess> {
+        self.fetch_project_access(repo).await
+    }
+
+    async fn add_pull_request_comment(
+        &self,
+        repo: &GitHubRepo,
+        pull: &PullRequestOutput,
+        body: &str,
+    ) -> AnyResult<()> {
+        self.add_note(repo, pull.number, body).await
+    }
+}
+
+#[async_trait]
+impl ForgeClient for BitbucketClient {
+    async fn sync_pull_request(
+        &self,
+        repo: &GitHubRepo,
+        description: PullRequestDescription<'_>,
+        existing: Option<i64>,
+    ) -> AnyResult<PullRequestOutput> {
+        BitbucketClient::sync_pull_request(self, repo, description, existing).await
+    }
+
+    async fn fetch_pull_state(
+        &self,
+        repo: &GitHubRepo,
+        number: i64,
+        ignored_checks: &[Glob],
+    ) -> AnyResult<PullState> {
+        BitbucketClient::fetch_pull_state(self, repo, number, ignored_checks).await
+    }
+
+    async fn fetch_repo_details(&self, repo: &GitHubRepo) -> AnyResult<RepoAccess> {
+        self.fetch_repo_access(repo).await
+    }
+
+    async fn add_pull_request_comment(
+        &self,
+        repo: &GitHubRepo,
+        pull: &PullRequestOutput,
+        body: &str,
+    ) -> AnyResult<()> {
+        self.add_comment(repo, pull.number, body).await
+    }
+}
+
+#[async_trait]
+impl ForgeClient for AzureDevOpsClient {
+    async fn sync_pull_request(
+        &self,
+        repo: &GitHubRepo,
+        description: PullRequestDescription<'_>,
+        existing: Option<i64>,
+    ) -> AnyResult<PullRequestOutput> {
+        AzureDevOpsClient::sync_pull_request(self, repo, description, existing).await
+    }
+
+    async fn fetch_pull_state(
+        &self,
+        repo: &GitHubRepo,
+        number: i64,
+        ignored_checks: &[Glob],
+    ) -> AnyResult<PullState> {
+        AzureDevOpsClient::fetch_pull_state(self, repo, number, ignored_checks).await
+    }
+
+    async fn fetch_repo_details(&self, repo: &GitHubRepo) -> AnyResult<RepoAccess> {
+        self.fetch_repo_access(repo).await
+    }
+
+    async fn add_pull_request_comment(
+        &self,
+        repo: &GitHubRepo,
+        pull: &PullRequestOutput,
+        body: &str,
+    ) -> AnyResult<()> {
+        self.add_comment(repo, pull.number, body).await
+    }
+}
+
+#[async_trait]
+impl ForgeClient for CodeCommitClient {
+    async fn sync_pull_request(
+        &self,
+        repo: &GitHubRepo,
+        description: PullRequestDescription<'_>,
+        existing: Option<i64>,
+    ) -> AnyResult<PullRequestOutput> {
+        CodeCommitClient::sync_pull_request(self, repo, description, existing).await
+    }
+
+    async fn fetch_pull_state(
+        &self,
+        repo: &GitHubRepo,
+        number: i64,
+        ignored_checks: &[Glob],
+    ) -> AnyResult<PullState> {
+        CodeCommitClient::fetch_pull_state(self, repo, number, ignored_checks).await
+    }
+
+    async fn fetch_repo_details(&self, repo: &GitHubRepo) -> AnyResult<RepoAccess> {
+        self.fetch_repo_access(repo).await
+    }
+
+    async fn add_pull_request_comment(
+        &self,
+        repo: &GitHubRepo,
+        pull: &PullRequestOutput,
+        body: &str,
+    ) -> AnyResult<()> {
+        self.add_comment(repo, pull.number, body).await
+    }
+}
+
+#[async_trait]
+impl ForgeClient for GerritClient {
+    async fn sync_pull_request(
+        &self,
+        repo: &GitHubRepo,
+        description: PullRequestDescription<'_>,
+        existing: Option<i64>,
+    ) -> AnyResult<PullRequestOutput> {
+        self.sync_change(repo, description, existing).await
+    }
+
+    async fn fetch_pull_state(
+        &self,
+        repo: &GitHubRepo,
+        number: i64,
+        ignored_checks: &[Glob],
+    ) -> AnyResult<PullState> {
+        self.fetch_change_state(repo, number, ignored_checks).await
+    }
+
+    async fn fetch_repo_details(&self, repo: &GitHubRepo) -> AnyResult<RepoAccess> {
+        self.fetch_project_access(repo).await
+    }
+
+    async fn add_pull_request_comment(
+        &self,
+        repo: &GitHubRepo,
+        pull: &PullRequestOutput,
+        body: &str,
+    ) -> AnyResult<()> {
+        self.add_review_message(repo, pull.number, body).await
+    }
+
+    fn as_gerrit(&self) -> Option<&GerritClient> {
+        Some(self)
+    }
+}
@@ -0,0 +1,383 @@
+use anyhow::{bail, Result as AnyResult};
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use tracing::{debug, warn};
+
+use crate::github::{
+    CheckResult, CheckState, GitHubRepo, PullRequestDescription, PullRequestOutput, PullReviews,
+    PullState, PullStatus, RepoAccess,
+};
+use crate::glob::Glob;
+
+/// Environment variable with the `<username>:<HTTP password>` for Gerrit hosts that don't name
+/// their own.
+pub const GERRIT_TOKEN_ENV: &str = "GERRIT_TOKEN";
+
+/// Gerrit prefixes its JSON responses with this so they can't be run as a script.
+const XSSI_PREFIX: &str = ")]}'";
+
+/// The label reviewers vote on, any other label is reported as a check.
+const CODE_REVIEW_LABEL: &str = "Code-Review";
+
+/// Client for the REST API of a Gerrit server. Changes aren't opened through it, they're pushed
+/// for review to `refs/for/<branch>`, so it finds, follows and comments on them.
+#[derive(Debug)]
+pub struct GerritClient {
+    http: Client,
+    /// Base of the site, like `https://gerrit.example.com`.
+    api_url: String,
+    username: String,
+    password: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Account {
+    username: Option<String>,
+    name: Option<String>,
+}
+
+impl Account {
+    fn display_name(&self) -> String {
+        self.username
+            .clone()
+            .or_else(|| self.name.clone())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Label {
+    approved: Option<Account>,
+    rejected: Option<Account>,
+    #[serde(default)]
+    optional: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Change {
+    id: String,
+    project: String,
+    branch: String,
+    #[serde(rename = "_number")]
+    number: i64,
+    status: String,
+    /// Like `2013-02-21 11:16:36.775000000`, in UTC.
+    created: String,
+    updated: String,
+    submitted: Option<String>,
+    current_revision: Option<String>,
+    mergeable: Option<bool>,
+    #[serde(default)]
+    submittable: bool,
+    #[serde(default)]
+    labels: BTreeMap<String, Label>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Project {
+    state: Option<String>,
+}
+
+impl GerritClient {
+    pub fn new(token: &str, api_url: &str) -> AnyResult<Self> {
+        let (username, password) = match token.split_once(':') {
+            Some(credentials) => credentials,
+            None => bail!("The Gerrit token needs to be `<username>:<HTTP password>`"),
+        };
+        let http = Client::builder()
+            .user_agent(crate::github::user_agent(
+                std::env::var("CLU_USER_AGENT_SUFFIX").ok().as_deref(),
+            ))
+            .build()?;
+
+        Ok(Self {
+            http,
+            api_url: api_url.trim_end_matches('/').to_owned(),
+            username: username.to_owned(),
+            password: password.to_owned(),
+        })
+    }
+
+    /// The change was already created or updated by the push, its subject and description are
+    /// the commit message. Finds it by the `Change-Id` of the migration branch.
+    pub async fn sync_change(
+        &self,
+        repo: &GitHubRepo,
+        description: PullRequestDescription<'_>,
+        existing: Option<i64>,
+    ) -> AnyResult<PullRequestOutput> {
+        if description.milestone.is_some() {
+            warn!("{} is on Gerrit, which has no milestones", repo);
+        }
+
+        let change = match existing {
+            Some(number) => self.change(number).await?,
+            None => {
+                let mut query = format!(
+                    "change:{} project:{}",
+                    change_id(repo, description.branch),
+                    project(repo)
+                );
+                if let Some(base) = description.base {
+                    query.push_str(&format!(" branch:{}", base));
+                }
+                let request = self
+                    .http
+                    .get(format!("{}/a/changes/", self.api_url))
+                    .query(&[("q", query.as_str())]);
+                let mut changes: Vec<Change> = self.send(request).await?;
+                match changes.is_empty() {
+                    true => bail!("No change of {} was found after pushing it", repo),
+                    false => changes.remove(0),
+                }
+            }
+        };
+
+        Ok(PullRequestOutput {
+            id: change.id.clone(),
+            number: change.number,
+            permalink: self.permalink(&change),
+            created_at: gerrit_timestamp(&change.created),
+            head_ref: description.branch.to_owned(),
+        })
+    }
+
+    pub async fn fetch_change_state(
+        &self,
+        _repo: &GitHubRepo,
+        number: i64,
+        ignored_checks: &[Glob],
+    ) -> AnyResult<PullState> {
+        let change = self.change(number).await?;
+        let permalink = self.permalink(&change);
+
+        Ok(change_state(change, permalink, ignored_checks))
+    }
+
+    pub async fn add_review_message(
+        &self,
+        _repo: &GitHubRepo,
+        number: i64,
+        body: &str,
+    ) -> AnyResult<()> {
+        let request = self
+            .http
+            .post(format!(
+                "{}/a/changes/{}/revisions/current/review",
+                self.api_url, number
+            ))
+            .json(&serde_json::json!({ "message": body }));
+        let _: serde_json::Value = self.send(request).await?;
+
+        Ok(())
+    }
+
+    /// Read only projects count as archived. Gerrit can't tell up front whether the account
+    /// may push for review, a push that's refused fails the push.
+    pub async fn fetch_project_access(&self, repo: &GitHubRepo) -> AnyResult<RepoAccess> {
+        let request = self.http.get(format!(
+            "{}/a/projects/{}",
+            self.api_url,
+            project(repo).replace('/', "%2F")
+        ));
+        let project: Project = self.send(request).await?;
+
+        Ok(RepoAccess {
+            archived: project.state.as_deref() == Some("READ_ONLY"),
+            can_push: true,
+        })
+    }
+
+    async fn change(&self, number: i64) -> AnyResult<Change> {
+        let request = self
+            .http
+            .get(format!("{}/a/changes/{}", self.api_url, number))
+            .query(&[
+                ("o", "LABELS"),
+                ("o", "CURRENT_REVISION"),
+                ("o", "SUBMITTABLE"),
+            ]);
+
+        self.send(request).await
+    }
+
+    fn permalink(&self, change: &Change) -> String {
+        format!("{}/c/{}/+/{}", self.api_url, change.project, change.number)
+    }
+
+    async fn send<T: DeserializeOwned>(&self, request: reqwest::RequestBuilder) -> AnyResult<T> {
+        let response = request
+            .basic_auth(&self.username, Some(&self.password))
+            .send()
+            .await?;
+        let status = response.status();
+        let text = response.text().await?;
+        debug!("Gerrit Response: {}", text);
+
+        if !status.is_success() {
+            bail!("Gerrit responded {}: {}", status, text);
+        }
+
+        Ok(serde_json::from_str(text.trim_start_matches(XSSI_PREFIX))?)
+    }
+}
+
+/// The `Change-Id` of the migration branch of a repo. It's the same on every run so pushing
+/// again adds a patch set to the change instead of opening another one.
+pub fn change_id(repo: &GitHubRepo, branch: &str) -> String {
+    let digest = openssl::sha::sha1(format!("{}/{}/{}", repo.host, repo, branch).as_bytes());
+    let digits: Vec<String> = digest.iter().map(|it| format!("{:02x}", it)).collect();
+
+    format!("I{}", digits.concat())
+}
+
+/// Projects are addressed by their path. HTTPS clone URLs with authentication have an `a/`
+/// before it.
+fn project(repo: &GitHubRepo) -> String {
+    match repo.owner.as_str() {
+        "a" => repo.repo.clone(),
+        owner => format!(
+            "{}/{}",
+            owner.strip_prefix("a/").unwrap_or(owner),
+            repo.repo
+        ),
+    }
+}
+
+/// Gerrit's `2013-02-21 11:16:36.775000000` as `2013-02-21T11:16:36Z`.
+fn gerrit_timestamp(timestamp: &str) -> String {
+    format!(
+        "{}Z",
+        timestamp.get(..19).unwrap_or(timestamp).replace(' ', "T")
+    )
+}
+
+fn change_state(change: Change, permalink: String, ignored_checks: &[Glob]) -> PullState {
+    let checks: Vec<CheckResult> = change
+        .labels
+        .iter()
+        .filter(|(name, _)| name.as_str() != CODE_REVIEW_LABEL)
+        .map(|(name, label)| CheckResult {
+            name: name.clone(),
+            state: match (&label.approved, &label.rejected) {
+                (_, Some(_)) => CheckState::Failed,
+                (Some(_), None) => CheckState::Passed,
+                (None, None) => CheckState::Pending,
+            },
+            url: None,
+            ignored: ignored_checks.iter().any(|it| it.is_match(name)),
+            required: !label.optional,
+        })
+        .collect();
+    let review = change.labels.get(CODE_REVIEW_LABEL);
+
+    let failed = checks
+        .iter()
+        .any(|it| it.state == CheckState::Failed && !it.ignored);
+    let status = match change.status.as_str() {
+        "MERGED" => PullStatus::Merged,
+        "ABANDONED" => PullStatus::ClosedWithoutMerge,
+        _ if change.mergeable == Some(false) => PullStatus::Conflicting,
+        _ if failed => PullStatus::ChecksFailed,
+        _ if !change.submittable => PullStatus::NeedsApproval,
+        _ => PullStatus::Mergeable,
+    };
+
+    let closed_at = match change.status.as_str() {
+        "MERGED" => Some(gerrit_timestamp(
+            change.submitted.as_deref().unwrap_or(&change.updated),
+        )),
+        "ABANDONED" => Some(gerrit_timestamp(&change.updated)),
+        _ => None,
+    };
+
+    PullState {
+        id: change.id,
+        status,
+        permalink,
+        created_at: gerrit_timestamp(&change.created),
+        closed_at,
+        base_ref: change.branch,
+        checks,
+        head_oid: change.current_revision.unwrap_or_default(),
+        head_ref_id: None,
+        behind: false,
+        required_checks: Vec::new(),
+        reviews: PullReviews {
+            approved_by: review
+                .and_then(|it| it.approved.as_ref())
+                .map(Account::display_name)
+                .into_iter()
+                .collect(),
+            changes_requested_by: review
+                .and_then(|it| it.rejected.as_ref())
+                .map(Account::display_name)
+                .into_iter()
+                .collect(),
+            ..PullReviews::default()
+        },
+    }
+}
+
+#[test]
+fn validate_change_id() {
+    let repo = crate::github::extract_github_info("ssh://ethan@gerrit.example.com:29418/plat/clu")
+        .unwrap();
+    let id = change_id(&repo, "migration");
+    assert_eq!(41, id.len());
+    assert!(id.starts_with('I'));
+    assert_eq!(id, change_id(&repo, "migration"));
+    assert_ne!(id, change_id(&repo, "other-migration"));
+
+    assert_eq!("plat/clu", project(&repo));
+    let repo = crate::github::extract_github_info("https://gerrit.example.com/a/plat/clu").unwrap();
+    assert_eq!("plat/clu", project(&repo));
+    assert_eq!(
+        "2013-02-21T11:16:36Z",
+        gerrit_timestamp("2013-02-21 11:16:36.775000000")
+    );
+}
+
+#[test]
+fn validate_change_state() {
+    let change = |status: &str, verified: &str, submittable: bool| -> Change {
+        serde_json::from_value(serde_json::json!({
+            "id": "plat%2Fclu~main~I8473b95934b5732ac55d26311a706c9c2bde9940",
+            "project": "plat/clu", "branch": "main", "_number": 7, "status": status,
+            "created": "2021-03-21 17:30:00.000000000", "updated": "2021-03-22 17:30:00.000000000",
+            "current_revision": "abc", "mergeable": true, "submittable": submittable,
+            "labels": {
+                "Code-Review": { "approved": { "username": "ethan" } },
+                "Verified": { verified: { "username": "ci" } },
+            },
+        }))
+        .unwrap()
+    };
+
+    let state = change_state(change("NEW", "approved", true), String::new(), &[]);
+    assert_eq!(PullStatus::Mergeable, state.status);
+    assert_eq!("2021-03-21T17:30:00Z", state.created_at);
+    assert_eq!(vec!["ethan".to_owned()], state.reviews.approved_by);
+    assert_eq!(
+        PullStatus::ChecksFailed,
+        change_state(change("NEW", "rejected", false), String::new(), &[]).status
+    );
+    assert_eq!(
+        PullStatus::NeedsApproval,
+        change_state(
+            change("NEW", "rejected", false),
+            String::new(),
+            &[Glob::new("Verified")]
+        )
+        .status
+    );
+    let state = change_state(change("MERGED", "approved", false), String::new(), &[]);
+    assert_eq!(PullStatus::Merged, state.status);
+    assert_eq!(Some("2021-03-22T17:30:00Z".to_owned()), state.closed_at);
+    assert_eq!(
+        PullStatus::ClosedWithoutMerge,
+        change_state(change("ABANDONED", "approved", false), String::new(), &[]).status
+    );
+}
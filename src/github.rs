@@ -1,13 +1,33 @@
+use crate::azure::{AzureDevOpsClient, AZURE_DEVOPS_HOST, AZURE_DEVOPS_TOKEN_ENV};
+use crate::bitbucket::{
+    BitbucketClient, BitbucketFlavor, BITBUCKET_API_URL, BITBUCKET_HOST, BITBUCKET_TOKEN_ENV,
+};
+use crate::codecommit::{codecommit_region, CodeCommitClient};
+use crate::forge::ForgeClient;
+use crate::gerrit::{GerritClient, GERRIT_TOKEN_ENV};
+use crate::gitlab::{GitLabClient, GITLAB_HOST, GITLAB_TOKEN_ENV};
+use crate::glob::Glob;
+use crate::models::{Forge, HostConfig, TargetDescription};
 use anyhow::{bail, Result as AnyResult};
+use futures::stream::{self, StreamExt};
 use graphql_client::GraphQLQuery;
 use regex::Regex;
-use reqwest::Client;
+use reqwest::header::HeaderMap;
+use reqwest::{Client, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::fmt;
+use std::time::{Duration, Instant, SystemTime};
 use thiserror::Error;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 #[allow(clippy::upper_case_acronyms)]
 type URI = String;
+type GitObjectID = String;
+type Base64String = String;
+type DateTime = String;
+type GitSSHRemote = String;
 
 #[derive(GraphQLQuery)]
 #[graphql(
@@ -37,20 +57,295 @@ pub struct GetRepositoryQuery;
 #[graphql(
     schema_path = "src/graphql/schema.docs.graphql",
     query_path = "src/graphql/UpdatePullRequest.graphql",
-    response_derives = "Debug,PartialEq"
+    response_derives = "Debug,PartialEq",
+    skip_serializing_none
 )]
 pub struct UpdatePullRequestMutation;
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/graphql/schema.docs.graphql",
+    query_path = "src/graphql/GetBranchesQuery.graphql",
+    response_derives = "Debug,PartialEq"
+)]
+pub struct GetBranchesQuery;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/graphql/schema.docs.graphql",
+    query_path = "src/graphql/DeleteRef.graphql",
+    response_derives = "Debug,PartialEq"
+)]
+pub struct DeleteRefMutation;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/graphql/schema.docs.graphql",
+    query_path = "src/graphql/GetLabelQuery.graphql",
+    response_derives = "Debug,PartialEq"
+)]
+pub struct GetLabelQuery;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/graphql/schema.docs.graphql",
+    query_path = "src/graphql/CreateLabel.graphql",
+    response_derives = "Debug,PartialEq"
+)]
+pub struct CreateLabelMutation;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/graphql/schema.docs.graphql",
+    query_path = "src/graphql/AddLabels.graphql",
+    response_derives = "Debug,PartialEq"
+)]
+pub struct AddLabelsMutation;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/graphql/schema.docs.graphql",
+    query_path = "src/graphql/AddComment.graphql",
+    response_derives = "Debug,PartialEq"
+)]
+pub struct AddCommentMutation;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/graphql/schema.docs.graphql",
+    query_path = "src/graphql/ReopenPullRequest.graphql",
+    response_derives = "Debug,PartialEq"
+)]
+pub struct ReopenPullRequestMutation;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/graphql/schema.docs.graphql",
+    query_path = "src/graphql/ClosePullRequest.graphql",
+    response_derives = "Debug,PartialEq"
+)]
+pub struct ClosePullRequestMutation;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/graphql/schema.docs.graphql",
+    query_path = "src/graphql/CreateRef.graphql",
+    response_derives = "Debug,PartialEq"
+)]
+pub struct CreateRefMutation;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/graphql/schema.docs.graphql",
+    query_path = "src/graphql/AddPullRequestReview.graphql",
+    response_derives = "Debug,PartialEq"
+)]
+pub struct AddPullRequestReviewMutation;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/graphql/schema.docs.graphql",
+    query_path = "src/graphql/GetOrgRepositoriesQuery.graphql",
+    response_derives = "Debug,PartialEq"
+)]
+pub struct GetOrgRepositoriesQuery;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/graphql/schema.docs.graphql",
+    query_path = "src/graphql/GetBranchHeadQuery.graphql",
+    response_derives = "Debug,PartialEq"
+)]
+pub struct GetBranchHeadQuery;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/graphql/schema.docs.graphql",
+    query_path = "src/graphql/CreateCommitOnBranch.graphql",
+    response_derives = "Debug,PartialEq"
+)]
+pub struct CreateCommitOnBranchMutation;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/graphql/schema.docs.graphql",
+    query_path = "src/graphql/GetBranchStatusQuery.graphql",
+    response_derives = "Debug,PartialEq"
+)]
+pub struct GetBranchStatusQuery;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/graphql/schema.docs.graphql",
+    query_path = "src/graphql/CreateIssue.graphql",
+    response_derives = "Debug,PartialEq"
+)]
+pub struct CreateIssueMutation;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/graphql/schema.docs.graphql",
+    query_path = "src/graphql/GetIssueQuery.graphql",
+    response_derives = "Debug,PartialEq"
+)]
+pub struct GetIssueQuery;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/graphql/schema.docs.graphql",
+    query_path = "src/graphql/RemoveLabels.graphql",
+    response_derives = "Debug,PartialEq"
+)]
+pub struct RemoveLabelsMutation;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/graphql/schema.docs.graphql",
+    query_path = "src/graphql/GetUserQuery.graphql",
+    response_derives = "Debug,PartialEq"
+)]
+pub struct GetUserQuery;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/graphql/schema.docs.graphql",
+    query_path = "src/graphql/GetMilestoneQuery.graphql",
+    response_derives = "Debug,PartialEq"
+)]
+pub struct GetMilestoneQuery;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "src/graphql/schema.docs.graphql",
+    query_path = "src/graphql/SetPullRequestMetadata.graphql",
+    response_derives = "Debug,PartialEq",
+    skip_serializing_none
+)]
+pub struct SetPullRequestMetadataMutation;
+
 pub struct PullRequestDescription<'a> {
     pub branch: &'a str,
     pub title: &'a str,
     pub body: &'a str,
+    pub labels: &'a [String],
+    pub assignees: &'a [String],
+    pub milestone: Option<&'a str>,
+    /// Branch the PR is opened against, instead of the default branch of the repo.
+    pub base: Option<&'a str>,
 }
 
 #[derive(Debug)]
 pub struct PullRequestOutput {
+    pub id: String,
+    pub number: i64,
+    pub permalink: String,
+    /// ISO-8601 timestamp of when the PR was opened.
+    pub created_at: String,
+    /// Name of the PR branch.
+    pub head_ref: String,
+}
+
+#[derive(Debug)]
+pub struct RepoAccess {
+    pub archived: bool,
+    /// The token is allowed to push branches to the repo.
+    pub can_push: bool,
+}
+
+/// Fine-grained token permissions, named the way GitHub shows them when creating a token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenPermission {
+    ContentsRead,
+    ContentsWrite,
+    PullRequestsWrite,
+}
+
+impl fmt::Display for TokenPermission {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenPermission::ContentsRead => write!(f, "Contents: Read-only"),
+            TokenPermission::ContentsWrite => write!(f, "Contents: Read and write"),
+            TokenPermission::PullRequestsWrite => write!(f, "Pull requests: Read and write"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct IssueOutput {
+    pub id: String,
+    pub number: i64,
+    pub url: String,
+}
+
+#[derive(Debug)]
+pub struct IssueDetails {
+    pub id: String,
+    pub url: String,
+    pub labels: Vec<IssueLabel>,
+}
+
+#[derive(Debug)]
+pub struct IssueLabel {
+    pub id: String,
+    pub name: String,
+}
+
+/// A repository found while discovering targets.
+#[derive(Debug)]
+pub struct DiscoveredRepo {
+    pub name: String,
+    pub name_with_owner: String,
+    pub ssh_url: String,
+    pub archived: bool,
+    pub language: Option<String>,
+    pub topics: Vec<String>,
+}
+
+impl From<get_org_repositories_query::GetOrgRepositoriesQueryOrganizationRepositoriesNodes>
+    for DiscoveredRepo
+{
+    fn from(
+        gh_repo: get_org_repositories_query::GetOrgRepositoriesQueryOrganizationRepositoriesNodes,
+    ) -> Self {
+        let topics = gh_repo
+            .repository_topics
+            .nodes
+            .unwrap_or_default()
+            .into_iter()
+            .flatten()
+            .map(|it| it.topic.name)
+            .collect();
+
+        DiscoveredRepo {
+            name: gh_repo.name,
+            name_with_owner: gh_repo.name_with_owner,
+            ssh_url: gh_repo.ssh_url,
+            archived: gh_repo.is_archived,
+            language: gh_repo.primary_language.map(|it| it.name),
+            topics,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RemoteBranch {
+    pub id: String,
+    pub name: String,
+    pub pull_requests: Vec<BranchPullRequest>,
+}
+
+impl RemoteBranch {
+    /// A branch can be removed once every PR that used it has been merged or closed.
+    pub fn is_prunable(&self) -> bool {
+        !self.pull_requests.is_empty() && self.pull_requests.iter().all(|it| !it.open)
+    }
+}
+
+#[derive(Debug)]
+pub struct BranchPullRequest {
     pub number: i64,
     pub permalink: String,
+    pub open: bool,
 }
 
 #[derive(Error, Debug)]
@@ -58,7 +353,10 @@ pub enum GitHubError {
     #[error("Unable to determine GitHub owner/repo from {path}")]
     UnableToDetermineRepo { path: String },
     #[error("GraphQL responded with errors: {error}")]
-    GraphQlError { error: String },
+    GraphQlError {
+        kind: GraphQlErrorKind,
+        error: String,
+    },
     #[error("Repository {owner}/{repo} does not exist")]
     NoSuchRepository { owner: String, repo: String },
     #[error("Pull Request {owner}/{repo}/{number} does not exist")]
@@ -67,258 +365,3175 @@ pub enum GitHubError {
         repo: String,
         number: i64,
     },
+    #[error("Branch {branch} does not exist in {owner}/{repo}")]
+    NoSuchBranch {
+        owner: String,
+        repo: String,
+        branch: String,
+    },
+    #[error("Issue {owner}/{repo}/{number} does not exist")]
+    NoSuchIssue {
+        owner: String,
+        repo: String,
+        number: i64,
+    },
+    #[error("User {login} does not exist")]
+    NoSuchUser { login: String },
+    #[error("Repository {owner}/{repo} has no open milestone named {title}")]
+    NoSuchMilestone {
+        owner: String,
+        repo: String,
+        title: String,
+    },
+    #[error("Unable to find organization {org}")]
+    NoSuchOrganization { org: String },
+    #[error("Unable to find team {team} in {org}")]
+    NoSuchTeam { org: String, team: String },
     #[error("Repository {owner}/{repo} has no default branch")]
     NoDefaultBranch { owner: String, repo: String },
     #[error("Unable to create Pull Request")]
     UnableToCreatePullRequest,
-    #[error(transparent)]
-    NetworkError(#[from] anyhow::Error),
+    #[error("GitHub is rate limiting requests, retry in {}s", wait.as_secs())]
+    RateLimited { wait: Duration },
+    #[error("{host} isn't github.com or one of the hosts of the migration")]
+    UnknownHost { host: String },
+    #[error("{host} isn't a GitHub host")]
+    NotGitHub { host: String },
+    #[error("GitHub rejected the token, it may have expired or been revoked")]
+    InvalidToken,
+    #[error("The token is missing the {scopes} scope(s) needed for the run")]
+    MissingTokenScopes { scopes: String },
+    #[error("Unable to reach GitHub after {attempts} attempts: {error}")]
+    NetworkError { attempts: u32, error: String },
+    /// Sending the change again could make it twice, like a second comment or PR.
+    #[error(
+        "The change may have been made before the request failed, not sending it again: {error}"
+    )]
+    MutationNotConfirmed { error: String },
+}
+
+impl GitHubError {
+    /// Builds a `GraphQlError` from the errors of a response, classified by the first error
+    /// GitHub gave a reason for.
+    pub fn from_graphql_errors(errors: &Option<Vec<graphql_client::Error>>) -> Self {
+        let errors: &[graphql_client::Error] = errors.as_deref().unwrap_or_default();
+
+        let kind = errors
+            .iter()
+            .map(GraphQlErrorKind::classify)
+            .find(|it| *it != GraphQlErrorKind::Other)
+            .unwrap_or(GraphQlErrorKind::Other);
+        let error = if errors.is_empty() {
+            "no data or errors in the response".to_owned()
+        } else {
+            errors
+                .iter()
+                .map(|it| it.message.as_str())
+                .collect::<Vec<_>>()
+                .join("; ")
+        };
+
+        GitHubError::GraphQlError { kind, error }
+    }
+
+    /// How long to wait before the work that hit this error should be tried again, `None`
+    /// when trying again won't help.
+    pub fn retry_wait(&self) -> Option<Duration> {
+        match self {
+            GitHubError::RateLimited { wait } => Some(*wait),
+            GitHubError::GraphQlError { kind, .. } => match kind {
+                GraphQlErrorKind::RateLimited => Some(DEFAULT_RATE_LIMIT_WAIT),
+                GraphQlErrorKind::Transient => Some(TRANSIENT_ERROR_WAIT),
+                _ => None,
+            },
+            GitHubError::NetworkError { .. } => Some(TRANSIENT_ERROR_WAIT),
+            _ => None,
+        }
+    }
+
+    /// The repo, PR, or other object doesn't exist, or the token can't see it.
+    pub fn is_not_found(&self) -> bool {
+        matches!(
+            self,
+            GitHubError::NoSuchRepository { .. }
+                | GitHubError::GraphQlError {
+                    kind: GraphQlErrorKind::NotFound,
+                    ..
+                }
+        )
+    }
+}
+
+/// Why GitHub rejected a GraphQL request, from the `type` GitHub adds to each error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphQlErrorKind {
+    NotFound,
+    Forbidden,
+    RateLimited,
+    /// Timeouts and server errors, which usually work on the next try.
+    Transient,
+    Other,
+}
+
+impl GraphQlErrorKind {
+    fn classify(error: &graphql_client::Error) -> Self {
+        let error_type = error
+            .extensions
+            .as_ref()
+            .and_then(|it| it.get("type"))
+            .and_then(|it| it.as_str());
+
+        match error_type {
+            Some("NOT_FOUND") => return GraphQlErrorKind::NotFound,
+            Some("FORBIDDEN") => return GraphQlErrorKind::Forbidden,
+            Some("RATE_LIMITED") => return GraphQlErrorKind::RateLimited,
+            Some("SERVICE_UNAVAILABLE") | Some("INTERNAL") | Some("TIMEOUT") => {
+                return GraphQlErrorKind::Transient
+            }
+            _ => {}
+        }
+
+        let message = error.message.to_lowercase();
+        if message.contains("not accessible") {
+            GraphQlErrorKind::Forbidden
+        } else if message.contains("something went wrong") || message.contains("timedout") {
+            GraphQlErrorKind::Transient
+        } else {
+            GraphQlErrorKind::Other
+        }
+    }
 }
 
+#[test]
+fn validate_graphql_error_kind() {
+    let error = |json: serde_json::Value| -> graphql_client::Error {
+        serde_json::from_value(json).unwrap()
+    };
+
+    assert_eq!(
+        GraphQlErrorKind::NotFound,
+        GraphQlErrorKind::classify(&error(serde_json::json!({
+            "message": "Could not resolve to a Repository with the name 'ethankhall/nope'.",
+            "extensions": { "type": "NOT_FOUND" }
+        })))
+    );
+    assert_eq!(
+        GraphQlErrorKind::Forbidden,
+        GraphQlErrorKind::classify(&error(serde_json::json!({
+            "message": "Resource not accessible by integration"
+        })))
+    );
+    assert_eq!(
+        GraphQlErrorKind::Transient,
+        GraphQlErrorKind::classify(&error(serde_json::json!({
+            "message": "Something went wrong while executing your query. Please try again."
+        })))
+    );
+    assert_eq!(
+        GraphQlErrorKind::Other,
+        GraphQlErrorKind::classify(&error(serde_json::json!({ "message": "Bad input" })))
+    );
+}
+
+/// The API clients for github.com and each of the `hosts` of a migration, so every target is
+/// worked on through the host it's on.
 #[derive(Debug)]
-pub struct GithubApiClient {
-    client: Client,
+pub struct GitHubHosts {
+    github: GithubApiClient,
+    hosts: BTreeMap<String, GithubApiClient>,
+    /// Hosts that run GitLab, Bitbucket, Azure DevOps, CodeCommit or Gerrit.
+    forges: BTreeMap<String, Box<dyn ForgeClient>>,
+    /// Hosts with `forge = "none"`.
+    push_only: BTreeSet<String>,
 }
 
-impl GithubApiClient {
-    pub fn new(github_token: &str) -> Result<Self, anyhow::Error> {
-        let client = Client::builder()
-            .user_agent(format!("clu/{}", env!("CARGO_PKG_VERSION")))
-            .default_headers(
-                std::iter::once((
-                    reqwest::header::AUTHORIZATION,
-                    reqwest::header::HeaderValue::from_str(&format!("Bearer {}", github_token))
-                        .unwrap(),
-                ))
+impl GitHubHosts {
+    pub fn new(github_token: &str, hosts: &BTreeMap<String, HostConfig>) -> AnyResult<Self> {
+        let mut clients = BTreeMap::new();
+        let mut forges: BTreeMap<String, Box<dyn ForgeClient>> = BTreeMap::new();
+        let mut push_only = BTreeSet::new();
+        let mut github = None;
+        for (host, config) in hosts {
+            let forge = match config.forge {
+                Some(forge) => forge,
+                None if host == GITLAB_HOST => Forge::Gitlab,
+                None if host == BITBUCKET_HOST => Forge::Bitbucket,
+                None if host == AZURE_DEVOPS_HOST => Forge::AzureDevops,
+                None if codecommit_region(host).is_some() => Forge::CodeCommit,
+                None => Forge::Github,
+            };
+            if forge == Forge::None {
+                push_only.insert(host.clone());
+                continue;
+            }
+
+            let token_env = match (&config.token_env, forge) {
+                (Some(name), _) => Some(name.as_str()),
+                // CodeCommit is signed with the AWS credentials instead
+                (None, Forge::Github | Forge::None | Forge::CodeCommit) => None,
+                (None, Forge::Gitlab) => Some(GITLAB_TOKEN_ENV),
+                (None, Forge::Bitbucket | Forge::BitbucketServer) => Some(BITBUCKET_TOKEN_ENV),
+                (None, Forge::AzureDevops) => Some(AZURE_DEVOPS_TOKEN_ENV),
+                (None, Forge::Gerrit) => Some(GERRIT_TOKEN_ENV),
+            };
+            let token = match token_env {
+                Some(name) => match std::env::var(name) {
+                    Ok(token) => token,
+                    Err(_) => bail!("{} has the token for {}, but isn't set", name, host),
+                },
+                None => github_token.to_owned(),
+            };
+
+            if forge == Forge::Github && host == GITHUB_HOST {
+                // An entry for github.com sends its targets elsewhere, like the sandbox of a
+                // profile
+                github = Some(match &config.api_url {
+                    Some(api_url) => GithubApiClient::for_host(&token, api_url)?,
+                    None => GithubApiClient::new(&token)?,
+                });
+            } else if forge == Forge::Github {
+                let api_url = match &config.api_url {
+                    Some(api_url) => api_url.clone(),
+                    None => format!("https://{}/api/v3", host),
+                };
+                clients.insert(host.clone(), GithubApiClient::for_host(&token, &api_url)?);
+            } else {
+                let client = forge_client(host, forge, &token, config.api_url.as_deref())?;
+                forges.insert(host.clone(), client);
+            }
+        }
+
+        // gitlab.com, bitbucket.org and dev.azure.com work without an entry, as long as there's a
+        // token for them
+        let implicit = [
+            (GITLAB_HOST, Forge::Gitlab, GITLAB_TOKEN_ENV),
+            (BITBUCKET_HOST, Forge::Bitbucket, BITBUCKET_TOKEN_ENV),
+            (
+                AZURE_DEVOPS_HOST,
+                Forge::AzureDevops,
+                AZURE_DEVOPS_TOKEN_ENV,
+            ),
+        ];
+        for (host, forge, token_env) in implicit {
+            if hosts.contains_key(host) {
+                continue;
+            }
+            if let Ok(token) = std::env::var(token_env) {
+                forges.insert(host.to_owned(), forge_client(host, forge, &token, None)?);
+            }
+        }
+
+        Ok(Self {
+            github: match github {
+                Some(github) => github,
+                None => GithubApiClient::new(github_token)?,
+            },
+            hosts: clients,
+            forges,
+            push_only,
+        })
+    }
+
+    /// The repo is on a host with `forge = "none"`, so gets its branch pushed without a PR.
+    pub fn is_push_only(&self, repo: &GitHubRepo) -> bool {
+        self.push_only.contains(&repo.host)
+    }
+
+    /// The repo is on GitHub.com or a GitHub Enterprise Server, rather than another forge.
+    pub fn is_github(&self, repo: &GitHubRepo) -> bool {
+        !self.forges.contains_key(&repo.host) && !self.is_push_only(repo)
+    }
+
+    /// Like `client_for`, but gives the github.com client for repos on other forges.
+    pub fn github_or_default(&self, repo: &GitHubRepo) -> Result<&GithubApiClient, GitHubError> {
+        if self.is_github(repo) {
+            self.client_for(repo)
+        } else {
+            Ok(&self.github)
+        }
+    }
+
+    pub fn with_max_prs_per_hour(self, limit: Option<u32>) -> Self {
+        Self {
+            github: self.github.with_max_prs_per_hour(limit),
+            hosts: self
+                .hosts
+                .into_iter()
+                .map(|(host, client)| (host, client.with_max_prs_per_hour(limit)))
                 .collect(),
-            )
-            .build()?;
+            forges: self.forges,
+            push_only: self.push_only,
+        }
+    }
 
-        Ok(Self { client })
+    /// Sends the targets on `host` to `forge`, for backends that clu doesn't come with.
+    pub fn with_forge(mut self, host: &str, forge: Box<dyn ForgeClient>) -> Self {
+        self.push_only.remove(host);
+        self.forges.insert(host.to_owned(), forge);
+        self
     }
 
-    pub async fn fetch_pull_state(
+    /// The client for the GitHub host `repo` is on.
+    pub fn client_for(&self, repo: &GitHubRepo) -> Result<&GithubApiClient, GitHubError> {
+        if repo.host == GITHUB_HOST {
+            return Ok(&self.github);
+        }
+        if !self.is_github(repo) {
+            return Err(GitHubError::NotGitHub {
+                host: repo.host.clone(),
+            });
+        }
+
+        self.hosts
+            .get(&repo.host)
+            .ok_or_else(|| GitHubError::UnknownHost {
+                host: repo.host.clone(),
+            })
+    }
+
+    /// The client of whichever forge `repo` is on.
+    pub fn forge_for(&self, repo: &GitHubRepo) -> Result<&dyn ForgeClient, GitHubError> {
+        match self.forges.get(&repo.host) {
+            Some(forge) => Ok(forge.as_ref()),
+            None => Ok(self.client_for(repo)?),
+        }
+    }
+
+    /// The forge the PR of `target` is opened on, `None` when the target is push-only.
+    pub fn forge_for_target(
         &self,
+        target: &TargetDescription,
         repo: &GitHubRepo,
-        pr_number: i64,
-    ) -> AnyResult<PullState> {
-        let gh_pull = fetch_pr_details(
-            &self.client,
-            repo.owner.clone(),
-            repo.repo.clone(),
-            pr_number,
-        )
-        .await?;
+    ) -> Result<Option<&dyn ForgeClient>, GitHubError> {
+        if target.forge == Some(Forge::None) || target.is_local() || self.is_push_only(repo) {
+            return Ok(None);
+        }
+
+        self.forge_for(repo).map(Some)
+    }
+
+    /// The client for github.com, for what isn't tied to a repo, like gists.
+    pub fn github(&self) -> &GithubApiClient {
+        &self.github
+    }
+
+    pub fn clients(&self) -> impl Iterator<Item = &GithubApiClient> {
+        std::iter::once(&self.github).chain(self.hosts.values())
+    }
+
+    /// Like `GithubApiClient::fetch_pull_states`, with each PR read from the host it's on.
+    pub async fn fetch_pull_states(
+        &self,
+        pulls: &[(GitHubRepo, i64)],
+        ignored_checks: &[Glob],
+    ) -> AnyResult<Vec<PullState>> {
+        let mut by_host: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+        for (index, (repo, _)) in pulls.iter().enumerate() {
+            by_host.entry(&repo.host).or_default().push(index);
+        }
+
+        let mut states: Vec<Option<PullState>> = pulls.iter().map(|_| None).collect();
+        for indexes in by_host.values() {
+            let host_pulls: Vec<(GitHubRepo, i64)> =
+                indexes.iter().map(|it| pulls[*it].clone()).collect();
+            let host_states = self
+                .forge_for(&host_pulls[0].0)?
+                .fetch_pull_states(&host_pulls, ignored_checks)
+                .await?;
+            // Zipped with the wrong count, states would be reported for the wrong PR's
+            if host_states.len() != host_pulls.len() {
+                bail!(
+                    "Asked {} for {} pull requests, but got {} back",
+                    host_pulls[0].0.host,
+                    host_pulls.len(),
+                    host_states.len()
+                );
+            }
+            for (index, state) in indexes.iter().zip(host_states) {
+                states[*index] = Some(state);
+            }
+        }
+
+        Ok(states.into_iter().flatten().collect())
+    }
+}
+
+fn forge_client(
+    host: &str,
+    forge: Forge,
+    token: &str,
+    api_url: Option<&str>,
+) -> AnyResult<Box<dyn ForgeClient>> {
+    let or_default = |default: String| api_url.map(str::to_owned).unwrap_or(default);
+
+    Ok(match forge {
+        Forge::Gitlab => {
+            let api_url = or_default(format!("https://{}/api/v4", host));
+            Box::new(GitLabClient::new(token, &api_url)?)
+        }
+        Forge::Bitbucket => {
+            let api_url = or_default(BITBUCKET_API_URL.to_owned());
+            Box::new(BitbucketClient::new(
+                token,
+                &api_url,
+                BitbucketFlavor::Cloud,
+            )?)
+        }
+        Forge::BitbucketServer => {
+            let api_url = or_default(format!("https://{}/rest/api/1.0", host));
+            Box::new(BitbucketClient::new(
+                token,
+                &api_url,
+                BitbucketFlavor::Server,
+            )?)
+        }
+        Forge::AzureDevops => Box::new(AzureDevOpsClient::new(token, api_url)?),
+        Forge::Gerrit => {
+            let api_url = or_default(format!("https://{}", host));
+            Box::new(GerritClient::new(token, &api_url)?)
+        }
+        Forge::CodeCommit => match codecommit_region(host) {
+            Some(region) => Box::new(CodeCommitClient::new(region, api_url)?),
+            None => bail!(
+                "{} isn't a CodeCommit host, like git-codecommit.<region>.amazonaws.com",
+                host
+            ),
+        },
+        Forge::Github | Forge::None => {
+            let api_url = or_default(format!("https://{}/api/v3", host));
+            Box::new(GithubApiClient::for_host(token, &api_url)?)
+        }
+    })
+}
+
+#[test]
+fn validate_github_hosts() {
+    let mut hosts = BTreeMap::new();
+    hosts.insert("ghe.example.com".to_owned(), HostConfig::default());
+    let github_hosts = GitHubHosts::new("token", &hosts).unwrap();
+
+    let client = |url: &str| {
+        github_hosts
+            .client_for(&extract_github_info(url).unwrap())
+            .map(|it| it.client.api_url.clone())
+    };
+    assert_eq!(
+        DEFAULT_API_URL,
+        client("git@github.com:ethankhall/clu.git").unwrap()
+    );
+    assert_eq!(
+        "https://ghe.example.com/api/v3",
+        client("git@ghe.example.com:platform/clu.git").unwrap()
+    );
+    assert!(client("git@gitlab.com:platform/clu.git").is_err());
+}
+
+/// HTTP client for the API of a single GitHub host, github.com or a GitHub Enterprise Server.
+#[derive(Debug)]
+pub struct ApiClient {
+    http: Client,
+    /// Base of the REST API, like `https://api.github.com` or `https://ghe.example.com/api/v3`.
+    api_url: String,
+    retry: RetryPolicy,
+}
+
+/// How GraphQL requests are retried when the connection fails, times out, or GitHub answers
+/// with a server error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts before giving up, including the first one.
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled for every retry after it.
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay: Duration::from_secs(2),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The default policy, with `CLU_NETWORK_RETRIES` retries when it's set.
+    pub fn from_env() -> Self {
+        let policy = Self::default();
+        match std::env::var(NETWORK_RETRIES_ENV).map(|it| it.parse::<u32>()) {
+            Ok(Ok(retries)) => Self {
+                max_attempts: retries + 1,
+                ..policy
+            },
+            Ok(Err(_)) => {
+                warn!("{} isn't a number, using the default", NETWORK_RETRIES_ENV);
+                policy
+            }
+            Err(_) => policy,
+        }
+    }
+
+    /// The wait before retrying after `attempt` failed, without jitter.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.base_delay
+            .checked_mul(factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+
+    /// The backoff plus up to half of it again, so workers that failed together don't all
+    /// retry at the same moment.
+    fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self.backoff(attempt);
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|it| it.subsec_nanos())
+            .unwrap_or_default();
+        backoff + backoff.mul_f64(f64::from(nanos % 1000) / 2000.0)
+    }
+}
+
+#[test]
+fn validate_retry_policy() {
+    let policy = RetryPolicy::default();
+    assert_eq!(Duration::from_secs(2), policy.backoff(1));
+    assert_eq!(Duration::from_secs(8), policy.backoff(3));
+    assert_eq!(policy.max_delay, policy.backoff(40));
+
+    let delay = policy.delay(2);
+    assert!(delay >= Duration::from_secs(4) && delay <= Duration::from_secs(6));
+}
+
+impl ApiClient {
+    fn get(&self, path: impl AsRef<str>) -> reqwest::RequestBuilder {
+        self.http.get(format!("{}{}", self.api_url, path.as_ref()))
+    }
+
+    fn post(&self, path: impl AsRef<str>) -> reqwest::RequestBuilder {
+        self.http.post(format!("{}{}", self.api_url, path.as_ref()))
+    }
+
+    fn put(&self, path: impl AsRef<str>) -> reqwest::RequestBuilder {
+        self.http.put(format!("{}{}", self.api_url, path.as_ref()))
+    }
+
+    fn graphql(&self) -> reqwest::RequestBuilder {
+        self.http.post(graphql_url(&self.api_url))
+    }
+}
+
+/// GitHub Enterprise Server has its GraphQL API at `/api/graphql`, next to the `/api/v3` REST
+/// API, while github.com has it at `/graphql` of the REST API.
+fn graphql_url(api_url: &str) -> String {
+    let api_url = api_url.trim_end_matches('/');
+    match api_url.strip_suffix("/v3") {
+        Some(base) => format!("{}/graphql", base),
+        None => format!("{}/graphql", api_url),
+    }
+}
+
+#[test]
+fn validate_graphql_url() {
+    assert_eq!(
+        "https://api.github.com/graphql",
+        graphql_url(DEFAULT_API_URL)
+    );
+    assert_eq!(
+        "https://ghe.example.com/api/graphql",
+        graphql_url("https://ghe.example.com/api/v3/")
+    );
+}
+
+#[derive(Debug)]
+pub struct GithubApiClient {
+    client: ApiClient,
+    /// When the next mutation may be sent. Holding the lock is what serializes mutations.
+    mutation_gate: tokio::sync::Mutex<Instant>,
+    max_prs_per_hour: Option<u32>,
+    /// When the PR's of the last hour were created, oldest first.
+    created_pull_requests: tokio::sync::Mutex<VecDeque<Instant>>,
+}
+
+impl GithubApiClient {
+    /// `CLU_USER_AGENT_SUFFIX` is appended to the user agent, and every request is tagged
+    /// with `X-Clu-Run-Id`, so audit logs can attribute the traffic to a migration run.
+    pub fn new(github_token: &str) -> Result<Self, anyhow::Error> {
+        Self::for_host(github_token, DEFAULT_API_URL)
+    }
+
+    /// Client for the GitHub host with its REST API at `api_url`, like
+    /// `https://ghe.example.com/api/v3` for a GitHub Enterprise Server.
+    pub fn for_host(github_token: &str, api_url: &str) -> Result<Self, anyhow::Error> {
+        let http = Client::builder()
+            .user_agent(user_agent(
+                std::env::var(USER_AGENT_SUFFIX_ENV).ok().as_deref(),
+            ))
+            .default_headers(
+                vec![
+                    (
+                        reqwest::header::AUTHORIZATION,
+                        reqwest::header::HeaderValue::from_str(&format!("Bearer {}", github_token))
+                            .unwrap(),
+                    ),
+                    (
+                        reqwest::header::HeaderName::from_static(RUN_ID_HEADER),
+                        reqwest::header::HeaderValue::from_str(run_id())?,
+                    ),
+                    (
+                        // createLabel and mergeStateStatus are still behind schema previews
+                        reqwest::header::ACCEPT,
+                        reqwest::header::HeaderValue::from_static(
+                            "application/vnd.github.bane-preview+json, \
+                            application/vnd.github.merge-info-preview+json",
+                        ),
+                    ),
+                ]
+                .into_iter()
+                .collect(),
+            )
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+
+        Ok(Self {
+            client: ApiClient {
+                http,
+                api_url: api_url.trim_end_matches('/').to_owned(),
+                retry: RetryPolicy::from_env(),
+            },
+            mutation_gate: tokio::sync::Mutex::new(Instant::now()),
+            max_prs_per_hour: None,
+            created_pull_requests: tokio::sync::Mutex::new(VecDeque::new()),
+        })
+    }
+
+    /// Creates at most `limit` PR's in any hour, new PR's wait for a free slot while their
+    /// branches are already pushed. Updating an existing PR isn't limited.
+    pub fn with_max_prs_per_hour(mut self, limit: Option<u32>) -> Self {
+        self.max_prs_per_hour = limit;
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.client.retry = retry;
+        self
+    }
+
+    async fn wait_for_pull_request_slot(&self, repo: &GitHubRepo) {
+        let limit = match self.max_prs_per_hour {
+            Some(limit) => limit,
+            None => return,
+        };
+
+        let mut created = self.created_pull_requests.lock().await;
+        if let Some(wait) = pull_request_slot_wait(&mut created, limit, Instant::now()) {
+            info!(
+                "{} PR's were created in the last hour, waiting {}s before creating the PR in {}",
+                limit,
+                wait.as_secs(),
+                repo
+            );
+            tokio::time::sleep(wait).await;
+            pull_request_slot_wait(&mut created, limit, Instant::now());
+        }
+        created.push_back(Instant::now());
+    }
+
+    /// Runs a request that creates or changes something. GitHub's secondary rate limits
+    /// (abuse detection) trip when content is created concurrently, so no matter how many
+    /// targets are migrated at once mutations are sent one at a time, `MUTATION_SPACING`
+    /// apart. A mutation waiting out a `Retry-After` holds up the others too.
+    async fn mutation<F: std::future::Future>(&self, request: F) -> F::Output {
+        let mut next_allowed = self.mutation_gate.lock().await;
+        tokio::time::sleep_until((*next_allowed).into()).await;
+
+        let output = request.await;
+        *next_allowed = Instant::now() + MUTATION_SPACING;
+        output
+    }
+
+    /// Sends a REST mutation through `mutation`, waiting and retrying when GitHub asks for it
+    /// with `Retry-After`. A rate limited request wasn't applied, but one that failed part way
+    /// may have been, so it's never sent again.
+    async fn send_mutation(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> AnyResult<reqwest::Response> {
+        self.mutation(async {
+            let mut attempt = 1;
+            loop {
+                let response = match request.try_clone() {
+                    Some(request) => match request.send().await {
+                        Ok(response) => response,
+                        Err(e) if e.is_connect() => return Err(e.into()),
+                        Err(e) => bail!(GitHubError::MutationNotConfirmed {
+                            error: e.to_string()
+                        }),
+                    },
+                    None => bail!("Unable to send a mutation with a streaming body"),
+                };
+
+                let status = response.status();
+                let limited =
+                    status == StatusCode::FORBIDDEN || status == StatusCode::TOO_MANY_REQUESTS;
+                if !limited
+                    || !response
+                        .headers()
+                        .contains_key(reqwest::header::RETRY_AFTER)
+                    || attempt >= MAX_RATE_LIMIT_ATTEMPTS
+                {
+                    return Ok(response);
+                }
+
+                let wait = rate_limit_wait(response.headers(), SystemTime::now());
+                warn!(
+                    "GitHub secondary rate limit hit (attempt {}/{}), waiting {}s before retrying",
+                    attempt,
+                    MAX_RATE_LIMIT_ATTEMPTS,
+                    wait.as_secs()
+                );
+                tokio::time::sleep(wait).await;
+                attempt += 1;
+            }
+        })
+        .await
+    }
+
+    /// Failing checks that match `ignored_checks` don't count against the PR.
+    pub async fn fetch_pull_state(
+        &self,
+        repo: &GitHubRepo,
+        pr_number: i64,
+        ignored_checks: &[Glob],
+    ) -> AnyResult<PullState> {
+        let mut gh_pull = fetch_pr_details(
+            &self.client,
+            repo.owner.clone(),
+            repo.repo.clone(),
+            pr_number,
+            None,
+        )
+        .await?;
+        self.fetch_remaining_checks(repo, pr_number, &mut gh_pull)
+            .await?;
+
+        Ok(PullState::new(gh_pull, ignored_checks))
+    }
+
+    /// Looks up many PR's at once by batching them into aliased GraphQL queries, the results
+    /// are in the same order as `pulls`.
+    pub async fn fetch_pull_states(
+        &self,
+        pulls: &[(GitHubRepo, i64)],
+        ignored_checks: &[Glob],
+    ) -> AnyResult<Vec<PullState>> {
+        // Collected first, a lazy iterator of the futures isn't Send enough for `ForgeClient`
+        let requests: Vec<_> = pulls
+            .chunks(PULL_STATE_BATCH_SIZE)
+            .map(|batch| self.fetch_pull_state_batch(batch, ignored_checks))
+            .collect();
+        let batches: Vec<AnyResult<Vec<PullState>>> = stream::iter(requests)
+            .buffered(PULL_STATE_CONCURRENCY)
+            .collect()
+            .await;
+
+        let mut states = Vec::with_capacity(pulls.len());
+        for batch in batches {
+            states.extend(batch?);
+        }
+
+        Ok(states)
+    }
+
+    async fn fetch_pull_state_batch(
+        &self,
+        pulls: &[(GitHubRepo, i64)],
+        ignored_checks: &[Glob],
+    ) -> AnyResult<Vec<PullState>> {
+        let mut query = String::from("query {");
+        for (index, (repo, pr_number)) in pulls.iter().enumerate() {
+            query.push_str(&format!(
+                "r{}: repository(owner: {}, name: {}) {{ pullRequest(number: {}) {} }} ",
+                index,
+                serde_json::to_string(&repo.owner)?,
+                serde_json::to_string(&repo.repo)?,
+                pr_number,
+                PULL_REQUEST_STATUS_FIELDS
+            ));
+        }
+        query.push('}');
+
+        info!("Getting details for {} pull requests", pulls.len());
+
+        let response: graphql_client::Response<
+            BTreeMap<
+                String,
+                Option<get_pull_request_status_query::GetPullRequestStatusQueryRepository>,
+            >,
+        > = post_graphql_body(&self.client, &serde_json::json!({ "query": query })).await?;
+
+        debug!("GitHub Response: {:?}", response);
+
+        let mut response_data = match response.data {
+            Some(data) => data,
+            None => bail!(GitHubError::from_graphql_errors(&response.errors)),
+        };
+
+        // A PR that's gone is reported as `Missing`, instead of failing the rest of the batch
+        let mut states = Vec::with_capacity(pulls.len());
+        for (index, (repo, pr_number)) in pulls.iter().enumerate() {
+            let gh_pull = response_data
+                .remove(&format!("r{}", index))
+                .flatten()
+                .and_then(|it| it.pull_request);
+
+            match gh_pull {
+                Some(mut gh_pull) => {
+                    self.fetch_remaining_checks(repo, *pr_number, &mut gh_pull)
+                        .await?;
+                    states.push(PullState::new(gh_pull, ignored_checks));
+                }
+                None => {
+                    warn!("PR {} of {} can't be found", pr_number, repo);
+                    states.push(PullState::missing(repo, *pr_number));
+                }
+            }
+        }
+
+        Ok(states)
+    }
+
+    /// Only the first page of checks comes back with the PR, the rest of the pages are fetched
+    /// here and added to the head commit.
+    async fn fetch_remaining_checks(
+        &self,
+        repo: &GitHubRepo,
+        pr_number: i64,
+        gh_pull: &mut PullRequestStatus,
+    ) -> AnyResult<()> {
+        loop {
+            let cursor = match head_rollup(gh_pull) {
+                Some(rollup) if rollup.contexts.page_info.has_next_page => {
+                    match &rollup.contexts.page_info.end_cursor {
+                        Some(cursor) => cursor.clone(),
+                        None => return Ok(()),
+                    }
+                }
+                _ => return Ok(()),
+            };
+
+            let next_page = fetch_pr_details(
+                &self.client,
+                repo.owner.clone(),
+                repo.repo.clone(),
+                pr_number,
+                Some(cursor),
+            )
+            .await?;
+
+            // A new commit was pushed while paging, the checks that were already fetched are
+            // not for the head anymore.
+            if next_page.head_ref_oid != gh_pull.head_ref_oid {
+                warn!(
+                    "Head of PR {} in {} changed while fetching checks",
+                    pr_number, repo
+                );
+                return Ok(());
+            }
+
+            let next_contexts = match next_page.head_ref.and_then(|it| it.target) {
+                Some(HeadTarget::Commit(commit)) if commit.oid == next_page.head_ref_oid => {
+                    match commit.status_check_rollup {
+                        Some(rollup) => rollup.contexts,
+                        None => return Ok(()),
+                    }
+                }
+                _ => return Ok(()),
+            };
+
+            if let Some(rollup) = head_rollup_mut(gh_pull) {
+                rollup
+                    .contexts
+                    .nodes
+                    .get_or_insert_with(Vec::new)
+                    .extend(next_contexts.nodes.unwrap_or_default());
+                rollup.contexts.page_info = next_contexts.page_info;
+            }
+        }
+    }
+
+    pub async fn sync_pull_request(
+        &self,
+        repo: &GitHubRepo,
+        pr_description: PullRequestDescription<'_>,
+        pr_number: Option<i64>,
+    ) -> AnyResult<PullRequestOutput> {
+        let update_pr = match pr_number {
+            Some(num) => self.reopen_if_closed(repo, num).await?,
+            None => false,
+        };
+
+        let labels = pr_description.labels;
+        let assignees = pr_description.assignees;
+        let milestone = pr_description.milestone;
+        let output = if update_pr {
+            self.update_pull_request(repo, pr_description, pr_number.unwrap())
+                .await?
+        } else {
+            self.create_pull_request(repo, pr_description).await?
+        };
+
+        if !labels.is_empty() {
+            self.add_labels(repo, &output.id, labels).await?;
+        }
+
+        if !assignees.is_empty() || milestone.is_some() {
+            self.set_pull_request_metadata(repo, &output.id, assignees, milestone)
+                .await?;
+        }
+
+        Ok(output)
+    }
+
+    async fn set_pull_request_metadata(
+        &self,
+        repo: &GitHubRepo,
+        pull_request_id: &str,
+        assignees: &[String],
+        milestone: Option<&str>,
+    ) -> AnyResult<()> {
+        let mut assignee_ids = Vec::new();
+        for login in assignees {
+            assignee_ids.push(self.find_user(login).await?);
+        }
+
+        let milestone_id = match milestone {
+            Some(title) => Some(self.find_milestone(repo, title).await?),
+            None => None,
+        };
+
+        let variables = set_pull_request_metadata_mutation::Variables {
+            pull_request_id: pull_request_id.to_owned(),
+            assignee_ids: if assignee_ids.is_empty() {
+                None
+            } else {
+                Some(assignee_ids)
+            },
+            milestone_id,
+        };
+
+        info!(
+            "Setting assignees {:?} and milestone {:?} in {}",
+            assignees, milestone, &repo
+        );
+        let response = self
+            .mutation(post_graphql::<SetPullRequestMetadataMutation>(
+                &self.client,
+                variables,
+            ))
+            .await?;
+        debug!("GitHub Response: {:?}", response);
+
+        if response
+            .data
+            .and_then(|it| it.update_pull_request)
+            .is_none()
+        {
+            bail!(GitHubError::from_graphql_errors(&response.errors));
+        }
+
+        Ok(())
+    }
+
+    async fn find_user(&self, login: &str) -> AnyResult<String> {
+        let variables = get_user_query::Variables {
+            login: login.to_owned(),
+        };
+
+        let response = post_graphql::<GetUserQuery>(&self.client, variables).await?;
+        debug!("GitHub Response: {:?}", response);
+
+        match response.data.and_then(|it| it.user) {
+            Some(user) => Ok(user.id),
+            None => bail!(GitHubError::NoSuchUser {
+                login: login.to_owned()
+            }),
+        }
+    }
+
+    async fn find_milestone(&self, repo: &GitHubRepo, title: &str) -> AnyResult<String> {
+        let variables = get_milestone_query::Variables {
+            owner: repo.owner.clone(),
+            repo: repo.repo.clone(),
+            title: title.to_owned(),
+        };
+
+        let response = post_graphql::<GetMilestoneQuery>(&self.client, variables).await?;
+        debug!("GitHub Response: {:?}", response);
+
+        let milestone = response
+            .data
+            .and_then(|it| it.repository)
+            .and_then(|it| it.milestones)
+            .and_then(|it| it.nodes)
+            .unwrap_or_default()
+            .into_iter()
+            .flatten()
+            .find(|it| it.title == title);
+
+        match milestone {
+            Some(milestone) => Ok(milestone.id),
+            None => bail!(GitHubError::NoSuchMilestone {
+                owner: repo.owner.clone(),
+                repo: repo.repo.clone(),
+                title: title.to_owned(),
+            }),
+        }
+    }
+
+    pub async fn create_issue(
+        &self,
+        repo: &GitHubRepo,
+        title: &str,
+        body: &str,
+    ) -> AnyResult<IssueOutput> {
+        let repo_details =
+            fetch_repo_details(&self.client, repo.owner.clone(), repo.repo.clone()).await?;
+
+        let variables = create_issue_mutation::Variables {
+            repository_id: repo_details.id,
+            title: title.to_owned(),
+            body: body.to_owned(),
+        };
+
+        let response = self
+            .mutation(post_graphql::<CreateIssueMutation>(&self.client, variables))
+            .await?;
+        debug!("GitHub Response: {:?}", response);
+
+        match response
+            .data
+            .and_then(|it| it.create_issue)
+            .and_then(|it| it.issue)
+        {
+            Some(issue) => {
+                info!("Created issue {}", issue.url);
+                Ok(IssueOutput {
+                    id: issue.id,
+                    number: issue.number,
+                    url: issue.url,
+                })
+            }
+            None => bail!(GitHubError::from_graphql_errors(&response.errors)),
+        }
+    }
+
+    pub async fn fetch_issue(&self, repo: &GitHubRepo, number: i64) -> AnyResult<IssueDetails> {
+        let variables = get_issue_query::Variables {
+            owner: repo.owner.clone(),
+            repo: repo.repo.clone(),
+            number,
+        };
+
+        let response = post_graphql::<GetIssueQuery>(&self.client, variables).await?;
+        debug!("GitHub Response: {:?}", response);
+
+        let issue = match response
+            .data
+            .and_then(|it| it.repository)
+            .and_then(|it| it.issue)
+        {
+            Some(issue) => issue,
+            None => bail!(GitHubError::NoSuchIssue {
+                owner: repo.owner.clone(),
+                repo: repo.repo.clone(),
+                number,
+            }),
+        };
+
+        let labels = issue
+            .labels
+            .and_then(|it| it.nodes)
+            .unwrap_or_default()
+            .into_iter()
+            .flatten()
+            .map(|label| IssueLabel {
+                id: label.id,
+                name: label.name,
+            })
+            .collect();
+
+        Ok(IssueDetails {
+            id: issue.id,
+            url: issue.url,
+            labels,
+        })
+    }
+
+    pub async fn remove_labels(&self, labelable_id: &str, label_ids: Vec<String>) -> AnyResult<()> {
+        let variables = remove_labels_mutation::Variables {
+            labelable_id: labelable_id.to_owned(),
+            label_ids,
+        };
+
+        let response = self
+            .mutation(post_graphql::<RemoveLabelsMutation>(
+                &self.client,
+                variables,
+            ))
+            .await?;
+        debug!("GitHub Response: {:?}", response);
+
+        if response
+            .data
+            .and_then(|it| it.remove_labels_from_labelable)
+            .is_none()
+        {
+            bail!(GitHubError::from_graphql_errors(&response.errors));
+        }
+
+        Ok(())
+    }
+
+    /// Attaches labels to a PR or issue, creating the labels in the repo when they don't exist.
+    pub async fn add_labels(
+        &self,
+        repo: &GitHubRepo,
+        labelable_id: &str,
+        labels: &[String],
+    ) -> AnyResult<()> {
+        let mut label_ids = Vec::new();
+        for label in labels {
+            label_ids.push(self.find_or_create_label(repo, label).await?);
+        }
+
+        let variables = add_labels_mutation::Variables {
+            labelable_id: labelable_id.to_owned(),
+            label_ids,
+        };
+
+        info!("Adding labels {:?} in {}", labels, &repo);
+        let response = self
+            .mutation(post_graphql::<AddLabelsMutation>(&self.client, variables))
+            .await?;
+        debug!("GitHub Response: {:?}", response);
+
+        if response
+            .data
+            .and_then(|it| it.add_labels_to_labelable)
+            .is_none()
+        {
+            bail!(GitHubError::from_graphql_errors(&response.errors));
+        }
+
+        Ok(())
+    }
+
+    /// Posts a markdown comment on a PR or issue, returning the URL of the comment.
+    pub async fn add_comment(&self, subject_id: &str, body: &str) -> AnyResult<String> {
+        let variables = add_comment_mutation::Variables {
+            subject_id: subject_id.to_owned(),
+            body: body.to_owned(),
+        };
+
+        let response = self
+            .mutation(post_graphql::<AddCommentMutation>(&self.client, variables))
+            .await?;
+        debug!("GitHub Response: {:?}", response);
+
+        match response
+            .data
+            .and_then(|it| it.add_comment)
+            .and_then(|it| it.comment_edge)
+            .and_then(|it| it.node)
+        {
+            Some(comment) => Ok(comment.url),
+            None => bail!(GitHubError::from_graphql_errors(&response.errors)),
+        }
+    }
+
+    /// Merges the base branch into the PR branch. GitHub does the update in the background,
+    /// and refuses it when the branch moved past `expected_head_oid`.
+    pub async fn update_pull_request_branch(
+        &self,
+        repo: &GitHubRepo,
+        pr_number: i64,
+        expected_head_oid: &str,
+    ) -> AnyResult<()> {
+        let response = self
+            .send_mutation(
+                self.client
+                    .put(format!(
+                        "/repos/{}/{}/pulls/{}/update-branch",
+                        repo.owner, repo.repo, pr_number
+                    ))
+                    .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+                    .json(&serde_json::json!({ "expected_head_sha": expected_head_oid })),
+            )
+            .await?;
+
+        let status = response.status();
+        let response: serde_json::Value = response.json().await?;
+        debug!("GitHub Response: {:?}", response);
+
+        if !status.is_success() {
+            bail!(
+                "Unable to update the branch, GitHub responded {}: {}",
+                status,
+                response
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Asks GitHub to run the check suites that failed on `head_oid` again, returning how many
+    /// were re-requested. Commit statuses reported by other CI can't be re-run this way.
+    pub async fn rerequest_failed_check_suites(
+        &self,
+        repo: &GitHubRepo,
+        head_oid: &str,
+    ) -> AnyResult<usize> {
+        let response = self
+            .client
+            .get(format!(
+                "/repos/{}/{}/commits/{}/check-suites",
+                repo.owner, repo.repo, head_oid
+            ))
+            .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+            .send()
+            .await?;
+
+        let status = response.status();
+        let body: serde_json::Value = response.json().await?;
+        debug!("GitHub Response: {:?}", body);
+        if !status.is_success() {
+            bail!(
+                "Unable to list the check suites of {}, GitHub responded {}: {}",
+                head_oid,
+                status,
+                body
+            );
+        }
+
+        let mut rerequested = 0;
+        for suite in failed_check_suites(&body) {
+            let response = self
+                .send_mutation(
+                    self.client
+                        .post(format!(
+                            "/repos/{}/{}/check-suites/{}/rerequest",
+                            repo.owner, repo.repo, suite
+                        ))
+                        .header(reqwest::header::ACCEPT, "application/vnd.github+json"),
+                )
+                .await?;
+            // Apps can refuse to run a suite again, the others are still worth re-running
+            match response.status().is_success() {
+                true => rerequested += 1,
+                false => debug!(
+                    "Check suite {} wasn't re-requested, GitHub responded {}",
+                    suite,
+                    response.status()
+                ),
+            }
+        }
+
+        Ok(rerequested)
+    }
+
+    /// Runs a workflow that has a `workflow_dispatch` trigger on `branch`.
+    pub async fn dispatch_workflow(
+        &self,
+        repo: &GitHubRepo,
+        workflow: &str,
+        branch: &str,
+        inputs: &BTreeMap<String, String>,
+    ) -> AnyResult<()> {
+        let response = self
+            .send_mutation(
+                self.client
+                    .post(format!(
+                        "/repos/{}/{}/actions/workflows/{}/dispatches",
+                        repo.owner, repo.repo, workflow
+                    ))
+                    .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+                    .json(&serde_json::json!({ "ref": branch, "inputs": inputs })),
+            )
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let response = response.text().await?;
+            debug!("GitHub Response: {:?}", response);
+            bail!(
+                "Unable to dispatch {}, GitHub responded {}: {}",
+                workflow,
+                status,
+                response
+            );
+        }
+
+        Ok(())
+    }
+
+    /// How many commits `head` has that `base` doesn't, `base` can be a SHA.
+    pub async fn commits_ahead(&self, repo: &GitHubRepo, base: &str, head: &str) -> AnyResult<u64> {
+        let response = self
+            .client
+            .get(format!(
+                "/repos/{}/{}/compare/{}...{}",
+                repo.owner, repo.repo, base, head
+            ))
+            .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+            .send()
+            .await?;
+
+        let status = response.status();
+        let body: serde_json::Value = response.json().await?;
+        debug!("GitHub Response: {:?}", body);
+        if !status.is_success() {
+            bail!(
+                "Unable to compare {} to {}, GitHub responded {}: {}",
+                base,
+                head,
+                status,
+                body
+            );
+        }
+
+        match body["ahead_by"].as_u64() {
+            Some(ahead_by) => Ok(ahead_by),
+            None => bail!("GitHub didn't say how far {} is ahead of {}", head, base),
+        }
+    }
+
+    /// Creates a secret gist with a single file, returning its URL.
+    pub async fn create_gist(&self, file_name: &str, content: &str) -> AnyResult<String> {
+        let body = serde_json::json!({
+            "public": false,
+            "files": { file_name: { "content": content } },
+        });
+
+        let response = self
+            .send_mutation(self.client.post("/gists").json(&body))
+            .await?;
+
+        let status = response.status();
+        let response: serde_json::Value = response.json().await?;
+        debug!("GitHub Response: {:?}", response);
+
+        match response.get("html_url").and_then(|it| it.as_str()) {
+            Some(url) if status.is_success() => Ok(url.to_owned()),
+            _ => bail!(
+                "Unable to create gist, GitHub responded {}: {}",
+                status,
+                response
+            ),
+        }
+    }
+
+    /// Submits an approving review on a PR, returning the URL of the review.
+    pub async fn approve_pull_request(
+        &self,
+        pull_request_id: &str,
+        body: Option<&str>,
+    ) -> AnyResult<String> {
+        let variables = add_pull_request_review_mutation::Variables {
+            pull_request_id: pull_request_id.to_owned(),
+            body: body.map(|it| it.to_owned()),
+        };
+
+        let response = self
+            .mutation(post_graphql::<AddPullRequestReviewMutation>(
+                &self.client,
+                variables,
+            ))
+            .await?;
+        debug!("GitHub Response: {:?}", response);
+
+        match response
+            .data
+            .and_then(|it| it.add_pull_request_review)
+            .and_then(|it| it.pull_request_review)
+        {
+            Some(review) => Ok(review.url),
+            None => bail!(GitHubError::from_graphql_errors(&response.errors)),
+        }
+    }
+
+    /// Closes a PR without merging it.
+    pub async fn close_pull_request(&self, pull_request_id: &str) -> AnyResult<()> {
+        let variables = close_pull_request_mutation::Variables {
+            pull_request_id: pull_request_id.to_owned(),
+        };
+
+        let response = self
+            .mutation(post_graphql::<ClosePullRequestMutation>(
+                &self.client,
+                variables,
+            ))
+            .await?;
+        debug!("GitHub Response: {:?}", response);
+
+        match response
+            .data
+            .and_then(|it| it.close_pull_request)
+            .and_then(|it| it.pull_request)
+        {
+            Some(_) => Ok(()),
+            None => bail!(GitHubError::from_graphql_errors(&response.errors)),
+        }
+    }
+
+    async fn find_or_create_label(&self, repo: &GitHubRepo, name: &str) -> AnyResult<String> {
+        let variables = get_label_query::Variables {
+            owner: repo.owner.clone(),
+            repo: repo.repo.clone(),
+            name: name.to_owned(),
+        };
+
+        let response = post_graphql::<GetLabelQuery>(&self.client, variables).await?;
+        debug!("GitHub Response: {:?}", response);
+
+        let gh_repository = match response.data.and_then(|it| it.repository) {
+            Some(r) => r,
+            None => bail!(GitHubError::NoSuchRepository {
+                owner: repo.owner.clone(),
+                repo: repo.repo.clone()
+            }),
+        };
+
+        if let Some(label) = gh_repository.label {
+            return Ok(label.id);
+        }
+
+        info!("Creating label {} in {}", name, &repo);
+        let variables = create_label_mutation::Variables {
+            repository_id: gh_repository.id,
+            name: name.to_owned(),
+            color: DEFAULT_LABEL_COLOR.to_owned(),
+        };
+
+        let response = self
+            .mutation(post_graphql::<CreateLabelMutation>(&self.client, variables))
+            .await?;
+        debug!("GitHub Response: {:?}", response);
+
+        match response
+            .data
+            .and_then(|it| it.create_label)
+            .and_then(|it| it.label)
+        {
+            Some(label) => Ok(label.id),
+            None => bail!(GitHubError::from_graphql_errors(&response.errors)),
+        }
+    }
+
+    pub async fn list_branches(
+        &self,
+        repo: &GitHubRepo,
+        pattern: &Glob,
+    ) -> AnyResult<Vec<RemoteBranch>> {
+        let mut branches = Vec::new();
+        let mut after = None;
+
+        loop {
+            let variables = get_branches_query::Variables {
+                owner: repo.owner.clone(),
+                repo: repo.repo.clone(),
+                query: pattern.literal_prefix().to_owned(),
+                after,
+            };
+
+            let response = post_graphql::<GetBranchesQuery>(&self.client, variables).await?;
+            debug!("GitHub Response: {:?}", response);
+
+            let response_data: get_branches_query::ResponseData = match response.data {
+                Some(data) => data,
+                None => bail!(GitHubError::from_graphql_errors(&response.errors)),
+            };
+
+            let refs = match response_data.repository.and_then(|it| it.refs) {
+                Some(refs) => refs,
+                None => bail!(GitHubError::NoSuchRepository {
+                    owner: repo.owner.clone(),
+                    repo: repo.repo.clone()
+                }),
+            };
+
+            for gh_ref in refs.nodes.unwrap_or_default().into_iter().flatten() {
+                if !pattern.is_match(&gh_ref.name) {
+                    continue;
+                }
+
+                let pull_requests = gh_ref
+                    .associated_pull_requests
+                    .nodes
+                    .unwrap_or_default()
+                    .into_iter()
+                    .flatten()
+                    .map(|pr| BranchPullRequest {
+                        number: pr.number,
+                        permalink: pr.permalink,
+                        open: pr.state == get_branches_query::PullRequestState::OPEN,
+                    })
+                    .collect();
+
+                branches.push(RemoteBranch {
+                    id: gh_ref.id,
+                    name: gh_ref.name,
+                    pull_requests,
+                });
+            }
+
+            if !refs.page_info.has_next_page {
+                break;
+            }
+            after = refs.page_info.end_cursor;
+        }
+
+        Ok(branches)
+    }
+
+    pub async fn list_org_repositories(&self, org: &str) -> AnyResult<Vec<DiscoveredRepo>> {
+        let mut repos = Vec::new();
+        let mut after = None;
+
+        loop {
+            let variables = get_org_repositories_query::Variables {
+                org: org.to_owned(),
+                after,
+            };
+
+            let response = post_graphql::<GetOrgRepositoriesQuery>(&self.client, variables).await?;
+            debug!("GitHub Response: {:?}", response);
+
+            let response_data: get_org_repositories_query::ResponseData = match response.data {
+                Some(data) => data,
+                None => bail!(GitHubError::from_graphql_errors(&response.errors)),
+            };
+
+            let gh_repos = match response_data.organization {
+                Some(organization) => organization.repositories,
+                None => bail!(GitHubError::NoSuchOrganization {
+                    org: org.to_owned()
+                }),
+            };
+
+            repos.extend(
+                gh_repos
+                    .nodes
+                    .unwrap_or_default()
+                    .into_iter()
+                    .flatten()
+                    .map(DiscoveredRepo::from),
+            );
+
+            if !gh_repos.page_info.has_next_page {
+                break;
+            }
+            after = gh_repos.page_info.end_cursor;
+        }
+
+        Ok(repos)
+    }
+
+    /// Lists the repos `team` in `org` has admin access to, which are the repos the team owns.
+    pub async fn list_team_repositories(
+        &self,
+        org: &str,
+        team: &str,
+    ) -> AnyResult<Vec<DiscoveredRepo>> {
+        let query = format!(
+            "query($org: String!, $team: String!, $after: String) {{ organization(login: $org) {{ \
+            team(slug: $team) {{ repositories(first: 100, after: $after) {{ \
+            pageInfo {{ hasNextPage endCursor }} edges {{ permission node {} }} }} }} }} }}",
+            DISCOVERED_REPO_FIELDS
+        );
+
+        let mut repos = Vec::new();
+        let mut after: Option<String> = None;
+        loop {
+            let body = serde_json::json!({
+                "query": query,
+                "variables": { "org": org, "team": team, "after": after },
+            });
+
+            let response: graphql_client::Response<serde_json::Value> =
+                post_graphql_body(&self.client, &body).await?;
+            debug!("GitHub Response: {:?}", response);
+
+            let repositories = match response
+                .data
+                .as_ref()
+                .and_then(|it| it.pointer("/organization/team/repositories"))
+            {
+                Some(repositories) if !repositories.is_null() => repositories,
+                _ if response.errors.is_some() => {
+                    bail!(GitHubError::from_graphql_errors(&response.errors))
+                }
+                _ => bail!(GitHubError::NoSuchTeam {
+                    org: org.to_owned(),
+                    team: team.to_owned()
+                }),
+            };
+
+            let edges = repositories
+                .get("edges")
+                .and_then(|it| it.as_array())
+                .cloned()
+                .unwrap_or_default();
+            for edge in edges {
+                if edge.get("permission").and_then(|it| it.as_str()) != Some("ADMIN") {
+                    continue;
+                }
+
+                let gh_repo: get_org_repositories_query::GetOrgRepositoriesQueryOrganizationRepositoriesNodes =
+                    serde_json::from_value(edge["node"].clone())?;
+                repos.push(DiscoveredRepo::from(gh_repo));
+            }
+
+            let has_next_page = repositories
+                .pointer("/pageInfo/hasNextPage")
+                .and_then(|it| it.as_bool())
+                .unwrap_or_default();
+            if !has_next_page {
+                break;
+            }
+            after = repositories
+                .pointer("/pageInfo/endCursor")
+                .and_then(|it| it.as_str())
+                .map(|it| it.to_owned());
+        }
+
+        Ok(repos)
+    }
+
+    /// Finds the repos with code matching a GitHub code search query, like
+    /// `filename:Dockerfile FROM ubuntu:18.04`.
+    pub async fn search_code_repositories(&self, query: &str) -> AnyResult<Vec<DiscoveredRepo>> {
+        let mut names = BTreeSet::new();
+
+        for page in 1..=MAX_CODE_SEARCH_PAGES {
+            let response = self.get_code_search_page(query, page).await?;
+
+            let items = response
+                .get("items")
+                .and_then(|it| it.as_array())
+                .cloned()
+                .unwrap_or_default();
+            for item in &items {
+                if let Some(name) = item
+                    .pointer("/repository/full_name")
+                    .and_then(|it| it.as_str())
+                {
+                    names.insert(name.to_owned());
+                }
+            }
+
+            let total = response
+                .get("total_count")
+                .and_then(|it| it.as_u64())
+                .unwrap_or_default();
+            if items.is_empty() || u64::from(page) * 100 >= total {
+                break;
+            }
+        }
+
+        info!("Code search matched {} repositories", names.len());
+        let names: Vec<String> = names.into_iter().collect();
+        let mut repos = Vec::with_capacity(names.len());
+        for batch in names.chunks(PULL_STATE_BATCH_SIZE) {
+            repos.extend(self.fetch_discovered_repos(batch).await?);
+        }
+
+        Ok(repos)
+    }
+
+    /// Code search has its own, much lower, rate limit so it's retried the same way as
+    /// `post_graphql_body`.
+    async fn get_code_search_page(&self, query: &str, page: u32) -> AnyResult<serde_json::Value> {
+        let mut attempt = 1;
+        loop {
+            let response = self
+                .client
+                .get("/search/code")
+                .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+                .query(&[
+                    ("q", query.to_owned()),
+                    ("per_page", "100".to_owned()),
+                    ("page", page.to_string()),
+                ])
+                .send()
+                .await?;
+
+            let status = response.status();
+            let headers = response.headers().clone();
+            let body: serde_json::Value = response.json().await?;
+            debug!("GitHub Response: {:?}", body);
+
+            if status.is_success() {
+                return Ok(body);
+            }
+
+            if !is_rate_limited(status, &headers, &body) {
+                bail!("Code search failed, GitHub responded {}: {}", status, body);
+            }
+
+            let wait = rate_limit_wait(&headers, SystemTime::now());
+            if attempt >= MAX_RATE_LIMIT_ATTEMPTS {
+                bail!(GitHubError::RateLimited { wait });
+            }
+
+            warn!(
+                "GitHub search rate limit hit (attempt {}/{}), waiting {}s before retrying",
+                attempt,
+                MAX_RATE_LIMIT_ATTEMPTS,
+                wait.as_secs()
+            );
+            tokio::time::sleep(wait).await;
+            attempt += 1;
+        }
+    }
+
+    /// Looks up `owner/name` repos with one aliased query.
+    async fn fetch_discovered_repos(&self, names: &[String]) -> AnyResult<Vec<DiscoveredRepo>> {
+        let mut query = String::from("query {");
+        for (index, name) in names.iter().enumerate() {
+            let (owner, repo) = name.split_once('/').unwrap_or_default();
+            query.push_str(&format!(
+                "r{}: repository(owner: {}, name: {}) {} ",
+                index,
+                serde_json::to_string(owner)?,
+                serde_json::to_string(repo)?,
+                DISCOVERED_REPO_FIELDS
+            ));
+        }
+        query.push('}');
+
+        let response: graphql_client::Response<
+            BTreeMap<
+                String,
+                Option<get_org_repositories_query::GetOrgRepositoriesQueryOrganizationRepositoriesNodes>,
+            >,
+        > = post_graphql_body(&self.client, &serde_json::json!({ "query": query })).await?;
+        debug!("GitHub Response: {:?}", response);
+
+        let response_data = match response.data {
+            Some(data) => data,
+            None => bail!(GitHubError::from_graphql_errors(&response.errors)),
+        };
+
+        Ok(response_data
+            .into_values()
+            .flatten()
+            .map(DiscoveredRepo::from)
+            .collect())
+    }
+
+    pub async fn delete_branch(&self, branch: &RemoteBranch) -> AnyResult<()> {
+        self.delete_ref(&branch.id).await
+    }
+
+    pub async fn delete_ref(&self, ref_id: &str) -> AnyResult<()> {
+        let variables = delete_ref_mutation::Variables {
+            ref_id: ref_id.to_owned(),
+        };
+
+        let response = self
+            .mutation(post_graphql::<DeleteRefMutation>(&self.client, variables))
+            .await?;
+        debug!("GitHub Response: {:?}", response);
+
+        if response.data.and_then(|it| it.delete_ref).is_none() {
+            bail!(GitHubError::from_graphql_errors(&response.errors));
+        }
+
+        Ok(())
+    }
+
+    /// Does the operations of a run against `repo` to find the permissions the token is
+    /// missing. A branch is created and deleted again, and a PR is opened from it. GitHub
+    /// rejects the PR because the branch has no commits, which is enough to show the token
+    /// is allowed to create one.
+    pub async fn probe_permissions(
+        &self,
+        repo: &GitHubRepo,
+        needs_pull_request: bool,
+    ) -> AnyResult<Vec<TokenPermission>> {
+        let repo_details =
+            match fetch_repo_details(&self.client, repo.owner.clone(), repo.repo.clone()).await {
+                Ok(details) => details,
+                Err(e) => {
+                    warn!("Unable to read {}: {:?}", repo, e);
+                    return Ok(vec![TokenPermission::ContentsRead]);
+                }
+            };
+
+        let head_oid = self
+            .fetch_ref_head(repo, &repo_details.target_branch)
+            .await?;
+        let probe_branch = format!(
+            "clu-permission-probe-{}",
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)?
+                .as_secs()
+        );
+
+        let variables = create_ref_mutation::Variables {
+            repository_id: repo_details.id.clone(),
+            name: format!("{}{}", repo_details.prefix, probe_branch),
+            oid: head_oid,
+        };
+
+        info!("Checking token permissions against {}", repo);
+        let response = self
+            .mutation(post_graphql::<CreateRefMutation>(&self.client, variables))
+            .await?;
+        debug!("GitHub Response: {:?}", response);
+
+        let ref_id = match response
+            .data
+            .and_then(|it| it.create_ref)
+            .and_then(|it| it.ref_)
+        {
+            Some(created) => created.id,
+            None if is_permission_error(&response.errors) => {
+                return Ok(vec![TokenPermission::ContentsWrite])
+            }
+            None => bail!(GitHubError::from_graphql_errors(&response.errors)),
+        };
+
+        let mut missing = Vec::new();
+        if needs_pull_request {
+            let variables = create_pull_request_migration::Variables {
+                repository_id: repo_details.id,
+                base_ref: repo_details.target_branch,
+                head_ref: format!("{}{}", repo_details.prefix, probe_branch),
+                title: "clu permission probe".to_owned(),
+                body: "Opened by `clu` to check the permissions of its token.".to_owned(),
+            };
+
+            let response = self
+                .mutation(post_graphql::<CreatePullRequestMigration>(
+                    &self.client,
+                    variables,
+                ))
+                .await?;
+            debug!("GitHub Response: {:?}", response);
+
+            if is_permission_error(&response.errors) {
+                missing.push(TokenPermission::PullRequestsWrite);
+            }
+        }
+
+        // Deleting the branch also closes the PR, if GitHub did open one.
+        let variables = delete_ref_mutation::Variables { ref_id };
+        let response = self
+            .mutation(post_graphql::<DeleteRefMutation>(&self.client, variables))
+            .await?;
+        if response.data.and_then(|it| it.delete_ref).is_none() {
+            warn!(
+                "Unable to delete {} from {}, it needs to be deleted by hand",
+                probe_branch, repo
+            );
+        }
+
+        Ok(missing)
+    }
+
+    /// Checks the scopes of the token against the ones a run needs, so a token without them
+    /// fails before any target is migrated. Only classic tokens list their scopes,
+    /// fine-grained and app tokens are left to `probe_permissions`.
+    pub async fn check_token_scopes(&self) -> AnyResult<()> {
+        let response = self.client.get("/rate_limit").send().await?;
+        if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+            bail!(GitHubError::InvalidToken);
+        }
+
+        let scopes = match response.headers().get(OAUTH_SCOPES_HEADER) {
+            Some(scopes) => scopes.to_str()?,
+            None => return Ok(()),
+        };
+        debug!("Token scopes: {}", scopes);
+
+        let missing = missing_token_scopes(scopes);
+        if !missing.is_empty() {
+            bail!(GitHubError::MissingTokenScopes {
+                scopes: missing.join(", ")
+            });
+        }
+
+        Ok(())
+    }
+
+    pub async fn fetch_repo_access(&self, repo: &GitHubRepo) -> AnyResult<RepoAccess> {
+        let repo_details =
+            fetch_repo_details(&self.client, repo.owner.clone(), repo.repo.clone()).await?;
+
+        Ok(RepoAccess {
+            archived: repo_details.archived,
+            can_push: repo_details.can_push,
+        })
+    }
+
+    /// Checks if the latest commit on a branch has failing checks, used to tell failures that
+    /// the migration caused from ones that already exist.
+    pub async fn is_branch_failing(&self, repo: &GitHubRepo, branch: &str) -> AnyResult<bool> {
+        let variables = get_branch_status_query::Variables {
+            owner: repo.owner.clone(),
+            repo: repo.repo.clone(),
+            qualified_name: format!("refs/heads/{}", branch),
+        };
+
+        let response = post_graphql::<GetBranchStatusQuery>(&self.client, variables).await?;
+        debug!("GitHub Response: {:?}", response);
+
+        let target = match response
+            .data
+            .and_then(|it| it.repository)
+            .and_then(|it| it.ref_)
+            .and_then(|it| it.target)
+        {
+            Some(target) => target,
+            None => bail!(GitHubError::NoSuchBranch {
+                owner: repo.owner.clone(),
+                repo: repo.repo.clone(),
+                branch: branch.to_owned(),
+            }),
+        };
+
+        let state = match target {
+            get_branch_status_query::GetBranchStatusQueryRepositoryRefTarget::Commit(commit) => {
+                commit.status_check_rollup.map(|it| it.state)
+            }
+            _ => None,
+        };
+
+        Ok(matches!(
+            state,
+            Some(get_branch_status_query::StatusState::FAILURE)
+                | Some(get_branch_status_query::StatusState::ERROR)
+        ))
+    }
+
+    async fn fetch_ref_head(&self, repo: &GitHubRepo, qualified_name: &str) -> AnyResult<String> {
+        let variables = get_branch_head_query::Variables {
+            owner: repo.owner.clone(),
+            repo: repo.repo.clone(),
+            qualified_name: qualified_name.to_owned(),
+        };
+
+        let response = post_graphql::<GetBranchHeadQuery>(&self.client, variables).await?;
+        debug!("GitHub Response: {:?}", response);
+
+        match response
+            .data
+            .and_then(|it| it.repository)
+            .and_then(|it| it.ref_)
+            .and_then(|it| it.target)
+        {
+            Some(target) => Ok(target.oid),
+            None => bail!(GitHubError::NoSuchBranch {
+                owner: repo.owner.clone(),
+                repo: repo.repo.clone(),
+                branch: qualified_name.to_owned(),
+            }),
+        }
+    }
+
+    /// Commits files directly to a branch through the API, without needing a clone.
+    /// Returns the URL of the new commit.
+    pub async fn commit_files(
+        &self,
+        repo: &GitHubRepo,
+        branch: &str,
+        headline: &str,
+        files: Vec<(String, Vec<u8>)>,
+    ) -> AnyResult<String> {
+        use base64::Engine;
+
+        let head_oid = self
+            .fetch_ref_head(repo, &format!("refs/heads/{}", branch))
+            .await?;
+
+        let additions = files
+            .into_iter()
+            .map(
+                |(path, contents)| create_commit_on_branch_mutation::FileAddition {
+                    path,
+                    contents: base64::engine::general_purpose::STANDARD.encode(contents),
+                },
+            )
+            .collect();
+
+        let variables = create_commit_on_branch_mutation::Variables {
+            repository_name_with_owner: format!("{}/{}", repo.owner, repo.repo),
+            branch_name: branch.to_owned(),
+            expected_head_oid: head_oid,
+            headline: headline.to_owned(),
+            additions,
+        };
+
+        info!("Committing to {} on {}", &repo, branch);
+        let response = self
+            .mutation(post_graphql::<CreateCommitOnBranchMutation>(
+                &self.client,
+                variables,
+            ))
+            .await?;
+        debug!("GitHub Response: {:?}", response);
+
+        match response
+            .data
+            .and_then(|it| it.create_commit_on_branch)
+            .and_then(|it| it.commit)
+        {
+            Some(commit) => Ok(commit.url),
+            None => bail!(GitHubError::from_graphql_errors(&response.errors)),
+        }
+    }
+
+    /// Checks if the PR can be updated. A PR that was closed without being merged is reopened
+    /// so the migration keeps its history, when GitHub won't reopen it (like when the branch was
+    /// force pushed while it was closed) a new PR is created instead.
+    async fn reopen_if_closed(&self, repo: &GitHubRepo, pr_number: i64) -> AnyResult<bool> {
+        use get_pull_request_status_query::PullRequestState;
+
+        let gh_pull = fetch_pr_details(
+            &self.client,
+            repo.owner.clone(),
+            repo.repo.clone(),
+            pr_number,
+            None,
+        )
+        .await?;
+
+        match gh_pull.state {
+            PullRequestState::OPEN => Ok(true),
+            PullRequestState::CLOSED => match self.reopen_pull_request(&gh_pull.id).await {
+                Ok(_) => {
+                    info!("Reopened {}", gh_pull.permalink);
+                    Ok(true)
+                }
+                Err(e) => {
+                    warn!(
+                        "Unable to reopen {}, creating a new PR: {}",
+                        gh_pull.permalink, e
+                    );
+                    Ok(false)
+                }
+            },
+            _ => Ok(false),
+        }
+    }
+
+    async fn reopen_pull_request(&self, pull_request_id: &str) -> AnyResult<()> {
+        let variables = reopen_pull_request_mutation::Variables {
+            pull_request_id: pull_request_id.to_owned(),
+        };
+
+        let response = self
+            .mutation(post_graphql::<ReopenPullRequestMutation>(
+                &self.client,
+                variables,
+            ))
+            .await?;
+        debug!("GitHub Response: {:?}", response);
+
+        match response
+            .data
+            .and_then(|it| it.reopen_pull_request)
+            .and_then(|it| it.pull_request)
+        {
+            Some(_) => Ok(()),
+            None => bail!(GitHubError::from_graphql_errors(&response.errors)),
+        }
+    }
+
+    async fn update_pull_request(
+        &self,
+        repo: &GitHubRepo,
+        pr_description: PullRequestDescription<'_>,
+        pr_number: i64,
+    ) -> AnyResult<PullRequestOutput> {
+        let gh_pull = fetch_pr_details(
+            &self.client,
+            repo.owner.clone(),
+            repo.repo.clone(),
+            pr_number,
+            None,
+        )
+        .await?;
+        let pull_id = gh_pull.id;
+
+        let variables = update_pull_request_mutation::Variables {
+            pull_request_id: pull_id,
+            body: pr_description.body.to_owned(),
+            title: pr_description.title.to_owned(),
+            base_ref_name: pr_description.base.map(|it| it.to_owned()),
+        };
+
+        info!("Updating PR for {}", &repo);
+
+        let updated_pr = self
+            .mutation(post_graphql::<UpdatePullRequestMutation>(
+                &self.client,
+                variables,
+            ))
+            .await?;
+
+        debug!("GitHub Response: {:?}", updated_pr);
+
+        let response_data: update_pull_request_mutation::ResponseData = match updated_pr.data {
+            Some(data) => data,
+            None => bail!(GitHubError::from_graphql_errors(&updated_pr.errors)),
+        };
+
+        let pr = match response_data
+            .update_pull_request
+            .and_then(|it| it.pull_request)
+        {
+            Some(pr) => pr,
+            None => bail!(GitHubError::UnableToCreatePullRequest),
+        };
+
+        info!("Updated PR {}", pr.permalink);
+
+        Ok(PullRequestOutput {
+            id: pr.id,
+            number: pr.number,
+            permalink: pr.permalink,
+            created_at: pr.created_at,
+            head_ref: pr.head_ref_name,
+        })
+    }
+
+    async fn create_pull_request(
+        &self,
+        repo: &GitHubRepo,
+        pr_description: PullRequestDescription<'_>,
+    ) -> Result<PullRequestOutput, anyhow::Error> {
+        let repo_details =
+            fetch_repo_details(&self.client, repo.owner.clone(), repo.repo.clone()).await?;
+
+        self.wait_for_pull_request_slot(repo).await;
+
+        let base_ref = match pr_description.base {
+            Some(base) => format!("{}{}", repo_details.prefix, base),
+            None => repo_details.target_branch,
+        };
+        let variables = create_pull_request_migration::Variables {
+            repository_id: repo_details.id,
+            base_ref,
+            head_ref: format!("{}{}", repo_details.prefix, pr_description.branch),
+            body: pr_description.body.to_owned(),
+            title: pr_description.title.to_owned(),
+        };
+
+        let created_pr = self
+            .mutation(post_graphql::<CreatePullRequestMigration>(
+                &self.client,
+                variables,
+            ))
+            .await?;
+
+        debug!("GitHub Response: {:?}", created_pr);
+
+        let response_data: create_pull_request_migration::ResponseData = match created_pr.data {
+            Some(data) => data,
+            None => bail!(GitHubError::from_graphql_errors(&created_pr.errors)),
+        };
+
+        let pr = match response_data
+            .create_pull_request
+            .and_then(|it| it.pull_request)
+        {
+            Some(pr) => pr,
+            None => bail!(GitHubError::UnableToCreatePullRequest),
+        };
+
+        info!("Create PR at {}", pr.permalink);
+
+        Ok(PullRequestOutput {
+            id: pr.id,
+            number: pr.number,
+            permalink: pr.permalink,
+            created_at: pr.created_at,
+            head_ref: pr.head_ref_name,
+        })
+    }
+}
+
+/// Forgets the PR's created more than an hour before `now`, and returns how long to wait until
+/// another one can be created without going over `limit`.
+fn pull_request_slot_wait(
+    created: &mut VecDeque<Instant>,
+    limit: u32,
+    now: Instant,
+) -> Option<Duration> {
+    while created
+        .front()
+        .map_or(false, |it| now.duration_since(*it) >= PULL_REQUEST_WINDOW)
+    {
+        created.pop_front();
+    }
+
+    if created.len() < limit as usize {
+        return None;
+    }
+
+    let oldest = created[created.len() - limit as usize];
+    Some(PULL_REQUEST_WINDOW - now.duration_since(oldest))
+}
+
+#[test]
+fn validate_pull_request_slot_wait() {
+    let start = Instant::now();
+    let mut created: VecDeque<Instant> = vec![start, start + Duration::from_secs(600)].into();
+
+    assert_eq!(
+        None,
+        pull_request_slot_wait(&mut created, 3, start + Duration::from_secs(900))
+    );
+    assert_eq!(
+        Some(Duration::from_secs(2700)),
+        pull_request_slot_wait(&mut created, 2, start + Duration::from_secs(900))
+    );
+    assert_eq!(
+        None,
+        pull_request_slot_wait(&mut created, 2, start + Duration::from_secs(3600))
+    );
+    assert_eq!(1, created.len());
+}
+
+/// Color used for labels that clu has to create.
+const DEFAULT_LABEL_COLOR: &str = "ededed";
+
+const USER_AGENT_SUFFIX_ENV: &str = "CLU_USER_AGENT_SUFFIX";
+const RUN_ID_ENV: &str = "CLU_RUN_ID";
+const NETWORK_RETRIES_ENV: &str = "CLU_NETWORK_RETRIES";
+const RUN_ID_HEADER: &str = "x-clu-run-id";
+const DEFAULT_API_URL: &str = "https://api.github.com";
+
+/// Host of the repos that don't have an entry in `hosts`.
+pub const GITHUB_HOST: &str = "github.com";
+const OAUTH_SCOPES_HEADER: &str = "x-oauth-scopes";
+
+/// Classic token scopes a run needs, `workflow` lets it push changes to `.github/workflows`.
+const REQUIRED_TOKEN_SCOPES: &[&str] = &["repo", "workflow"];
+
+/// Fields of a repository selected by `GetOrgRepositoriesQuery`, so aliased lookups of single
+/// repos decode into the same type. Must be kept in sync with the query.
+const DISCOVERED_REPO_FIELDS: &str = "{ name nameWithOwner sshUrl isArchived \
+    primaryLanguage { name } repositoryTopics(first: 20) { nodes { topic { name } } } }";
+
+/// GitHub stops returning code search results after this many pages of 100.
+const MAX_CODE_SEARCH_PAGES: u32 = 10;
+
+/// Number of PR's looked up in a single aliased query by `fetch_pull_states`.
+const PULL_STATE_BATCH_SIZE: usize = 25;
+
+/// Number of batched PR queries that are in flight at the same time.
+const PULL_STATE_CONCURRENCY: usize = 4;
+
+/// Selection used by the batched PR lookups, this must stay in sync with
+/// `GetPullRequestStatusQuery.graphql` since the response is decoded into its types.
+const PULL_REQUEST_STATUS_FIELDS: &str =
+    "{ id state merged mergeable mergeStateStatus permalink createdAt closedAt baseRefName \
+    baseRef { branchProtectionRule { requiredStatusCheckContexts } } reviewDecision \
+    reviewRequests(first: 25) { nodes { requestedReviewer { __typename ... on User { login } \
+    ... on Team { combinedSlug } ... on Mannequin { login } } } } \
+    latestOpinionatedReviews(first: 25) { nodes { state author { __typename login } } } \
+    headRefOid headRef { id target { __typename ... on Commit { oid statusCheckRollup { state \
+    contexts(first: 100) { pageInfo { hasNextPage endCursor } nodes { __typename \
+    ... on CheckRun { name status conclusion detailsUrl } \
+    ... on StatusContext { context state targetUrl } } } } } } } }";
+
+/// Number of times a request that was rejected by GitHub's rate limiting is attempted before
+/// the response is handed back to the caller.
+const MAX_RATE_LIMIT_ATTEMPTS: u32 = 5;
+
+/// Used when GitHub reports a rate limit without telling us how long to wait.
+const DEFAULT_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+
+/// Window that `max-prs-per-hour` is counted over.
+const PULL_REQUEST_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// GitHub recommends waiting at least a second between requests that create content.
+const MUTATION_SPACING: Duration = Duration::from_secs(1);
+
+/// How long a run waits before trying a target again after it hit a timeout or server error.
+const TRANSIENT_ERROR_WAIT: Duration = Duration::from_secs(5);
+
+/// GitHub gives up on queries after 10 seconds, anything much longer is a stuck connection.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub async fn post_graphql<Q: GraphQLQuery>(
+    client: &ApiClient,
+    variables: Q::Variables,
+) -> AnyResult<graphql_client::Response<Q::ResponseData>> {
+    let body = Q::build_query(variables);
+    post_graphql_body(client, &body).await
+}
+
+/// Sends a GraphQL body, retrying when GitHub rejects the request because of rate limits.
+/// Used directly for queries that are built at runtime instead of derived with `GraphQLQuery`.
+///
+/// Queries are retried after server errors and dropped connections too. Mutations only are when
+/// the connection couldn't be made, GitHub may have applied one that failed after it was sent.
+pub async fn post_graphql_body<B: Serialize, R: DeserializeOwned>(
+    client: &ApiClient,
+    body: &B,
+) -> AnyResult<graphql_client::Response<R>> {
+    debug!("GitHub Body: {:?}", serde_json::to_string(body));
+    let mutation = is_mutation(&serde_json::to_value(body)?);
+
+    let retry = client.retry;
+    let mut attempt = 1;
+    loop {
+        // 502s and 504s come back as HTML when a query takes too long
+        let failure = match client.graphql().json(body).send().await {
+            Ok(response) if mutation && response.status().is_server_error() => {
+                bail!(GitHubError::MutationNotConfirmed {
+                    error: format!("GitHub responded {}", response.status())
+                });
+            }
+            Ok(response) if response.status().is_server_error() => {
+                format!("GitHub responded {}", response.status())
+            }
+            Ok(response) => {
+                let status = response.status();
+                let headers = response.headers().clone();
+                let mut response: serde_json::Value = response.json().await?;
+
+                if !is_rate_limited(status, &headers, &response) {
+                    keep_error_types(&mut response);
+                    return Ok(serde_json::from_value(response)?);
+                }
+
+                let wait = rate_limit_wait(&headers, SystemTime::now());
+                if attempt >= MAX_RATE_LIMIT_ATTEMPTS {
+                    bail!(GitHubError::RateLimited { wait });
+                }
+
+                warn!(
+                    "GitHub rate limit hit (attempt {}/{}), waiting {}s before retrying",
+                    attempt,
+                    MAX_RATE_LIMIT_ATTEMPTS,
+                    wait.as_secs()
+                );
+                tokio::time::sleep(wait).await;
+                attempt += 1;
+                continue;
+            }
+            Err(e) if mutation && !e.is_connect() && (e.is_timeout() || e.is_request()) => {
+                bail!(GitHubError::MutationNotConfirmed {
+                    error: e.to_string()
+                });
+            }
+            Err(e) if e.is_connect() || e.is_timeout() || e.is_request() => e.to_string(),
+            Err(e) => return Err(e.into()),
+        };
+
+        if attempt >= retry.max_attempts {
+            bail!(GitHubError::NetworkError {
+                attempts: attempt,
+                error: failure,
+            });
+        }
+
+        let wait = retry.delay(attempt);
+        warn!(
+            "{} (attempt {}/{}), waiting {}ms before retrying",
+            failure,
+            attempt,
+            retry.max_attempts,
+            wait.as_millis()
+        );
+        tokio::time::sleep(wait).await;
+        attempt += 1;
+    }
+}
+
+/// Whether a GraphQL body changes something, rather than only reading.
+fn is_mutation(body: &serde_json::Value) -> bool {
+    body.get("query")
+        .and_then(|it| it.as_str())
+        .map_or(false, |it| it.trim_start().starts_with("mutation"))
+}
+
+#[tokio::test]
+async fn validate_mutation_not_retried_after_timeout() {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    // Takes every request and never answers, so each one times out
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let api_url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(AtomicU32::new(0));
+    let counted = requests.clone();
+    std::thread::spawn(move || {
+        let mut held = Vec::new();
+        for stream in listener.incoming() {
+            counted.fetch_add(1, Ordering::SeqCst);
+            held.push(stream);
+        }
+    });
+    let client = ApiClient {
+        http: Client::builder()
+            .timeout(Duration::from_millis(200))
+            .build()
+            .unwrap(),
+        api_url,
+        retry: RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(1),
+        },
+    };
+
+    let mutation =
+        serde_json::json!({ "query": "mutation { addComment(input: {}) { clientMutationId } }" });
+    let error = post_graphql_body::<_, serde_json::Value>(&client, &mutation)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<GitHubError>(),
+        Some(GitHubError::MutationNotConfirmed { .. })
+    ));
+    assert_eq!(1, requests.load(Ordering::SeqCst));
+
+    let query = serde_json::json!({ "query": "query { viewer { login } }" });
+    let error = post_graphql_body::<_, serde_json::Value>(&client, &query)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<GitHubError>(),
+        Some(GitHubError::NetworkError { attempts: 3, .. })
+    ));
+    assert_eq!(4, requests.load(Ordering::SeqCst));
+}
+
+/// The `REQUIRED_TOKEN_SCOPES` missing from the comma separated scopes of a token.
+fn missing_token_scopes(scopes: &str) -> Vec<&'static str> {
+    let granted: Vec<&str> = scopes.split(',').map(|it| it.trim()).collect();
+    REQUIRED_TOKEN_SCOPES
+        .iter()
+        .filter(|it| !granted.contains(it))
+        .copied()
+        .collect()
+}
+
+#[test]
+fn validate_missing_token_scopes() {
+    assert!(missing_token_scopes("repo, workflow, read:org").is_empty());
+    assert_eq!(vec!["workflow"], missing_token_scopes("repo, read:org"));
+    assert_eq!(vec!["repo", "workflow"], missing_token_scopes(""));
+}
+
+/// GitHub answers with "Resource not accessible by personal access token" (or "by
+/// integration" for apps) when the token lacks a permission.
+fn is_permission_error(errors: &Option<Vec<graphql_client::Error>>) -> bool {
+    errors
+        .iter()
+        .flatten()
+        .any(|error| GraphQlErrorKind::classify(error) == GraphQlErrorKind::Forbidden)
+}
+
+/// GitHub also answers 403 when the token lacks a permission, which waiting doesn't fix. A 403
+/// is only a rate limit when it says so, with `Retry-After`, an exhausted `X-RateLimit-Remaining`
+/// or the message of a secondary rate limit.
+fn is_rate_limited(status: StatusCode, headers: &HeaderMap, body: &serde_json::Value) -> bool {
+    if status == StatusCode::TOO_MANY_REQUESTS || is_graphql_rate_limited(body) {
+        return true;
+    }
+    if status != StatusCode::FORBIDDEN {
+        return false;
+    }
+
+    let header = |name: &str| headers.get(name).and_then(|it| it.to_str().ok());
+    let message = body
+        .get("message")
+        .and_then(|it| it.as_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    header("retry-after").is_some()
+        || header("x-ratelimit-remaining") == Some("0")
+        || message.contains("secondary rate limit")
+}
+
+#[test]
+fn validate_is_rate_limited() {
+    use reqwest::header::HeaderValue;
+
+    let forbidden = serde_json::json!({ "message": "Resource not accessible by integration" });
+    let mut headers = HeaderMap::new();
+    // 403 without rate-limit headers is not retried
+    assert!(!is_rate_limited(
+        StatusCode::FORBIDDEN,
+        &headers,
+        &forbidden
+    ));
+    assert!(is_rate_limited(
+        StatusCode::TOO_MANY_REQUESTS,
+        &headers,
+        &forbidden
+    ));
+    assert!(is_rate_limited(
+        StatusCode::FORBIDDEN,
+        &headers,
+        &serde_json::json!({ "message": "You have exceeded a secondary rate limit." })
+    ));
+    assert!(is_rate_limited(
+        StatusCode::OK,
+        &headers,
+        &serde_json::json!({ "errors": [{ "type": "RATE_LIMITED", "message": "API rate limit exceeded" }] })
+    ));
+
+    headers.insert("x-ratelimit-remaining", HeaderValue::from_static("12"));
+    assert!(!is_rate_limited(
+        StatusCode::FORBIDDEN,
+        &headers,
+        &forbidden
+    ));
+    headers.insert("x-ratelimit-remaining", HeaderValue::from_static("0"));
+    assert!(is_rate_limited(StatusCode::FORBIDDEN, &headers, &forbidden));
+
+    let mut headers = HeaderMap::new();
+    headers.insert("retry-after", HeaderValue::from_static("60"));
+    assert!(is_rate_limited(StatusCode::FORBIDDEN, &headers, &forbidden));
+}
+
+fn is_graphql_rate_limited(response: &serde_json::Value) -> bool {
+    match response.get("errors").and_then(|it| it.as_array()) {
+        Some(errors) => errors
+            .iter()
+            .any(|error| error.get("type").and_then(|it| it.as_str()) == Some("RATE_LIMITED")),
+        None => false,
+    }
+}
+
+/// GitHub puts the error `type` next to the message, where `graphql_client::Error` drops it, so
+/// it's moved into `extensions` to be available for `GraphQlErrorKind`.
+fn keep_error_types(response: &mut serde_json::Value) {
+    let errors = match response.get_mut("errors").and_then(|it| it.as_array_mut()) {
+        Some(errors) => errors,
+        None => return,
+    };
+
+    for error in errors.iter_mut().filter_map(|it| it.as_object_mut()) {
+        if let Some(error_type) = error.remove("type") {
+            let extensions = error
+                .entry("extensions")
+                .or_insert_with(|| serde_json::json!({}));
+            if let Some(extensions) = extensions.as_object_mut() {
+                extensions.entry("type").or_insert(error_type);
+            }
+        }
+    }
+}
+
+/// Works out how long to back off, preferring `Retry-After` (secondary rate limits) and then
+/// `X-RateLimit-Reset` when the primary budget is exhausted.
+fn rate_limit_wait(headers: &HeaderMap, now: SystemTime) -> Duration {
+    let header_value = |name: &str| -> Option<u64> {
+        headers
+            .get(name)
+            .and_then(|it| it.to_str().ok())
+            .and_then(|it| it.parse().ok())
+    };
+
+    if let Some(seconds) = header_value("retry-after") {
+        return Duration::from_secs(seconds);
+    }
+
+    if header_value("x-ratelimit-remaining") == Some(0) {
+        if let Some(reset) = header_value("x-ratelimit-reset") {
+            let reset = SystemTime::UNIX_EPOCH + Duration::from_secs(reset);
+            return reset
+                .duration_since(now)
+                .unwrap_or_default()
+                .max(Duration::from_secs(1));
+        }
+    }
+
+    DEFAULT_RATE_LIMIT_WAIT
+}
+
+#[test]
+fn validate_rate_limit_wait() {
+    use reqwest::header::HeaderValue;
+
+    let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+
+    let mut headers = HeaderMap::new();
+    assert_eq!(DEFAULT_RATE_LIMIT_WAIT, rate_limit_wait(&headers, now));
+
+    headers.insert("x-ratelimit-remaining", HeaderValue::from_static("0"));
+    headers.insert("x-ratelimit-reset", HeaderValue::from_static("1030"));
+    assert_eq!(Duration::from_secs(30), rate_limit_wait(&headers, now));
+
+    headers.insert("retry-after", HeaderValue::from_static("5"));
+    assert_eq!(Duration::from_secs(5), rate_limit_wait(&headers, now));
+}
+
+#[test]
+fn validate_missing_pull_state() {
+    let repo = extract_github_info("git@ghe.example.com:platform/clu.git").unwrap();
+    let state = PullState::missing(&repo, 7);
+
+    assert_eq!(PullStatus::Missing, state.status);
+    assert_eq!(
+        "https://ghe.example.com/platform/clu/pull/7",
+        state.permalink
+    );
+    assert!(!state.status.is_closed());
+}
+
+pub struct PullState {
+    /// Node ID of the PR, for mutations.
+    pub id: String,
+    pub status: PullStatus,
+    pub permalink: String,
+    /// ISO-8601 timestamp of when the PR was opened.
+    pub created_at: String,
+    /// ISO-8601 timestamp of when the PR was closed or merged.
+    pub closed_at: Option<String>,
+    /// Branch the PR will be merged into.
+    pub base_ref: String,
+    /// Checks reported on the head commit of the PR.
+    pub checks: Vec<CheckResult>,
+    /// Commit the PR branch points at.
+    pub head_oid: String,
+    /// Node ID of the PR branch, `None` once the branch has been deleted.
+    pub head_ref_id: Option<String>,
+    /// The base branch has commits that aren't in the PR branch.
+    pub behind: bool,
+    /// Status contexts the branch protection of the base branch requires. Empty when the
+    /// branch isn't protected, or the token can't see its protection.
+    pub required_checks: Vec<String>,
+    pub reviews: PullReviews,
+}
+
+impl PullState {
+    fn new(gh_pull: PullRequestStatus, ignored_checks: &[Glob]) -> Self {
+        let required_checks: Vec<String> = gh_pull
+            .base_ref
+            .as_ref()
+            .and_then(|it| it.branch_protection_rule.as_ref())
+            .and_then(|it| it.required_status_check_contexts.clone())
+            .unwrap_or_default()
+            .into_iter()
+            .flatten()
+            .collect();
+        let mut checks = check_results(&gh_pull, ignored_checks);
+        for check in checks.iter_mut() {
+            check.required = required_checks.contains(&check.name);
+        }
+
+        PullState {
+            required_checks,
+            reviews: pull_reviews(&gh_pull),
+            status: pull_status(&gh_pull, &checks),
+            checks,
+            behind: gh_pull.merge_state_status
+                == get_pull_request_status_query::MergeStateStatus::BEHIND,
+            id: gh_pull.id,
+            head_oid: gh_pull.head_ref_oid,
+            head_ref_id: gh_pull.head_ref.map(|it| it.id),
+            permalink: gh_pull.permalink,
+            created_at: gh_pull.created_at,
+            closed_at: gh_pull.closed_at,
+            base_ref: gh_pull.base_ref_name,
+        }
+    }
+
+    /// Stands in for a PR that can't be found, because it or its repo was deleted, or the repo
+    /// was renamed or transferred.
+    fn missing(repo: &GitHubRepo, pr_number: i64) -> Self {
+        PullState {
+            id: String::new(),
+            status: PullStatus::Missing,
+            permalink: format!(
+                "https://{}/{}/{}/pull/{}",
+                repo.host, repo.owner, repo.repo, pr_number
+            ),
+            created_at: String::new(),
+            closed_at: None,
+            base_ref: String::new(),
+            checks: Vec::new(),
+            head_oid: String::new(),
+            head_ref_id: None,
+            behind: false,
+            required_checks: Vec::new(),
+            reviews: PullReviews::default(),
+        }
+    }
+
+    /// Checks that failed and are not ignored, these are why a PR is `ChecksFailed`.
+    pub fn failed_checks(&self) -> impl Iterator<Item = &CheckResult> {
+        self.checks
+            .iter()
+            .filter(|it| it.state == CheckState::Failed && !it.ignored)
+    }
 
-        if gh_pull.merged {
-            return Ok(PullState {
-                permalink: gh_pull.permalink,
-                status: PullStatus::Merged,
-            });
-        }
+    /// Required checks that haven't been reported on the head commit at all.
+    pub fn missing_required_checks(&self) -> Vec<&str> {
+        self.required_checks
+            .iter()
+            .filter(|name| !self.checks.iter().any(|it| &it.name == *name))
+            .map(|it| it.as_str())
+            .collect()
+    }
 
-        if gh_pull.mergeable == get_pull_request_status_query::MergeableState::MERGEABLE {
-            return Ok(PullState {
-                permalink: gh_pull.permalink,
-                status: PullStatus::Mergeable,
-            });
-        }
+    pub fn checks_summary(&self) -> ChecksSummary {
+        checks_summary(&self.checks, &self.required_checks)
+    }
+}
 
-        let gh_nodes = gh_pull.commits.nodes.unwrap_or_default();
-        let gh_commit = match &gh_nodes.first() {
-            Some(Some(commit)) => commit,
-            _ => {
-                return Ok(PullState {
-                    permalink: gh_pull.permalink,
-                    status: PullStatus::ChecksFailed,
-                })
-            }
-        };
+/// How the checks of a PR stand, telling failures that block merging from the ones that don't.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksSummary {
+    /// No checks were reported and the base branch requires none.
+    NoChecksConfigured,
+    /// A required check failed, or hasn't been reported.
+    FailingRequired,
+    /// Only checks that branch protection doesn't require failed.
+    FailingOptional,
+    Passing,
+}
 
-        match &gh_commit.commit.status_check_rollup {
-            Some(check) => match check.state {
-                get_pull_request_status_query::StatusState::SUCCESS
-                | get_pull_request_status_query::StatusState::PENDING => Ok(PullState {
-                    permalink: gh_pull.permalink,
-                    status: PullStatus::Mergeable,
-                }),
-                _ => Ok(PullState {
-                    permalink: gh_pull.permalink,
-                    status: PullStatus::ChecksFailed,
-                }),
-            },
-            None => Ok(PullState {
-                permalink: gh_pull.permalink,
-                status: PullStatus::ChecksFailed,
-            }),
+impl fmt::Display for ChecksSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChecksSummary::NoChecksConfigured => write!(f, "no checks configured"),
+            ChecksSummary::FailingRequired => write!(f, "failing required checks"),
+            ChecksSummary::FailingOptional => write!(f, "failing optional checks"),
+            ChecksSummary::Passing => write!(f, "passing"),
         }
     }
+}
 
-    pub async fn sync_pull_request(
-        &self,
-        repo: &GitHubRepo,
-        pr_description: PullRequestDescription<'_>,
-        pr_number: Option<i64>,
-    ) -> AnyResult<PullRequestOutput> {
-        let update_pr = match pr_number {
-            Some(num) => self.is_pr_open(repo, num).await?,
-            None => false,
-        };
+fn checks_summary(checks: &[CheckResult], required_checks: &[String]) -> ChecksSummary {
+    if checks.is_empty() && required_checks.is_empty() {
+        return ChecksSummary::NoChecksConfigured;
+    }
 
-        if update_pr {
-            self.update_pull_request(repo, pr_description, pr_number.unwrap())
-                .await
-        } else {
-            self.create_pull_request(repo, pr_description).await
-        }
+    let missing_required = required_checks
+        .iter()
+        .any(|name| !checks.iter().any(|it| &it.name == name));
+    let mut failed = checks
+        .iter()
+        .filter(|it| it.state == CheckState::Failed && !it.ignored);
+
+    if missing_required || failed.clone().any(|it| it.required) {
+        ChecksSummary::FailingRequired
+    } else if failed.next().is_some() {
+        ChecksSummary::FailingOptional
+    } else {
+        ChecksSummary::Passing
     }
+}
 
-    async fn is_pr_open(&self, repo: &GitHubRepo, pr_number: i64) -> AnyResult<bool> {
-        let gh_pull = fetch_pr_details(
-            &self.client,
-            repo.owner.clone(),
-            repo.repo.clone(),
-            pr_number,
+#[test]
+fn validate_checks_summary() {
+    let check = |name: &str, state: CheckState, required: bool| CheckResult {
+        name: name.to_owned(),
+        state,
+        url: None,
+        ignored: false,
+        required,
+    };
+    let required = vec!["build".to_owned()];
+
+    assert_eq!(ChecksSummary::NoChecksConfigured, checks_summary(&[], &[]));
+    assert_eq!(
+        ChecksSummary::FailingRequired,
+        checks_summary(&[], &required)
+    );
+    assert_eq!(
+        ChecksSummary::FailingRequired,
+        checks_summary(&[check("build", CheckState::Failed, true)], &required)
+    );
+    assert_eq!(
+        ChecksSummary::FailingOptional,
+        checks_summary(
+            &[
+                check("build", CheckState::Passed, true),
+                check("lint", CheckState::Failed, false)
+            ],
+            &required
         )
-        .await?;
-        let is_open = gh_pull.state == get_pull_request_status_query::PullRequestState::OPEN;
+    );
+    assert_eq!(
+        ChecksSummary::Passing,
+        checks_summary(&[check("build", CheckState::Pending, true)], &required)
+    );
+}
+
+#[derive(Debug, Clone)]
+pub struct CheckResult {
+    /// Name of the check run, or the context of a commit status.
+    pub name: String,
+    pub state: CheckState,
+    /// Where the details of the check can be found.
+    pub url: Option<String>,
+    /// Matched one of the `ignored-checks` of the migration.
+    pub ignored: bool,
+    /// Required by the branch protection of the base branch.
+    pub required: bool,
+}
 
-        Ok(is_open)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckState {
+    Passed,
+    Pending,
+    Failed,
+    Skipped,
+}
+
+impl fmt::Display for CheckState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckState::Passed => write!(f, "passed"),
+            CheckState::Pending => write!(f, "pending"),
+            CheckState::Failed => write!(f, "failed"),
+            CheckState::Skipped => write!(f, "skipped"),
+        }
     }
+}
 
-    async fn update_pull_request(
-        &self,
-        repo: &GitHubRepo,
-        pr_description: PullRequestDescription<'_>,
-        pr_number: i64,
-    ) -> AnyResult<PullRequestOutput> {
-        let gh_pull = fetch_pr_details(
-            &self.client,
-            repo.owner.clone(),
-            repo.repo.clone(),
-            pr_number,
-        )
-        .await?;
-        let pull_id = gh_pull.id;
+type PullRequestStatus =
+    get_pull_request_status_query::GetPullRequestStatusQueryRepositoryPullRequest;
+type HeadTarget =
+    get_pull_request_status_query::GetPullRequestStatusQueryRepositoryPullRequestHeadRefTarget;
+type HeadCommit =
+    get_pull_request_status_query::GetPullRequestStatusQueryRepositoryPullRequestHeadRefTargetOnCommit;
+type PullRequestRollup =
+    get_pull_request_status_query::GetPullRequestStatusQueryRepositoryPullRequestHeadRefTargetOnCommitStatusCheckRollup;
+
+/// The commit the PR branch points at, read from the branch itself rather than the PR's list
+/// of commits. `None` once the branch has been deleted, or when it moved past `headRefOid`
+/// while the PR was being looked up.
+fn head_commit(gh_pull: &PullRequestStatus) -> Option<&HeadCommit> {
+    match gh_pull.head_ref.as_ref().map(|it| &it.target) {
+        Some(Some(HeadTarget::Commit(commit))) if commit.oid == gh_pull.head_ref_oid => {
+            Some(commit)
+        }
+        _ => None,
+    }
+}
 
-        let variables = update_pull_request_mutation::Variables {
-            pull_request_id: pull_id,
-            body: pr_description.body.to_owned(),
-            title: pr_description.title.to_owned(),
-        };
+fn head_rollup(gh_pull: &PullRequestStatus) -> Option<&PullRequestRollup> {
+    head_commit(gh_pull).and_then(|it| it.status_check_rollup.as_ref())
+}
 
-        info!("Updating PR for {}", &repo);
+fn head_rollup_mut(gh_pull: &mut PullRequestStatus) -> Option<&mut PullRequestRollup> {
+    let head_ref_oid = &gh_pull.head_ref_oid;
+    match gh_pull.head_ref.as_mut().map(|it| &mut it.target) {
+        Some(Some(HeadTarget::Commit(commit))) if commit.oid == *head_ref_oid => {
+            commit.status_check_rollup.as_mut()
+        }
+        _ => None,
+    }
+}
 
-        let updated_pr = post_graphql::<UpdatePullRequestMutation>(&self.client, variables).await?;
+fn pull_reviews(gh_pull: &PullRequestStatus) -> PullReviews {
+    use get_pull_request_status_query::{
+        GetPullRequestStatusQueryRepositoryPullRequestReviewRequestsNodesRequestedReviewer as Reviewer,
+        PullRequestReviewDecision, PullRequestReviewState,
+    };
 
-        debug!("GitHub Response: {:?}", updated_pr);
+    let mut reviews = PullReviews {
+        decision: match gh_pull.review_decision {
+            Some(PullRequestReviewDecision::APPROVED) => Some(ReviewDecision::Approved),
+            Some(PullRequestReviewDecision::CHANGES_REQUESTED) => {
+                Some(ReviewDecision::ChangesRequested)
+            }
+            Some(PullRequestReviewDecision::REVIEW_REQUIRED) => {
+                Some(ReviewDecision::ReviewRequired)
+            }
+            _ => None,
+        },
+        ..PullReviews::default()
+    };
 
-        let response_data: update_pull_request_mutation::ResponseData = match updated_pr.data {
-            Some(data) => data,
-            None => bail!(GitHubError::GraphQlError {
-                error: format!("{:?}", updated_pr.errors)
-            }),
+    let requests = gh_pull
+        .review_requests
+        .as_ref()
+        .and_then(|it| it.nodes.as_ref());
+    for request in requests.into_iter().flatten().flatten() {
+        let reviewer = match &request.requested_reviewer {
+            Some(Reviewer::User(user)) => user.login.clone(),
+            Some(Reviewer::Team(team)) => team.combined_slug.clone(),
+            Some(Reviewer::Mannequin(mannequin)) => mannequin.login.clone(),
+            None => continue,
         };
+        reviews.requested.push(reviewer);
+    }
 
-        let pr = match response_data
-            .update_pull_request
-            .and_then(|it| it.pull_request)
-        {
-            Some(pr) => pr,
-            None => bail!(GitHubError::UnableToCreatePullRequest),
+    let latest = gh_pull
+        .latest_opinionated_reviews
+        .as_ref()
+        .and_then(|it| it.nodes.as_ref());
+    for review in latest.into_iter().flatten().flatten() {
+        let author = match &review.author {
+            Some(author) => author.login.clone(),
+            None => continue,
         };
+        match review.state {
+            PullRequestReviewState::APPROVED => reviews.approved_by.push(author),
+            PullRequestReviewState::CHANGES_REQUESTED => reviews.changes_requested_by.push(author),
+            _ => {}
+        }
+    }
 
-        info!("Updated PR {}", pr.permalink);
+    reviews
+}
 
-        Ok(PullRequestOutput {
-            number: pr.number,
-            permalink: pr.permalink,
-        })
-    }
+fn check_results(gh_pull: &PullRequestStatus, ignored_checks: &[Glob]) -> Vec<CheckResult> {
+    use get_pull_request_status_query::{
+        CheckConclusionState, CheckStatusState,
+        GetPullRequestStatusQueryRepositoryPullRequestHeadRefTargetOnCommitStatusCheckRollupContextsNodes as RollupContext,
+        StatusState,
+    };
 
-    async fn create_pull_request(
-        &self,
-        repo: &GitHubRepo,
-        pr_description: PullRequestDescription<'_>,
-    ) -> Result<PullRequestOutput, anyhow::Error> {
-        let repo_details =
-            fetch_repo_details(&self.client, repo.owner.clone(), repo.repo.clone()).await?;
+    let contexts = head_rollup(gh_pull)
+        .into_iter()
+        .flat_map(|rollup| rollup.contexts.nodes.iter().flatten().flatten());
+
+    contexts
+        .map(|context| {
+            let (name, state, url) = match context {
+                RollupContext::CheckRun(run) => {
+                    let state = match (&run.status, &run.conclusion) {
+                        (CheckStatusState::COMPLETED, Some(CheckConclusionState::SUCCESS)) => {
+                            CheckState::Passed
+                        }
+                        (CheckStatusState::COMPLETED, Some(CheckConclusionState::NEUTRAL))
+                        | (CheckStatusState::COMPLETED, Some(CheckConclusionState::SKIPPED))
+                        | (CheckStatusState::COMPLETED, Some(CheckConclusionState::STALE)) => {
+                            CheckState::Skipped
+                        }
+                        (CheckStatusState::COMPLETED, Some(_)) => CheckState::Failed,
+                        _ => CheckState::Pending,
+                    };
+                    (run.name.clone(), state, run.details_url.clone())
+                }
+                RollupContext::StatusContext(status) => {
+                    let state = match status.state {
+                        StatusState::SUCCESS => CheckState::Passed,
+                        StatusState::FAILURE | StatusState::ERROR => CheckState::Failed,
+                        _ => CheckState::Pending,
+                    };
+                    (status.context.clone(), state, status.target_url.clone())
+                }
+            };
+
+            CheckResult {
+                ignored: ignored_checks.iter().any(|glob| glob.is_match(&name)),
+                name,
+                state,
+                url,
+                required: false,
+            }
+        })
+        .collect()
+}
 
-        let variables = create_pull_request_migration::Variables {
-            repository_id: repo_details.id,
-            base_ref: repo_details.target_branch,
-            head_ref: format!("{}{}", repo_details.prefix, pr_description.branch),
-            body: pr_description.body.to_owned(),
-            title: pr_description.title.to_owned(),
-        };
+fn pull_status(gh_pull: &PullRequestStatus, checks: &[CheckResult]) -> PullStatus {
+    use get_pull_request_status_query::{MergeableState, PullRequestState, StatusState};
 
-        let created_pr =
-            post_graphql::<CreatePullRequestMigration>(&self.client, variables).await?;
+    if gh_pull.merged {
+        return PullStatus::Merged;
+    }
 
-        debug!("GitHub Response: {:?}", created_pr);
+    if gh_pull.state == PullRequestState::CLOSED {
+        return PullStatus::ClosedWithoutMerge;
+    }
 
-        let response_data: create_pull_request_migration::ResponseData = match created_pr.data {
-            Some(data) => data,
-            None => bail!(GitHubError::GraphQlError {
-                error: format!("{:?}", created_pr.errors)
-            }),
-        };
+    if gh_pull.mergeable == MergeableState::CONFLICTING {
+        return PullStatus::Conflicting;
+    }
 
-        let pr = match response_data
-            .create_pull_request
-            .and_then(|it| it.pull_request)
-        {
-            Some(pr) => pr,
-            None => bail!(GitHubError::UnableToCreatePullRequest),
-        };
+    if gh_pull.mergeable == MergeableState::MERGEABLE {
+        return PullStatus::Mergeable;
+    }
 
-        info!("Create PR at {}", pr.permalink);
+    let rollup = match (head_commit(gh_pull), head_rollup(gh_pull)) {
+        (_, Some(rollup)) => rollup,
+        // Nothing reported any checks on the head commit, so nothing failed
+        (Some(_), None) => return PullStatus::Mergeable,
+        (None, None) => return PullStatus::ChecksFailed,
+    };
 
-        Ok(PullRequestOutput {
-            number: pr.number,
-            permalink: pr.permalink,
-        })
+    match rollup.state {
+        StatusState::SUCCESS | StatusState::PENDING => PullStatus::Mergeable,
+        _ if only_ignored_checks_failed(checks) => PullStatus::Mergeable,
+        _ => PullStatus::ChecksFailed,
     }
 }
 
-pub async fn post_graphql<Q: GraphQLQuery>(
-    client: &reqwest::Client,
-    variables: Q::Variables,
-) -> Result<graphql_client::Response<Q::ResponseData>, reqwest::Error> {
-    let body = Q::build_query(variables);
-    debug!("GitHub Body: {:?}", serde_json::to_string(&body));
-    let reqwest_response = client
-        .post("https://api.github.com/graphql")
-        .json(&body)
-        .send()
-        .await?;
+#[test]
+fn validate_pull_status_of_head_commit() {
+    let pull = |target: serde_json::Value| -> PullRequestStatus {
+        serde_json::from_value(serde_json::json!({
+            "id": "PR_1", "state": "OPEN", "merged": false, "mergeable": "UNKNOWN",
+            "mergeStateStatus": "UNKNOWN", "permalink": "https://github.com/o/r/pull/1",
+            "createdAt": "2021-01-01T00:00:00Z", "closedAt": null, "baseRefName": "main",
+            "baseRef": null, "headRefOid": "abc", "headRef": { "id": "REF_1", "target": target },
+        }))
+        .unwrap()
+    };
+    let failing = serde_json::json!({ "state": "FAILURE", "contexts": {
+        "pageInfo": { "hasNextPage": false, "endCursor": null },
+        "nodes": [{ "__typename": "StatusContext", "context": "ci", "state": "FAILURE",
+            "targetUrl": null }],
+    }});
+
+    let no_checks = pull(serde_json::json!({
+        "__typename": "Commit", "oid": "abc", "statusCheckRollup": null
+    }));
+    assert_eq!(PullStatus::Mergeable, pull_status(&no_checks, &[]));
+
+    let failed = pull(serde_json::json!({
+        "__typename": "Commit", "oid": "abc", "statusCheckRollup": failing
+    }));
+    let checks = check_results(&failed, &[]);
+    assert_eq!(
+        vec!["ci"],
+        checks.iter().map(|it| &it.name).collect::<Vec<_>>()
+    );
+    assert_eq!(PullStatus::ChecksFailed, pull_status(&failed, &checks));
 
-    reqwest_response.json().await
+    // The branch moved after the PR was read, so its checks aren't for `headRefOid`
+    let moved = pull(serde_json::json!({
+        "__typename": "Commit", "oid": "def", "statusCheckRollup": failing
+    }));
+    assert!(check_results(&moved, &[]).is_empty());
 }
 
-pub struct PullState {
-    pub status: PullStatus,
-    pub permalink: String,
+/// When GitHub says the checks failed but none of the failures can be found, the PR is still
+/// treated as failing.
+fn only_ignored_checks_failed(checks: &[CheckResult]) -> bool {
+    let mut failed = checks.iter().filter(|it| it.state == CheckState::Failed);
+    failed.clone().next().is_some() && failed.all(|it| it.ignored)
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[test]
+fn validate_only_ignored_checks_failed() {
+    let check = |name: &str, state: CheckState, ignored: bool| CheckResult {
+        name: name.to_owned(),
+        state,
+        url: None,
+        ignored,
+        required: false,
+    };
+
+    assert!(only_ignored_checks_failed(&[
+        check("license-scan (optional)", CheckState::Failed, true),
+        check("build", CheckState::Passed, false),
+    ]));
+    assert!(!only_ignored_checks_failed(&[
+        check("license-scan (optional)", CheckState::Failed, true),
+        check("build", CheckState::Failed, false),
+    ]));
+    assert!(!only_ignored_checks_failed(&[check(
+        "build",
+        CheckState::Passed,
+        false
+    )]));
+    assert!(!only_ignored_checks_failed(&[]));
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
 pub enum PullStatus {
     ChecksFailed,
+    /// The PR branch conflicts with its base branch and has to be rebased.
+    Conflicting,
     NeedsApproval,
     Mergeable,
     Merged,
+    /// The PR was closed without being merged, so the migration never landed.
+    ClosedWithoutMerge,
+    /// The PR can't be found anymore, it or its repo was deleted, or the repo was moved.
+    Missing,
+}
+
+/// The same names the statuses are recorded with in the migration file.
+impl fmt::Display for PullStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PullStatus::ChecksFailed => write!(f, "checks-failed"),
+            PullStatus::Conflicting => write!(f, "conflicting"),
+            PullStatus::NeedsApproval => write!(f, "needs-approval"),
+            PullStatus::Mergeable => write!(f, "mergeable"),
+            PullStatus::Merged => write!(f, "merged"),
+            PullStatus::ClosedWithoutMerge => write!(f, "closed-without-merge"),
+            PullStatus::Missing => write!(f, "missing"),
+        }
+    }
+}
+
+impl PullStatus {
+    /// Merged and closed PR's won't change anymore.
+    pub fn is_closed(&self) -> bool {
+        matches!(self, PullStatus::Merged | PullStatus::ClosedWithoutMerge)
+    }
+}
+
+/// How GitHub sums up the reviews of a PR.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReviewDecision {
+    Approved,
+    ChangesRequested,
+    /// Branch protection requires a review that hasn't been given yet.
+    ReviewRequired,
+}
+
+/// Who was asked to review a PR and how the reviewers answered.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PullReviews {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decision: Option<ReviewDecision>,
+    /// Users, and teams as `org/team`, that were requested and haven't reviewed yet.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requested: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub approved_by: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changes_requested_by: Vec<String>,
+}
+
+impl PullReviews {
+    /// Reviewers were requested and none of them have answered.
+    pub fn is_pending(&self) -> bool {
+        !self.requested.is_empty()
+            && self.approved_by.is_empty()
+            && self.changes_requested_by.is_empty()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct GitHubRepo {
+    /// Host the repo is on, `github.com` or a GitHub Enterprise Server.
+    pub host: String,
     pub owner: String,
     pub repo: String,
     pub clone_url: String,
 }
 
 impl GitHubRepo {
-    fn new<G: Into<String>>(owner: G, repo: G, clone_url: G) -> Self {
+    fn new<G: Into<String>>(host: G, owner: G, repo: G, clone_url: G) -> Self {
         Self {
+            host: host.into(),
             owner: owner.into(),
             repo: repo.into(),
             clone_url: clone_url.into(),
@@ -326,23 +3541,213 @@ impl GitHubRepo {
     }
 }
 
+/// How GitHub repos are cloned and pushed to, regardless of the form of the target's URL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum CloneProtocol {
+    Ssh,
+    Https,
+}
+
+impl GitHubRepo {
+    /// Replaces the clone URL with the `protocol` form of it.
+    pub fn with_protocol(self, protocol: CloneProtocol) -> Self {
+        let clone_url = match protocol {
+            // The organization, project and repo of Azure DevOps aren't laid out like a path
+            CloneProtocol::Ssh if self.host == AZURE_DEVOPS_HOST => {
+                format!("git@ssh.dev.azure.com:v3/{}/{}", self.owner, self.repo)
+            }
+            CloneProtocol::Https if self.host == AZURE_DEVOPS_HOST => {
+                let (organization, project) = self.owner.split_once('/').unwrap_or_default();
+                format!(
+                    "https://dev.azure.com/{}/{}/_git/{}",
+                    organization, project, self.repo
+                )
+            }
+            // CodeCommit repos are only named, the region is in the host
+            CloneProtocol::Ssh if codecommit_region(&self.host).is_some() => {
+                format!("ssh://{}/v1/repos/{}", self.host, self.repo)
+            }
+            CloneProtocol::Https if codecommit_region(&self.host).is_some() => {
+                format!("https://{}/v1/repos/{}", self.host, self.repo)
+            }
+            CloneProtocol::Ssh => format!("git@{}:{}/{}.git", self.host, self.owner, self.repo),
+            CloneProtocol::Https => {
+                format!("https://{}/{}/{}.git", self.host, self.owner, self.repo)
+            }
+        };
+
+        Self { clone_url, ..self }
+    }
+}
+
 impl fmt::Display for GitHubRepo {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}/{}", self.owner, self.repo)
     }
 }
 
+pub(crate) fn user_agent(suffix: Option<&str>) -> String {
+    let user_agent = format!("clu/{}", env!("CARGO_PKG_VERSION"));
+    match suffix.map(str::trim) {
+        Some(suffix) if !suffix.is_empty() => format!("{} {}", user_agent, suffix),
+        _ => user_agent,
+    }
+}
+
+#[test]
+fn validate_user_agent() {
+    let version = env!("CARGO_PKG_VERSION");
+    assert_eq!(format!("clu/{}", version), user_agent(None));
+    assert_eq!(format!("clu/{}", version), user_agent(Some(" ")));
+    assert_eq!(
+        format!("clu/{} acme-platform", version),
+        user_agent(Some("acme-platform"))
+    );
+}
+
+/// Identifies this invocation of `clu` in the `X-Clu-Run-Id` header. It's `CLU_RUN_ID` when
+/// set, so CI can use its own build id, otherwise it's made from the start time and pid.
+pub fn run_id() -> &'static str {
+    static RUN_ID: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+    RUN_ID.get_or_init(|| match std::env::var(RUN_ID_ENV) {
+        Ok(run_id) if !run_id.is_empty() => run_id,
+        _ => {
+            let started = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|it| it.as_secs())
+                .unwrap_or_default();
+            format!("{}-{}", started, std::process::id())
+        }
+    })
+}
+
+/// Converts a GitHub `DateTime` (`2021-03-21T17:30:00Z`) into seconds since the epoch.
+pub fn parse_github_timestamp(timestamp: &str) -> Option<u64> {
+    let re = Regex::new(r"^(\d{4})-(\d{2})-(\d{2})T(\d{2}):(\d{2}):(\d{2})").unwrap();
+    let captures = re.captures(timestamp)?;
+    let part = |index: usize| -> i64 { captures[index].parse().unwrap() };
+
+    // Days from civil, see http://howardhinnant.github.io/date_algorithms.html
+    let (year, month, day) = (part(1), part(2), part(3));
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    let seconds = days * 86400 + part(4) * 3600 + part(5) * 60 + part(6);
+    if seconds < 0 {
+        None
+    } else {
+        Some(seconds as u64)
+    }
+}
+
+/// The ids of the check suites in a `check-suites` response that didn't pass.
+fn failed_check_suites(body: &serde_json::Value) -> Vec<u64> {
+    let suites = match body["check_suites"].as_array() {
+        Some(suites) => suites,
+        None => return Vec::new(),
+    };
+
+    suites
+        .iter()
+        .filter(|suite| {
+            matches!(
+                suite["conclusion"].as_str(),
+                Some("failure" | "timed_out" | "cancelled" | "startup_failure")
+            )
+        })
+        .filter_map(|suite| suite["id"].as_u64())
+        .collect()
+}
+
+#[test]
+fn validate_failed_check_suites() {
+    let body = serde_json::json!({
+        "total_count": 3,
+        "check_suites": [
+            { "id": 1, "status": "completed", "conclusion": "failure" },
+            { "id": 2, "status": "completed", "conclusion": "success" },
+            { "id": 3, "status": "completed", "conclusion": "timed_out" },
+            { "id": 4, "status": "in_progress", "conclusion": null },
+        ],
+    });
+
+    assert_eq!(vec![1, 3], failed_check_suites(&body));
+    assert!(failed_check_suites(&serde_json::json!({})).is_empty());
+}
+
+/// Formats seconds since the epoch the way GitHub formats a `DateTime`.
+pub fn format_github_timestamp(seconds: u64) -> String {
+    // Civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let days = (seconds / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    let time = seconds % 86400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+#[test]
+fn validate_parse_github_timestamp() {
+    assert_eq!(Some(0), parse_github_timestamp("1970-01-01T00:00:00Z"));
+    assert_eq!(
+        Some(1616347800),
+        parse_github_timestamp("2021-03-21T17:30:00Z")
+    );
+    assert_eq!(None, parse_github_timestamp("yesterday"));
+    assert_eq!("1970-01-01T00:00:00Z", format_github_timestamp(0));
+    assert_eq!("2021-03-21T17:30:00Z", format_github_timestamp(1616347800));
+    assert_eq!("2024-02-29T23:59:59Z", format_github_timestamp(1709251199));
+}
+
 pub fn extract_github_info(url: &str) -> Result<GitHubRepo, GitHubError> {
-    let re =
-        Regex::new("^(https://github.com/|git@github.com:)(?P<owner>.+?)/(?P<repo>.+?)(\\.git)?$")
-            .unwrap();
+    if let Some(repo) = crate::azure::extract_azure_devops_info(url) {
+        return Ok(repo);
+    }
+    if let Some(repo) = crate::codecommit::extract_codecommit_info(url) {
+        return Ok(repo);
+    }
+
+    let re = Regex::new(
+        "^(https://(?P<https_host>[^/@]+)/|git@(?P<ssh_host>[^:]+):|ssh://([^@/]+@)?(?P<ssh_url_host>[^/:]+)(:\\d+)?/)(?P<owner>.+?)/(?P<repo>.+?)(\\.git)?$",
+    )
+    .unwrap();
 
     match re.captures(url) {
         Some(matches) => {
+            let host = matches
+                .name("https_host")
+                .or_else(|| matches.name("ssh_host"))
+                .or_else(|| matches.name("ssh_url_host"))
+                .unwrap()
+                .as_str();
             let owner = matches.name("owner").unwrap().as_str();
             let repo = matches.name("repo").unwrap().as_str();
 
-            Ok(GitHubRepo::new(owner, repo, url))
+            Ok(GitHubRepo::new(host, owner, repo, url))
         }
         None => Err(GitHubError::UnableToDetermineRepo {
             path: url.to_owned(),
@@ -390,16 +3795,100 @@ fn validate_extract_github_info() {
     );
 }
 
+/// Paths on disk, rather than URLs or scp-like `host:path` remotes.
+pub fn is_local_path(url: &str) -> bool {
+    url == "."
+        || url.starts_with('/')
+        || url.starts_with("./")
+        || url.starts_with("../")
+        || url.starts_with("file://")
+}
+
+/// Reads any git remote, like a local path or a `git://` URL, for push-only targets. The host is
+/// empty when the remote has none, and the last two parts of the path are the owner and repo.
+pub fn extract_remote_info(url: &str) -> GitHubRepo {
+    if let Ok(repo) = extract_github_info(url) {
+        return repo;
+    }
+
+    let host = Regex::new("^[a-z+]+://([^@/]+@)?(?P<host>[^/:]+)")
+        .unwrap()
+        .captures(url)
+        .map(|it| it["host"].to_owned())
+        .unwrap_or_default();
+    let path = url.trim_end_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    let mut parts = path.rsplit(|it| it == '/' || it == ':');
+    let repo = parts.next().unwrap_or_default();
+    let owner = parts.next().unwrap_or_default();
+
+    GitHubRepo::new(host.as_str(), owner, repo, url)
+}
+
+#[test]
+fn validate_extract_remote_info() {
+    let repo = extract_remote_info("/srv/git/platform/clu.git");
+    assert_eq!("", repo.host);
+    assert_eq!("platform/clu", repo.to_string());
+    assert_eq!("/srv/git/platform/clu.git", repo.clone_url);
+
+    let repo = extract_remote_info("git://git.example.com/platform/clu");
+    assert_eq!("git.example.com", repo.host);
+    assert_eq!("platform/clu", repo.to_string());
+
+    assert!(is_local_path("../clu"));
+    assert!(is_local_path("file:///srv/git/clu.git"));
+    assert!(!is_local_path("git@github.com:ethankhall/clu.git"));
+    assert!(!is_local_path("https://github.com/ethankhall/clu"));
+}
+
+#[test]
+fn validate_extract_github_enterprise_info() {
+    let repo = extract_github_info("git@ghe.example.com:platform/clu.git").unwrap();
+    assert_eq!("ghe.example.com", repo.host);
+    assert_eq!("platform", repo.owner);
+    assert_eq!(
+        "https://ghe.example.com/platform/clu.git",
+        repo.with_protocol(CloneProtocol::Https).clone_url
+    );
+
+    let repo = extract_github_info("ssh://git@bitbucket.example.com:7999/plat/clu.git").unwrap();
+    assert_eq!("bitbucket.example.com", repo.host);
+    assert_eq!("plat", repo.owner);
+    assert_eq!("clu", repo.repo);
+}
+
+#[test]
+fn validate_with_protocol() {
+    let repo = extract_github_info("https://github.com/ethankhall/clu").unwrap();
+    assert_eq!(
+        "git@github.com:ethankhall/clu.git",
+        repo.clone().with_protocol(CloneProtocol::Ssh).clone_url
+    );
+    assert_eq!(
+        "https://github.com/ethankhall/clu.git",
+        repo.with_protocol(CloneProtocol::Https).clone_url
+    );
+
+    let repo = extract_github_info("https://dev.azure.com/acme/platform/_git/clu").unwrap();
+    assert_eq!(
+        "git@ssh.dev.azure.com:v3/acme/platform/clu",
+        repo.with_protocol(CloneProtocol::Ssh).clone_url
+    );
+}
+
 async fn fetch_pr_details(
-    client: &reqwest::Client,
+    client: &ApiClient,
     owner: String,
     repo: String,
     pr_number: i64,
-) -> AnyResult<get_pull_request_status_query::GetPullRequestStatusQueryRepositoryPullRequest> {
+    checks_after: Option<String>,
+) -> AnyResult<PullRequestStatus> {
     let variables = get_pull_request_status_query::Variables {
         owner: owner.clone(),
         repo: repo.clone(),
         number: pr_number,
+        checks_after,
     };
 
     info!("Getting repo details for {}/{}", &owner, &repo);
@@ -410,9 +3899,7 @@ async fn fetch_pr_details(
 
     let response_data: get_pull_request_status_query::ResponseData = match pr_status.data {
         Some(data) => data,
-        None => bail!(GitHubError::GraphQlError {
-            error: format!("{:?}", pr_status.errors)
-        }),
+        None => bail!(GitHubError::from_graphql_errors(&pr_status.errors)),
     };
 
     let gh_repository = match response_data.repository {
@@ -437,10 +3924,12 @@ struct GithubApiRepo {
     id: String,
     target_branch: String,
     prefix: String,
+    archived: bool,
+    can_push: bool,
 }
 
 async fn fetch_repo_details(
-    client: &reqwest::Client,
+    client: &ApiClient,
     owner: String,
     repo: String,
 ) -> AnyResult<GithubApiRepo> {
@@ -455,9 +3944,7 @@ async fn fetch_repo_details(
 
     let response_data: get_repository_query::ResponseData = match pr_status.data {
         Some(data) => data,
-        None => bail!(GitHubError::GraphQlError {
-            error: format!("{:?}", pr_status.errors)
-        }),
+        None => bail!(GitHubError::from_graphql_errors(&pr_status.errors)),
     };
 
     let gh_repository = match response_data.repository {
@@ -482,9 +3969,18 @@ async fn fetch_repo_details(
     let target_branch_name = format!("{}{}", default_branch.prefix, default_branch.name);
     debug!("Target Branch: {}", target_branch_name);
 
+    let can_push = matches!(
+        gh_repository.viewer_permission,
+        Some(get_repository_query::RepositoryPermission::ADMIN)
+            | Some(get_repository_query::RepositoryPermission::MAINTAIN)
+            | Some(get_repository_query::RepositoryPermission::WRITE)
+    );
+
     Ok(GithubApiRepo {
         id: repo_id,
         target_branch: target_branch_name,
         prefix: default_branch.prefix,
+        archived: gh_repository.is_archived,
+        can_push,
     })
 }
@@ -0,0 +1,369 @@
+use anyhow::{bail, Result as AnyResult};
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+
+use crate::github::{
+    CheckResult, CheckState, GitHubRepo, PullRequestDescription, PullRequestOutput, PullReviews,
+    PullState, PullStatus, RepoAccess,
+};
+use crate::glob::Glob;
+
+/// Environment variable with the token for GitLab hosts that don't name their own.
+pub const GITLAB_TOKEN_ENV: &str = "GITLAB_TOKEN";
+
+/// Host of GitLab's SaaS, recognized as GitLab without an entry in `hosts`.
+pub const GITLAB_HOST: &str = "gitlab.com";
+
+/// Name of the check a merge request's pipeline is reported as.
+const PIPELINE_CHECK: &str = "pipeline";
+
+/// Client for the REST API of a GitLab instance, gitlab.com or self-managed.
+#[derive(Debug)]
+pub struct GitLabClient {
+    http: Client,
+    /// Base of the API, like `https://gitlab.com/api/v4`.
+    api_url: String,
+}
+
+/// Developers and above can push branches.
+const DEVELOPER_ACCESS_LEVEL: i64 = 30;
+
+#[derive(Debug, Deserialize)]
+struct Project {
+    default_branch: Option<String>,
+    #[serde(default)]
+    archived: bool,
+    permissions: Option<ProjectPermissions>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProjectPermissions {
+    project_access: Option<Access>,
+    group_access: Option<Access>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Access {
+    access_level: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct User {
+    id: i64,
+    username: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Milestone {
+    id: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Pipeline {
+    status: String,
+    web_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MergeRequest {
+    id: i64,
+    iid: i64,
+    web_url: String,
+    state: String,
+    created_at: String,
+    closed_at: Option<String>,
+    merged_at: Option<String>,
+    source_branch: String,
+    target_branch: String,
+    sha: Option<String>,
+    #[serde(default)]
+    has_conflicts: bool,
+    detailed_merge_status: Option<String>,
+    head_pipeline: Option<Pipeline>,
+    #[serde(default)]
+    reviewers: Vec<User>,
+}
+
+impl GitLabClient {
+    pub fn new(token: &str, api_url: &str) -> AnyResult<Self> {
+        let http = Client::builder()
+            .user_agent(crate::github::user_agent(
+                std::env::var("CLU_USER_AGENT_SUFFIX").ok().as_deref(),
+            ))
+            .default_headers(
+                vec![(
+                    reqwest::header::HeaderName::from_static("private-token"),
+                    reqwest::header::HeaderValue::from_str(token)?,
+                )]
+                .into_iter()
+                .collect(),
+            )
+            .build()?;
+
+        Ok(Self {
+            http,
+            api_url: api_url.trim_end_matches('/').to_owned(),
+        })
+    }
+
+    /// Opens a merge request for the branch, or updates the one that's already open.
+    pub async fn sync_merge_request(
+        &self,
+        repo: &GitHubRepo,
+        description: PullRequestDescription<'_>,
+        existing: Option<i64>,
+    ) -> AnyResult<PullRequestOutput> {
+        let target_branch = match description.base {
+            Some(base) => base.to_owned(),
+            None => self.default_branch(repo).await?,
+        };
+
+        let mut body = serde_json::json!({
+            "title": description.title,
+            "description": description.body,
+            "target_branch": target_branch,
+            "labels": description.labels.join(","),
+        });
+        if !description.assignees.is_empty() {
+            body["assignee_ids"] = serde_json::json!(self.user_ids(description.assignees).await?);
+        }
+        if let Some(title) = description.milestone {
+            body["milestone_id"] = serde_json::json!(self.milestone_id(repo, title).await?);
+        }
+
+        let existing = match existing {
+            Some(iid) => Some(iid),
+            None => self.open_merge_request(repo, description.branch).await?,
+        };
+
+        let merge_request: MergeRequest = match existing {
+            Some(iid) => {
+                info!("Updating merge request !{} of {}", iid, repo);
+                let request = self
+                    .http
+                    .put(self.project_url(repo, &format!("/merge_requests/{}", iid)))
+                    .json(&body);
+                self.send(request).await?
+            }
+            None => {
+                info!("Opening a merge request on {}", repo);
+                body["source_branch"] = serde_json::json!(description.branch);
+                let request = self
+                    .http
+                    .post(self.project_url(repo, "/merge_requests"))
+                    .json(&body);
+                self.send(request).await?
+            }
+        };
+
+        Ok(PullRequestOutput {
+            id: merge_request.id.to_string(),
+            number: merge_request.iid,
+            permalink: merge_request.web_url,
+            created_at: merge_request.created_at,
+            head_ref: merge_request.source_branch,
+        })
+    }
+
+    pub async fn fetch_merge_request_state(
+        &self,
+        repo: &GitHubRepo,
+        iid: i64,
+        ignored_checks: &[Glob],
+    ) -> AnyResult<PullState> {
+        let request = self
+            .http
+            .get(self.project_url(repo, &format!("/merge_requests/{}", iid)));
+        let merge_request: MergeRequest = self.send(request).await?;
+
+        Ok(merge_request_state(merge_request, ignored_checks))
+    }
+
+    pub async fn add_note(&self, repo: &GitHubRepo, iid: i64, body: &str) -> AnyResult<()> {
+        let request = self
+            .http
+            .post(self.project_url(repo, &format!("/merge_requests/{}/notes", iid)))
+            .json(&serde_json::json!({ "body": body }));
+        let _: serde_json::Value = self.send(request).await?;
+
+        Ok(())
+    }
+
+    pub async fn fetch_project_access(&self, repo: &GitHubRepo) -> AnyResult<RepoAccess> {
+        let project: Project = self.send(self.http.get(self.project_url(repo, ""))).await?;
+        let access_level = project
+            .permissions
+            .iter()
+            .flat_map(|it| it.project_access.iter().chain(it.group_access.iter()))
+            .map(|it| it.access_level)
+            .max();
+
+        Ok(RepoAccess {
+            archived: project.archived,
+            // Tokens that aren't members, like admin tokens, aren't given permissions
+            can_push: access_level.map_or(true, |it| it >= DEVELOPER_ACCESS_LEVEL),
+        })
+    }
+
+    async fn default_branch(&self, repo: &GitHubRepo) -> AnyResult<String> {
+        let project: Project = self.send(self.http.get(self.project_url(repo, ""))).await?;
+        match project.default_branch {
+            Some(branch) => Ok(branch),
+            None => bail!("{} has no default branch", repo),
+        }
+    }
+
+    async fn open_merge_request(&self, repo: &GitHubRepo, branch: &str) -> AnyResult<Option<i64>> {
+        let request = self
+            .http
+            .get(self.project_url(repo, "/merge_requests"))
+            .query(&[("source_branch", branch), ("state", "opened")]);
+        let open: Vec<MergeRequest> = self.send(request).await?;
+
+        Ok(open.first().map(|it| it.iid))
+    }
+
+    async fn user_ids(&self, usernames: &[String]) -> AnyResult<Vec<i64>> {
+        let mut ids = Vec::new();
+        for username in usernames {
+            let request = self
+                .http
+                .get(format!("{}/users", self.api_url))
+                .query(&[("username", username)]);
+            let users: Vec<User> = self.send(request).await?;
+            match users.iter().find(|it| &it.username == username) {
+                Some(user) => ids.push(user.id),
+                None => warn!("GitLab user {} doesn't exist, not assigning them", username),
+            }
+        }
+
+        Ok(ids)
+    }
+
+    async fn milestone_id(&self, repo: &GitHubRepo, title: &str) -> AnyResult<i64> {
+        let request = self
+            .http
+            .get(self.project_url(repo, "/milestones"))
+            .query(&[("title", title), ("state", "active")]);
+        let milestones: Vec<Milestone> = self.send(request).await?;
+        match milestones.first() {
+            Some(milestone) => Ok(milestone.id),
+            None => bail!("{} has no active milestone named {}", repo, title),
+        }
+    }
+
+    /// Projects are addressed by their URL encoded path, `group/subgroup/name`.
+    fn project_url(&self, repo: &GitHubRepo, path: &str) -> String {
+        format!(
+            "{}/projects/{}{}",
+            self.api_url,
+            repo.to_string().replace('/', "%2F"),
+            path
+        )
+    }
+
+    async fn send<T: DeserializeOwned>(&self, request: reqwest::RequestBuilder) -> AnyResult<T> {
+        let response = request.send().await?;
+        let status = response.status();
+        let body: serde_json::Value = response.json().await?;
+        debug!("GitLab Response: {:?}", body);
+
+        if !status.is_success() {
+            bail!("GitLab responded {}: {}", status, body);
+        }
+
+        Ok(serde_json::from_value(body)?)
+    }
+}
+
+fn merge_request_state(merge_request: MergeRequest, ignored_checks: &[Glob]) -> PullState {
+    let checks: Vec<CheckResult> = merge_request
+        .head_pipeline
+        .iter()
+        .map(|pipeline| CheckResult {
+            name: PIPELINE_CHECK.to_owned(),
+            state: match pipeline.status.as_str() {
+                "success" => CheckState::Passed,
+                "failed" | "canceled" => CheckState::Failed,
+                "skipped" | "manual" => CheckState::Skipped,
+                _ => CheckState::Pending,
+            },
+            url: pipeline.web_url.clone(),
+            ignored: ignored_checks.iter().any(|it| it.is_match(PIPELINE_CHECK)),
+            required: false,
+        })
+        .collect();
+
+    let failed = checks
+        .iter()
+        .any(|it| it.state == CheckState::Failed && !it.ignored);
+    let status = match merge_request.state.as_str() {
+        "merged" => PullStatus::Merged,
+        "closed" | "locked" => PullStatus::ClosedWithoutMerge,
+        _ if merge_request.has_conflicts => PullStatus::Conflicting,
+        _ if failed => PullStatus::ChecksFailed,
+        _ if merge_request.detailed_merge_status.as_deref() == Some("not_approved") => {
+            PullStatus::NeedsApproval
+        }
+        _ => PullStatus::Mergeable,
+    };
+
+    PullState {
+        id: merge_request.id.to_string(),
+        status,
+        permalink: merge_request.web_url,
+        created_at: merge_request.created_at,
+        closed_at: merge_request.merged_at.or(merge_request.closed_at),
+        base_ref: merge_request.target_branch,
+        checks,
+        head_oid: merge_request.sha.unwrap_or_default(),
+        head_ref_id: None,
+        behind: false,
+        required_checks: Vec::new(),
+        reviews: PullReviews {
+            requested: merge_request
+                .reviewers
+                .into_iter()
+                .map(|it| it.username)
+                .collect(),
+            ..PullReviews::default()
+        },
+    }
+}
+
+#[test]
+fn validate_merge_request_state() {
+    let merge_request = |state: &str, pipeline: &str| -> MergeRequest {
+        serde_json::from_value(serde_json::json!({
+            "id": 100, "iid": 7, "web_url": "https://gitlab.com/platform/clu/-/merge_requests/7",
+            "state": state, "created_at": "2021-01-01T00:00:00Z", "closed_at": null,
+            "merged_at": null, "source_branch": "migration", "target_branch": "main",
+            "sha": "abc", "has_conflicts": false, "detailed_merge_status": "mergeable",
+            "head_pipeline": { "status": pipeline, "web_url": null },
+        }))
+        .unwrap()
+    };
+
+    assert_eq!(
+        PullStatus::Mergeable,
+        merge_request_state(merge_request("opened", "success"), &[]).status
+    );
+    assert_eq!(
+        PullStatus::ChecksFailed,
+        merge_request_state(merge_request("opened", "failed"), &[]).status
+    );
+    assert_eq!(
+        PullStatus::Mergeable,
+        merge_request_state(merge_request("opened", "failed"), &[Glob::new("pipeline")]).status
+    );
+    assert_eq!(
+        PullStatus::Merged,
+        merge_request_state(merge_request("merged", "success"), &[]).status
+    );
+    assert_eq!(
+        PullStatus::ClosedWithoutMerge,
+        merge_request_state(merge_request("closed", "failed"), &[]).status
+    );
+}
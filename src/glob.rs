@@ -0,0 +1,61 @@
+use regex::Regex;
+use std::fmt;
+
+/// Shell style pattern, `*` matches any run of characters and `?` matches a single one.
+#[derive(Debug, Clone)]
+pub struct Glob {
+    pattern: String,
+    regex: Regex,
+}
+
+impl Glob {
+    pub fn new<S: Into<String>>(pattern: S) -> Self {
+        let pattern = pattern.into();
+        let mut regex = String::from("^");
+        for c in pattern.chars() {
+            match c {
+                '*' => regex.push_str(".*"),
+                '?' => regex.push('.'),
+                c => regex.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        regex.push('$');
+
+        Self {
+            pattern,
+            regex: Regex::new(&regex).unwrap(),
+        }
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.regex.is_match(text)
+    }
+
+    /// The part of the pattern before the first wildcard, useful for narrowing API queries.
+    pub fn literal_prefix(&self) -> &str {
+        match self.pattern.find(['*', '?']) {
+            Some(index) => &self.pattern[..index],
+            None => &self.pattern,
+        }
+    }
+}
+
+impl fmt::Display for Glob {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.pattern)
+    }
+}
+
+#[test]
+fn validate_glob() {
+    let glob = Glob::new("clu/2021-*");
+    assert!(glob.is_match("clu/2021-03-21-upgrade"));
+    assert!(!glob.is_match("clu/2022-03-21-upgrade"));
+    assert!(!glob.is_match("other/clu/2021-03"));
+    assert_eq!("clu/2021-", glob.literal_prefix());
+
+    let glob = Glob::new("payments-?.git");
+    assert!(glob.is_match("payments-1.git"));
+    assert!(!glob.is_match("payments-12.git"));
+    assert!(!glob.is_match("payments-1xgit"));
+}
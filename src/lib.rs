@@ -1,6 +1,26 @@
+pub mod azure;
+pub mod bitbucket;
+pub mod codecommit;
 pub mod commands;
+pub mod dashboard;
+pub mod events;
+pub mod filter;
+pub mod forge;
+pub mod gerrit;
 pub mod github;
+pub mod gitlab;
+pub mod glob;
+pub mod metadata;
 pub mod migration;
 pub mod models;
+pub mod notify;
+pub mod output;
+pub mod prompt;
+pub mod queue;
+pub mod run;
+pub mod state;
 pub mod steps;
+pub mod template;
+pub mod tracking;
+pub mod watch;
 pub mod workspace;
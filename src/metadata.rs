@@ -0,0 +1,104 @@
+use async_process::Command;
+use std::collections::BTreeMap;
+use thiserror::Error;
+use tracing::{debug, info};
+
+use crate::github::GitHubRepo;
+
+#[derive(Error, Debug)]
+pub enum MetadataError {
+    #[error("Unable to run metadata command `{command}`: {source}")]
+    Io {
+        command: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("Metadata command `{command}` exited with {status}: {stderr}")]
+    Failed {
+        command: String,
+        status: std::process::ExitStatus,
+        stderr: String,
+    },
+    #[error("Metadata command `{command}` didn't print a JSON object: {source}")]
+    InvalidJson {
+        command: String,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+/// Runs the `metadata-command` of the migration for a target. The command is given the
+/// `owner/repo` of the target, and `CLU_TARGET` and `CLU_REPO` are set, so it can look the
+/// target up in an inventory like a service catalog.
+pub async fn fetch_metadata(
+    command: &str,
+    target_name: &str,
+    repo: &GitHubRepo,
+) -> Result<BTreeMap<String, String>, MetadataError> {
+    let full_command = format!("{} '{}'", command, repo);
+    info!("Fetching metadata of {}", target_name);
+    debug!("Running {}", full_command);
+
+    let output = Command::new("/bin/sh")
+        .arg("-c")
+        .arg(&full_command)
+        .env("CLU_TARGET", target_name)
+        .env("CLU_REPO", repo.to_string())
+        .output()
+        .await
+        .map_err(|source| MetadataError::Io {
+            command: full_command.clone(),
+            source,
+        })?;
+
+    if !output.status.success() {
+        return Err(MetadataError::Failed {
+            command: full_command,
+            status: output.status,
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        });
+    }
+
+    parse_metadata(&output.stdout).map_err(|source| MetadataError::InvalidJson {
+        command: full_command,
+        source,
+    })
+}
+
+/// Flattens the JSON object printed by the metadata command. Strings are used as is, other
+/// values as their JSON, and `null` is left out.
+fn parse_metadata(stdout: &[u8]) -> Result<BTreeMap<String, String>, serde_json::Error> {
+    let values: BTreeMap<String, serde_json::Value> = serde_json::from_slice(stdout)?;
+
+    Ok(values
+        .into_iter()
+        .filter_map(|(key, value)| match value {
+            serde_json::Value::Null => None,
+            serde_json::Value::String(value) => Some((key, value)),
+            value => Some((key, value.to_string())),
+        })
+        .collect())
+}
+
+/// The template variables for the metadata of a target, `team` is `{{metadata.team}}`.
+pub fn metadata_variables(metadata: &BTreeMap<String, String>) -> Vec<(String, String)> {
+    metadata
+        .iter()
+        .map(|(key, value)| (format!("metadata.{}", key), value.clone()))
+        .collect()
+}
+
+#[test]
+fn validate_parse_metadata() {
+    let metadata =
+        parse_metadata(br#"{"team": "platform", "tier": 1, "on-call": null, "pci": false}"#)
+            .unwrap();
+
+    let mut expected = BTreeMap::new();
+    expected.insert("pci".to_owned(), "false".to_owned());
+    expected.insert("team".to_owned(), "platform".to_owned());
+    expected.insert("tier".to_owned(), "1".to_owned());
+    assert_eq!(expected, metadata);
+
+    assert!(parse_metadata(b"[\"platform\"]").is_err());
+}
@@ -1,14 +1,22 @@
 use std::collections::BTreeMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use thiserror::Error;
-use tracing::{error, info, instrument};
+use tracing::{debug, error, info, instrument, warn};
 
-use crate::github::{GitHubRepo, GithubApiClient};
-use crate::models::{CreatedPullRequest, MigrationDefinition};
+use crate::events::{EventLog, StepStatus};
+use crate::forge::ForgeClient;
+use crate::github::{GitHubHosts, GitHubRepo, GithubApiClient};
+use crate::models::{
+    CreatedPullRequest, MigrationDefinition, PatchSeries, PushedBranch, RunOutcome, RunResult,
+    StepMetrics, TargetDescription, TargetMetrics,
+};
+use crate::prompt::{PushDecision, PushPrompt};
 use crate::steps::MigrationStep;
 use crate::steps::{
-    CloneRepoStep, MigrationScriptStep, MigrationStepResult, PreFlightCheckStep, PushRepoStep,
-    UpdateGithubStep,
+    BundleRepoStep, CloneRepoStep, DiffReviewStep, FormatPatchStep, MigrationScriptStep,
+    MigrationStepResult, PreFlightCheckStep, PushForReviewStep, PushRepoStep, RepoAccessCheckStep,
+    RepoCheck, SendPatchesStep, UpdateGithubStep,
 };
 use crate::workspace::Workspace;
 
@@ -17,18 +25,62 @@ pub struct ExecutionOptions<'a> {
     pub skip_pull_request: bool,
     pub skip_push: bool,
     pub dry_run: bool,
+    /// When set, the prepared branch is written as a git bundle into this
+    /// directory instead of being pushed.
+    pub bundle_dir: Option<PathBuf>,
+    /// When set, the prepared branch is written as a `git format-patch` series into a
+    /// directory per target under this one instead of being pushed.
+    pub patch_dir: Option<PathBuf>,
+    /// Mail the series to the `email` section of the definition once it's written.
+    pub send_patches: bool,
     pub env: BTreeMap<String, String>,
     pub work_dir: PathBuf,
     pub github_client: &'a GithubApiClient,
+    /// The forge the target's PR is opened on. Unset when the target has `forge = "none"`, its
+    /// branch is pushed without opening a PR.
+    pub forge: Option<&'a dyn ForgeClient>,
+    /// Where the branch is pushed, instead of the repo it was cloned from.
+    pub push_remote: Option<String>,
+    /// The target is a directory on disk, its branch is only pushed when there's a
+    /// `push_remote`.
+    pub local: bool,
+    /// Asks whether to push the target once its steps are done, for `--interactive`.
+    pub push_prompt: Option<&'a PushPrompt>,
+    /// Where an event is appended as each step finishes, for `--events`.
+    pub events: Option<&'a EventLog>,
+}
+
+/// The options every target of a run shares, the rest of its `ExecutionOptions` come from the
+/// target.
+#[derive(Debug, Clone, Default)]
+pub struct RunSettings<'a> {
+    pub skip_pull_request: bool,
+    pub skip_push: bool,
+    pub dry_run: bool,
+    pub bundle_dir: Option<PathBuf>,
+    pub patch_dir: Option<PathBuf>,
+    pub send_patches: bool,
+    pub work_dir: PathBuf,
+    pub push_prompt: Option<&'a PushPrompt>,
+    pub events: Option<&'a EventLog>,
 }
 
 impl<'a> ExecutionOptions<'a> {
+    /// The target is on GitHub, rather than one of the other forges.
+    pub fn is_on_github(&self) -> bool {
+        self.forge.and_then(|it| it.as_github()).is_some()
+    }
+
+    pub fn is_push_only(&self) -> bool {
+        self.forge.is_none()
+    }
+
     fn is_push_enabled(&self) -> bool {
         !self.dry_run && !self.skip_push
     }
 
     fn is_pr_enabled(&self) -> bool {
-        !self.dry_run && !self.skip_pull_request
+        !self.dry_run && !self.skip_pull_request && !self.is_push_only()
     }
 }
 
@@ -59,6 +111,12 @@ pub enum MigrationError {
         step_name: String,
         files: Vec<String>,
     },
+    #[error("Reviewer `{reviewer}` rejected the changes.")]
+    ChangesRejected { reviewer: String },
+    #[error("{repo} uses Git LFS, but `git lfs` is not installed.")]
+    LfsNotSupported { repo: String },
+    #[error("{message}")]
+    MetadataUnavailable { message: String },
     #[error(transparent)]
     IoError(#[from] std::io::Error),
     #[error(transparent)]
@@ -69,10 +127,178 @@ pub enum MigrationError {
     CommandError(#[from] crate::workspace::CommandError),
 }
 
+impl MigrationError {
+    /// A short name for the kind of error, the same for every target that hit it.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            MigrationError::UnableToCheckoutRepo { .. } => "checkout",
+            MigrationError::UnableToCreatePullRequest { .. } => "pull-request",
+            MigrationError::InvalidGitRepo { .. } => "invalid-repo",
+            MigrationError::MigrationNotRequired => "not-required",
+            MigrationError::MigrationStepErrored { .. } => "step-exited",
+            MigrationError::WorkingDirNotClean { .. } => "untracked-files",
+            MigrationError::ChangesRejected { .. } => "rejected",
+            MigrationError::LfsNotSupported { .. } => "lfs-missing",
+            MigrationError::MetadataUnavailable { .. } => "metadata",
+            MigrationError::IoError(_) => "io",
+            MigrationError::AnyHowError(_) => "other",
+            MigrationError::GitError(_) => "git",
+            MigrationError::CommandError(_) => "command",
+        }
+    }
+}
+
+/// The run went through, but some of its targets failed.
+#[derive(Error, Debug)]
+#[error("{failed} of the {total} targets that were run failed")]
+pub struct TargetsFailed {
+    pub failed: usize,
+    pub total: usize,
+    /// How many of the failed targets ran out of retries while GitHub was rate limiting or
+    /// failing.
+    pub gave_up: usize,
+}
+
+impl MigrationError {
+    /// When the error was caused by GitHub rate limiting or a transient GitHub failure, how
+    /// long to wait before retrying.
+    pub fn retry_wait(&self) -> Option<Duration> {
+        let source = match self {
+            MigrationError::UnableToCreatePullRequest { source } => source,
+            MigrationError::AnyHowError(source) => source,
+            _ => return None,
+        };
+
+        source
+            .downcast_ref::<crate::github::GitHubError>()
+            .and_then(|it| it.retry_wait())
+    }
+}
+
 #[derive(Debug)]
 pub enum MigrationStatus {
     EmptyResponse(MigrationStepResult<()>),
     PullRequest(MigrationStepResult<CreatedPullRequest>),
+    /// The branch of a push-only target was pushed.
+    Pushed(MigrationStepResult<PushedBranch>),
+    /// The branch was written, and maybe mailed, as a patch series.
+    Patches(MigrationStepResult<PatchSeries>),
+}
+
+impl MigrationStatus {
+    pub fn run_result(&self) -> RunResult {
+        let (step, failed) = match self {
+            MigrationStatus::EmptyResponse(result) => (&result.name, result.result.is_err()),
+            MigrationStatus::PullRequest(result) => (&result.name, result.result.is_err()),
+            MigrationStatus::Pushed(result) => (&result.name, result.result.is_err()),
+            MigrationStatus::Patches(result) => (&result.name, result.result.is_err()),
+        };
+
+        let outcome = match self {
+            _ if failed => RunOutcome::Failed,
+            MigrationStatus::PullRequest(_)
+            | MigrationStatus::Pushed(_)
+            | MigrationStatus::Patches(_) => RunOutcome::Succeeded,
+            MigrationStatus::EmptyResponse(_) => RunOutcome::Stopped,
+        };
+
+        RunResult {
+            outcome,
+            step: step.clone(),
+            error: self.error().map(|e| e.to_string()),
+            error_kind: self.error().map(|e| e.kind().to_owned()),
+        }
+    }
+
+    pub fn error(&self) -> Option<&MigrationError> {
+        match self {
+            MigrationStatus::EmptyResponse(result) => result.result.as_ref().err(),
+            MigrationStatus::PullRequest(result) => result.result.as_ref().err(),
+            MigrationStatus::Pushed(result) => result.result.as_ref().err(),
+            MigrationStatus::Patches(result) => result.result.as_ref().err(),
+        }
+    }
+
+    pub fn is_failure(&self) -> bool {
+        match self {
+            MigrationStatus::EmptyResponse(result) => result.result.is_err(),
+            MigrationStatus::PullRequest(result) => result.result.is_err(),
+            MigrationStatus::Pushed(result) => result.result.is_err(),
+            MigrationStatus::Patches(result) => result.result.is_err(),
+        }
+    }
+
+    pub fn retry_wait(&self) -> Option<Duration> {
+        let error = match self {
+            MigrationStatus::EmptyResponse(result) => result.result.as_ref().err(),
+            MigrationStatus::PullRequest(result) => result.result.as_ref().err(),
+            MigrationStatus::Pushed(result) => result.result.as_ref().err(),
+            MigrationStatus::Patches(result) => result.result.as_ref().err(),
+        };
+
+        error.and_then(|it| it.retry_wait())
+    }
+}
+
+/// Work that was rejected because of rate limiting or transient errors, ordered by when it can be resumed.
+#[derive(Debug)]
+pub struct RetryQueue<T> {
+    entries: Vec<RetryEntry<T>>,
+}
+
+#[derive(Debug)]
+pub struct RetryEntry<T> {
+    pub resume_at: Instant,
+    pub attempt: u32,
+    pub item: T,
+}
+
+impl<T> RetryQueue<T> {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn park(&mut self, item: T, wait: Duration, attempt: u32) {
+        self.entries.push(RetryEntry {
+            resume_at: Instant::now() + wait,
+            attempt,
+            item,
+        });
+    }
+
+    /// Removes the entry that can be resumed the soonest.
+    pub fn next_ready(&mut self) -> Option<RetryEntry<T>> {
+        let index = self
+            .entries
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, entry)| entry.resume_at)
+            .map(|(index, _)| index)?;
+
+        Some(self.entries.remove(index))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<T> Default for RetryQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
+pub struct MigrationOutcome {
+    pub status: MigrationStatus,
+    pub metrics: TargetMetrics,
 }
 
 #[derive(Debug)]
@@ -83,6 +309,15 @@ pub struct MigrationTask<'a> {
     pub exec_opts: ExecutionOptions<'a>,
     pub pull_request: Option<CreatedPullRequest>,
     pub skip: bool,
+    /// Output of the `metadata-command` for the target.
+    pub metadata: BTreeMap<String, String>,
+    /// Why the `metadata-command` failed for the target, which fails the target without
+    /// running it.
+    pub metadata_error: Option<String>,
+    /// Branch the PR is opened against, the default branch when `None`.
+    pub base_ref: Option<String>,
+    /// Commit the migration branch is created from.
+    pub base_sha: Option<String>,
 }
 
 impl<'a> MigrationTask<'a> {
@@ -101,14 +336,114 @@ impl<'a> MigrationTask<'a> {
             exec_opts,
             pull_request,
             skip,
+            metadata: BTreeMap::new(),
+            metadata_error: None,
+            base_ref: None,
+            base_sha: None,
         }
     }
 
+    /// Builds the task of a target: resolves its repo and forge, and runs the
+    /// `metadata-command`. A metadata command that fails only fails this target once it's run.
+    pub async fn prepare(
+        definition: &MigrationDefinition,
+        github_hosts: &'a GitHubHosts,
+        settings: &RunSettings<'a>,
+        name: &str,
+        target: &TargetDescription,
+    ) -> anyhow::Result<MigrationTask<'a>> {
+        debug!("Processing {:?}", name);
+        let github_repo = match target.github_repo() {
+            Ok(repo) => repo,
+            Err(e) => anyhow::bail!(MigrationError::InvalidGitRepo { source: e }),
+        };
+        let forge = github_hosts.forge_for_target(target, &github_repo)?;
+        let github_repo = match definition.clone_protocol {
+            // Remotes without a host are paths, which don't have protocols
+            Some(protocol) if !github_repo.host.is_empty() => github_repo.with_protocol(protocol),
+            _ => github_repo,
+        };
+
+        let exec_opts = ExecutionOptions {
+            skip_pull_request: settings.skip_pull_request,
+            skip_push: settings.skip_push,
+            dry_run: settings.dry_run,
+            bundle_dir: settings.bundle_dir.clone(),
+            patch_dir: settings.patch_dir.clone(),
+            send_patches: settings.send_patches,
+            env: target.env.clone().unwrap_or_default(),
+            work_dir: settings.work_dir.clone(),
+            // Push-only remotes can be on hosts that aren't in `hosts`, or have no host at all
+            github_client: match forge {
+                Some(_) => github_hosts.github_or_default(&github_repo)?,
+                None => github_hosts.github(),
+            },
+            forge,
+            push_remote: target.push_remote.clone(),
+            local: target.is_local(),
+            push_prompt: settings.push_prompt,
+            events: settings.events,
+        };
+
+        let (metadata, metadata_error) = match &definition.metadata_command {
+            Some(command) if !target.skip => {
+                match crate::metadata::fetch_metadata(command, name, &github_repo).await {
+                    Ok(metadata) => (metadata, None),
+                    // Only this target fails, the others are still run
+                    Err(e) => (target.metadata.clone(), Some(e.to_string())),
+                }
+            }
+            _ => (target.metadata.clone(), None),
+        };
+
+        Ok(MigrationTask::new(
+            name,
+            github_repo,
+            definition.with_metadata(&metadata),
+            exec_opts,
+            target.pull_request.clone(),
+            target.skip,
+        )
+        .with_metadata(metadata)
+        .with_metadata_error(metadata_error)
+        .with_base(target.base_ref.clone(), target.base_sha.clone()))
+    }
+
+    pub fn with_base(mut self, base_ref: Option<String>, base_sha: Option<String>) -> Self {
+        self.base_ref = base_ref;
+        self.base_sha = base_sha;
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: BTreeMap<String, String>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    pub fn with_metadata_error(mut self, metadata_error: Option<String>) -> Self {
+        self.metadata_error = metadata_error;
+        self
+    }
+
     #[instrument(name = "migrate", skip(self), fields(name = %self.pretty_name))]
-    pub async fn run(&self) -> MigrationStatus {
+    pub async fn run(&self) -> MigrationOutcome {
+        let mut metrics = TargetMetrics::default();
+        let status = self.run_steps(&mut metrics).await;
+        MigrationOutcome { status, metrics }
+    }
+
+    async fn run_steps(&self, metrics: &mut TargetMetrics) -> MigrationStatus {
         if self.skip {
             return MigrationStatus::EmptyResponse(MigrationStepResult::abort("skip"));
         }
+        if let Some(message) = &self.metadata_error {
+            return MigrationStatus::EmptyResponse(MigrationStepResult::failure(
+                "metadata",
+                MigrationError::MetadataUnavailable {
+                    message: message.clone(),
+                },
+            ));
+        }
 
         let work_dir = match self.exec_opts.work_dir.canonicalize() {
             Ok(dir) => dir,
@@ -133,38 +468,218 @@ impl<'a> MigrationTask<'a> {
             }
         };
 
-        let status = CloneRepoStep::from(self).execute_step(&mut workspace).await;
+        // Push-only targets are pushed to without the check, a push they refuse fails the push
+        if let Some(forge) = self.exec_opts.forge {
+            let status = timed_step(
+                self.exec_opts.events,
+                metrics,
+                "repo-access",
+                &mut workspace,
+                RepoAccessCheckStep::new(forge, &self.repo, self.exec_opts.is_push_enabled()),
+            )
+            .await;
+            if status.terminal {
+                return MigrationStatus::EmptyResponse(status);
+            }
+        }
+
+        let status = timed_step(
+            self.exec_opts.events,
+            metrics,
+            "clone",
+            &mut workspace,
+            CloneRepoStep::from(self),
+        )
+        .await;
         if status.terminal {
             return MigrationStatus::EmptyResponse(status);
         }
 
-        let status = PreFlightCheckStep::from(self)
-            .execute_step(&mut workspace)
-            .await;
+        let status = timed_step(
+            self.exec_opts.events,
+            metrics,
+            "pre-flight",
+            &mut workspace,
+            PreFlightCheckStep::from(self),
+        )
+        .await;
         if status.terminal {
             return MigrationStatus::EmptyResponse(status);
         }
 
         for step in &self.definition.steps {
-            let status = MigrationScriptStep::from(step)
-                .execute_step(&mut workspace)
-                .await;
+            let status = timed_step(
+                self.exec_opts.events,
+                metrics,
+                &step.name,
+                &mut workspace,
+                MigrationScriptStep::from(step),
+            )
+            .await;
             if status.terminal {
                 return MigrationStatus::EmptyResponse(status);
             }
         }
 
+        // Compared with where the branch was created from, which can be behind the default branch
+        let base = RepoCheck::base_revision(self.base_ref.as_deref(), self.base_sha.as_deref());
+        match RepoCheck::diff_stats(&workspace, &base) {
+            Ok(diff) if diff.files_changed == 0 => {
+                warn!("Pre-flight passed, but the migration steps made no changes");
+                metrics.diff = Some(diff);
+                return MigrationStatus::EmptyResponse(MigrationStepResult::abort("no-changes"));
+            }
+            Ok(diff) => metrics.diff = Some(diff),
+            Err(e) => warn!("Unable to compute diff stats: {}", e),
+        }
+
+        let mut findings = Vec::new();
+        for reviewer in &self.definition.reviewers {
+            let status = timed_step(
+                self.exec_opts.events,
+                metrics,
+                &reviewer.name,
+                &mut workspace,
+                DiffReviewStep::from(reviewer)
+                    .with_base(self.base_ref.as_deref(), self.base_sha.as_deref()),
+            )
+            .await;
+            match status.result {
+                Ok(Some(found)) => findings.push(format!("## {}\n\n{}", reviewer.name, found)),
+                Ok(None) => {}
+                Err(e) => {
+                    return MigrationStatus::EmptyResponse(MigrationStepResult::failure(
+                        status.name,
+                        e,
+                    ))
+                }
+            }
+        }
+
+        let mut body = match crate::template::render_pr_body(
+            &self.definition,
+            &self.repo,
+            &self.pretty_name,
+            metrics.diff.as_ref(),
+            &self.metadata,
+        ) {
+            Ok(body) => body,
+            Err(e) => {
+                error!("Unable to render PR body: {:?}", e);
+                return MigrationStatus::EmptyResponse(MigrationStepResult::failure(
+                    "pr-template",
+                    MigrationError::IoError(e),
+                ));
+            }
+        };
+        for found in findings {
+            body.push_str("\n\n");
+            body.push_str(&found);
+        }
+
+        if let Some(bundle_dir) = &self.exec_opts.bundle_dir {
+            let status = timed_step(
+                self.exec_opts.events,
+                metrics,
+                "bundle",
+                &mut workspace,
+                BundleRepoStep::new(&self.definition.checkout.branch_name, bundle_dir),
+            )
+            .await;
+            if status.terminal {
+                return MigrationStatus::EmptyResponse(status);
+            }
+
+            return MigrationStatus::EmptyResponse(MigrationStepResult::abort("bundle"));
+        }
+
+        if let Some(patch_dir) = &self.exec_opts.patch_dir {
+            return MigrationStatus::Patches(
+                self.export_patches(metrics, &mut workspace, patch_dir, &body)
+                    .await,
+            );
+        }
+
+        if self.exec_opts.local && self.exec_opts.push_remote.is_none() {
+            info!(
+                "{} is a local directory, the branch is left in {}",
+                self.pretty_name,
+                workspace.root_dir.join("repo").display()
+            );
+            return MigrationStatus::EmptyResponse(MigrationStepResult::success("local"));
+        }
+
+        if let Some(prompt) = self
+            .exec_opts
+            .push_prompt
+            .filter(|_| self.exec_opts.is_push_enabled())
+        {
+            let git_repo = workspace.root_dir.join("repo");
+            match prompt.ask(&self.pretty_name, &git_repo, &base, metrics.diff.as_ref()) {
+                PushDecision::Push => {}
+                PushDecision::Skip | PushDecision::Abort => {
+                    info!("{} was left without pushing it", self.pretty_name);
+                    return MigrationStatus::EmptyResponse(MigrationStepResult::abort(
+                        "interactive",
+                    ));
+                }
+            }
+        }
+
         if self.exec_opts.is_push_enabled() {
-            let status = PushRepoStep::new().execute_step(&mut workspace).await;
+            let status = match self.exec_opts.forge.and_then(|it| it.as_gerrit()) {
+                Some(_) => {
+                    let change_id =
+                        crate::gerrit::change_id(&self.repo, &self.definition.checkout.branch_name);
+                    let push = PushForReviewStep::new(
+                        &change_id,
+                        &self.definition.checkout.branch_name,
+                        &self.definition.pr.title,
+                        &body,
+                    )
+                    .with_base(self.base_ref.as_deref(), self.base_sha.as_deref())
+                    .with_people(&self.definition.pr.labels, &self.definition.pr.assignees);
+                    timed_step(self.exec_opts.events, metrics, "push", &mut workspace, push).await
+                }
+                None => {
+                    let push = PushRepoStep::new().with_remote(
+                        self.exec_opts.push_remote.as_deref(),
+                        &self.definition.checkout.branch_name,
+                    );
+                    timed_step(self.exec_opts.events, metrics, "push", &mut workspace, push).await
+                }
+            };
             if status.terminal {
                 return MigrationStatus::EmptyResponse(status);
             }
 
-            if self.exec_opts.is_pr_enabled() {
+            if self.exec_opts.is_push_only() {
+                let pushed = match RepoCheck::head_commit(&workspace) {
+                    Ok(commit) => MigrationStepResult::success_with_result(
+                        "push",
+                        PushedBranch {
+                            remote: self
+                                .exec_opts
+                                .push_remote
+                                .clone()
+                                .unwrap_or_else(|| self.repo.clone_url.clone()),
+                            branch: self.definition.checkout.branch_name.clone(),
+                            commit,
+                        },
+                    ),
+                    Err(e) => MigrationStepResult::failure("push", e),
+                };
+                MigrationStatus::Pushed(pushed)
+            } else if self.exec_opts.is_pr_enabled() {
                 MigrationStatus::PullRequest(
-                    UpdateGithubStep::from(self)
-                        .execute_step(&mut workspace)
-                        .await,
+                    timed_step(
+                        self.exec_opts.events,
+                        metrics,
+                        "pull-request",
+                        &mut workspace,
+                        UpdateGithubStep::from(self).with_body(&body),
+                    )
+                    .await,
                 )
             } else {
                 MigrationStatus::EmptyResponse(MigrationStepResult::abort("pull-request"))
@@ -173,4 +688,192 @@ impl<'a> MigrationTask<'a> {
             MigrationStatus::EmptyResponse(MigrationStepResult::abort("push"))
         }
     }
+
+    async fn export_patches(
+        &self,
+        metrics: &mut TargetMetrics,
+        workspace: &mut Workspace,
+        patch_dir: &Path,
+        body: &str,
+    ) -> MigrationStepResult<PatchSeries> {
+        let email = self.definition.email.as_ref();
+        let status = timed_step(
+            self.exec_opts.events,
+            metrics,
+            "format-patch",
+            workspace,
+            FormatPatchStep::new(patch_dir, &self.definition.pr.title, body)
+                .with_base(self.base_ref.as_deref(), self.base_sha.as_deref())
+                .with_email(email),
+        )
+        .await;
+
+        let mut series = match (&status.result, email) {
+            (Ok(series), Some(email)) if self.exec_opts.send_patches => {
+                let sent = timed_step(
+                    self.exec_opts.events,
+                    metrics,
+                    "send-email",
+                    workspace,
+                    SendPatchesStep::new(series, email),
+                )
+                .await;
+                if let Err(e) = sent.result {
+                    return MigrationStepResult::failure(sent.name, e);
+                }
+                series.clone()
+            }
+            _ => return status,
+        };
+        series.sent = true;
+
+        MigrationStepResult::success_with_result("send-email", series)
+    }
+}
+
+/// Finds the tasks that would push the same branch to the same repo as another task, which
+/// happens when a generated inventory lists a repo under more than one name. Each duplicate is
+/// mapped to the task that is run instead, the first one by name that isn't skipped.
+pub fn find_duplicate_tasks(tasks: &[MigrationTask<'_>]) -> BTreeMap<String, String> {
+    let mut primaries: BTreeMap<(String, String, String, &str), &str> = BTreeMap::new();
+    let mut duplicates = BTreeMap::new();
+
+    for task in tasks.iter().filter(|it| !it.skip) {
+        // The same owner and name on another host, like a GitHub Enterprise mirror, is another repo
+        let key = (
+            task.repo.host.to_lowercase(),
+            task.repo.owner.to_lowercase(),
+            task.repo.repo.to_lowercase(),
+            task.definition.checkout.branch_name.as_str(),
+        );
+        match primaries.get(&key) {
+            Some(primary) => {
+                duplicates.insert(task.pretty_name.clone(), primary.to_string());
+            }
+            None => {
+                primaries.insert(key, &task.pretty_name);
+            }
+        }
+    }
+
+    duplicates
+}
+
+/// Gives each duplicate target the results of the target that was run in its place.
+pub fn alias_duplicate_targets(
+    targets: &mut BTreeMap<String, TargetDescription>,
+    duplicates: &BTreeMap<String, String>,
+) {
+    for (duplicate, primary) in duplicates {
+        let primary = match targets.get(primary) {
+            Some(primary) => primary.clone(),
+            None => continue,
+        };
+
+        if let Some(target) = targets.get_mut(duplicate) {
+            target.pull_request = primary.pull_request;
+            target.pushed = primary.pushed;
+            target.patches = primary.patches;
+            target.metrics = primary.metrics;
+            target.runs = primary.runs;
+        }
+    }
+}
+
+/// Runs a step, recording how long it took and the exit code of the last command it ran.
+async fn timed_step<Output, S: MigrationStep<Output>>(
+    events: Option<&EventLog>,
+    metrics: &mut TargetMetrics,
+    name: &str,
+    workspace: &mut Workspace,
+    step: S,
+) -> MigrationStepResult<Output> {
+    workspace.last_exit_code = None;
+    let start = Instant::now();
+    let result = step.execute_step(workspace).await;
+
+    let duration_ms = start.elapsed().as_millis() as u64;
+    metrics.steps.push(StepMetrics {
+        name: name.to_owned(),
+        duration_ms,
+        exit_code: workspace.last_exit_code,
+    });
+
+    if let Some(events) = events {
+        let (status, error) = match &result.result {
+            Err(e) => (StepStatus::Failed, Some(e.to_string())),
+            Ok(_) if result.terminal => (StepStatus::Stopped, None),
+            Ok(_) => (StepStatus::Succeeded, None),
+        };
+        events.record(
+            &workspace.workspace_name,
+            name,
+            status,
+            duration_ms,
+            workspace.last_exit_code,
+            error,
+        );
+    }
+
+    result
+}
+
+#[tokio::test]
+async fn validate_find_duplicate_tasks() {
+    let migration: crate::models::MigrationFile = toml::from_str(
+        r#"
+        [targets.clu]
+        repo = "git@github.com:ethankhall/clu.git"
+        forge = "none"
+
+        [targets.clu-enterprise]
+        repo = "git@ghe.example.com:ethankhall/clu.git"
+        forge = "none"
+
+        [targets.clu-renamed]
+        repo = "git@github.com:EthanKhall/CLU.git"
+        forge = "none"
+
+        [targets.clu-skipped]
+        repo = "git@github.com:ethankhall/clu.git"
+        forge = "none"
+        skip = true
+
+        [checkout]
+        branch-name = "clu/update"
+        pre-flight = "/usr/bin/true"
+
+        [pr]
+        title = "Update"
+        description = "Update"
+
+        [[steps]]
+        name = "update"
+        migration-script = "update.sh"
+        "#,
+    )
+    .unwrap();
+    let github_hosts = GitHubHosts::new("token", &BTreeMap::new()).unwrap();
+    let settings = RunSettings::default();
+
+    let mut tasks = Vec::new();
+    for (name, target) in &migration.targets {
+        tasks.push(
+            MigrationTask::prepare(
+                &migration.definition,
+                &github_hosts,
+                &settings,
+                name,
+                target,
+            )
+            .await
+            .unwrap(),
+        );
+    }
+
+    let duplicates = find_duplicate_tasks(&tasks);
+    assert_eq!(
+        vec![(&"clu-renamed".to_owned(), &"clu".to_owned())],
+        duplicates.iter().collect::<Vec<_>>()
+    );
 }
@@ -1,5 +1,8 @@
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::github::{CloneProtocol, GitHubError, GitHubRepo, PullReviews, PullStatus};
+use crate::glob::Glob;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "kebab-case")]
@@ -9,6 +12,177 @@ pub struct MigrationDefinition {
     pub pr: PrCreationDetails,
 
     pub steps: Vec<MigrationStepDefinition>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tracking: Option<TrackingRepo>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tracking_issues: Option<IssueTrackingRepo>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notifications: Option<NotificationConfig>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub targets_from: Option<TargetSource>,
+
+    /// Names of checks that don't count when deciding if a PR has failed, for checks that are
+    /// known to be flaky or are optional. `*` and `?` can be used as wildcards.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignored_checks: Vec<String>,
+
+    /// Clone and push every target over this protocol, instead of the one in its URL.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clone_protocol: Option<CloneProtocol>,
+
+    /// Commands that review the diff once all the steps have run, before it's pushed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reviewers: Vec<DiffReviewerDefinition>,
+
+    /// Command that prints a JSON object of metadata for a target, like its team and tier
+    /// from a service catalog. The values can be used as `{{metadata.<key>}}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata_command: Option<String>,
+
+    /// Directory, relative to the migration file, where the state of each target is kept in
+    /// its own file instead of in the migration file. Keeps migrations with thousands of
+    /// targets quick to load and save.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state_dir: Option<String>,
+
+    /// GitHub Enterprise Servers and GitLab instances that targets are on, by host name.
+    /// Targets on github.com and gitlab.com don't need an entry.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub hosts: BTreeMap<String, HostConfig>,
+
+    /// Where the patch series written by `--export-patches` are sent, for projects that take
+    /// patches on a mailing list.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<EmailDefinition>,
+
+    /// Settings picked with `--profile`, by name, so the same migration can be tried against a
+    /// sandbox org before production.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Profile>,
+
+    /// Scripts `run-followup --name` runs for the open PR's, by name, so the follow-ups of a
+    /// migration are kept with it.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub followups: BTreeMap<String, FollowupDefinition>,
+}
+
+/// A follow-up kept in the migration file, like `[followups.nudge-owners]`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct FollowupDefinition {
+    /// Relative paths are from where `clu` is run, like a positional script.
+    pub script: String,
+    /// Set for the script, next to `CLU_PULL_REQUEST_URL` and `CLU_CLONE_URL`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+}
+
+/// A named set of defaults for `run-migration` and `check-status`. The flags that are given win
+/// over them, except for the token.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct Profile {
+    /// Environment variable holding the token used instead of `--github-token`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_env: Option<String>,
+    /// Base of the REST API used for github.com targets, like a GitHub Enterprise Server
+    /// holding the sandbox.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_url: Option<String>,
+    /// Used when `--work-directory` isn't given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub work_directory: Option<String>,
+    /// Runs as `--dry-run`, unless one of the other publishing flags is given.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct EmailDefinition {
+    pub to: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cc: Vec<String>,
+    /// Used in the subject of each patch instead of `PATCH`, like `PATCH tools`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject_prefix: Option<String>,
+    /// The server to send through. `git send-email` falls back to the `sendemail.*` git config
+    /// when `smtp-host` isn't set.
+    #[serde(default, flatten, skip_serializing_if = "Option::is_none")]
+    pub smtp: Option<SmtpConfig>,
+}
+
+/// How to reach the API of a GitHub Enterprise Server, GitLab instance or Bitbucket Server.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct HostConfig {
+    /// What the host runs, GitHub unless it's `gitlab.com`, `bitbucket.org`, `dev.azure.com` or
+    /// a CodeCommit region.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forge: Option<Forge>,
+    /// Base of the REST API when not set is `https://<host>/api/v3` for GitHub,
+    /// `https://<host>/api/v4` for GitLab and `https://<host>/rest/api/1.0` for Bitbucket
+    /// Server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_url: Option<String>,
+    /// Environment variable holding the token for the host. GitHub hosts use the
+    /// `--github-token`, GitLab hosts `GITLAB_TOKEN`, Bitbucket hosts `BITBUCKET_TOKEN` and
+    /// Azure DevOps `AZURE_DEVOPS_TOKEN` when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_env: Option<String>,
+}
+
+/// The service a repo is hosted on, deciding how its PR's are opened and checked.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Forge {
+    Github,
+    Gitlab,
+    /// Bitbucket Cloud, on `bitbucket.org`.
+    Bitbucket,
+    /// Bitbucket Server or Data Center.
+    BitbucketServer,
+    AzureDevops,
+    /// AWS CodeCommit, signed with the credentials in the `AWS_*` environment variables.
+    #[serde(rename = "codecommit")]
+    CodeCommit,
+    /// Gerrit, changes are pushed for review to `refs/for/<branch>` instead of opening a PR.
+    Gerrit,
+    /// Any git remote. The branch is pushed, but no PR is opened.
+    None,
+}
+
+impl MigrationDefinition {
+    pub fn ignored_check_patterns(&self) -> Vec<Glob> {
+        self.ignored_checks.iter().map(Glob::new).collect()
+    }
+
+    /// The definition for a single target, with `{{metadata.<key>}}` replaced in the branch
+    /// name, PR title, labels and assignees.
+    pub fn with_metadata(&self, metadata: &BTreeMap<String, String>) -> MigrationDefinition {
+        let mut definition = self.clone();
+        if metadata.is_empty() {
+            return definition;
+        }
+
+        let owned = crate::metadata::metadata_variables(metadata);
+        let variables = owned.iter().map(|(k, v)| (k.as_str(), v.clone())).collect();
+        let render = |value: &str| crate::template::render(value, &variables);
+
+        definition.checkout.branch_name = render(&self.checkout.branch_name);
+        definition.pr.title = render(&self.pr.title);
+        definition.pr.labels = self.pr.labels.iter().map(|it| render(it)).collect();
+        definition.pr.assignees = self.pr.assignees.iter().map(|it| render(it)).collect();
+        if let (Some(email), Some(rendered)) = (&self.email, definition.email.as_mut()) {
+            rendered.to = email.to.iter().map(|it| render(it)).collect();
+            rendered.cc = email.cc.iter().map(|it| render(it)).collect();
+        }
+        definition
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -23,6 +197,204 @@ pub struct RepoCheckout {
     /// returns an exit-code 0, then the migration will continue. Any other
     /// value will cause the migration to be skipped for this repo.
     pub pre_flight: String,
+
+    /// Leave Git LFS files as pointers instead of downloading them, for migrations that
+    /// don't need the contents. Changed LFS files are still pushed correctly.
+    #[serde(default)]
+    pub skip_lfs_smudge: bool,
+
+    /// Only check out files matching these gitignore style patterns, like
+    /// `services/payments/**`. Useful for monorepos that are too large to clone.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sparse_paths: Vec<String>,
+
+    /// Prefix for the branch, like `clu/{{migration-id}}/`, so migration branches can't
+    /// collide with the ones people make. `{{migration-id}}` is the name of the migration
+    /// file without its extension.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch_namespace: Option<String>,
+}
+
+impl RepoCheckout {
+    /// The branch that is pushed to GitHub, `branch-name` inside the `branch-namespace`.
+    pub fn head_branch(&self, migration_file: &str) -> String {
+        let namespace = match &self.branch_namespace {
+            Some(namespace) => namespace,
+            None => return self.branch_name.clone(),
+        };
+
+        let migration_id = std::path::Path::new(migration_file)
+            .file_stem()
+            .map(|it| it.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut variables = BTreeMap::new();
+        variables.insert("migration-id", migration_id);
+
+        format!(
+            "{}{}",
+            crate::template::render(namespace, &variables),
+            self.branch_name
+        )
+    }
+}
+
+#[test]
+fn validate_head_branch() {
+    let mut checkout = RepoCheckout {
+        branch_name: "upgrade-terraform".to_owned(),
+        pre_flight: "/usr/bin/true".to_owned(),
+        skip_lfs_smudge: false,
+        sparse_paths: Vec::new(),
+        branch_namespace: None,
+    };
+    assert_eq!(
+        "upgrade-terraform",
+        checkout.head_branch("migrations/tf.toml")
+    );
+
+    checkout.branch_namespace = Some("clu/{{migration-id}}/".to_owned());
+    assert_eq!(
+        "clu/tf/upgrade-terraform",
+        checkout.head_branch("migrations/tf.toml")
+    );
+}
+
+/// Repository where the results of every run are committed, so the state of the migration
+/// lives somewhere the whole team can see it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct TrackingRepo {
+    /// GitHub URL of the tracking repo.
+    pub repo: String,
+
+    /// Branch to commit to, it must already exist.
+    pub branch: String,
+
+    /// Directory in the tracking repo the files are written to.
+    pub path: String,
+}
+
+/// Repository where `clu sync-issues` keeps one issue per target, labeled with the state of
+/// the target's PR.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct IssueTrackingRepo {
+    /// GitHub URL of the repo the issues are created in.
+    pub repo: String,
+}
+
+/// Where `clu discover` looks for targets, and which of the repos it finds are kept.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct TargetSource {
+    /// Every repository of this GitHub organization.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub org: Option<String>,
+
+    /// Every repository a team administers, as `org/team-slug`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub team: Option<String>,
+
+    /// Repositories with code matching this GitHub code search query, like
+    /// `filename:Dockerfile FROM ubuntu:18.04`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub search: Option<String>,
+
+    /// Only keep repos whose primary language is one of these.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub languages: Vec<String>,
+
+    /// Only keep repos that have at least one of these topics.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub topics: Vec<String>,
+
+    /// Drop repos that have any of these topics.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude_topics: Vec<String>,
+
+    /// Drop repos whose `owner/name` matches one of these, `*` and `?` can be used as
+    /// wildcards.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+
+    /// Archived repos can't be migrated, so they are dropped unless this is set.
+    #[serde(default)]
+    pub include_archived: bool,
+}
+
+/// Where `clu digest` and `run-migration` send their summaries.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct NotificationConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<EmailConfig>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub teams: Option<TeamsConfig>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct TeamsConfig {
+    /// URL of the incoming webhook for the channel.
+    pub webhook_url: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct EmailConfig {
+    #[serde(flatten)]
+    pub smtp: SmtpConfig,
+
+    pub from: String,
+
+    pub to: Vec<String>,
+}
+
+/// The SMTP server used by both `[email]` and `[notifications.email]`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct SmtpConfig {
+    pub smtp_host: String,
+
+    #[serde(default = "default_smtp_port")]
+    pub smtp_port: u16,
+
+    /// Upgrade the connection with STARTTLS before sending credentials.
+    #[serde(default = "default_true")]
+    pub starttls: bool,
+
+    /// User to authenticate as.
+    #[serde(default)]
+    pub username: Option<String>,
+
+    /// Environment variable holding the password of `username`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_env: Option<String>,
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// An external check of the generated changes, like a linter, semgrep, or an LLM gateway.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct DiffReviewerDefinition {
+    /// Name of the reviewer, used for reporting and as the heading of its findings.
+    pub name: String,
+
+    /// Script that is given the path to the diff as its only argument. Anything it prints
+    /// is added to the PR body, and exiting non-zero stops the change from being pushed.
+    pub command: String,
+
+    /// Only report the findings, a non-zero exit doesn't stop the push.
+    #[serde(default)]
+    pub advisory: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -48,6 +420,55 @@ pub struct PrCreationDetails {
 
     /// This message will also show up in the GitHub PR.
     pub description: String,
+
+    /// Labels to add to the PR. Labels that don't exist in the repo will be created.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
+
+    /// GitHub logins that the PR will be assigned to.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub assignees: Vec<String>,
+
+    /// Title of an open milestone in the target repo to add the PR to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub milestone: Option<String>,
+
+    /// Path to a markdown template used for the PR body instead of `description`. See the
+    /// README for the variables and partials that can be used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+
+    /// Markdown comment posted on the PR when it's created. `{{repo}}`, `{{owner}}`,
+    /// `{{name}}`, `{{branch}}` and `{{pr_url}}` are replaced with the values for the target.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+
+    /// When the PR can't be created, open an issue in the target repo asking for the
+    /// migration to be done by hand.
+    #[serde(default)]
+    pub issue_on_failure: bool,
+
+    /// Workflow that is dispatched on the migration branch every time the PR is created or
+    /// updated, for repos whose CI only runs on `workflow_dispatch`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dispatch_workflow: Option<WorkflowDispatch>,
+
+    /// Most PR's created in any hour. Once it's reached, the remaining targets are prepared
+    /// and pushed but wait to have their PR created, so reviewers and GitHub's abuse
+    /// detection aren't flooded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_prs_per_hour: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct WorkflowDispatch {
+    /// File name of the workflow, like `ci.yml`, or its ID.
+    pub workflow: String,
+
+    /// Inputs of the workflow, the same variables as `comment` are replaced in the values.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub inputs: BTreeMap<String, String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -55,33 +476,626 @@ pub struct MigrationFile {
     pub targets: BTreeMap<String, TargetDescription>,
     #[serde(flatten)]
     pub definition: MigrationDefinition,
+    /// Targets whose `state-dir` file wasn't read, which `save_migration` leaves as they are.
+    #[serde(skip)]
+    pub unloaded: BTreeSet<String>,
+}
+
+impl MigrationFile {
+    /// The `hosts` of the definition, with the hosts of targets that name their `forge`.
+    pub fn host_configs(&self) -> BTreeMap<String, HostConfig> {
+        host_configs(&self.definition.hosts, &self.targets)
+    }
+
+    /// Like `host_configs`, with github.com reached through the `api-url` of the profile.
+    pub fn profile_host_configs(&self, profile: Option<&str>) -> BTreeMap<String, HostConfig> {
+        let mut hosts = self.host_configs();
+        let api_url = profile
+            .and_then(|name| self.definition.profiles.get(name))
+            .and_then(|profile| profile.api_url.clone());
+        if let Some(api_url) = api_url {
+            hosts
+                .entry(crate::github::GITHUB_HOST.to_owned())
+                .or_default()
+                .api_url = Some(api_url);
+        }
+
+        hosts
+    }
+}
+
+/// Adds the hosts of targets with a `forge` to `hosts`. A host that's already listed keeps its
+/// own `forge`.
+pub fn host_configs(
+    hosts: &BTreeMap<String, HostConfig>,
+    targets: &BTreeMap<String, TargetDescription>,
+) -> BTreeMap<String, HostConfig> {
+    let mut hosts = hosts.clone();
+    for target in targets.values() {
+        let repo = match crate::github::extract_github_info(&target.repo) {
+            Ok(repo) => repo,
+            Err(_) => continue,
+        };
+        let forge = match target.forge {
+            // Push-only targets are decided one by one, the other targets on the host still get
+            // PR's
+            Some(Forge::None) => continue,
+            Some(forge) => forge,
+            // CodeCommit has a host per region, so they're found from the targets
+            None if crate::codecommit::codecommit_region(&repo.host).is_some() => Forge::CodeCommit,
+            None => continue,
+        };
+        let config = hosts.entry(repo.host).or_default();
+        config.forge.get_or_insert(forge);
+    }
+
+    hosts
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct TargetDescription {
     pub repo: String,
+    /// What the host of the repo runs, for hosts that aren't in `hosts`. With `none`, `repo` can
+    /// be any git remote.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forge: Option<Forge>,
+    /// Where the branch of a target whose `repo` is a directory on disk is pushed. Without it
+    /// the branch is only kept in the workspace.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub push_remote: Option<String>,
     #[serde(default)]
     pub skip: bool,
+    /// Labels like the team, language or risk tier, for picking targets with `--tag`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
     pub env: Option<BTreeMap<String, String>>,
     #[serde(default)]
     pub pull_request: Option<CreatedPullRequest>,
+    #[serde(default)]
+    pub metrics: Option<TargetMetrics>,
+    /// Issue number in the `tracking-issues` repo for this target.
+    #[serde(default)]
+    pub tracking_issue: Option<i64>,
+    /// Issue asking for the migration to be done by hand, because the PR couldn't be created.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manual_issue: Option<CreatedIssue>,
+    /// PR status seen by the last `clu digest`, used to report what changed.
+    #[serde(default)]
+    pub last_status: Option<PullStatus>,
+    /// Statuses seen by `clu check-status`, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub status_history: Vec<StatusObservation>,
+    /// Reviews of the PR seen by the last `clu check-status`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reviews: Option<ReviewTracking>,
+    /// How the target ended in each run that was given a `--run-label`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub runs: BTreeMap<String, RunResult>,
+    /// How the target ended the last time `run-migration` ran it, used by `--only-failed`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_run: Option<RunResult>,
+    /// Branch to start the migration from and open the PR against, instead of the default
+    /// branch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_ref: Option<String>,
+    /// Commit the migration branch is created from, like the last commit before a release
+    /// freeze. The PR is still opened against `base-ref`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_sha: Option<String>,
+    /// Output of the `metadata-command` the last time the target was run.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+    /// The branch pushed for a target with `forge = "none"`, which gets no PR.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pushed: Option<PushedBranch>,
+    /// The patch series written by `--export-patches`, used instead of a PR.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub patches: Option<PatchSeries>,
 }
 
 impl TargetDescription {
     pub fn new(repo: &str) -> Self {
         Self {
             repo: repo.to_owned(),
+            forge: None,
+            push_remote: None,
             skip: false,
+            tags: Vec::new(),
             env: None,
             pull_request: None,
+            metrics: None,
+            tracking_issue: None,
+            manual_issue: None,
+            last_status: None,
+            status_history: Vec::new(),
+            reviews: None,
+            runs: BTreeMap::new(),
+            last_run: None,
+            base_ref: None,
+            base_sha: None,
+            metadata: BTreeMap::new(),
+            pushed: None,
+            patches: None,
+        }
+    }
+
+    /// Takes what was recorded about the target by a run elsewhere, like a queue worker or a
+    /// shard.
+    pub fn merge_results(&mut self, result: TargetDescription) {
+        if result.pull_request.is_some() {
+            self.pull_request = result.pull_request;
+        }
+        if result.metrics.is_some() {
+            self.metrics = result.metrics;
+        }
+        if result.manual_issue.is_some() {
+            self.manual_issue = result.manual_issue;
+        }
+        if result.pushed.is_some() {
+            self.pushed = result.pushed;
+        }
+        if result.patches.is_some() {
+            self.patches = result.patches;
+        }
+        if result.last_run.is_some() {
+            self.last_run = result.last_run;
+        }
+        self.runs.extend(result.runs);
+        if !result.metadata.is_empty() {
+            self.metadata = result.metadata;
         }
     }
+
+    /// The repo of the target. Push-only targets can be any git remote, so remotes that aren't
+    /// on a forge are accepted for them.
+    pub fn github_repo(&self) -> Result<GitHubRepo, GitHubError> {
+        if self.is_local() {
+            // Commands run in the workspace, so relative paths have to be resolved first
+            let path = match std::fs::canonicalize(&self.repo) {
+                Ok(path) => path.to_string_lossy().into_owned(),
+                Err(_) => self.repo.clone(),
+            };
+            return Ok(crate::github::extract_remote_info(&path));
+        }
+
+        match crate::github::extract_github_info(&self.repo) {
+            Err(_) if self.forge == Some(Forge::None) => {
+                Ok(crate::github::extract_remote_info(&self.repo))
+            }
+            result => result,
+        }
+    }
+
+    /// The target is a checkout on disk, which gets no PR.
+    pub fn is_local(&self) -> bool {
+        crate::github::is_local_path(&self.repo)
+    }
+
+    /// Adds a status to the history, dropping the oldest ones once there are more than
+    /// `MAX_STATUS_HISTORY`.
+    pub fn record_status(&mut self, status: PullStatus, observed_at: u64) {
+        self.status_history.push(StatusObservation {
+            observed_at,
+            status,
+        });
+
+        if self.status_history.len() > MAX_STATUS_HISTORY {
+            let extra = self.status_history.len() - MAX_STATUS_HISTORY;
+            self.status_history.drain(..extra);
+        }
+    }
+
+    /// Records the reviews of the PR, keeping when the requested reviewers were first seen
+    /// without answering for as long as they still haven't.
+    pub fn record_reviews(&mut self, reviews: PullReviews, observed_at: u64) {
+        let pending_since = match &self.reviews {
+            _ if !reviews.is_pending() => None,
+            Some(previous) => previous.pending_since.or(Some(observed_at)),
+            None => Some(observed_at),
+        };
+
+        self.reviews = Some(ReviewTracking {
+            observed_at,
+            pending_since,
+            reviews,
+        });
+    }
+
+    /// How many times the PR went between mergeable and failing checks. PR's that keep
+    /// flipping usually have flaky checks rather than a broken migration.
+    pub fn status_flips(&self) -> usize {
+        let statuses: Vec<&PullStatus> = self
+            .status_history
+            .iter()
+            .map(|it| &it.status)
+            .filter(|it| matches!(it, PullStatus::Mergeable | PullStatus::ChecksFailed))
+            .collect();
+
+        statuses.windows(2).filter(|it| it[0] != it[1]).count()
+    }
+
+    /// When the PR was first seen with the status it has now, as far back as the history goes.
+    pub fn status_since(&self) -> Option<u64> {
+        let current = &self.status_history.last()?.status;
+        self.status_history
+            .iter()
+            .rev()
+            .take_while(|it| &it.status == current)
+            .last()
+            .map(|it| it.observed_at)
+    }
+}
+
+/// Number of `check-status` observations kept for each target.
+const MAX_STATUS_HISTORY: usize = 50;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct StatusObservation {
+    /// Seconds since the Unix epoch.
+    pub observed_at: u64,
+    pub status: PullStatus,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct ReviewTracking {
+    /// Seconds since the Unix epoch.
+    pub observed_at: u64,
+    /// Since when the requested reviewers haven't answered, seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending_since: Option<u64>,
+    #[serde(flatten)]
+    pub reviews: PullReviews,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RunOutcome {
+    /// The PR was created or updated.
+    Succeeded,
+    /// The run ended early without an error, like when the pre-flight says the target is
+    /// already migrated.
+    Stopped,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct RunResult {
+    pub outcome: RunOutcome,
+    /// The step the run ended on.
+    pub step: String,
+    /// Why the step failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// What kind of error it failed with, like `step-exited`, so `clu stats` can group the
+    /// failures.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_kind: Option<String>,
+}
+
+impl std::fmt::Display for RunResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let outcome = match self.outcome {
+            RunOutcome::Succeeded => "succeeded",
+            RunOutcome::Stopped => "stopped",
+            RunOutcome::Failed => "failed",
+        };
+        write!(f, "{} at `{}`", outcome, self.step)
+    }
+}
+
+/// Issue opened in a target repo by `issue-on-failure`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CreatedIssue {
+    pub issue_number: i64,
+    pub url: String,
+}
+
+/// Where a push-only target's branch was pushed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct PushedBranch {
+    pub remote: String,
+    pub branch: String,
+    pub commit: String,
+}
+
+/// A `git format-patch` series of a target's branch, cover letter first.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct PatchSeries {
+    pub dir: String,
+    /// The `Message-Id` of each mail, which replies and later versions of the series refer to.
+    pub message_ids: Vec<String>,
+    /// The series was sent with `git send-email`.
+    #[serde(default)]
+    pub sent: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
 pub struct CreatedPullRequest {
+    // Older migration files only have `pr_number` and `url`, so everything else is optional
+    #[serde(rename = "pr_number")]
     pub pr_number: i64,
     #[serde(default)]
     pub url: String,
+    /// Owner of the repo the PR is in.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub owner: String,
+    /// Name of the repo the PR is in.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub repo: String,
+    /// Name of the PR branch.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub head_ref: String,
+    /// ISO-8601 timestamp of when the PR was opened.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+    /// Status seen by the last `clu check-status`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<PullStatus>,
+    /// The gist with the full body, when the body was too long for GitHub.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_gist: Option<BodyGist>,
+}
+
+/// A secret gist holding a PR body, kept so the next run only creates one when the body changes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub struct BodyGist {
+    pub url: String,
+    /// Hex SHA-256 of the full body in the gist.
+    pub sha256: String,
+}
+
+impl CreatedPullRequest {
+    /// The repo the PR is in. Falls back to parsing the clone URL of the target for PR's
+    /// recorded before the repo was stored with them.
+    pub fn github_repo(&self, clone_url: &str) -> Result<GitHubRepo, GitHubError> {
+        if self.owner.is_empty() || self.repo.is_empty() {
+            return crate::github::extract_github_info(clone_url);
+        }
+
+        let host = crate::github::extract_github_info(clone_url)
+            .map(|it| it.host)
+            .unwrap_or_else(|_| crate::github::GITHUB_HOST.to_owned());
+        Ok(GitHubRepo {
+            host,
+            owner: self.owner.clone(),
+            repo: self.repo.clone(),
+            clone_url: clone_url.to_owned(),
+        })
+    }
+}
+
+#[test]
+fn validate_created_pull_request_repo() {
+    let mut pull: CreatedPullRequest =
+        toml::from_str("pr_number = 12\nurl = \"https://github.com/ethankhall/clu/pull/12\"")
+            .unwrap();
+    let clone_url = "git@github.com:ethankhall/clu.git";
+    assert_eq!("clu", pull.github_repo(clone_url).unwrap().repo);
+
+    pull.owner = "ethankhall".to_owned();
+    pull.repo = "renamed".to_owned();
+    assert_eq!("renamed", pull.github_repo(clone_url).unwrap().repo);
+    assert!(toml::to_string(&pull).unwrap().contains("pr_number = 12"));
+}
+
+/// Measurements from the last `run-migration` of a target, used by `clu stats`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct TargetMetrics {
+    #[serde(default)]
+    pub steps: Vec<StepMetrics>,
+    #[serde(default)]
+    pub diff: Option<DiffStats>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct StepMetrics {
+    pub name: String,
+    pub duration_ms: u64,
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+}
+
+/// Size of the change the migration steps made compared to the default branch.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct DiffStats {
+    pub files_changed: u64,
+    pub insertions: u64,
+    pub deletions: u64,
+}
+
+impl TargetMetrics {
+    /// The pre-flight said the migration was needed, but the steps didn't change anything.
+    /// Usually this is a bug in the migration script.
+    pub fn is_zero_diff(&self) -> bool {
+        matches!(&self.diff, Some(diff) if diff.files_changed == 0)
+    }
+}
+
+impl DiffStats {
+    pub fn lines_changed(&self) -> u64 {
+        self.insertions + self.deletions
+    }
+}
+
+#[test]
+fn validate_status_flips() {
+    let mut target = TargetDescription::new("git@github.com:ethankhall/dummy-repo.git");
+    for status in &[
+        PullStatus::ChecksFailed,
+        PullStatus::Mergeable,
+        PullStatus::NeedsApproval,
+        PullStatus::Mergeable,
+        PullStatus::ChecksFailed,
+    ] {
+        target.record_status(status.clone(), 0);
+    }
+    assert_eq!(2, target.status_flips());
+    target.record_status(PullStatus::ChecksFailed, 10);
+    target.record_status(PullStatus::Merged, 20);
+    target.record_status(PullStatus::Merged, 30);
+    assert_eq!(Some(20), target.status_since());
+
+    for _ in 0..MAX_STATUS_HISTORY {
+        target.record_status(PullStatus::Merged, 0);
+    }
+    assert_eq!(MAX_STATUS_HISTORY, target.status_history.len());
+    assert_eq!(0, target.status_flips());
+}
+
+#[test]
+fn validate_record_reviews() {
+    let mut target = TargetDescription::new("git@github.com:ethankhall/dummy-repo.git");
+    let requested = PullReviews {
+        requested: vec!["ethankhall".to_owned()],
+        ..PullReviews::default()
+    };
+
+    target.record_reviews(requested.clone(), 10);
+    target.record_reviews(requested.clone(), 20);
+    assert_eq!(Some(10), target.reviews.as_ref().unwrap().pending_since);
+
+    let approved = PullReviews {
+        approved_by: vec!["ethankhall".to_owned()],
+        ..requested
+    };
+    target.record_reviews(approved, 30);
+    assert_eq!(None, target.reviews.as_ref().unwrap().pending_since);
+}
+
+#[test]
+fn validate_host_configs() {
+    let mut hosts = BTreeMap::new();
+    hosts.insert(
+        "git.example.com".to_owned(),
+        HostConfig {
+            forge: Some(Forge::Gitlab),
+            ..HostConfig::default()
+        },
+    );
+    let mut targets = BTreeMap::new();
+    let mut server = TargetDescription::new("ssh://git@bitbucket.example.com:7999/plat/clu.git");
+    server.forge = Some(Forge::BitbucketServer);
+    targets.insert("server".to_owned(), server);
+    let mut listed = TargetDescription::new("git@git.example.com:plat/clu.git");
+    listed.forge = Some(Forge::Github);
+    targets.insert("listed".to_owned(), listed);
+    targets.insert(
+        "github".to_owned(),
+        TargetDescription::new("git@github.com:ethankhall/clu.git"),
+    );
+    targets.insert(
+        "codecommit".to_owned(),
+        TargetDescription::new("codecommit::us-east-2://clu"),
+    );
+
+    let configs = host_configs(&hosts, &targets);
+    assert_eq!(3, configs.len());
+    assert_eq!(
+        Some(Forge::CodeCommit),
+        configs["git-codecommit.us-east-2.amazonaws.com"].forge
+    );
+    assert_eq!(
+        Some(Forge::BitbucketServer),
+        configs["bitbucket.example.com"].forge
+    );
+    assert_eq!(Some(Forge::Gitlab), configs["git.example.com"].forge);
+}
+
+#[test]
+fn validate_profile_host_configs() {
+    let migration: MigrationFile = toml::from_str(
+        r#"
+        [targets.clu]
+        repo = "git@github.com:ethankhall/clu.git"
+
+        [checkout]
+        branch-name = "clu/update"
+        pre-flight = "/usr/bin/true"
+
+        [pr]
+        title = "Update"
+        description = "Update"
+
+        [[steps]]
+        name = "update"
+        migration-script = "update.sh"
+
+        [profiles.staging]
+        token-env = "STAGING_GITHUB_TOKEN"
+        api-url = "https://ghe.staging.example.com/api/v3"
+        dry-run = true
+        "#,
+    )
+    .unwrap();
+
+    let profile = &migration.definition.profiles["staging"];
+    assert!(profile.dry_run);
+    assert_eq!(None, profile.work_directory);
+
+    assert!(migration.profile_host_configs(None).is_empty());
+    let hosts = migration.profile_host_configs(Some("staging"));
+    assert_eq!(
+        Some("https://ghe.staging.example.com/api/v3"),
+        hosts["github.com"].api_url.as_deref()
+    );
+}
+
+#[test]
+fn validate_smtp_config() {
+    let migration: MigrationFile = toml::from_str(
+        r#"
+        [targets.clu]
+        repo = "git@github.com:ethankhall/clu.git"
+
+        [checkout]
+        branch-name = "clu/update"
+        pre-flight = "/usr/bin/true"
+
+        [pr]
+        title = "Update"
+        description = "Update"
+
+        [[steps]]
+        name = "update"
+        migration-script = "update.sh"
+
+        [email]
+        to = ["dev@lists.example.org"]
+        smtp-host = "smtp.example.org"
+        username = "clu"
+        password-env = "SMTP_PASSWORD"
+
+        [notifications.email]
+        smtp-host = "smtp.example.org"
+        username = "clu"
+        password-env = "SMTP_PASSWORD"
+        from = "clu@example.org"
+        to = ["platform-team@example.org"]
+        "#,
+    )
+    .unwrap();
+
+    let patches = migration.definition.email.unwrap().smtp.unwrap();
+    assert_eq!("smtp.example.org", patches.smtp_host);
+    assert_eq!(587, patches.smtp_port);
+    assert!(patches.starttls);
+    assert_eq!(Some("SMTP_PASSWORD"), patches.password_env.as_deref());
+
+    let notifications = migration.definition.notifications.unwrap().email.unwrap();
+    assert_eq!("smtp.example.org", notifications.smtp.smtp_host);
+    assert_eq!(Some("clu"), notifications.smtp.username.as_deref());
+
+    let plain: EmailDefinition = toml::from_str(r#"to = ["dev@lists.example.org"]"#).unwrap();
+    assert!(plain.smtp.is_none());
 }
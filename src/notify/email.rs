@@ -0,0 +1,268 @@
+use anyhow::{bail, Result as AnyResult};
+use base64::Engine;
+use std::time::SystemTime;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tracing::{debug, info};
+
+use super::Digest;
+use crate::github::format_github_timestamp;
+use crate::models::EmailConfig;
+
+/// Minimal SMTP client, enough to hand a plain text message to a relay.
+pub struct EmailNotifier<'a> {
+    config: &'a EmailConfig,
+}
+
+impl<'a> EmailNotifier<'a> {
+    pub fn new(config: &'a EmailConfig) -> Self {
+        Self { config }
+    }
+
+    pub async fn send_digest(&self, digest: &Digest) -> AnyResult<()> {
+        self.send(&digest.subject(), &digest.render_text()).await
+    }
+
+    pub async fn send(&self, subject: &str, body: &str) -> AnyResult<()> {
+        let smtp = &self.config.smtp;
+        if smtp.username.is_some() && !smtp.starttls {
+            bail!("`username` needs `starttls`, the SMTP password is never sent without TLS");
+        }
+
+        let stream = TcpStream::connect((smtp.smtp_host.as_str(), smtp.smtp_port)).await?;
+        let mut connection = SmtpConnection::new(stream);
+        connection.expect_reply(220).await?;
+        connection.command("EHLO clu", 250).await?;
+
+        if smtp.starttls {
+            connection.command("STARTTLS", 220).await?;
+            let connector = tokio_native_tls::TlsConnector::from(native_tls_connector()?);
+            let stream = connector
+                .connect(&smtp.smtp_host, connection.into_inner())
+                .await?;
+
+            let mut connection = SmtpConnection::new(stream);
+            connection.command("EHLO clu", 250).await?;
+            self.deliver(connection, subject, body).await
+        } else {
+            self.deliver(connection, subject, body).await
+        }
+    }
+
+    async fn deliver<S: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        mut connection: SmtpConnection<S>,
+        subject: &str,
+        body: &str,
+    ) -> AnyResult<()> {
+        if let Some(username) = &self.config.smtp.username {
+            let credentials = format!("\0{}\0{}", username, self.password()?);
+            let credentials = base64::engine::general_purpose::STANDARD.encode(credentials);
+            connection
+                .command(&format!("AUTH PLAIN {}", credentials), 235)
+                .await?;
+        }
+
+        connection
+            .command(&format!("MAIL FROM:<{}>", self.config.from), 250)
+            .await?;
+        for to in &self.config.to {
+            connection
+                .command(&format!("RCPT TO:<{}>", to), 250)
+                .await?;
+        }
+
+        connection.command("DATA", 354).await?;
+        connection
+            .write_raw(&format_message(
+                &self.config.from,
+                &self.config.to,
+                subject,
+                body,
+                SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)?
+                    .as_secs(),
+            ))
+            .await?;
+        connection.expect_reply(250).await?;
+        connection.command("QUIT", 221).await?;
+
+        info!("Sent `{}` to {}", subject, self.config.to.join(", "));
+        Ok(())
+    }
+
+    fn password(&self) -> AnyResult<String> {
+        match &self.config.smtp.password_env {
+            Some(name) => match std::env::var(name) {
+                Ok(password) => Ok(password),
+                Err(_) => bail!("The SMTP password is read from {}, which isn't set", name),
+            },
+            None => Ok(String::new()),
+        }
+    }
+}
+
+fn native_tls_connector() -> AnyResult<tokio_native_tls::native_tls::TlsConnector> {
+    Ok(tokio_native_tls::native_tls::TlsConnector::new()?)
+}
+
+/// Builds the DATA section, including the terminating `.` line. `now` is the seconds since the
+/// epoch for the `Date` header.
+fn format_message(from: &str, to: &[String], subject: &str, body: &str, now: u64) -> String {
+    let mut message = format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n",
+        from,
+        to.join(", "),
+        encode_header(subject),
+        format_date(now)
+    );
+
+    for line in body.lines() {
+        // Lines starting with `.` need to be escaped, otherwise they could end the message
+        if line.starts_with('.') {
+            message.push('.');
+        }
+        message.push_str(line);
+        message.push_str("\r\n");
+    }
+    message.push_str(".\r\n");
+
+    message
+}
+
+/// Headers can only hold ASCII, anything else is sent as RFC 2047 encoded words. Each word is
+/// kept under the 75 characters the RFC allows, and folded onto its own line.
+fn encode_header(value: &str) -> String {
+    if value.is_ascii() {
+        return value.to_owned();
+    }
+
+    let mut words = Vec::new();
+    let mut chunk = String::new();
+    for c in value.chars() {
+        if chunk.len() + c.len_utf8() > 45 {
+            words.push(std::mem::take(&mut chunk));
+        }
+        chunk.push(c);
+    }
+    words.push(chunk);
+
+    words
+        .iter()
+        .map(|it| {
+            format!(
+                "=?utf-8?B?{}?=",
+                base64::engine::general_purpose::STANDARD.encode(it)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\r\n ")
+}
+
+/// Formats seconds since the epoch like RFC 5322 dates, `Thu, 01 Jan 1970 00:00:00 +0000`.
+fn format_date(seconds: u64) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    // `2026-10-14T13:18:00Z`
+    let timestamp = format_github_timestamp(seconds);
+    let month: usize = timestamp[5..7].parse().unwrap_or(1);
+    format!(
+        "{}, {} {} {} {} +0000",
+        DAYS[(seconds / 86400 % 7) as usize],
+        &timestamp[8..10],
+        MONTHS[month - 1],
+        &timestamp[0..4],
+        &timestamp[11..19]
+    )
+}
+
+struct SmtpConnection<S> {
+    stream: BufReader<S>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> SmtpConnection<S> {
+    fn new(stream: S) -> Self {
+        Self {
+            stream: BufReader::new(stream),
+        }
+    }
+
+    fn into_inner(self) -> S {
+        self.stream.into_inner()
+    }
+
+    async fn command(&mut self, command: &str, expected: u16) -> AnyResult<()> {
+        // Don't leak credentials into the logs
+        if command.starts_with("AUTH") {
+            debug!("SMTP > AUTH ...");
+        } else {
+            debug!("SMTP > {}", command);
+        }
+        self.write_raw(&format!("{}\r\n", command)).await?;
+        self.expect_reply(expected).await
+    }
+
+    async fn write_raw(&mut self, data: &str) -> AnyResult<()> {
+        self.stream.get_mut().write_all(data.as_bytes()).await?;
+        self.stream.get_mut().flush().await?;
+        Ok(())
+    }
+
+    /// Reads a (possibly multi-line) reply and checks the status code.
+    async fn expect_reply(&mut self, expected: u16) -> AnyResult<()> {
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line).await? == 0 {
+                bail!("SMTP server closed the connection");
+            }
+            debug!("SMTP < {}", line.trim_end());
+
+            let code: u16 = line.get(0..3).and_then(|it| it.parse().ok()).unwrap_or(0);
+            if code != expected {
+                bail!(
+                    "SMTP server replied `{}`, expected {}",
+                    line.trim_end(),
+                    expected
+                );
+            }
+
+            // `250-` means there are more lines to this reply, `250 ` is the last one
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[test]
+fn validate_format_message() {
+    let message = format_message(
+        "clu@example.com",
+        &["a@example.com".to_owned(), "b@example.com".to_owned()],
+        "Digest",
+        "hello\n.hidden",
+        1_792_000_000,
+    );
+
+    assert_eq!(
+        "From: clu@example.com\r\nTo: a@example.com, b@example.com\r\nSubject: Digest\r\n\
+        Date: Wed, 14 Oct 2026 17:46:40 +0000\r\n\
+        Content-Type: text/plain; charset=utf-8\r\n\r\nhello\r\n..hidden\r\n.\r\n",
+        message
+    );
+    assert_eq!("Thu, 01 Jan 1970 00:00:00 +0000", format_date(0));
+}
+
+#[test]
+fn validate_encode_header() {
+    assert_eq!("Digest", encode_header("Digest"));
+    assert_eq!("=?utf-8?B?RGlnZXN0IOKckw==?=", encode_header("Digest ✓"));
+
+    let encoded = encode_header(&"é".repeat(40));
+    let words: Vec<&str> = encoded.split("\r\n ").collect();
+    assert_eq!(2, words.len());
+    assert!(words.iter().all(|it| it.len() <= 75));
+}
@@ -0,0 +1,93 @@
+mod email;
+mod teams;
+
+pub use email::EmailNotifier;
+pub use teams::TeamsNotifier;
+
+/// Outcome of a single `run-migration`.
+#[derive(Debug, Default)]
+pub struct RunSummary {
+    pub migration: String,
+    pub total: usize,
+    pub pull_requests: Vec<String>,
+    pub errors: Vec<String>,
+    pub no_changes: Vec<String>,
+}
+
+impl RunSummary {
+    pub fn subject(&self) -> String {
+        format!(
+            "[clu] {}: run finished with {} errors",
+            self.migration,
+            self.errors.len()
+        )
+    }
+}
+
+/// Summary of how a migration changed since the last digest.
+#[derive(Debug, Default)]
+pub struct Digest {
+    pub migration: String,
+    pub newly_merged: Vec<String>,
+    pub newly_failing: Vec<String>,
+    pub stale: Vec<String>,
+    pub merged: usize,
+    pub total: usize,
+}
+
+impl Digest {
+    pub fn subject(&self) -> String {
+        format!(
+            "[clu] {}: {}/{} merged",
+            self.migration, self.merged, self.total
+        )
+    }
+
+    pub fn completion(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.merged as f64 * 100.0 / self.total as f64
+        }
+    }
+
+    pub fn render_text(&self) -> String {
+        format!(
+            "# {}
+
+{} of {} PR's merged ({:.0}% complete)
+
+## Newly Merged
+
+{}
+
+## Newly Failing
+
+{}
+
+## Stale
+
+{}
+",
+            self.migration,
+            self.merged,
+            self.total,
+            self.completion(),
+            render_list(&self.newly_merged),
+            render_list(&self.newly_failing),
+            render_list(&self.stale)
+        )
+    }
+}
+
+fn render_list(items: &[String]) -> String {
+    if items.is_empty() {
+        "None".to_owned()
+    } else {
+        items
+            .iter()
+            .map(|it| format!("- {}", it))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
@@ -0,0 +1,143 @@
+use anyhow::{bail, Result as AnyResult};
+use serde_json::{json, Value};
+use tracing::info;
+
+use super::{Digest, RunSummary};
+use crate::models::TeamsConfig;
+
+/// Posts adaptive cards to a Microsoft Teams incoming webhook.
+pub struct TeamsNotifier<'a> {
+    config: &'a TeamsConfig,
+    client: reqwest::Client,
+}
+
+impl<'a> TeamsNotifier<'a> {
+    pub fn new(config: &'a TeamsConfig) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub async fn send_digest(&self, digest: &Digest) -> AnyResult<()> {
+        let card = adaptive_card(
+            &digest.subject(),
+            vec![
+                (
+                    "Merged".to_owned(),
+                    format!("{}/{}", digest.merged, digest.total),
+                ),
+                (
+                    "Complete".to_owned(),
+                    format!("{:.0}%", digest.completion()),
+                ),
+            ],
+            vec![
+                ("Newly Merged", &digest.newly_merged),
+                ("Newly Failing", &digest.newly_failing),
+                ("Stale", &digest.stale),
+            ],
+        );
+        self.post(card).await
+    }
+
+    pub async fn send_run_summary(&self, summary: &RunSummary) -> AnyResult<()> {
+        let card = adaptive_card(
+            &summary.subject(),
+            vec![
+                ("Targets".to_owned(), summary.total.to_string()),
+                (
+                    "Pull Requests".to_owned(),
+                    summary.pull_requests.len().to_string(),
+                ),
+                ("Errors".to_owned(), summary.errors.len().to_string()),
+                (
+                    "No Changes".to_owned(),
+                    summary.no_changes.len().to_string(),
+                ),
+            ],
+            vec![
+                ("Pull Requests", &summary.pull_requests),
+                ("Errors", &summary.errors),
+                ("No Changes", &summary.no_changes),
+            ],
+        );
+        self.post(card).await
+    }
+
+    async fn post(&self, card: Value) -> AnyResult<()> {
+        let response = self
+            .client
+            .post(&self.config.webhook_url)
+            .json(&card)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            bail!(
+                "Teams webhook responded with {}: {}",
+                response.status(),
+                response.text().await.unwrap_or_default()
+            );
+        }
+
+        info!("Posted notification to Teams");
+        Ok(())
+    }
+}
+
+/// Wraps an adaptive card in the message envelope that incoming webhooks expect. Empty
+/// sections are left out to keep the card short.
+fn adaptive_card(
+    title: &str,
+    facts: Vec<(String, String)>,
+    sections: Vec<(&str, &Vec<String>)>,
+) -> Value {
+    let mut body = vec![
+        json!({
+            "type": "TextBlock",
+            "size": "Large",
+            "weight": "Bolder",
+            "text": title,
+            "wrap": true,
+        }),
+        json!({
+            "type": "FactSet",
+            "facts": facts
+                .into_iter()
+                .map(|(title, value)| json!({ "title": title, "value": value }))
+                .collect::<Vec<Value>>(),
+        }),
+    ];
+
+    for (heading, items) in sections {
+        if items.is_empty() {
+            continue;
+        }
+
+        body.push(json!({
+            "type": "TextBlock",
+            "weight": "Bolder",
+            "text": heading,
+            "separator": true,
+        }));
+        body.push(json!({
+            "type": "TextBlock",
+            "text": items.iter().map(|it| format!("- {}", it)).collect::<Vec<String>>().join("\n"),
+            "wrap": true,
+        }));
+    }
+
+    json!({
+        "type": "message",
+        "attachments": [{
+            "contentType": "application/vnd.microsoft.card.adaptive",
+            "content": {
+                "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+                "type": "AdaptiveCard",
+                "version": "1.4",
+                "body": body,
+            }
+        }]
+    })
+}
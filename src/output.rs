@@ -0,0 +1,48 @@
+use clap::ValueEnum;
+use serde::Serialize;
+
+use anyhow::Result as AnyResult;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Markdown meant to be read, mixed in with the logs.
+    Text,
+    /// JSON meant for scripts. It's the only thing written to stdout, the logs go to stderr.
+    Json,
+}
+
+impl OutputFormat {
+    pub fn is_json(&self) -> bool {
+        *self == OutputFormat::Json
+    }
+}
+
+pub fn print_json<T: Serialize>(value: &T) -> AnyResult<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// A row of comma separated values, quoting the fields that need it.
+pub fn csv_row(fields: &[&str]) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|field| {
+            if field.contains(|c| matches!(c, ',' | '"' | '\n' | '\r')) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect();
+
+    fields.join(",")
+}
+
+#[test]
+fn validate_csv_row() {
+    assert_eq!("clu,merged", csv_row(&["clu", "merged"]));
+    assert_eq!(
+        "\"Update, again\",\"say \"\"hi\"\"\"",
+        csv_row(&["Update, again", "say \"hi\""])
+    );
+}
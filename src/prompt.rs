@@ -0,0 +1,175 @@
+use anyhow::{bail, Result as AnyResult};
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::warn;
+
+use crate::models::DiffStats;
+use crate::steps::RepoCheck;
+
+/// What to do with a target whose steps are done, decided at the prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushDecision {
+    Push,
+    /// Leave the target without pushing it, the run goes on with the others.
+    Skip,
+    /// Stop the run. Targets that haven't been started aren't run.
+    Abort,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Answer {
+    Decision(PushDecision),
+    Shell,
+}
+
+/// Asks before each target is pushed, for `run-migration --interactive`, which doesn't draw the
+/// progress bars. The prompt is written to stderr, so it stays out of `--output json`.
+#[derive(Debug, Default)]
+pub struct PushPrompt {
+    aborted: AtomicBool,
+}
+
+impl PushPrompt {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::SeqCst)
+    }
+
+    /// Shows what the target changed and asks what to do with it. Opening a shell in the repo
+    /// asks again once the shell exits, commits made in it are pushed with the rest. The changes
+    /// are counted since `base`, the commit the branch was created from.
+    pub fn ask(
+        &self,
+        name: &str,
+        git_repo: &Path,
+        base: &str,
+        diff: Option<&DiffStats>,
+    ) -> PushDecision {
+        if self.is_aborted() {
+            return PushDecision::Abort;
+        }
+
+        let decision = read_decision(name, git_repo, base, diff);
+
+        if decision == PushDecision::Abort {
+            self.aborted.store(true, Ordering::SeqCst);
+        }
+        decision
+    }
+}
+
+/// Prints what's about to be done and asks to go ahead, before pushing or deleting across many
+/// repos. `yes` goes ahead without asking, and is needed when there's no terminal to ask on.
+pub fn confirm(summary: &str, yes: bool) -> AnyResult<()> {
+    if yes {
+        return Ok(());
+    }
+    if !std::io::stdin().is_terminal() {
+        bail!("{}\nPass --yes to go ahead without being asked", summary);
+    }
+
+    let mut stderr = std::io::stderr();
+    let _ = write!(stderr, "{}\nContinue? [y/N] ", summary);
+    let _ = stderr.flush();
+    let mut line = String::new();
+    std::io::stdin().lock().read_line(&mut line)?;
+    match is_yes(&line) {
+        true => Ok(()),
+        false => bail!("Cancelled, nothing was changed"),
+    }
+}
+
+fn is_yes(line: &str) -> bool {
+    matches!(line.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+fn read_decision(
+    name: &str,
+    git_repo: &Path,
+    base: &str,
+    diff: Option<&DiffStats>,
+) -> PushDecision {
+    let stdin = std::io::stdin();
+    let mut stderr = std::io::stderr();
+    let mut diff = diff.cloned();
+    loop {
+        let summary = match &diff {
+            Some(diff) => format!(
+                "{} files changed, {} insertions(+), {} deletions(-)",
+                diff.files_changed, diff.insertions, diff.deletions
+            ),
+            None => "the changes can't be counted".to_owned(),
+        };
+        let stat = RepoCheck::diff_stat_text(git_repo, base).unwrap_or_default();
+        let _ = write!(
+            stderr,
+            "\n{}: {}\n{}\n[p]ush, [s]kip, [a]bort the run, or [o]pen a shell in the repo? ",
+            name, summary, stat
+        );
+        let _ = stderr.flush();
+
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            // Without anyone to answer, nothing is pushed
+            Ok(0) | Err(_) => return PushDecision::Abort,
+            Ok(_) => {}
+        }
+        match parse_answer(&line) {
+            Some(Answer::Decision(decision)) => return decision,
+            Some(Answer::Shell) => {
+                open_shell(git_repo);
+                diff = RepoCheck::diff_stats_of(git_repo, base).ok();
+            }
+            None => {
+                let _ = writeln!(stderr, "`{}` isn't one of the choices", line.trim());
+            }
+        }
+    }
+}
+
+fn parse_answer(line: &str) -> Option<Answer> {
+    match line.trim().to_lowercase().as_str() {
+        "p" | "push" => Some(Answer::Decision(PushDecision::Push)),
+        "s" | "skip" => Some(Answer::Decision(PushDecision::Skip)),
+        "a" | "abort" => Some(Answer::Decision(PushDecision::Abort)),
+        "o" | "shell" => Some(Answer::Shell),
+        _ => None,
+    }
+}
+
+fn open_shell(git_repo: &Path) {
+    let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_owned());
+    let status = std::process::Command::new(&shell)
+        .current_dir(git_repo)
+        .status();
+    if let Err(e) = status {
+        warn!("Unable to start {}: {}", shell, e);
+    }
+}
+
+#[test]
+fn validate_parse_answer() {
+    assert_eq!(
+        Some(Answer::Decision(PushDecision::Push)),
+        parse_answer("p\n")
+    );
+    assert_eq!(
+        Some(Answer::Decision(PushDecision::Skip)),
+        parse_answer(" Skip ")
+    );
+    assert_eq!(
+        Some(Answer::Decision(PushDecision::Abort)),
+        parse_answer("a")
+    );
+    assert_eq!(Some(Answer::Shell), parse_answer("shell"));
+    assert_eq!(None, parse_answer("yes"));
+
+    assert!(is_yes("Y\n"));
+    assert!(is_yes("yes"));
+    assert!(!is_yes(""));
+    assert!(!is_yes("n"));
+}
@@ -0,0 +1,261 @@
+use anyhow::Result as AnyResult;
+use async_trait::async_trait;
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::{create_dir_all, read_dir, read_to_string, remove_file, rename, File};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use tracing::debug;
+
+use crate::models::TargetDescription;
+
+/// Hands out the targets of a migration to the workers running it.
+#[async_trait]
+pub trait JobQueue: Send + Sync {
+    /// Takes the next target to run that `runnable` accepts, `None` once there is nothing left.
+    /// Targets it doesn't accept are left for other workers.
+    async fn claim(
+        &self,
+        runnable: &(dyn for<'n> Fn(&'n str) -> bool + Sync),
+    ) -> AnyResult<Option<String>>;
+
+    /// Records the updated target once its run is done. Completing it again rewrites what was
+    /// recorded.
+    async fn complete(&self, name: &str, target: &TargetDescription) -> AnyResult<()>;
+
+    /// Gives a claimed target back without running it, so another worker can.
+    async fn release(&self, name: &str) -> AnyResult<()>;
+}
+
+/// Queue for a single `run-migration`, the results are written back by the run itself.
+pub struct LocalQueue {
+    pending: Mutex<VecDeque<String>>,
+}
+
+impl LocalQueue {
+    pub fn new<I: IntoIterator<Item = String>>(names: I) -> Self {
+        Self {
+            pending: Mutex::new(names.into_iter().collect()),
+        }
+    }
+}
+
+#[async_trait]
+impl JobQueue for LocalQueue {
+    async fn claim(
+        &self,
+        runnable: &(dyn for<'n> Fn(&'n str) -> bool + Sync),
+    ) -> AnyResult<Option<String>> {
+        let mut pending = self.pending.lock().unwrap();
+        while let Some(name) = pending.pop_front() {
+            if runnable(&name) {
+                return Ok(Some(name));
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn complete(&self, _name: &str, _target: &TargetDescription) -> AnyResult<()> {
+        Ok(())
+    }
+
+    async fn release(&self, _name: &str) -> AnyResult<()> {
+        Ok(())
+    }
+}
+
+/// Queue in a directory shared by every worker, like an NFS mount, so very large migrations
+/// can be split across machines.
+///
+/// Each target is a file in `pending`. A worker claims it by renaming it into `claimed`, which
+/// only one rename can win, and writes the updated target into `done` when it's finished. Claims
+/// of workers that died are put back with `requeue_stale`.
+pub struct DirectoryQueue {
+    root: PathBuf,
+}
+
+impl DirectoryQueue {
+    pub fn new(root: &Path) -> AnyResult<Self> {
+        for dir in &["pending", "claimed", "done"] {
+            create_dir_all(root.join(dir))?;
+        }
+
+        Ok(Self {
+            root: root.to_path_buf(),
+        })
+    }
+
+    /// Adds targets to the queue, returning how many weren't already queued or done.
+    pub fn enqueue<'a, I: IntoIterator<Item = &'a String>>(&self, names: I) -> AnyResult<usize> {
+        let mut added = 0;
+        for name in names {
+            let queued = ["pending", "claimed"]
+                .iter()
+                .any(|dir| self.root.join(dir).join(name).exists());
+            if queued || self.done_path(name).exists() {
+                continue;
+            }
+
+            File::create(self.root.join("pending").join(name))?;
+            added += 1;
+        }
+
+        Ok(added)
+    }
+
+    /// Puts targets claimed longer than `max_age` ago back into `pending`, for workers that died
+    /// before finishing them. Returns the names that were put back.
+    pub fn requeue_stale(&self, max_age: Duration) -> AnyResult<Vec<String>> {
+        let mut requeued = Vec::new();
+        for entry in read_dir(self.root.join("claimed"))? {
+            let entry = entry?;
+            let age = entry.metadata()?.modified()?.elapsed().unwrap_or_default();
+            if age < max_age {
+                continue;
+            }
+
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // The worker finished it or another `enqueue` put it back in the meantime
+            match rename(entry.path(), self.root.join("pending").join(&name)) {
+                Ok(_) => requeued.push(name),
+                Err(e) => debug!("Unable to requeue {}: {}", name, e),
+            }
+        }
+        requeued.sort();
+
+        Ok(requeued)
+    }
+
+    /// The targets that workers have finished.
+    pub fn results(&self) -> AnyResult<BTreeMap<String, TargetDescription>> {
+        let mut results = BTreeMap::new();
+        for entry in read_dir(self.root.join("done"))? {
+            let path = entry?.path();
+            let name = match path.file_stem() {
+                Some(name) if path.extension().map_or(false, |it| it == "toml") => {
+                    name.to_string_lossy().into_owned()
+                }
+                _ => continue,
+            };
+
+            results.insert(name, toml::from_str(&read_to_string(&path)?)?);
+        }
+
+        Ok(results)
+    }
+
+    fn done_path(&self, name: &str) -> PathBuf {
+        self.root.join("done").join(format!("{}.toml", name))
+    }
+}
+
+#[async_trait]
+impl JobQueue for DirectoryQueue {
+    async fn claim(
+        &self,
+        runnable: &(dyn for<'n> Fn(&'n str) -> bool + Sync),
+    ) -> AnyResult<Option<String>> {
+        let mut pending: Vec<String> = read_dir(self.root.join("pending"))?
+            .filter_map(|it| it.ok())
+            .map(|it| it.file_name().to_string_lossy().into_owned())
+            .filter(|it| runnable(it))
+            .collect();
+        pending.sort();
+
+        for name in pending {
+            // Another worker got to it first when the rename fails
+            let claimed = self.root.join("claimed").join(&name);
+            match rename(self.root.join("pending").join(&name), &claimed) {
+                Ok(_) => {
+                    // The rename keeps when the target was queued, `requeue_stale` needs when
+                    // it was claimed
+                    File::options()
+                        .write(true)
+                        .open(&claimed)?
+                        .set_modified(SystemTime::now())?;
+                    return Ok(Some(name));
+                }
+                Err(e) => debug!("Unable to claim {}: {}", name, e),
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn complete(&self, name: &str, target: &TargetDescription) -> AnyResult<()> {
+        // Written next to it and renamed into place, so `results` never reads half a file
+        let partial = self
+            .root
+            .join("done")
+            .join(format!("{}.toml.partial", name));
+        let mut done = File::create(&partial)?;
+        done.write_all(toml::to_string_pretty(target)?.as_bytes())?;
+        done.sync_all()?;
+        rename(&partial, self.done_path(name))?;
+        match remove_file(self.root.join("claimed").join(name)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn release(&self, name: &str) -> AnyResult<()> {
+        rename(
+            self.root.join("claimed").join(name),
+            self.root.join("pending").join(name),
+        )?;
+
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn validate_directory_queue() {
+    let root = std::env::temp_dir().join(format!("clu-queue-{}", std::process::id()));
+    let queue = DirectoryQueue::new(&root).unwrap();
+    let names = vec!["clu".to_owned(), "dummy-repo".to_owned()];
+
+    assert_eq!(2, queue.enqueue(&names).unwrap());
+    assert_eq!(0, queue.enqueue(&names).unwrap());
+
+    let any = |_: &str| true;
+    assert_eq!(None, queue.claim(&|_: &str| false).await.unwrap());
+    assert_eq!(Some("clu".to_owned()), queue.claim(&any).await.unwrap());
+    queue
+        .complete(
+            "clu",
+            &TargetDescription::new("git@github.com:ethankhall/clu.git"),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        Some("dummy-repo".to_owned()),
+        queue.claim(&any).await.unwrap()
+    );
+    assert_eq!(None, queue.claim(&any).await.unwrap());
+    queue.release("dummy-repo").await.unwrap();
+    assert_eq!(
+        Some("dummy-repo".to_owned()),
+        queue.claim(&any).await.unwrap()
+    );
+
+    assert!(queue
+        .requeue_stale(Duration::from_secs(3600))
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        vec!["dummy-repo"],
+        queue.requeue_stale(Duration::from_secs(0)).unwrap()
+    );
+    assert_eq!(
+        Some("dummy-repo".to_owned()),
+        queue.claim(&any).await.unwrap()
+    );
+
+    let results = queue.results().unwrap();
+    assert_eq!(vec!["clu"], results.keys().collect::<Vec<_>>());
+    assert_eq!(0, queue.enqueue(&names).unwrap());
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,433 @@
+use anyhow::Result as AnyResult;
+use futures::channel::mpsc;
+use futures::future::join_all;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use crate::github::GitHubHosts;
+use crate::migration::{
+    alias_duplicate_targets, find_duplicate_tasks, MigrationOutcome, MigrationStatus,
+    MigrationTask, RunSettings,
+};
+use crate::models::{
+    host_configs, CreatedPullRequest, MigrationDefinition, RunResult, TargetDescription,
+};
+use crate::steps::MigrationStepResult;
+
+/// Stops a run started with `run`. Targets that are already being migrated are finished,
+/// the ones that haven't started yet are skipped.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    pub github_token: String,
+    /// Directory the workspaces of the targets are created in.
+    pub work_dir: PathBuf,
+    /// How many targets are migrated at the same time.
+    pub concurrency: usize,
+    pub skip_push: bool,
+    pub skip_pull_request: bool,
+    pub dry_run: bool,
+    pub cancellation: CancellationToken,
+}
+
+impl RunOptions {
+    pub fn new<S: Into<String>, P: Into<PathBuf>>(github_token: S, work_dir: P) -> Self {
+        Self {
+            github_token: github_token.into(),
+            work_dir: work_dir.into(),
+            concurrency: 3,
+            skip_push: false,
+            skip_pull_request: false,
+            dry_run: false,
+            cancellation: CancellationToken::new(),
+        }
+    }
+}
+
+/// What happened to a target during a run, in the order it happened.
+#[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
+pub enum RunEvent {
+    Started {
+        target: String,
+    },
+    Finished {
+        target: String,
+        result: RunResult,
+        /// The PR that was created or updated.
+        pull_request: Option<CreatedPullRequest>,
+        /// Why the target failed, when it did.
+        error: Option<String>,
+    },
+    /// The target is marked as `skip`, or the run was cancelled before it started.
+    Skipped {
+        target: String,
+    },
+}
+
+#[derive(Debug)]
+pub struct RunReport {
+    /// The targets of the run, with the PR's and metrics of the run recorded like
+    /// `run-migration` records them in the migration file.
+    pub targets: BTreeMap<String, TargetDescription>,
+    /// How each target that was run ended.
+    pub results: BTreeMap<String, RunResult>,
+    pub cancelled: bool,
+}
+
+/// A run started by `run`. `events` ends once every target is done, then `report` resolves.
+pub struct MigrationRun {
+    pub events: mpsc::UnboundedReceiver<RunEvent>,
+    pub report: JoinHandle<AnyResult<RunReport>>,
+}
+
+/// Runs a migration without the CLI, for tools that start runs themselves.
+///
+/// ```no_run
+/// use clu::models::MigrationFile;
+/// use clu::run::{run, RunEvent, RunOptions};
+/// use futures::StreamExt;
+///
+/// # async fn example() -> anyhow::Result<()> {
+/// let file: MigrationFile = toml::from_str(&std::fs::read_to_string("migration.toml")?)?;
+/// let options = RunOptions::new(std::env::var("GITHUB_TOKEN")?, "work-dir");
+/// let cancellation = options.cancellation.clone();
+///
+/// let mut migration = run(file.definition, file.targets, options).await?;
+/// while let Some(event) = migration.events.next().await {
+///     match event {
+///         // Stop starting new targets after the first failure
+///         RunEvent::Finished { target, error: Some(error), .. } => {
+///             println!("{} failed: {}", target, error);
+///             cancellation.cancel();
+///         }
+///         RunEvent::Finished { target, result, .. } => println!("{}: {}", target, result),
+///         RunEvent::Started { .. } | RunEvent::Skipped { .. } => {}
+///     }
+/// }
+///
+/// let report = migration.report.await??;
+/// println!("{} targets were run", report.results.len());
+/// # Ok(())
+/// # }
+/// ```
+pub async fn run(
+    definition: MigrationDefinition,
+    targets: BTreeMap<String, TargetDescription>,
+    options: RunOptions,
+) -> AnyResult<MigrationRun> {
+    let github_hosts = GitHubHosts::new(
+        &options.github_token,
+        &host_configs(&definition.hosts, &targets),
+    )?
+    .with_max_prs_per_hour(definition.pr.max_prs_per_hour);
+    std::fs::create_dir_all(&options.work_dir)?;
+
+    let (sender, events) = mpsc::unbounded();
+    let report = tokio::spawn(async move {
+        run_targets(github_hosts, definition, targets, options, sender).await
+    });
+
+    Ok(MigrationRun { events, report })
+}
+
+async fn run_targets(
+    github_hosts: GitHubHosts,
+    definition: MigrationDefinition,
+    mut targets: BTreeMap<String, TargetDescription>,
+    options: RunOptions,
+    sender: mpsc::UnboundedSender<RunEvent>,
+) -> AnyResult<RunReport> {
+    let settings = RunSettings {
+        skip_pull_request: options.skip_pull_request,
+        skip_push: options.skip_push,
+        dry_run: options.dry_run,
+        work_dir: options.work_dir.clone(),
+        ..RunSettings::default()
+    };
+    let mut tasks = Vec::new();
+    for (name, target) in &targets {
+        tasks.push(
+            MigrationTask::prepare(&definition, &github_hosts, &settings, name, target).await?,
+        );
+    }
+
+    let duplicates = find_duplicate_tasks(&tasks);
+    for task in tasks.iter_mut() {
+        if let Some(primary) = duplicates.get(&task.pretty_name) {
+            warn!(
+                "{} pushes the same branch to the same repo as {}, skipping it",
+                task.pretty_name, primary
+            );
+            task.skip = true;
+        }
+    }
+
+    let cancellation = &options.cancellation;
+    let slots = Semaphore::new(options.concurrency.max(1));
+    let outcomes = join_all(
+        tasks
+            .iter()
+            .map(|task| run_task(task, &slots, cancellation, &sender)),
+    )
+    .await;
+
+    let mut results = BTreeMap::new();
+    for (name, outcome, pull_request) in outcomes.into_iter().flatten() {
+        let target = match targets.get_mut(&name) {
+            Some(target) => target,
+            None => {
+                warn!("{} was run, but isn't a target of the migration", name);
+                continue;
+            }
+        };
+
+        if pull_request.is_some() {
+            target.pull_request = pull_request;
+        }
+        if let MigrationStatus::Pushed(MigrationStepResult {
+            result: Ok(pushed), ..
+        }) = &outcome.status
+        {
+            target.pushed = Some(pushed.clone());
+        }
+        if !outcome.metrics.steps.is_empty() {
+            target.metrics = Some(outcome.metrics);
+        }
+        let result = outcome.status.run_result();
+        target.last_run = Some(result.clone());
+        results.insert(name, result);
+    }
+
+    for task in &tasks {
+        if let Some(target) = targets.get_mut(&task.pretty_name) {
+            target.metadata = task.metadata.clone();
+        }
+    }
+
+    alias_duplicate_targets(&mut targets, &duplicates);
+    for (duplicate, primary) in &duplicates {
+        if let Some(result) = results.get(primary).cloned() {
+            results.insert(duplicate.clone(), result);
+        }
+    }
+
+    Ok(RunReport {
+        targets,
+        results,
+        cancelled: cancellation.is_cancelled(),
+    })
+}
+
+async fn run_task(
+    task: &MigrationTask<'_>,
+    slots: &Semaphore,
+    cancellation: &CancellationToken,
+    sender: &mpsc::UnboundedSender<RunEvent>,
+) -> Option<(String, MigrationOutcome, Option<CreatedPullRequest>)> {
+    let target = task.pretty_name.clone();
+    // Skipped targets don't wait for a slot, and a run cancelled while waiting starts nothing
+    if task.skip || cancellation.is_cancelled() {
+        let _ = sender.unbounded_send(RunEvent::Skipped { target });
+        return None;
+    }
+    let _slot = slots.acquire().await.ok()?;
+    if cancellation.is_cancelled() {
+        let _ = sender.unbounded_send(RunEvent::Skipped { target });
+        return None;
+    }
+
+    let _ = sender.unbounded_send(RunEvent::Started {
+        target: target.clone(),
+    });
+    let outcome = task.run().await;
+    let (pull_request, error) = match &outcome.status {
+        MigrationStatus::PullRequest(result) => match &result.result {
+            Ok(pr) => (Some(pr.clone()), None),
+            Err(e) => (None, Some(e.to_string())),
+        },
+        MigrationStatus::EmptyResponse(result) => {
+            (None, result.result.as_ref().err().map(|e| e.to_string()))
+        }
+        MigrationStatus::Pushed(result) => {
+            (None, result.result.as_ref().err().map(|e| e.to_string()))
+        }
+        MigrationStatus::Patches(result) => {
+            (None, result.result.as_ref().err().map(|e| e.to_string()))
+        }
+    };
+    let _ = sender.unbounded_send(RunEvent::Finished {
+        target: target.clone(),
+        result: outcome.status.run_result(),
+        pull_request: pull_request.clone(),
+        error,
+    });
+
+    Some((target, outcome, pull_request))
+}
+
+#[tokio::test]
+async fn validate_run_targets() {
+    use futures::StreamExt;
+
+    let migration: crate::models::MigrationFile = toml::from_str(
+        r#"
+        [targets.clu]
+        repo = "git@github.com:ethankhall/clu.git"
+        forge = "none"
+
+        [targets.clu-renamed]
+        repo = "git@github.com:EthanKhall/CLU.git"
+        forge = "none"
+
+        [targets.clu-skipped]
+        repo = "git@github.com:ethankhall/clu.git"
+        forge = "none"
+        skip = true
+
+        [checkout]
+        branch-name = "clu/update"
+        pre-flight = "/usr/bin/true"
+
+        [pr]
+        title = "Update"
+        description = "Update"
+
+        [[steps]]
+        name = "update"
+        migration-script = "update.sh"
+        "#,
+    )
+    .unwrap();
+    let event_names = |events: Vec<RunEvent>| -> Vec<String> {
+        events
+            .into_iter()
+            .map(|event| match event {
+                RunEvent::Started { target } => format!("started {}", target),
+                RunEvent::Finished { target, result, .. } => {
+                    format!("finished {} at {}", target, result.step)
+                }
+                RunEvent::Skipped { target } => format!("skipped {}", target),
+            })
+            .collect()
+    };
+    // The work dir doesn't exist, so the targets that are run fail before cloning anything
+    let work_dir = PathBuf::from("/nonexistent/clu-run");
+
+    let mut options = RunOptions::new("token", &work_dir);
+    options.concurrency = 1;
+    let (sender, events) = mpsc::unbounded();
+    let report = run_targets(
+        GitHubHosts::new("token", &BTreeMap::new()).unwrap(),
+        migration.definition.clone(),
+        migration.targets.clone(),
+        options,
+        sender,
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        vec![
+            "started clu",
+            "finished clu at init",
+            "skipped clu-renamed",
+            "skipped clu-skipped",
+        ],
+        event_names(events.collect().await)
+    );
+    assert!(!report.cancelled);
+    // The duplicate gets the result of the target it duplicates, the skipped one none
+    assert_eq!(
+        vec!["clu", "clu-renamed"],
+        report.results.keys().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        crate::models::RunOutcome::Failed,
+        report.results["clu-renamed"].outcome
+    );
+    assert_eq!(
+        Some("init"),
+        report.targets["clu"]
+            .last_run
+            .as_ref()
+            .map(|it| it.step.as_str())
+    );
+    assert!(report.targets["clu-skipped"].last_run.is_none());
+
+    let github_hosts = GitHubHosts::new("token", &BTreeMap::new()).unwrap();
+    let settings = RunSettings {
+        work_dir,
+        ..RunSettings::default()
+    };
+    let mut tasks = Vec::new();
+    for (name, target) in &migration.targets {
+        tasks.push(
+            MigrationTask::prepare(
+                &migration.definition,
+                &github_hosts,
+                &settings,
+                name,
+                target,
+            )
+            .await
+            .unwrap(),
+        );
+    }
+    let (clu, skipped) = (&tasks[0], &tasks[2]);
+
+    let (sender, events) = mpsc::unbounded();
+    let slots = Semaphore::new(1);
+    let cancellation = CancellationToken::new();
+
+    // Skipped targets don't wait for the slot that's taken
+    let held = slots.acquire().await.unwrap();
+    assert!(run_task(skipped, &slots, &cancellation, &sender)
+        .await
+        .is_none());
+
+    // Cancelling while waiting for a slot starts nothing once the slot frees up
+    {
+        let waiting = run_task(clu, &slots, &cancellation, &sender);
+        futures::pin_mut!(waiting);
+        assert!(futures::poll!(&mut waiting).is_pending());
+        cancellation.cancel();
+        drop(held);
+        assert!(waiting.await.is_none());
+    }
+
+    // Once cancelled, targets are skipped without waiting for a slot
+    let _held = slots.acquire().await.unwrap();
+    assert!(run_task(clu, &slots, &cancellation, &sender)
+        .await
+        .is_none());
+
+    drop(sender);
+    assert_eq!(
+        vec!["skipped clu-skipped", "skipped clu", "skipped clu"],
+        event_names(events.collect().await)
+    );
+}
@@ -0,0 +1,554 @@
+use anyhow::Result as AnyResult;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{create_dir_all, read_dir, read_to_string, remove_file, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+use crate::github::PullStatus;
+use crate::glob::Glob;
+use crate::models::{
+    CreatedIssue, CreatedPullRequest, MigrationFile, PatchSeries, PushedBranch, ReviewTracking,
+    RunResult, StatusObservation, TargetDescription, TargetMetrics,
+};
+
+/// What clu records about a target as it runs, kept in its own file when the migration has a
+/// `state-dir`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+struct TargetState {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pull_request: Option<CreatedPullRequest>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    metrics: Option<TargetMetrics>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tracking_issue: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    manual_issue: Option<CreatedIssue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_status: Option<PullStatus>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    status_history: Vec<StatusObservation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reviews: Option<ReviewTracking>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    runs: BTreeMap<String, RunResult>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_run: Option<RunResult>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    metadata: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pushed: Option<PushedBranch>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    patches: Option<PatchSeries>,
+}
+
+impl TargetState {
+    fn take(target: &mut TargetDescription) -> Self {
+        Self {
+            pull_request: target.pull_request.take(),
+            metrics: target.metrics.take(),
+            tracking_issue: target.tracking_issue.take(),
+            manual_issue: target.manual_issue.take(),
+            last_status: target.last_status.take(),
+            status_history: std::mem::take(&mut target.status_history),
+            reviews: target.reviews.take(),
+            runs: std::mem::take(&mut target.runs),
+            last_run: target.last_run.take(),
+            metadata: std::mem::take(&mut target.metadata),
+            pushed: target.pushed.take(),
+            patches: target.patches.take(),
+        }
+    }
+
+    fn restore(self, target: &mut TargetDescription) {
+        target.pull_request = self.pull_request;
+        target.metrics = self.metrics;
+        target.tracking_issue = self.tracking_issue;
+        target.manual_issue = self.manual_issue;
+        target.last_status = self.last_status;
+        target.status_history = self.status_history;
+        target.reviews = self.reviews;
+        target.runs = self.runs;
+        target.last_run = self.last_run;
+        target.metadata = self.metadata;
+        target.pushed = self.pushed;
+        target.patches = self.patches;
+    }
+}
+
+/// Reads a migration file, along with the state of its targets when it has a `state-dir`.
+pub fn load_migration(path: &str) -> AnyResult<MigrationFile> {
+    load_migration_matching(path, |_, _| true)
+}
+
+/// Like `load_migration`, only reading the state of the targets `wanted` picks, so commands
+/// that work on a few targets of a large migration don't read every state file. The others are
+/// in `unloaded`, and keep their state when the migration is saved.
+pub fn load_migration_matching<F>(path: &str, wanted: F) -> AnyResult<MigrationFile>
+where
+    F: Fn(&str, &TargetDescription) -> bool,
+{
+    let mut migration: MigrationFile = toml::from_str(&read_to_string(path)?)?;
+
+    if let Some(state_dir) = state_dir(path, &migration) {
+        for (name, target) in migration.targets.iter_mut() {
+            if !wanted(name, target) {
+                migration.unloaded.insert(name.clone());
+                continue;
+            }
+            let state_file = state_dir.join(state_file_name(name));
+            if state_file.exists() {
+                let state: TargetState = toml::from_str(&read_to_string(&state_file)?)?;
+                state.restore(target);
+            }
+        }
+    }
+
+    Ok(migration)
+}
+
+/// Writes a migration file back. With a `state-dir`, the state of each target goes into
+/// `<state-dir>/<name>.toml` so the migration file only holds what people edit. Only the
+/// files that changed are rewritten, and the files of targets that were removed from the
+/// migration are deleted.
+pub fn save_migration(path: &str, migration: &MigrationFile) -> AnyResult<()> {
+    let state_dir = match state_dir(path, migration) {
+        Some(dir) => dir,
+        None => return write_if_changed(Path::new(path), &toml::to_string_pretty(migration)?),
+    };
+
+    create_dir_all(&state_dir)?;
+    let mut definition = migration.clone();
+    for (name, target) in definition.targets.iter_mut() {
+        if !migration.unloaded.contains(name) {
+            save_target_state(&state_dir, name, target)?;
+        }
+        TargetState::take(target);
+    }
+    write_if_changed(Path::new(path), &toml::to_string_pretty(&definition)?)?;
+
+    let names: BTreeSet<String> = migration
+        .targets
+        .keys()
+        .map(|it| state_file_name(it))
+        .collect();
+    for entry in read_dir(&state_dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if file_name.ends_with(".toml") && !names.contains(&file_name) {
+            debug!("Removing state of {}, it's no longer a target", file_name);
+            remove_file(entry.path())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The targets a `run-migration` has finished so far, kept next to the migration file until
+/// the run is done. When it's still there the run was interrupted, and `clu resume` runs the
+/// targets that aren't in it.
+///
+/// Each target is appended as it finishes, so checkpointing doesn't get slower as the run goes
+/// on. The migration file itself is only written once the run is done.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct Checkpoint {
+    /// Seconds since the Unix epoch.
+    pub started_at: u64,
+    /// The finished targets, as they were when they finished.
+    #[serde(default)]
+    pub targets: BTreeMap<String, TargetDescription>,
+}
+
+/// One target appended to a checkpoint.
+#[derive(Serialize)]
+struct CheckpointEntry<'a> {
+    targets: BTreeMap<&'a str, &'a TargetDescription>,
+}
+
+fn checkpoint_path(path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.checkpoint", path))
+}
+
+/// The checkpoint of the interrupted run of a migration, if there is one.
+pub fn load_checkpoint(path: &str) -> AnyResult<Option<Checkpoint>> {
+    let checkpoint = checkpoint_path(path);
+    if !checkpoint.exists() {
+        return Ok(None);
+    }
+
+    Ok(Some(toml::from_str(&read_to_string(checkpoint)?)?))
+}
+
+/// Starts the checkpoint of a new run, replacing the one of an interrupted run.
+pub fn start_checkpoint(path: &str, started_at: u64) -> AnyResult<()> {
+    let checkpoint = Checkpoint {
+        started_at,
+        ..Checkpoint::default()
+    };
+    let mut file = File::create(checkpoint_path(path))?;
+    file.write_all(toml::to_string_pretty(&checkpoint)?.as_bytes())?;
+
+    Ok(())
+}
+
+/// Appends a finished target to the checkpoint. With a `state-dir` its state file is written
+/// too, the other targets aren't touched.
+pub fn checkpoint_target(path: &str, migration: &MigrationFile, name: &str) -> AnyResult<()> {
+    let target = match migration.targets.get(name) {
+        Some(target) => target,
+        None => return Ok(()),
+    };
+
+    let entry = CheckpointEntry {
+        targets: BTreeMap::from([(name, target)]),
+    };
+    let mut file = OpenOptions::new()
+        .append(true)
+        .open(checkpoint_path(path))?;
+    file.write_all(format!("\n{}", toml::to_string_pretty(&entry)?).as_bytes())?;
+
+    if let Some(state_dir) = state_dir(path, migration) {
+        create_dir_all(&state_dir)?;
+        save_target_state(&state_dir, name, target)?;
+    }
+
+    Ok(())
+}
+
+/// Called once the run is done, so it isn't resumed.
+pub fn remove_checkpoint(path: &str) -> AnyResult<()> {
+    let checkpoint = checkpoint_path(path);
+    if checkpoint.exists() {
+        remove_file(checkpoint)?;
+    }
+
+    Ok(())
+}
+
+/// The runs of a migration, kept next to the migration file as `<file>.history`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct RunHistory {
+    #[serde(default)]
+    pub runs: Vec<RunRecord>,
+}
+
+/// One `run-migration`, with how each target it ran ended.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct RunRecord {
+    /// The id sent in the `X-Clu-Run-Id` header.
+    pub id: String,
+    /// Seconds since the Unix epoch.
+    pub started_at: u64,
+    pub finished_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default)]
+    pub targets: BTreeMap<String, RunResult>,
+}
+
+fn history_path(path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.history", path))
+}
+
+/// The runs of the migration, oldest first. Empty before the first run.
+pub fn load_history(path: &str) -> AnyResult<RunHistory> {
+    let history = history_path(path);
+    if !history.exists() {
+        return Ok(RunHistory::default());
+    }
+
+    Ok(toml::from_str(&read_to_string(history)?)?)
+}
+
+pub fn record_run(path: &str, record: RunRecord) -> AnyResult<()> {
+    let mut history = load_history(path)?;
+    history.runs.push(record);
+    write_if_changed(&history_path(path), &toml::to_string_pretty(&history)?)
+}
+
+/// The migration files named by `--migration-definition`, in order and without the same file
+/// twice. A directory stands for the `.toml` files in it, and a pattern like `migrations/*.toml`
+/// for the files its name matches. The results of shards are left out of both.
+pub fn expand_migration_definitions(paths: &[String]) -> AnyResult<Vec<String>> {
+    let mut expanded = Vec::new();
+    for path in paths {
+        let as_path = Path::new(path);
+        let file_name = as_path
+            .file_name()
+            .map(|it| it.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let (dir, pattern) = if as_path.is_dir() {
+            (as_path, Glob::new("*.toml"))
+        } else if file_name.contains(['*', '?']) {
+            (
+                as_path.parent().unwrap_or_else(|| Path::new("")),
+                Glob::new(file_name),
+            )
+        } else {
+            expanded.push(path.clone());
+            continue;
+        };
+
+        let mut found = Vec::new();
+        let listed = match dir.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => dir,
+        };
+        for entry in read_dir(listed)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if entry.path().is_file() && pattern.is_match(&name) && !name.contains(".shard-") {
+                found.push(dir.join(&name).to_string_lossy().into_owned());
+            }
+        }
+        if found.is_empty() {
+            anyhow::bail!("There are no migration files in {}", path);
+        }
+        found.sort();
+        expanded.extend(found);
+    }
+
+    let mut seen = BTreeSet::new();
+    expanded.retain(|it| seen.insert(it.clone()));
+    Ok(expanded)
+}
+
+/// Writes `<state-dir>/<name>.toml` with what clu recorded about the target, removing it when
+/// nothing was.
+fn save_target_state(state_dir: &Path, name: &str, target: &TargetDescription) -> AnyResult<()> {
+    let state = toml::to_string_pretty(&TargetState::take(&mut target.clone()))?;
+    let state_file = state_dir.join(state_file_name(name));
+    if !state.trim().is_empty() {
+        write_if_changed(&state_file, &state)?;
+    } else if state_file.exists() {
+        remove_file(&state_file)?;
+    }
+
+    Ok(())
+}
+
+/// The name of the state file of a target. Anything but letters, digits, `-`, `_` and `.` is
+/// percent-encoded, like the `/` of `owner/repo`, so every target gets a file inside the
+/// `state-dir`. A leading `.` is encoded too, so `..` can't escape it.
+fn state_file_name(name: &str) -> String {
+    let mut file_name = String::new();
+    for (index, byte) in name.bytes().enumerate() {
+        match byte {
+            b'.' if index == 0 => file_name.push_str("%2E"),
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' => {
+                file_name.push(byte as char)
+            }
+            _ => file_name.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    format!("{}.toml", file_name)
+}
+
+/// `state-dir` is relative to the migration file.
+fn state_dir(path: &str, migration: &MigrationFile) -> Option<PathBuf> {
+    let state_dir = migration.definition.state_dir.as_ref()?;
+    let parent = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+    Some(parent.join(state_dir))
+}
+
+fn write_if_changed(path: &Path, contents: &str) -> AnyResult<()> {
+    if path.exists() && read_to_string(path)? == contents {
+        return Ok(());
+    }
+
+    let mut file = File::create(path)?;
+    file.write_all(contents.as_bytes())?;
+    Ok(())
+}
+
+#[test]
+fn validate_partitioned_state() {
+    let root = std::env::temp_dir().join(format!("clu-state-{}", std::process::id()));
+    create_dir_all(&root).unwrap();
+    let path = root.join("migration.toml");
+    let path = path.to_str().unwrap();
+
+    std::fs::write(
+        path,
+        r#"state-dir = "state"
+
+[checkout]
+branch-name = "upgrade"
+pre-flight = "/usr/bin/true"
+
+[pr]
+title = "Upgrade"
+description = "Upgrade"
+
+[[steps]]
+name = "upgrade"
+migration-script = "/usr/bin/true"
+
+[targets.clu]
+repo = "git@github.com:ethankhall/clu.git"
+
+[targets.dummy]
+repo = "git@github.com:ethankhall/dummy.git"
+
+[targets."ethankhall/clu"]
+repo = "git@github.com:ethankhall/clu.git"
+"#,
+    )
+    .unwrap();
+    std::fs::create_dir_all(root.join("state")).unwrap();
+    std::fs::write(root.join("state").join("removed.toml"), "").unwrap();
+
+    let mut migration = load_migration(path).unwrap();
+    migration.targets.get_mut("clu").unwrap().last_status = Some(PullStatus::Merged);
+    save_migration(path, &migration).unwrap();
+
+    assert!(!read_to_string(path).unwrap().contains("last-status"));
+    assert!(root.join("state").join("clu.toml").exists());
+    assert!(!root.join("state").join("dummy.toml").exists());
+    assert!(!root.join("state").join("removed.toml").exists());
+
+    let mut migration = load_migration(path).unwrap();
+    assert_eq!(
+        Some(PullStatus::Merged),
+        migration.targets["clu"].last_status
+    );
+
+    migration
+        .targets
+        .get_mut("ethankhall/clu")
+        .unwrap()
+        .tracking_issue = Some(4);
+    save_migration(path, &migration).unwrap();
+    assert!(root.join("state").join("ethankhall%2Fclu.toml").exists());
+    assert_eq!("%2E..toml", state_file_name(".."));
+
+    // Only the state of the targets that were picked is read, the rest is kept as it is
+    let mut migration = load_migration_matching(path, |name, _| name == "dummy").unwrap();
+    assert_eq!(None, migration.targets["clu"].last_status);
+    assert!(migration.unloaded.contains("clu"));
+    migration.targets.get_mut("dummy").unwrap().tracking_issue = Some(5);
+    save_migration(path, &migration).unwrap();
+
+    let migration = load_migration(path).unwrap();
+    assert_eq!(
+        Some(PullStatus::Merged),
+        migration.targets["clu"].last_status
+    );
+    assert_eq!(Some(4), migration.targets["ethankhall/clu"].tracking_issue);
+    assert_eq!(Some(5), migration.targets["dummy"].tracking_issue);
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn validate_checkpoint() {
+    let path = std::env::temp_dir().join(format!("clu-checkpoint-{}.toml", std::process::id()));
+    let path = path.to_str().unwrap();
+    assert!(load_checkpoint(path).unwrap().is_none());
+
+    let mut migration: MigrationFile = toml::from_str(
+        r#"
+        [targets.clu]
+        repo = "git@github.com:ethankhall/clu.git"
+
+        [targets."ethankhall.dummy"]
+        repo = "git@github.com:ethankhall/dummy.git"
+
+        [checkout]
+        branch-name = "upgrade"
+        pre-flight = "/usr/bin/true"
+
+        [pr]
+        title = "Upgrade"
+        description = "Upgrade"
+
+        [[steps]]
+        name = "upgrade"
+        migration-script = "/usr/bin/true"
+        "#,
+    )
+    .unwrap();
+    start_checkpoint(path, 10).unwrap();
+    migration.targets.get_mut("clu").unwrap().last_status = Some(PullStatus::Merged);
+    checkpoint_target(path, &migration, "clu").unwrap();
+    checkpoint_target(path, &migration, "ethankhall.dummy").unwrap();
+
+    let checkpoint = load_checkpoint(path).unwrap().unwrap();
+    assert_eq!(10, checkpoint.started_at);
+    assert_eq!(
+        vec!["clu", "ethankhall.dummy"],
+        checkpoint.targets.keys().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        Some(PullStatus::Merged),
+        checkpoint.targets["clu"].last_status
+    );
+
+    remove_checkpoint(path).unwrap();
+    assert!(load_checkpoint(path).unwrap().is_none());
+}
+
+#[test]
+fn validate_history() {
+    let path = std::env::temp_dir().join(format!("clu-history-{}.toml", std::process::id()));
+    let path = path.to_str().unwrap();
+    assert!(load_history(path).unwrap().runs.is_empty());
+
+    let mut record = RunRecord {
+        id: "10-1".to_owned(),
+        started_at: 10,
+        finished_at: 20,
+        label: None,
+        targets: BTreeMap::new(),
+    };
+    record_run(path, record.clone()).unwrap();
+    record.id = "30-2".to_owned();
+    record.label = Some("retry".to_owned());
+    record_run(path, record.clone()).unwrap();
+
+    let history = load_history(path).unwrap();
+    assert_eq!(2, history.runs.len());
+    assert_eq!("10-1", history.runs[0].id);
+    assert_eq!(record, history.runs[1]);
+
+    std::fs::remove_file(history_path(path)).unwrap();
+}
+
+#[test]
+fn validate_expand_migration_definitions() {
+    let root = std::env::temp_dir().join(format!("clu-definitions-{}", std::process::id()));
+    create_dir_all(&root).unwrap();
+    for name in [
+        "billing.toml",
+        "api.toml",
+        "api.shard-1-of-2.toml",
+        "notes.md",
+    ] {
+        std::fs::write(root.join(name), "").unwrap();
+    }
+    let dir = root.to_str().unwrap().to_owned();
+    let path = |name: &str| root.join(name).to_str().unwrap().to_owned();
+
+    assert_eq!(
+        vec![path("api.toml"), path("billing.toml")],
+        expand_migration_definitions(&[dir.clone()]).unwrap()
+    );
+    assert_eq!(
+        vec![path("billing.toml"), path("api.toml")],
+        expand_migration_definitions(&[path("b*.toml"), path("a?i.toml"), path("billing.toml")])
+            .unwrap()
+    );
+    assert_eq!(
+        vec!["other.toml".to_owned()],
+        expand_migration_definitions(&["other.toml".to_owned()]).unwrap()
+    );
+    assert!(expand_migration_definitions(&[path("*.yaml")]).is_err());
+
+    std::fs::remove_dir_all(root).unwrap();
+}
@@ -1,41 +1,184 @@
 use anyhow::Result as AnyResult;
 use async_trait::async_trait;
-use git2::{Repository, StatusOptions};
+use git2::{IndexEntryExtendedFlag, Repository, Status, StatusOptions};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use tracing::{info, instrument};
 
 use super::{MigrationStep, MigrationStepResult};
+use crate::codecommit::{codecommit_region, CODECOMMIT_CREDENTIAL_HELPER};
 use crate::github::GitHubRepo;
 use crate::migration::{MigrationError, MigrationTask};
+use crate::models::{DiffStats, EmailDefinition, PatchSeries};
 use crate::workspace::Workspace;
 
 pub struct CloneRepoStep<'a> {
     branch_name: &'a str,
     repo: &'a GitHubRepo,
+    lfs_smudge: bool,
+    sparse_paths: &'a [String],
+    base_ref: Option<&'a str>,
+    base_sha: Option<&'a str>,
 }
 
 #[async_trait]
 impl<'a> MigrationStep<()> for CloneRepoStep<'a> {
     #[instrument(name = "clone", skip(self, workspace), fields(workspace_name = %workspace.workspace_name, repo = %self.repo))]
     async fn execute_step(&self, workspace: &mut Workspace) -> MigrationStepResult<()> {
-        match self.clone_repo(workspace).await {
-            Ok(_) => MigrationStepResult::success("clone"),
-            Err(e) => MigrationStepResult::failure(
+        if let Err(e) = self.clone_repo(workspace).await {
+            return MigrationStepResult::failure(
                 "clone",
                 MigrationError::UnableToCheckoutRepo {
                     repo: format!("{}/{}", self.repo.owner, self.repo.repo),
                     source: e,
                 },
-            ),
+            );
+        }
+
+        match self.setup_lfs(workspace).await {
+            Ok(_) => MigrationStepResult::success("clone"),
+            Err(e @ MigrationError::LfsNotSupported { .. }) => {
+                MigrationStepResult::failure("lfs-not-supported", e)
+            }
+            Err(e) => MigrationStepResult::failure("lfs", e),
         }
     }
 }
 
 impl<'a> CloneRepoStep<'a> {
-    pub fn new(branch_name: &'a str, repo: &'a GitHubRepo) -> Self {
-        Self { branch_name, repo }
+    pub fn new(branch_name: &'a str, repo: &'a GitHubRepo, lfs_smudge: bool) -> Self {
+        Self {
+            branch_name,
+            repo,
+            lfs_smudge,
+            sparse_paths: &[],
+            base_ref: None,
+            base_sha: None,
+        }
+    }
+
+    /// Creates the branch from `base_sha`, or the head of `base_ref`, instead of the head of
+    /// the default branch.
+    pub fn with_base(mut self, base_ref: Option<&'a str>, base_sha: Option<&'a str>) -> Self {
+        self.base_ref = base_ref;
+        self.base_sha = base_sha;
+        self
+    }
+
+    /// What the migration branch is created from, as understood by `git rev-parse`.
+    fn start_point(&self) -> String {
+        match (self.base_sha, self.base_ref) {
+            (Some(sha), _) => sha.to_owned(),
+            (None, Some(base_ref)) => format!("origin/{}", base_ref),
+            (None, None) => "HEAD".to_owned(),
+        }
+    }
+
+    /// HTTPS clones of CodeCommit are signed by the AWS CLI's credential helper. It's kept in
+    /// the clone's config so the push is signed too. `codecommit::` URLs sign themselves through
+    /// `git-remote-codecommit`.
+    fn clone_config(&self) -> String {
+        if codecommit_region(&self.repo.host).is_none()
+            || !self.repo.clone_url.starts_with("https://")
+        {
+            return String::new();
+        }
+
+        format!(
+            " -c {} -c credential.UseHttpPath=true",
+            shell_quote(&format!(
+                "credential.helper={}",
+                CODECOMMIT_CREDENTIAL_HELPER
+            ))
+        )
+    }
+
+    /// Only check out the files matching these gitignore style patterns.
+    pub fn with_sparse_paths(mut self, sparse_paths: &'a [String]) -> Self {
+        self.sparse_paths = sparse_paths;
+        self
+    }
+
+    /// Blobs are fetched on demand, so only the files in `sparse_paths` are downloaded.
+    /// `git sparse-checkout` isn't used because it turns on a repo extension that libgit2
+    /// can't open.
+    async fn sparse_clone_repo(&self, workspace: &mut Workspace) -> AnyResult<()> {
+        let git_repo = workspace.root_dir.join("repo");
+
+        info!(
+            "Cloning {} into {} with sparse paths {:?}",
+            workspace.workspace_name,
+            git_repo.to_str().unwrap(),
+            self.sparse_paths
+        );
+
+        workspace
+            .run_command_successfully(&format!(
+                "GIT_LFS_SKIP_SMUDGE=1 git clone{} --filter=blob:none --no-checkout {} {}",
+                self.clone_config(),
+                &self.repo.clone_url,
+                git_repo.to_str().unwrap()
+            ))
+            .await?;
+        workspace.set_working_dir("repo");
+
+        let repo = Repository::open(git_repo.to_str().unwrap())?;
+        repo.config()?.set_bool("core.sparseCheckout", true)?;
+        repo.config()?.set_str("push.default", "current")?;
+        std::fs::write(
+            repo.path().join("info").join("sparse-checkout"),
+            format!("{}\n", self.sparse_paths.join("\n")),
+        )?;
+
+        info!("Creating {} branch", &self.branch_name);
+        workspace
+            .run_command_successfully(&format!(
+                "GIT_LFS_SKIP_SMUDGE=1 git checkout -B {} {}",
+                self.branch_name,
+                self.start_point()
+            ))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Repos with LFS files need `git lfs` installed in the clone, otherwise the pre-push hook
+    /// is missing and changed LFS files are pushed as plain blobs.
+    async fn setup_lfs(&self, workspace: &mut Workspace) -> Result<(), MigrationError> {
+        if !uses_lfs(&workspace.working_dir) {
+            return Ok(());
+        }
+
+        if !workspace
+            .run_command("git lfs version")
+            .await?
+            .status
+            .success()
+        {
+            return Err(MigrationError::LfsNotSupported {
+                repo: self.repo.to_string(),
+            });
+        }
+
+        if self.lfs_smudge {
+            workspace
+                .run_command_successfully("git lfs install --local")
+                .await?;
+            workspace.run_command_successfully("git lfs pull").await?;
+        } else {
+            workspace
+                .run_command_successfully("git lfs install --local --skip-smudge")
+                .await?;
+        }
+
+        Ok(())
     }
 
     async fn clone_repo(&self, workspace: &mut Workspace) -> AnyResult<()> {
+        if !self.sparse_paths.is_empty() {
+            return self.sparse_clone_repo(workspace).await;
+        }
+
         let git_repo = workspace.root_dir.join("repo");
 
         info!(
@@ -46,7 +189,8 @@ impl<'a> CloneRepoStep<'a> {
 
         workspace
             .run_command_successfully(&format!(
-                "git clone {} {}",
+                "GIT_LFS_SKIP_SMUDGE=1 git clone{} {} {}",
+                self.clone_config(),
                 &self.repo.clone_url,
                 git_repo.to_str().unwrap()
             ))
@@ -55,7 +199,10 @@ impl<'a> CloneRepoStep<'a> {
 
         info!("Creating {} branch", &self.branch_name);
         let repo = Repository::open(git_repo.to_str().unwrap())?;
-        repo.branch(self.branch_name, &repo.head()?.peel_to_commit()?, true)?;
+        let start = repo
+            .revparse_single(&self.start_point())?
+            .peel_to_commit()?;
+        repo.branch(self.branch_name, &start, true)?;
 
         repo.config()?.set_str("push.default", "current")?;
 
@@ -71,41 +218,471 @@ impl<'a> CloneRepoStep<'a> {
 
 impl<'a> From<&'a MigrationTask<'_>> for CloneRepoStep<'a> {
     fn from(task: &'a MigrationTask) -> Self {
-        Self::new(&task.definition.checkout.branch_name, &task.repo)
+        Self::new(
+            &task.definition.checkout.branch_name,
+            &task.repo,
+            !task.definition.checkout.skip_lfs_smudge,
+        )
+        .with_sparse_paths(&task.definition.checkout.sparse_paths)
+        .with_base(task.base_ref.as_deref(), task.base_sha.as_deref())
     }
 }
 
-pub struct PushRepoStep {}
+/// Only the `.gitattributes` at the root of the repo is checked.
+fn uses_lfs(repo_dir: &Path) -> bool {
+    match std::fs::read_to_string(repo_dir.join(".gitattributes")) {
+        Ok(attributes) => attributes.contains("filter=lfs"),
+        Err(_) => false,
+    }
+}
+
+pub struct PushRepoStep<'a> {
+    remote: Option<(&'a str, &'a str)>,
+}
 
-impl PushRepoStep {
+impl<'a> PushRepoStep<'a> {
     pub fn new() -> Self {
-        Self {}
+        Self { remote: None }
+    }
+
+    /// Pushes `branch` to `remote`, rather than to the repo it was cloned from.
+    pub fn with_remote(mut self, remote: Option<&'a str>, branch: &'a str) -> Self {
+        self.remote = remote.map(|it| (it, branch));
+        self
     }
 }
 
-impl Default for PushRepoStep {
+impl<'a> Default for PushRepoStep<'a> {
     fn default() -> Self {
         Self::new()
     }
 }
 
 #[async_trait]
-impl MigrationStep<()> for PushRepoStep {
+impl<'a> MigrationStep<()> for PushRepoStep<'a> {
     #[instrument(name = "push", skip(self, workspace), fields(workspace_name = %workspace.workspace_name))]
     async fn execute_step(&self, workspace: &mut Workspace) -> MigrationStepResult<()> {
+        let command = match self.remote {
+            // There's no remote tracking branch of the other remote for a lease to check
+            Some((remote, branch)) => {
+                format!("git push --force {} {}", shell_quote(remote), branch)
+            }
+            None => "git push --force-with-lease".to_owned(),
+        };
+        match workspace.run_command_successfully(&command).await {
+            Err(e) => MigrationStepResult::failure("push", MigrationError::CommandError(e)),
+            Ok(_) => MigrationStepResult::success("push"),
+        }
+    }
+}
+
+/// Pushes the migration for review on Gerrit. The commits of the branch are squashed into one
+/// change, with the PR's title and body as the message and a `Change-Id` trailer, and pushed to
+/// `refs/for/<base>` with the branch as the topic.
+pub struct PushForReviewStep<'a> {
+    change_id: &'a str,
+    branch: &'a str,
+    title: &'a str,
+    body: &'a str,
+    hashtags: &'a [String],
+    reviewers: &'a [String],
+    base_ref: Option<&'a str>,
+    base_sha: Option<&'a str>,
+}
+
+impl<'a> PushForReviewStep<'a> {
+    pub fn new(change_id: &'a str, branch: &'a str, title: &'a str, body: &'a str) -> Self {
+        Self {
+            change_id,
+            branch,
+            title,
+            body,
+            hashtags: &[],
+            reviewers: &[],
+            base_ref: None,
+            base_sha: None,
+        }
+    }
+
+    /// The change is for `base_ref` instead of the default branch, and squashed onto
+    /// `base_sha` when the branch was created from it.
+    pub fn with_base(mut self, base_ref: Option<&'a str>, base_sha: Option<&'a str>) -> Self {
+        self.base_ref = base_ref;
+        self.base_sha = base_sha;
+        self
+    }
+
+    /// Tags the change with the labels of the PR, and asks the assignees for a review.
+    pub fn with_people(mut self, hashtags: &'a [String], reviewers: &'a [String]) -> Self {
+        self.hashtags = hashtags;
+        self.reviewers = reviewers;
+        self
+    }
+
+    fn base_branch(&self, workspace: &Workspace) -> Result<String, MigrationError> {
+        if let Some(base_ref) = self.base_ref {
+            return Ok(base_ref.to_owned());
+        }
+
+        let repo = Repository::open(&workspace.working_dir)?;
+        let head = repo.find_reference("refs/remotes/origin/HEAD")?;
+        match head
+            .symbolic_target()
+            .and_then(|it| it.strip_prefix("refs/remotes/origin/"))
+        {
+            Some(branch) => Ok(branch.to_owned()),
+            None => Err(anyhow::anyhow!("origin has no default branch").into()),
+        }
+    }
+
+    fn refspec(&self, base: &str) -> String {
+        let mut options = vec![format!("topic={}", self.branch)];
+        options.extend(self.hashtags.iter().map(|it| format!("t={}", it)));
+        options.extend(self.reviewers.iter().map(|it| format!("r={}", it)));
+
+        format!("HEAD:refs/for/{}%{}", base, options.join(","))
+    }
+
+    async fn push_for_review(&self, workspace: &mut Workspace) -> Result<(), MigrationError> {
+        let base = self.base_branch(workspace)?;
+        let start = match self.base_sha {
+            Some(sha) => sha.to_owned(),
+            None => format!("origin/{}", base),
+        };
+
+        let message = workspace.root_dir.join("change-message");
+        std::fs::write(
+            &message,
+            format!(
+                "{}\n\n{}\n\nChange-Id: {}\n",
+                self.title,
+                self.body.trim_end(),
+                self.change_id
+            ),
+        )?;
+
+        info!("Pushing {} for review on {}", self.branch, base);
+        workspace
+            .run_command_successfully(&format!("git reset --soft {}", shell_quote(&start)))
+            .await?;
+        workspace
+            .run_command_successfully(&format!(
+                "git commit --quiet --file {}",
+                shell_quote(message.to_str().unwrap())
+            ))
+            .await?;
+        workspace
+            .run_command_successfully(&format!(
+                "git push origin {}",
+                shell_quote(&self.refspec(&base))
+            ))
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<'a> MigrationStep<()> for PushForReviewStep<'a> {
+    #[instrument(name = "push-for-review", skip(self, workspace), fields(workspace_name = %workspace.workspace_name))]
+    async fn execute_step(&self, workspace: &mut Workspace) -> MigrationStepResult<()> {
+        match self.push_for_review(workspace).await {
+            Err(e) => MigrationStepResult::failure("push", e),
+            Ok(_) => MigrationStepResult::success("push"),
+        }
+    }
+}
+
+pub struct BundleRepoStep<'a> {
+    branch_name: &'a str,
+    bundle_dir: &'a Path,
+}
+
+impl<'a> BundleRepoStep<'a> {
+    pub fn new(branch_name: &'a str, bundle_dir: &'a Path) -> Self {
+        Self {
+            branch_name,
+            bundle_dir,
+        }
+    }
+}
+
+#[async_trait]
+impl<'a> MigrationStep<()> for BundleRepoStep<'a> {
+    #[instrument(name = "bundle", skip(self, workspace), fields(workspace_name = %workspace.workspace_name))]
+    async fn execute_step(&self, workspace: &mut Workspace) -> MigrationStepResult<()> {
+        let bundle = bundle_path(self.bundle_dir, &workspace.workspace_name);
+        info!("Exporting {} to {}", self.branch_name, bundle.display());
+
         match workspace
-            .run_command_successfully("git push --force-with-lease")
+            .run_command_successfully(&format!(
+                "git bundle create {} {}",
+                bundle.to_str().unwrap(),
+                self.branch_name
+            ))
             .await
         {
-            Err(e) => MigrationStepResult::failure("push", MigrationError::CommandError(e)),
-            Ok(_) => MigrationStepResult::success("push"),
+            Err(e) => MigrationStepResult::failure("bundle", MigrationError::CommandError(e)),
+            Ok(_) => MigrationStepResult::success("bundle"),
+        }
+    }
+}
+
+pub struct ImportBundleStep<'a> {
+    branch_name: &'a str,
+    bundle_dir: &'a Path,
+    repo: &'a GitHubRepo,
+}
+
+impl<'a> ImportBundleStep<'a> {
+    pub fn new(branch_name: &'a str, bundle_dir: &'a Path, repo: &'a GitHubRepo) -> Self {
+        Self {
+            branch_name,
+            bundle_dir,
+            repo,
+        }
+    }
+
+    async fn import_bundle(&self, workspace: &mut Workspace) -> Result<(), MigrationError> {
+        let bundle = bundle_path(self.bundle_dir, &workspace.workspace_name).canonicalize()?;
+        let git_repo = workspace.root_dir.join("repo");
+
+        info!("Importing {} from {}", self.branch_name, bundle.display());
+        workspace
+            .run_command_successfully(&format!(
+                "git clone --branch {} {} {}",
+                self.branch_name,
+                bundle.to_str().unwrap(),
+                git_repo.to_str().unwrap()
+            ))
+            .await?;
+        workspace.set_working_dir("repo");
+
+        workspace
+            .run_command_successfully(&format!(
+                "git push --force {} {}",
+                &self.repo.clone_url, self.branch_name
+            ))
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<'a> MigrationStep<()> for ImportBundleStep<'a> {
+    #[instrument(name = "import-bundle", skip(self, workspace), fields(workspace_name = %workspace.workspace_name, repo = %self.repo))]
+    async fn execute_step(&self, workspace: &mut Workspace) -> MigrationStepResult<()> {
+        match self.import_bundle(workspace).await {
+            Err(e) => MigrationStepResult::failure("import-bundle", e),
+            Ok(_) => MigrationStepResult::success("import-bundle"),
+        }
+    }
+}
+
+/// Writes the commits of the branch as a `git format-patch` series, with a cover letter that
+/// has the title and body of the PR.
+pub struct FormatPatchStep<'a> {
+    patch_dir: &'a Path,
+    base: String,
+    title: &'a str,
+    body: &'a str,
+    email: Option<&'a EmailDefinition>,
+}
+
+impl<'a> FormatPatchStep<'a> {
+    pub fn new(patch_dir: &'a Path, title: &'a str, body: &'a str) -> Self {
+        Self {
+            patch_dir,
+            base: "origin/HEAD".to_owned(),
+            title,
+            body,
+            email: None,
+        }
+    }
+
+    /// Starts the series from the commit the branch was created from, rather than the default
+    /// branch.
+    pub fn with_base(mut self, base_ref: Option<&str>, base_sha: Option<&str>) -> Self {
+        self.base = RepoCheck::base_revision(base_ref, base_sha);
+        self
+    }
+
+    /// Addresses the mails and sets the subject prefix.
+    pub fn with_email(mut self, email: Option<&'a EmailDefinition>) -> Self {
+        self.email = email;
+        self
+    }
+
+    async fn format_patches(
+        &self,
+        workspace: &mut Workspace,
+    ) -> Result<PatchSeries, MigrationError> {
+        let series_dir = self.patch_dir.join(&workspace.workspace_name);
+        if series_dir.exists() {
+            std::fs::remove_dir_all(&series_dir)?;
+        }
+
+        let mut command = format!(
+            "git format-patch --thread --cover-letter -o {} ",
+            shell_quote(series_dir.to_str().unwrap())
+        );
+        if let Some(email) = self.email {
+            for to in &email.to {
+                command.push_str(&format!("--to={} ", shell_quote(to)));
+            }
+            for cc in &email.cc {
+                command.push_str(&format!("--cc={} ", shell_quote(cc)));
+            }
+            if let Some(prefix) = &email.subject_prefix {
+                command.push_str(&format!("--subject-prefix={} ", shell_quote(prefix)));
+            }
+        }
+        command.push_str(&format!("{}..HEAD", shell_quote(&self.base)));
+
+        info!("Writing patches to {}", series_dir.display());
+        workspace.run_command_successfully(&command).await?;
+
+        let mut patches: Vec<PathBuf> = std::fs::read_dir(&series_dir)?
+            .map(|it| it.map(|entry| entry.path()))
+            .collect::<Result<_, _>>()?;
+        patches.sort();
+
+        let mut message_ids = Vec::new();
+        for patch in &patches {
+            let mut contents = std::fs::read_to_string(patch)?;
+            if patch.ends_with("0000-cover-letter.patch") {
+                contents = contents
+                    .replace("*** SUBJECT HERE ***", self.title)
+                    .replace("*** BLURB HERE ***", self.body);
+                std::fs::write(patch, &contents)?;
+            }
+            message_ids.extend(message_id(&contents));
+        }
+
+        Ok(PatchSeries {
+            dir: series_dir.to_str().unwrap().to_owned(),
+            message_ids,
+            sent: false,
+        })
+    }
+}
+
+#[async_trait]
+impl<'a> MigrationStep<PatchSeries> for FormatPatchStep<'a> {
+    #[instrument(name = "format-patch", skip(self, workspace), fields(workspace_name = %workspace.workspace_name))]
+    async fn execute_step(&self, workspace: &mut Workspace) -> MigrationStepResult<PatchSeries> {
+        match self.format_patches(workspace).await {
+            Err(e) => MigrationStepResult::failure("format-patch", e),
+            Ok(series) => MigrationStepResult::success_with_result("format-patch", series),
+        }
+    }
+}
+
+/// Mails a series written by `FormatPatchStep` with `git send-email`.
+pub struct SendPatchesStep<'a> {
+    series: &'a PatchSeries,
+    email: &'a EmailDefinition,
+}
+
+impl<'a> SendPatchesStep<'a> {
+    pub fn new(series: &'a PatchSeries, email: &'a EmailDefinition) -> Self {
+        Self { series, email }
+    }
+
+    fn command(&self) -> Result<String, MigrationError> {
+        // The series is already threaded, and keeps the Message-Id's it was written with
+        let mut command = "git send-email --confirm=never --no-thread ".to_owned();
+        for to in &self.email.to {
+            command.push_str(&format!("--to={} ", shell_quote(to)));
+        }
+        for cc in &self.email.cc {
+            command.push_str(&format!("--cc={} ", shell_quote(cc)));
         }
+        if let Some(smtp) = &self.email.smtp {
+            command.push_str(&format!("--smtp-server={} ", shell_quote(&smtp.smtp_host)));
+            command.push_str(&format!("--smtp-server-port={} ", smtp.smtp_port));
+            if let Some(username) = &smtp.username {
+                command.push_str(&format!("--smtp-user={} ", shell_quote(username)));
+            }
+            if let Some(password_env) = &smtp.password_env {
+                // Expanded by the shell, so the password isn't in the logged command
+                command.push_str(&format!("--smtp-pass=\"${{{}}}\" ", password_env));
+            }
+            if smtp.starttls {
+                command.push_str("--smtp-encryption=tls ");
+            }
+        }
+
+        let mut patches: Vec<PathBuf> = std::fs::read_dir(&self.series.dir)?
+            .map(|it| it.map(|entry| entry.path()))
+            .collect::<Result<_, _>>()?;
+        patches.sort();
+        let patches: Vec<String> = patches
+            .iter()
+            .map(|it| shell_quote(it.to_str().unwrap()))
+            .collect();
+        command.push_str(&patches.join(" "));
+
+        Ok(command)
     }
 }
 
+#[async_trait]
+impl<'a> MigrationStep<()> for SendPatchesStep<'a> {
+    #[instrument(name = "send-email", skip(self, workspace), fields(workspace_name = %workspace.workspace_name))]
+    async fn execute_step(&self, workspace: &mut Workspace) -> MigrationStepResult<()> {
+        let command = match self.command() {
+            Ok(command) => command,
+            Err(e) => return MigrationStepResult::failure("send-email", e),
+        };
+
+        info!("Sending {} to {:?}", self.series.dir, self.email.to);
+        match workspace.run_command_successfully(&command).await {
+            Err(e) => MigrationStepResult::failure("send-email", MigrationError::CommandError(e)),
+            Ok(_) => MigrationStepResult::success("send-email"),
+        }
+    }
+}
+
+/// The `Message-Id` header of a patch written by `git format-patch --thread`.
+fn message_id(patch: &str) -> Option<String> {
+    patch
+        .lines()
+        .take_while(|it| !it.is_empty())
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            match name.eq_ignore_ascii_case("message-id") {
+                true => Some(value.trim().to_owned()),
+                false => None,
+            }
+        })
+}
+
+/// Quotes `value` for `/bin/sh`, which the workspace runs commands with.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Location of the bundle for a workspace, shared between export and import so
+/// both sides agree on the file name.
+pub fn bundle_path(bundle_dir: &Path, workspace_name: &str) -> PathBuf {
+    bundle_dir.join(format!("{}.bundle", workspace_name))
+}
+
 pub struct RepoCheck {}
 
 impl RepoCheck {
+    /// The commit the migration branch was created from, as understood by `git rev-parse`,
+    /// which is what the branch is compared with.
+    pub fn base_revision(base_ref: Option<&str>, base_sha: Option<&str>) -> String {
+        match (base_sha, base_ref) {
+            (Some(sha), _) => sha.to_owned(),
+            (None, Some(base_ref)) => format!("origin/{}", base_ref),
+            (None, None) => "origin/HEAD".to_owned(),
+        }
+    }
+
     pub async fn check_for_untracked_files(
         step_name: &str,
         workspace: &mut Workspace,
@@ -116,12 +693,21 @@ impl RepoCheck {
         let mut repo_status_options = StatusOptions::new();
         repo_status_options.include_ignored(false);
         let status = repo.statuses(Some(&mut repo_status_options))?;
-        if !status.is_empty() {
-            let files: Vec<String> = status
-                .iter()
-                .map(|x| x.path().unwrap().to_owned())
-                .collect();
 
+        // libgit2 reports files left out of a sparse checkout as deleted.
+        let sparse: HashSet<Vec<u8>> = repo
+            .index()?
+            .iter()
+            .filter(|it| it.flags_extended & IndexEntryExtendedFlag::SKIP_WORKTREE.bits() != 0)
+            .map(|it| it.path)
+            .collect();
+
+        let files: Vec<String> = status
+            .iter()
+            .filter(|x| x.status() != Status::WT_DELETED || !sparse.contains(x.path_bytes()))
+            .map(|x| x.path().unwrap().to_owned())
+            .collect();
+        if !files.is_empty() {
             return Err(MigrationError::WorkingDirNotClean {
                 step_name: step_name.to_owned(),
                 files,
@@ -130,4 +716,150 @@ impl RepoCheck {
 
         Ok(())
     }
+
+    /// The changes on the migration branch since `base` as a patch, for reviewers.
+    pub fn diff_patch(workspace: &Workspace, base: &str) -> Result<String, MigrationError> {
+        let repo = Repository::open(workspace.root_dir.join("repo"))?;
+        let base = repo.revparse_single(base)?.peel_to_tree()?;
+        let head = repo.head()?.peel_to_tree()?;
+
+        let mut patch = Vec::new();
+        repo.diff_tree_to_tree(Some(&base), Some(&head), None)?
+            .print(git2::DiffFormat::Patch, |_, _, line| {
+                if matches!(line.origin(), '+' | '-' | ' ') {
+                    patch.push(line.origin() as u8);
+                }
+                patch.extend_from_slice(line.content());
+                true
+            })?;
+
+        Ok(String::from_utf8_lossy(&patch).into_owned())
+    }
+
+    /// The commit checked out in the repo of the workspace.
+    pub fn head_commit(workspace: &Workspace) -> Result<String, MigrationError> {
+        let repo = Repository::open(workspace.root_dir.join("repo"))?;
+        let commit = repo.head()?.peel_to_commit()?;
+
+        Ok(commit.id().to_string())
+    }
+
+    /// The paths the migration branch changed since `base` in the repo at `git_repo`, like
+    /// `diff_stats` counts them.
+    pub fn changed_files(git_repo: &Path, base: &str) -> Result<Vec<String>, MigrationError> {
+        let repo = Repository::open(git_repo)?;
+        let base = repo.revparse_single(base)?.peel_to_tree()?;
+        let head = repo.head()?.peel_to_tree()?;
+
+        let diff = repo.diff_tree_to_tree(Some(&base), Some(&head), None)?;
+        Ok(diff
+            .deltas()
+            .filter_map(|it| it.new_file().path().or_else(|| it.old_file().path()))
+            .map(|it| it.to_string_lossy().into_owned())
+            .collect())
+    }
+
+    /// Compares the migration branch with `base`, the commit it was created from.
+    pub fn diff_stats(workspace: &Workspace, base: &str) -> Result<DiffStats, MigrationError> {
+        Self::diff_stats_of(&workspace.root_dir.join("repo"), base)
+    }
+
+    /// `diff_stats` of the repo at `git_repo`.
+    pub fn diff_stats_of(git_repo: &Path, base: &str) -> Result<DiffStats, MigrationError> {
+        let stats = Self::branch_diff_stats(git_repo, base)?;
+
+        Ok(DiffStats {
+            files_changed: stats.files_changed() as u64,
+            insertions: stats.insertions() as u64,
+            deletions: stats.deletions() as u64,
+        })
+    }
+
+    /// The changes of the migration branch per file, like `git diff --stat`.
+    pub fn diff_stat_text(git_repo: &Path, base: &str) -> Result<String, MigrationError> {
+        let stats = Self::branch_diff_stats(git_repo, base)?;
+        let text = stats.to_buf(git2::DiffStatsFormat::FULL, 80)?;
+
+        Ok(String::from_utf8_lossy(&text).into_owned())
+    }
+
+    fn branch_diff_stats(git_repo: &Path, base: &str) -> Result<git2::DiffStats, MigrationError> {
+        let repo = Repository::open(git_repo)?;
+        let base = repo.revparse_single(base)?.peel_to_tree()?;
+        let head = repo.head()?.peel_to_tree()?;
+
+        let stats = repo
+            .diff_tree_to_tree(Some(&base), Some(&head), None)?
+            .stats()?;
+        Ok(stats)
+    }
+}
+
+#[test]
+fn validate_message_id() {
+    let patch = "From 1234 Mon Sep 17 00:00:00 2001\n\
+        Message-Id: <20210101.1234-1-clu@example.com>\n\
+        Subject: [PATCH 1/1] Update\n\
+        \n\
+        Message-Id: <in-the-body@example.com>\n";
+
+    assert_eq!(
+        Some("<20210101.1234-1-clu@example.com>".to_owned()),
+        message_id(patch)
+    );
+    assert_eq!(None, message_id("Subject: Update\n\nMessage-Id: <body>\n"));
+    assert_eq!("'it'\\''s'", shell_quote("it's"));
+}
+
+#[test]
+fn validate_diff_against_base_sha() {
+    let root = std::env::temp_dir().join(format!("clu-base-sha-{}", std::process::id()));
+    let repo = Repository::init(&root).unwrap();
+    let signature = git2::Signature::now("clu", "clu@example.com").unwrap();
+    let commit = |file: &str, parent: Option<git2::Oid>| {
+        std::fs::write(root.join(file), file).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new(file)).unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let parents: Vec<git2::Commit> = parent
+            .map(|it| repo.find_commit(it).unwrap())
+            .into_iter()
+            .collect();
+        let parents: Vec<&git2::Commit> = parents.iter().collect();
+        repo.commit(None, &signature, &signature, file, &tree, &parents)
+            .unwrap()
+    };
+
+    // The default branch moved on after the commit the migration branch was created from
+    let base_sha = commit("README.md", None);
+    let default_head = commit("CHANGELOG.md", Some(base_sha));
+    repo.reference("refs/remotes/origin/HEAD", default_head, true, "")
+        .unwrap();
+    repo.set_head_detached(base_sha).unwrap();
+    repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+        .unwrap();
+    let migrated = commit("migrated.txt", Some(base_sha));
+    repo.set_head_detached(migrated).unwrap();
+
+    let base = RepoCheck::base_revision(Some("main"), Some(&base_sha.to_string()));
+    assert_eq!(
+        vec!["migrated.txt"],
+        RepoCheck::changed_files(&root, &base).unwrap()
+    );
+    assert_eq!(
+        1,
+        RepoCheck::diff_stats_of(&root, &base)
+            .unwrap()
+            .files_changed
+    );
+    assert_eq!(
+        2,
+        RepoCheck::changed_files(&root, "origin/HEAD")
+            .unwrap()
+            .len()
+    );
+    assert_eq!("origin/main", RepoCheck::base_revision(Some("main"), None));
+    assert_eq!("origin/HEAD", RepoCheck::base_revision(None, None));
+
+    std::fs::remove_dir_all(&root).unwrap();
 }
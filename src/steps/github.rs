@@ -1,19 +1,34 @@
 use async_trait::async_trait;
-use tracing::instrument;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use tracing::{info, instrument, warn};
 
 use super::{MigrationStep, MigrationStepResult};
-use crate::github::{GitHubRepo, GithubApiClient, PullRequestDescription};
+use crate::forge::ForgeClient;
+use crate::github::{
+    GitHubError, GitHubRepo, GithubApiClient, PullRequestDescription, PullRequestOutput,
+};
 use crate::migration::{MigrationError, MigrationTask};
-use crate::models::CreatedPullRequest;
+use crate::models::{BodyGist, CreatedPullRequest, MigrationDefinition, WorkflowDispatch};
 use crate::workspace::Workspace;
 
+/// GitHub rejects PR bodies longer than this many characters.
+const MAX_BODY_CHARS: usize = 65536;
+
 pub struct UpdateGithubStep<'a> {
-    github_api: &'a GithubApiClient,
+    forge: &'a dyn ForgeClient,
     repo: &'a GitHubRepo,
     existing_pr: Option<CreatedPullRequest>,
     branch: &'a str,
     title: &'a str,
     body: &'a str,
+    labels: &'a [String],
+    assignees: &'a [String],
+    milestone: Option<&'a str>,
+    comment: Option<&'a str>,
+    dispatch_workflow: Option<&'a WorkflowDispatch>,
+    metadata: Vec<(String, String)>,
+    base: Option<&'a str>,
 }
 
 #[async_trait]
@@ -23,17 +38,24 @@ impl<'a> MigrationStep<CreatedPullRequest> for UpdateGithubStep<'a> {
         &self,
         _workspace: &mut Workspace,
     ) -> MigrationStepResult<CreatedPullRequest> {
+        // The gists for bodies that are too long are only for GitHub
+        let (body, body_gist) = match self.forge.as_github() {
+            Some(github_api) => self.fit_body(github_api).await,
+            None => (Cow::Borrowed(self.body), None),
+        };
+        let description = PullRequestDescription {
+            branch: self.branch,
+            title: self.title,
+            body: &body,
+            labels: self.labels,
+            assignees: self.assignees,
+            milestone: self.milestone,
+            base: self.base,
+        };
+        let existing = self.existing_pr.as_ref().map(|it| it.pr_number);
         match self
-            .github_api
-            .sync_pull_request(
-                self.repo,
-                PullRequestDescription {
-                    branch: self.branch,
-                    title: self.title,
-                    body: self.body,
-                },
-                self.existing_pr.as_ref().map(|it| it.pr_number),
-            )
+            .forge
+            .sync_pull_request(self.repo, description, existing)
             .await
         {
             Err(e) => MigrationStepResult::failure(
@@ -41,9 +63,24 @@ impl<'a> MigrationStep<CreatedPullRequest> for UpdateGithubStep<'a> {
                 MigrationError::UnableToCreatePullRequest { source: e },
             ),
             Ok(new_pr) => {
+                if self.existing_pr.is_none() {
+                    if let Some(comment) = self.comment {
+                        self.post_comment(comment, &new_pr).await;
+                    }
+                }
+                if let Some(dispatch) = self.dispatch_workflow {
+                    self.dispatch_workflow(dispatch, &new_pr).await;
+                }
+
                 let pr = CreatedPullRequest {
                     pr_number: new_pr.number,
                     url: new_pr.permalink,
+                    owner: self.repo.owner.clone(),
+                    repo: self.repo.repo.clone(),
+                    head_ref: new_pr.head_ref,
+                    created_at: Some(new_pr.created_at),
+                    state: None,
+                    body_gist,
                 };
                 MigrationStepResult::success_with_result("pull-request", pr)
             }
@@ -51,15 +88,292 @@ impl<'a> MigrationStep<CreatedPullRequest> for UpdateGithubStep<'a> {
     }
 }
 
+impl<'a> UpdateGithubStep<'a> {
+    pub fn new(
+        forge: &'a dyn ForgeClient,
+        repo: &'a GitHubRepo,
+        existing_pr: Option<CreatedPullRequest>,
+        definition: &'a MigrationDefinition,
+    ) -> Self {
+        Self {
+            forge,
+            repo,
+            existing_pr,
+            branch: &definition.checkout.branch_name,
+            title: &definition.pr.title,
+            body: &definition.pr.description,
+            labels: &definition.pr.labels,
+            assignees: &definition.pr.assignees,
+            milestone: definition.pr.milestone.as_deref(),
+            comment: definition.pr.comment.as_deref(),
+            dispatch_workflow: definition.pr.dispatch_workflow.as_ref(),
+            metadata: Vec::new(),
+            base: None,
+        }
+    }
+
+    /// Opens the PR against `base` rather than the default branch, and moves an existing PR
+    /// over to it.
+    pub fn with_base(mut self, base: Option<&'a str>) -> Self {
+        self.base = base;
+        self
+    }
+
+    /// Makes the metadata of the target available to the comment and workflow inputs.
+    pub fn with_metadata(mut self, metadata: &BTreeMap<String, String>) -> Self {
+        self.metadata = crate::metadata::metadata_variables(metadata);
+        self
+    }
+
+    /// Bodies that are too long for GitHub are truncated, with the full body put in a gist
+    /// when one can be created. The gist of the last run is linked again while the body is the
+    /// same, so every run doesn't leave a new gist behind.
+    async fn fit_body(&self, github_api: &GithubApiClient) -> (Cow<'a, str>, Option<BodyGist>) {
+        if self.body.chars().count() <= MAX_BODY_CHARS {
+            return (Cow::Borrowed(self.body), None);
+        }
+
+        let sha256 = body_sha256(self.body);
+        let existing = self
+            .existing_pr
+            .as_ref()
+            .and_then(|it| it.body_gist.clone())
+            .filter(|it| it.sha256 == sha256);
+        let gist = match existing {
+            Some(gist) => Some(gist),
+            None => match github_api
+                .create_gist("pull-request-body.md", self.body)
+                .await
+            {
+                Ok(url) => Some(BodyGist { url, sha256 }),
+                Err(e) => {
+                    warn!("Unable to create gist for the PR body: {:?}", e);
+                    None
+                }
+            },
+        };
+
+        let notice = match &gist {
+            Some(gist) => format!(
+                "\n\n---\n_Content truncated, the full description is at {}_",
+                gist.url
+            ),
+            None => "\n\n---\n_Content truncated_".to_owned(),
+        };
+
+        (
+            Cow::Owned(truncate_body(self.body, &notice, MAX_BODY_CHARS)),
+            gist,
+        )
+    }
+
+    /// Replaces the `description` of the migration, used when the body is rendered from a
+    /// template.
+    pub fn with_body(mut self, body: &'a str) -> Self {
+        self.body = body;
+        self
+    }
+
+    fn template_variables(&self, pr: &PullRequestOutput) -> BTreeMap<&str, String> {
+        let mut variables = BTreeMap::new();
+        for (name, value) in &self.metadata {
+            variables.insert(name.as_str(), value.clone());
+        }
+        variables.insert("repo", self.repo.to_string());
+        variables.insert("owner", self.repo.owner.clone());
+        variables.insert("name", self.repo.repo.clone());
+        variables.insert("branch", self.branch.to_owned());
+        variables.insert("pr_url", pr.permalink.clone());
+        variables
+    }
+
+    /// The PR already exists at this point, so a comment that can't be posted is only logged.
+    async fn post_comment(&self, comment: &str, pr: &PullRequestOutput) {
+        let body = crate::template::render(comment, &self.template_variables(pr));
+        match self
+            .forge
+            .add_pull_request_comment(self.repo, pr, &body)
+            .await
+        {
+            Ok(_) => info!("Posted comment on {}", pr.permalink),
+            Err(e) => warn!("Unable to comment on {}: {:?}", pr.permalink, e),
+        }
+    }
+
+    /// Like the comment, a workflow that can't be dispatched is only logged.
+    async fn dispatch_workflow(&self, dispatch: &WorkflowDispatch, pr: &PullRequestOutput) {
+        let github_api = match self.forge.as_github() {
+            Some(github_api) => github_api,
+            None => {
+                warn!(
+                    "{} isn't on GitHub, so has no workflows to dispatch, skipping {}",
+                    self.repo, dispatch.workflow
+                );
+                return;
+            }
+        };
+        let variables = self.template_variables(pr);
+        let inputs: BTreeMap<String, String> = dispatch
+            .inputs
+            .iter()
+            .map(|(name, value)| (name.clone(), crate::template::render(value, &variables)))
+            .collect();
+
+        match github_api
+            .dispatch_workflow(self.repo, &dispatch.workflow, self.branch, &inputs)
+            .await
+        {
+            Ok(_) => info!("Dispatched {} on {}", dispatch.workflow, self.branch),
+            Err(e) => warn!("Unable to dispatch {}: {:?}", dispatch.workflow, e),
+        }
+    }
+}
+
 impl<'a> From<&'a MigrationTask<'a>> for UpdateGithubStep<'a> {
     fn from(task: &'a MigrationTask) -> Self {
+        // Push-only targets never get to the step, so the fallback is never used
+        let forge = task.exec_opts.forge.unwrap_or(task.exec_opts.github_client);
+        Self::new(
+            forge,
+            &task.repo,
+            task.pull_request.clone(),
+            &task.definition,
+        )
+        .with_metadata(&task.metadata)
+        .with_base(task.base_ref.as_deref())
+    }
+}
+
+fn body_sha256(body: &str) -> String {
+    let digits: Vec<String> = openssl::sha::sha256(body.as_bytes())
+        .iter()
+        .map(|it| format!("{:02x}", it))
+        .collect();
+    digits.concat()
+}
+
+/// Cuts `body` down so that it plus `notice` fit in `max_chars`. Counts characters rather than
+/// bytes, the way GitHub does, and never splits a character.
+fn truncate_body(body: &str, notice: &str, max_chars: usize) -> String {
+    let keep = max_chars.saturating_sub(notice.chars().count());
+    let mut truncated: String = body.chars().take(keep).collect();
+    truncated.push_str(notice);
+    truncated
+}
+
+#[test]
+fn validate_truncate_body() {
+    assert_eq!("héllo…", truncate_body("héllo wörld", "…", 6));
+    assert_eq!(6, truncate_body("héllo wörld", "…", 6).chars().count());
+    assert_eq!("…", truncate_body("héllo wörld", "…", 0));
+}
+
+#[tokio::test]
+async fn validate_fit_body_reuses_gist() {
+    let migration: crate::models::MigrationFile = toml::from_str(
+        r#"
+        [targets.clu]
+        repo = "git@github.com:ethankhall/clu.git"
+
+        [checkout]
+        branch-name = "clu/update"
+        pre-flight = "/usr/bin/true"
+
+        [pr]
+        title = "Update"
+        description = "Update"
+
+        [[steps]]
+        name = "update"
+        migration-script = "update.sh"
+        "#,
+    )
+    .unwrap();
+    // Nothing listens there, so a gist can't be created
+    let github_api = GithubApiClient::for_host("token", "http://127.0.0.1:9").unwrap();
+    let repo = crate::github::extract_github_info("git@github.com:ethankhall/clu.git").unwrap();
+    let body = "a".repeat(MAX_BODY_CHARS + 1);
+    let gist = BodyGist {
+        url: "https://gist.github.com/clu/1".to_owned(),
+        sha256: body_sha256(&body),
+    };
+    let existing_pr = CreatedPullRequest {
+        pr_number: 1,
+        url: "https://github.com/ethankhall/clu/pull/1".to_owned(),
+        owner: "ethankhall".to_owned(),
+        repo: "clu".to_owned(),
+        head_ref: "clu/update".to_owned(),
+        created_at: None,
+        state: None,
+        body_gist: Some(gist.clone()),
+    };
+
+    let step = UpdateGithubStep::new(&github_api, &repo, Some(existing_pr), &migration.definition)
+        .with_body(&body);
+    let (fitted, body_gist) = step.fit_body(&github_api).await;
+    assert_eq!(Some(gist), body_gist);
+    assert!(fitted.ends_with("is at https://gist.github.com/clu/1_"));
+    assert_eq!(MAX_BODY_CHARS, fitted.chars().count());
+
+    // A changed body needs a new gist
+    let changed = "b".repeat(MAX_BODY_CHARS + 1);
+    let (fitted, body_gist) = step.with_body(&changed).fit_body(&github_api).await;
+    assert_eq!(None, body_gist);
+    assert!(fitted.ends_with("_Content truncated_"));
+}
+
+/// Stops the migration early for repos that can't be pushed to, instead of failing after all
+/// the steps have been run.
+pub struct RepoAccessCheckStep<'a> {
+    forge: &'a dyn ForgeClient,
+    repo: &'a GitHubRepo,
+    requires_push: bool,
+}
+
+impl<'a> RepoAccessCheckStep<'a> {
+    pub fn new(forge: &'a dyn ForgeClient, repo: &'a GitHubRepo, requires_push: bool) -> Self {
         Self {
-            github_api: task.exec_opts.github_client,
-            repo: &task.repo,
-            existing_pr: task.pull_request.clone(),
-            branch: &task.definition.checkout.branch_name,
-            title: &task.definition.pr.title,
-            body: &task.definition.pr.description,
+            forge,
+            repo,
+            requires_push,
+        }
+    }
+}
+
+#[async_trait]
+impl<'a> MigrationStep<()> for RepoAccessCheckStep<'a> {
+    #[instrument(name = "repo-access", skip(self, _workspace), fields(workspace_name = %_workspace.workspace_name, repo = %self.repo))]
+    async fn execute_step(&self, _workspace: &mut Workspace) -> MigrationStepResult<()> {
+        let access = match self.forge.fetch_repo_details(self.repo).await {
+            Ok(access) => access,
+            Err(e) => {
+                match e.downcast_ref::<GitHubError>() {
+                    Some(error) if error.is_not_found() => {
+                        warn!("Repo doesn't exist, or the token can't see it, skipping");
+                        return MigrationStepResult::abort("not-found");
+                    }
+                    Some(error) if error.retry_wait().is_some() => {
+                        return MigrationStepResult::failure(
+                            "repo-access",
+                            MigrationError::AnyHowError(e),
+                        );
+                    }
+                    _ => {}
+                }
+
+                warn!("Unable to check access to repo: {:?}", e);
+                return MigrationStepResult::success("repo-access");
+            }
+        };
+
+        if access.archived {
+            warn!("Repo is archived, skipping");
+            MigrationStepResult::abort("archived")
+        } else if self.requires_push && !access.can_push {
+            warn!("Token is not allowed to push to repo, skipping");
+            MigrationStepResult::abort("read-only")
+        } else {
+            MigrationStepResult::success("repo-access")
         }
     }
 }
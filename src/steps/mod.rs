@@ -9,10 +9,13 @@ mod script_exec;
 use crate::migration::MigrationError;
 use crate::workspace::Workspace;
 
-use git::RepoCheck;
-pub use git::{CloneRepoStep, PushRepoStep};
-pub use github::UpdateGithubStep;
-pub use script_exec::{FollowUpStep, MigrationScriptStep, PreFlightCheckStep};
+pub use git::RepoCheck;
+pub use git::{
+    bundle_path, BundleRepoStep, CloneRepoStep, FormatPatchStep, ImportBundleStep,
+    PushForReviewStep, PushRepoStep, SendPatchesStep,
+};
+pub use github::{RepoAccessCheckStep, UpdateGithubStep};
+pub use script_exec::{DiffReviewStep, FollowUpStep, MigrationScriptStep, PreFlightCheckStep};
 
 #[async_trait]
 pub trait MigrationStep<Output> {
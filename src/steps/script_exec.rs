@@ -4,7 +4,7 @@ use tracing::{info, instrument, warn};
 
 use super::{make_script_absolute, MigrationStep, MigrationStepResult, RepoCheck};
 use crate::migration::{MigrationError, MigrationTask};
-use crate::models::MigrationStepDefinition;
+use crate::models::{DiffReviewerDefinition, MigrationStepDefinition};
 use crate::workspace::{CommandError, Workspace};
 
 pub struct PreFlightCheckStep<'a> {
@@ -105,6 +105,75 @@ impl<'a> From<&'a MigrationStepDefinition> for MigrationScriptStep<'a> {
     }
 }
 
+/// Hands the diff to a reviewer command, the output is its findings.
+pub struct DiffReviewStep<'a> {
+    reviewer: &'a DiffReviewerDefinition,
+    base: String,
+}
+
+impl<'a> DiffReviewStep<'a> {
+    /// Reviews the changes since `base_sha`, or the head of `base_ref`, instead of the head of
+    /// the default branch.
+    pub fn with_base(mut self, base_ref: Option<&str>, base_sha: Option<&str>) -> Self {
+        self.base = RepoCheck::base_revision(base_ref, base_sha);
+        self
+    }
+}
+
+#[async_trait]
+impl<'a> MigrationStep<Option<String>> for DiffReviewStep<'a> {
+    #[instrument(name = "diff-review", skip(self, workspace), fields(workspace_name = %workspace.workspace_name, reviewer = %self.reviewer.name))]
+    async fn execute_step(&self, workspace: &mut Workspace) -> MigrationStepResult<Option<String>> {
+        let diff_file = workspace.root_dir.join("review.diff");
+        if let Err(e) = RepoCheck::diff_patch(workspace, &self.base)
+            .and_then(|patch| std::fs::write(&diff_file, patch).map_err(MigrationError::from))
+        {
+            return MigrationStepResult::failure("diff-review", e);
+        }
+
+        info!("Running reviewer {}", self.reviewer.name);
+        let command = format!(
+            "{} '{}'",
+            make_script_absolute(&self.reviewer.command),
+            diff_file.display()
+        );
+        let output = match workspace.run_command(&command).await {
+            Ok(output) => output,
+            Err(e) => {
+                return MigrationStepResult::failure("diff-review", MigrationError::from(e));
+            }
+        };
+
+        let findings = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+        let findings = if findings.is_empty() {
+            None
+        } else {
+            Some(findings)
+        };
+
+        if !output.status.success() && !self.reviewer.advisory {
+            warn!("Reviewer {} rejected the changes", self.reviewer.name);
+            return MigrationStepResult::failure(
+                "diff-review",
+                MigrationError::ChangesRejected {
+                    reviewer: self.reviewer.name.clone(),
+                },
+            );
+        }
+
+        MigrationStepResult::success_with_result("diff-review", findings)
+    }
+}
+
+impl<'a> From<&'a DiffReviewerDefinition> for DiffReviewStep<'a> {
+    fn from(reviewer: &'a DiffReviewerDefinition) -> Self {
+        Self {
+            reviewer,
+            base: RepoCheck::base_revision(None, None),
+        }
+    }
+}
+
 pub struct FollowUpStep<'a> {
     command: &'a str,
 }
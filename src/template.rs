@@ -0,0 +1,127 @@
+use regex::{Captures, Regex};
+use std::collections::BTreeMap;
+use std::fs::read_to_string;
+use std::io;
+use std::path::Path;
+
+use crate::github::GitHubRepo;
+use crate::models::{DiffStats, MigrationDefinition};
+
+/// How deep partials can include other partials, so a partial including itself fails instead
+/// of looping forever.
+const MAX_PARTIAL_DEPTH: usize = 8;
+
+/// Replaces `{{name}}` placeholders with the matching variable. Placeholders without a
+/// variable are left alone, so a typo shows up in the output instead of disappearing.
+pub fn render(template: &str, variables: &BTreeMap<&str, String>) -> String {
+    let placeholder = Regex::new(r"\{\{\s*([A-Za-z0-9_.-]+)\s*\}\}").unwrap();
+    placeholder
+        .replace_all(template, |caps: &Captures| match variables.get(&caps[1]) {
+            Some(value) => value.clone(),
+            None => caps[0].to_owned(),
+        })
+        .into_owned()
+}
+
+/// Reads a template file, including any `{{> name}}` partials. A partial is a file next to the
+/// template with the same extension, so `{{> footer}}` in `pr-body.md.hbs` reads
+/// `footer.md.hbs`.
+pub fn load(path: &Path) -> io::Result<String> {
+    let file_name = path
+        .file_name()
+        .and_then(|it| it.to_str())
+        .unwrap_or_default();
+    let extension = match file_name.find('.') {
+        Some(index) => &file_name[index..],
+        None => "",
+    };
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+    include_partials(&read_to_string(path)?, dir, extension, 0)
+}
+
+fn include_partials(
+    template: &str,
+    dir: &Path,
+    extension: &str,
+    depth: usize,
+) -> io::Result<String> {
+    let partial = Regex::new(r"\{\{>\s*([A-Za-z0-9_-]+)\s*\}\}").unwrap();
+
+    let mut rendered = String::new();
+    let mut last = 0;
+    for caps in partial.captures_iter(template) {
+        if depth >= MAX_PARTIAL_DEPTH {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Partials nested more than {} deep", MAX_PARTIAL_DEPTH),
+            ));
+        }
+
+        let whole = caps.get(0).unwrap();
+        let path = dir.join(format!("{}{}", &caps[1], extension));
+        rendered.push_str(&template[last..whole.start()]);
+        rendered.push_str(&include_partials(
+            &read_to_string(path)?,
+            dir,
+            extension,
+            depth + 1,
+        )?);
+        last = whole.end();
+    }
+    rendered.push_str(&template[last..]);
+
+    Ok(rendered)
+}
+
+/// The PR body for a target, from `pr.template` when it's set and `pr.description` otherwise.
+pub fn render_pr_body(
+    definition: &MigrationDefinition,
+    repo: &GitHubRepo,
+    target_name: &str,
+    diff: Option<&DiffStats>,
+    metadata: &BTreeMap<String, String>,
+) -> io::Result<String> {
+    let template = match &definition.pr.template {
+        Some(path) => load(Path::new(path))?,
+        None => return Ok(definition.pr.description.clone()),
+    };
+
+    let steps: Vec<String> = definition
+        .steps
+        .iter()
+        .map(|it| format!("- {}", it.name))
+        .collect();
+
+    let mut variables = BTreeMap::new();
+    variables.insert("title", definition.pr.title.clone());
+    variables.insert("description", definition.pr.description.clone());
+    variables.insert("branch", definition.checkout.branch_name.clone());
+    variables.insert("target", target_name.to_owned());
+    variables.insert("repo", repo.to_string());
+    variables.insert("owner", repo.owner.clone());
+    variables.insert("name", repo.repo.clone());
+    variables.insert("steps", steps.join("\n"));
+    if let Some(diff) = diff {
+        variables.insert("files_changed", diff.files_changed.to_string());
+        variables.insert("insertions", diff.insertions.to_string());
+        variables.insert("deletions", diff.deletions.to_string());
+    }
+    let metadata = crate::metadata::metadata_variables(metadata);
+    for (name, value) in &metadata {
+        variables.insert(name.as_str(), value.clone());
+    }
+
+    Ok(render(&template, &variables))
+}
+
+#[test]
+fn validate_render() {
+    let mut variables = BTreeMap::new();
+    variables.insert("repo", "ethankhall/clu".to_owned());
+
+    assert_eq!(
+        "Rolling out to ethankhall/clu, ping {{ owner }}",
+        render("Rolling out to {{repo}}, ping {{ owner }}", &variables)
+    );
+}
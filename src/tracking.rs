@@ -0,0 +1,40 @@
+use anyhow::Result as AnyResult;
+use std::path::Path;
+use tracing::info;
+
+use crate::github::{extract_github_info, GitHubHosts};
+use crate::models::TrackingRepo;
+
+/// Commits the given files into the tracking repo, files that don't exist locally are skipped.
+pub async fn publish_results(
+    github_hosts: &GitHubHosts,
+    tracking: &TrackingRepo,
+    headline: &str,
+    files: &[&Path],
+) -> AnyResult<()> {
+    let repo = extract_github_info(&tracking.repo)?;
+    let base_path = tracking.path.trim_end_matches('/');
+
+    let mut contents = Vec::new();
+    for file in files {
+        if !file.exists() {
+            continue;
+        }
+
+        let file_name = file.file_name().unwrap().to_string_lossy();
+        let path = if base_path.is_empty() {
+            file_name.to_string()
+        } else {
+            format!("{}/{}", base_path, file_name)
+        };
+        contents.push((path, std::fs::read(file)?));
+    }
+
+    let url = github_hosts
+        .client_for(&repo)?
+        .commit_files(&repo, &tracking.branch, headline, contents)
+        .await?;
+    info!("Published results to {}", url);
+
+    Ok(())
+}
@@ -0,0 +1,159 @@
+use anyhow::{bail, Result as AnyResult};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::github::PullStatus;
+
+/// The URL and status of the PR of each target, from one poll of `check-status --watch`.
+pub type PolledStatuses = BTreeMap<String, (String, PullStatus)>;
+
+/// A PR whose status changed between two polls.
+#[derive(Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct StatusChange {
+    pub target: String,
+    pub url: String,
+    /// `None` when the PR wasn't polled before, like when it was opened in the meantime.
+    pub from: Option<PullStatus>,
+    pub to: PullStatus,
+}
+
+/// Parses intervals like `90s`, `5m` or `1h`. A number without a unit is seconds.
+pub fn parse_interval(text: &str) -> AnyResult<Duration> {
+    let text = text.trim();
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => text.split_at(index),
+        None => (text, "s"),
+    };
+    let number: u64 = match number.parse() {
+        Ok(number) => number,
+        Err(_) => bail!("`{}` isn't an interval like `5m`", text),
+    };
+    let seconds = match unit {
+        "s" => number,
+        "m" => number * 60,
+        "h" => number * 60 * 60,
+        _ => bail!("`{}` has an unknown unit, use s, m or h", text),
+    };
+    if seconds == 0 {
+        bail!("The interval can't be zero");
+    }
+
+    Ok(Duration::from_secs(seconds))
+}
+
+pub fn status_changes(before: &PolledStatuses, after: &PolledStatuses) -> Vec<StatusChange> {
+    after
+        .iter()
+        .filter_map(|(target, (url, status))| {
+            let from = before.get(target).map(|(_, status)| status.clone());
+            match from.as_ref() == Some(status) {
+                true => None,
+                false => Some(StatusChange {
+                    target: target.clone(),
+                    url: url.clone(),
+                    from,
+                    to: status.clone(),
+                }),
+            }
+        })
+        .collect()
+}
+
+/// Once every PR is closed their status can't change anymore.
+pub fn all_closed(statuses: &PolledStatuses) -> bool {
+    statuses.values().all(|(_, status)| status.is_closed())
+}
+
+pub fn format_changes(changes: &[StatusChange]) -> String {
+    let mut merged = Vec::new();
+    let mut failing = Vec::new();
+    let mut other = Vec::new();
+    for change in changes {
+        let from = match &change.from {
+            Some(from) => from.to_string(),
+            None => "new".to_owned(),
+        };
+        let entry = format!("- {} ({} -> {})", change.url, from, change.to);
+        match change.to {
+            PullStatus::Merged => merged.push(entry),
+            PullStatus::ChecksFailed | PullStatus::Conflicting => failing.push(entry),
+            _ => other.push(entry),
+        }
+    }
+
+    format!(
+        "# Status Changes
+
+## Newly Merged
+
+{}
+
+## Newly Failing
+
+{}
+
+## Other Changes
+
+{}",
+        merged.join("\n"),
+        failing.join("\n"),
+        other.join("\n")
+    )
+}
+
+#[test]
+fn validate_parse_interval() {
+    assert_eq!(Duration::from_secs(90), parse_interval("90s").unwrap());
+    assert_eq!(Duration::from_secs(300), parse_interval("5m").unwrap());
+    assert_eq!(Duration::from_secs(7200), parse_interval("2h").unwrap());
+    assert_eq!(Duration::from_secs(30), parse_interval("30").unwrap());
+    assert!(parse_interval("5d").is_err());
+    assert!(parse_interval("m").is_err());
+    assert!(parse_interval("0m").is_err());
+}
+
+#[test]
+fn validate_status_changes() {
+    let polled = |statuses: &[(&str, PullStatus)]| -> PolledStatuses {
+        statuses
+            .iter()
+            .map(|(name, status)| {
+                (
+                    name.to_string(),
+                    (
+                        format!("https://github.com/org/{}/pull/1", name),
+                        status.clone(),
+                    ),
+                )
+            })
+            .collect()
+    };
+    let before = polled(&[
+        ("a", PullStatus::Mergeable),
+        ("b", PullStatus::NeedsApproval),
+    ]);
+    let after = polled(&[
+        ("a", PullStatus::Merged),
+        ("b", PullStatus::NeedsApproval),
+        ("c", PullStatus::ChecksFailed),
+    ]);
+
+    let changes = status_changes(&before, &after);
+    assert_eq!(2, changes.len());
+    assert_eq!(Some(PullStatus::Mergeable), changes[0].from);
+    assert_eq!(None, changes[1].from);
+
+    let text = format_changes(&changes);
+    assert!(
+        text.contains("## Newly Merged\n\n- https://github.com/org/a/pull/1 (mergeable -> merged)")
+    );
+    assert!(text.contains("- https://github.com/org/c/pull/1 (new -> checks-failed)"));
+
+    assert!(!all_closed(&after));
+    assert!(all_closed(&polled(&[
+        ("a", PullStatus::Merged),
+        ("b", PullStatus::ClosedWithoutMerge)
+    ])));
+}
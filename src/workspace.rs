@@ -23,6 +23,8 @@ pub struct Workspace {
     stdout: File,
     stderr: File,
     env_vars: BTreeMap<String, String>,
+    /// Exit code of the most recent command, `None` when it was killed by a signal.
+    pub last_exit_code: Option<i32>,
     pub root_dir: PathBuf,
     pub working_dir: PathBuf,
     pub workspace_name: String,
@@ -56,6 +58,7 @@ impl Workspace {
             stdout,
             stderr,
             env_vars: BTreeMap::new(),
+            last_exit_code: None,
             root_dir: workspace_dir.to_path_buf(),
             working_dir: workspace_dir.to_path_buf(),
         })
@@ -89,6 +92,7 @@ impl Workspace {
 
         self.stdout.write_all(&output.stdout)?;
         self.stderr.write_all(&output.stderr)?;
+        self.last_exit_code = output.status.code();
 
         Ok(output)
     }
@@ -110,3 +114,38 @@ impl Workspace {
         self.working_dir = self.root_dir.join(path);
     }
 }
+
+/// Archives a workspace into a `.tar.gz`, so a failure can be reproduced after the work dir is
+/// gone. Without `include_git_objects` the repo's object database is left out, which is most of
+/// the size, and the checkout can be recreated by fetching the branch again.
+pub async fn snapshot_workspace(
+    workspace_dir: &Path,
+    archive: &Path,
+    include_git_objects: bool,
+) -> Result<(), CommandError> {
+    let parent = workspace_dir.parent().unwrap_or_else(|| Path::new("."));
+    let name = workspace_dir
+        .file_name()
+        .map(|it| it.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut command = Command::new("tar");
+    command.arg("-czf").arg(archive).arg("-C").arg(parent);
+    if !include_git_objects {
+        command.arg(format!("--exclude={}/repo/.git/objects", name));
+        command.arg(format!("--exclude={}/repo/.git/lfs", name));
+    }
+    command.arg(&name);
+
+    debug!("Running {:?}", command);
+    let status = command.status().await?;
+    if !status.success() {
+        return Err(CommandError::NonZeroExit {
+            command: format!("tar -czf {}", archive.display()),
+            working_dir: parent.display().to_string(),
+            code: status.code().unwrap_or(-1),
+        });
+    }
+
+    Ok(())
+}